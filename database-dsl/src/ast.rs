@@ -9,6 +9,8 @@ pub enum TypeKind<'a> {
     InternedId(&'a InterningTable),
     Enum,
     RustType,
+    /// `Option<T>` where `T` is an incremental or interned id.
+    Optional,
 }
 
 impl<'a> TypeKind<'a> {
@@ -20,6 +22,24 @@ impl<'a> TypeKind<'a> {
     }
 }
 
+/// Returns `T` if `typ` is `Option<T>`.
+pub fn option_inner_type(typ: &syn::Type) -> Option<&syn::Type> {
+    if let syn::Type::Path(syn::TypePath { qself: None, path }) = typ {
+        if let [segment] = path.segments.iter().collect::<Vec<_>>().as_slice() {
+            if segment.ident == "Option" {
+                if let syn::PathArguments::AngleBracketed(arguments) = &segment.arguments {
+                    if let [syn::GenericArgument::Type(inner)] =
+                        arguments.args.iter().collect::<Vec<_>>().as_slice()
+                    {
+                        return Some(inner);
+                    }
+                }
+            }
+        }
+    }
+    None
+}
+
 /// A custom ID (most likely some ID taken directly from the Rust compiler).
 pub struct CustomId {
     pub name: syn::Ident,
@@ -80,11 +100,23 @@ pub struct InternedId {
 ///
 /// Note: the implementation assumes that if values are equal (by the definition of `==`),
 /// then the generates keys should also be the same.
-#[derive(Debug, Hash)]
+#[derive(Debug)]
 pub struct InterningTable {
     pub name: syn::Ident,
     pub key: InternedId,
     pub value: syn::Type,
+    /// The documentation comment of the table.
+    pub doc: String,
+}
+
+impl Hash for InterningTable {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // `doc` is not hashed because it does not affect how the table is
+        // stored.
+        self.name.hash(state);
+        self.key.hash(state);
+        self.value.hash(state);
+    }
 }
 
 impl InterningTable {
@@ -101,6 +133,21 @@ impl InterningTable {
 
         syn::Ident::new(&name, self.name.span())
     }
+    /// The name of the constant holding the `FileSchema` of the table.
+    pub fn get_file_schema_name(&self) -> syn::Ident {
+        let name = format!("{}_FILE_SCHEMA", self.name.to_string().to_uppercase());
+        syn::Ident::new(&name, self.name.span())
+    }
+    /// The name of the `Loader` method that reads the table from the base and
+    /// all segments of the database.
+    pub fn get_loader_read_function_name(&self) -> syn::Ident {
+        syn::Ident::new(&format!("read_{}_table", self.name), self.name.span())
+    }
+    /// The name of the `TableMerger` field that stores the number of values
+    /// that were already stored in the database.
+    pub fn get_segment_offset_name(&self) -> syn::Ident {
+        syn::Ident::new(&format!("{}_segment_offset", self.name), self.name.span())
+    }
     pub fn get_key_type(&self) -> syn::Type {
         syn::Type::Path(syn::TypePath {
             qself: None,
@@ -121,22 +168,41 @@ pub struct Enum {
     pub default: syn::Ident,
 }
 
+/// A column of another relation that must contain all values of a relation
+/// parameter, declared with `references relation.column`.
+#[derive(Clone)]
+pub struct ColumnReference {
+    pub relation: syn::Ident,
+    pub column: syn::Ident,
+}
+
 /// A relation parameter with types.
-#[derive(Hash)]
+#[derive(Clone)]
 pub struct RelationParameter {
     pub name: syn::Ident,
     pub typ: syn::Type,
     pub is_autogenerated: bool,
+    pub references: Vec<ColumnReference>,
+}
+
+impl Hash for RelationParameter {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // `references` are not hashed because they do not affect how the
+        // relation is stored.
+        self.name.hash(state);
+        self.typ.hash(state);
+        self.is_autogenerated.hash(state);
+    }
 }
 
-#[derive(Hash)]
+#[derive(Clone, Hash)]
 pub struct RelationKey {
     pub source: Vec<syn::Ident>,
     pub target: Option<syn::Ident>,
 }
 
 /// A Datalog relation.
-#[derive(Hash)]
+#[derive(Clone)]
 pub struct Relation {
     pub name: syn::Ident,
     pub parameters: Vec<RelationParameter>,
@@ -144,6 +210,18 @@ pub struct Relation {
     /// merging databases. That is, any duplicate entries having the same `key.source`
     /// should be dropped and `key.target` should be remapped.
     pub key: Option<RelationKey>,
+    /// The documentation comment of the relation.
+    pub doc: String,
+}
+
+impl Hash for Relation {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // `doc` is not hashed because it does not affect how the relation is
+        // stored.
+        self.name.hash(state);
+        self.parameters.hash(state);
+        self.key.hash(state);
+    }
 }
 
 impl Relation {
@@ -155,9 +233,53 @@ impl Relation {
         self.hash(&mut state);
         state.finish()
     }
+    /// The name of the constant holding the `FileSchema` of the relation.
+    pub fn get_file_schema_name(&self) -> syn::Ident {
+        let name = format!("{}_FILE_SCHEMA", self.name.to_string().to_uppercase());
+        syn::Ident::new(&name, self.name.span())
+    }
+    pub fn get_read_function_name(&self) -> syn::Ident {
+        syn::Ident::new(&format!("read_relation_{}", self.name), self.name.span())
+    }
+    /// The name of the `Loader` method that reads the facts from the base and
+    /// all segments of the database.
+    pub fn get_loader_read_function_name(&self) -> syn::Ident {
+        syn::Ident::new(&format!("read_{}_facts", self.name), self.name.span())
+    }
+    pub fn get_write_function_name(&self) -> syn::Ident {
+        syn::Ident::new(&format!("write_relation_{}", self.name), self.name.span())
+    }
     pub fn get_merge_map_name(&self) -> syn::Ident {
         syn::Ident::new(&format!("{}_merge_map", self.name), self.name.span())
     }
+    /// The name of the struct that stores a fact of the relation: the
+    /// singular of the relation name in `CamelCase`, for example,
+    /// `FunctionDefinition` for `function_definitions`.
+    pub fn get_row_struct_name(&self) -> syn::Ident {
+        let name = self.name.to_string();
+        let singular = if let Some(stem) = name.strip_suffix("ies") {
+            format!("{}y", stem)
+        } else if let Some(stem) = name.strip_suffix("sses") {
+            format!("{}ss", stem)
+        } else if name.ends_with("ss") {
+            name
+        } else if let Some(stem) = name.strip_suffix('s') {
+            stem.to_string()
+        } else {
+            name
+        };
+        let camel_case: String = singular
+            .split('_')
+            .map(|word| {
+                let mut chars = word.chars();
+                match chars.next() {
+                    Some(first) => first.to_uppercase().chain(chars).collect(),
+                    None => String::new(),
+                }
+            })
+            .collect();
+        syn::Ident::new(&camel_case, self.name.span())
+    }
     pub fn get_relation_key_target(&self) -> Option<&syn::Ident> {
         self.key.as_ref().and_then(|key| key.target.as_ref())
     }
@@ -198,6 +320,120 @@ impl From<Relations> for Vec<Relation> {
     }
 }
 
+/// A change of the schema that can be applied to an existing database.
+pub enum MigrationStep {
+    /// `add_column relation.column = default;`: the column was added and the
+    /// existing facts get the `default` value.
+    AddColumn {
+        relation: syn::Ident,
+        column: syn::Ident,
+        default: syn::Expr,
+    },
+    /// `rename_column relation.old_name -> new_name;`
+    RenameColumn {
+        relation: syn::Ident,
+        old_name: syn::Ident,
+        new_name: syn::Ident,
+    },
+    /// `add_variant Enum::Variant;`: the variant was added to the enum.
+    AddVariant {
+        enum_name: syn::Ident,
+        variant: syn::Ident,
+    },
+    /// `make_optional relation.column = none_value;`: the column of id type
+    /// `T` was changed to `Option<T>` and the stored id with the underlying
+    /// value `none_value`, which was used as a sentinel, becomes `None`.
+    MakeOptional {
+        relation: syn::Ident,
+        column: syn::Ident,
+        none_value: syn::Expr,
+    },
+}
+
+impl MigrationStep {
+    fn relation(&self) -> Option<&syn::Ident> {
+        match self {
+            MigrationStep::AddColumn { relation, .. }
+            | MigrationStep::RenameColumn { relation, .. }
+            | MigrationStep::MakeOptional { relation, .. } => Some(relation),
+            MigrationStep::AddVariant { .. } => None,
+        }
+    }
+}
+
+/// `migration N { ... }` lists the changes that turn a database of the
+/// schema version `N - 1` into a database of the version `N`.
+pub struct Migration {
+    pub version: syn::LitInt,
+    pub steps: Vec<MigrationStep>,
+}
+
+/// Where the values of a column of the current schema come from when
+/// migrating the facts stored by an older schema version.
+pub enum ColumnSource<'a> {
+    /// The column with the given index of the old facts. If the column is an
+    /// enum that got new variants since, `variants` contains the name of the
+    /// enum and the current variants in the old order of discriminants. If
+    /// the column was made optional since, `none_value` is the old value that
+    /// becomes `None`.
+    Old {
+        index: usize,
+        variants: Option<(syn::Ident, Vec<syn::Ident>)>,
+        none_value: Option<&'a syn::Expr>,
+    },
+    /// The column was added since and gets the given default value. If the
+    /// column was made optional after it was added, the second value is the
+    /// default that becomes `None`.
+    Default(&'a syn::Expr, Option<&'a syn::Expr>),
+}
+
+/// A relation as it was stored by an older schema version.
+pub struct MigratedRelation<'a> {
+    /// The relation as it was declared in the older schema.
+    pub old: Relation,
+    /// The sources of the columns of the current relation.
+    pub sources: Vec<ColumnSource<'a>>,
+}
+
+/// `index relation(columns);` declares a secondary index that groups the
+/// facts of the relation by the values of the columns. With `persist`, the
+/// index is also stored next to the relation file.
+pub struct Index {
+    pub relation: syn::Ident,
+    pub columns: Vec<syn::Ident>,
+    pub persist: bool,
+}
+
+impl Index {
+    /// The suffix of the generated names, for example, `by_module_and_abi`.
+    fn get_suffix(&self) -> String {
+        let columns: Vec<_> = self
+            .columns
+            .iter()
+            .map(|column| column.to_string())
+            .collect();
+        format!("by_{}", columns.join("_and_"))
+    }
+    /// The name of the `Loader` field that caches the index.
+    pub fn get_field_name(&self) -> syn::Ident {
+        let name = format!("{}_{}_index", self.relation, self.get_suffix());
+        syn::Ident::new(&name, self.relation.span())
+    }
+    pub fn get_group_function_name(&self) -> syn::Ident {
+        let name = format!("group_{}_{}", self.relation, self.get_suffix());
+        syn::Ident::new(&name, self.relation.span())
+    }
+    pub fn get_lookup_function_name(&self) -> syn::Ident {
+        let name = format!("lookup_{}_{}", self.relation, self.get_suffix());
+        syn::Ident::new(&name, self.relation.span())
+    }
+    /// The path of the file that stores a persisted index relative to the
+    /// database root.
+    pub fn get_file_name(&self) -> String {
+        format!("relations/{}.{}.bincode", self.relation, self.get_suffix())
+    }
+}
+
 /// Configuration of all tables.
 #[derive(Default)]
 pub struct DatabaseSchema {
@@ -208,6 +444,10 @@ pub struct DatabaseSchema {
     pub relations: Vec<Relation>,
     /// Derived relations are not stored in the ``table`` object.
     pub derived_relations: Vec<Relation>,
+    /// Migrations in the order of their versions.
+    pub migrations: Vec<Migration>,
+    /// Secondary indexes of core and derived relations.
+    pub indexes: Vec<Index>,
 }
 
 impl DatabaseSchema {
@@ -248,15 +488,38 @@ impl DatabaseSchema {
                     }
                 }
                 match ident.to_string().as_ref() {
-                    "bool" | "u16" | "u32" | "u64" | "u128" | "String" => {
+                    "bool" | "u8" | "u16" | "u32" | "u64" | "u128" | "i64" | "i128" | "String" => {
                         return TypeKind::RustType
                     }
                     _ => panic!("Unknown type: {:?}.", typ),
                 }
             }
         }
+        if let Some(inner) = option_inner_type(typ) {
+            let is_unsigned_integer = matches!(
+                inner,
+                syn::Type::Path(syn::TypePath { qself: None, path })
+                    if path.is_ident("u8") || path.is_ident("u16") || path.is_ident("u32") || path.is_ident("u64")
+            );
+            return match self.get_type_kind(inner) {
+                TypeKind::IncrementalId(_) | TypeKind::InternedId(_) => TypeKind::Optional,
+                TypeKind::RustType if is_unsigned_integer => TypeKind::Optional,
+                _ => panic!(
+                    "Only incremental and interned ids, and unsigned integers up to `u64` can be optional: {:?}.",
+                    typ
+                ),
+            };
+        }
         panic!("Type {:?} is not an identifier.", typ);
     }
+    /// The kind of the values stored in a column of type `typ` and whether the
+    /// column is optional. For `Option<T>`, this is the kind of `T`.
+    pub fn get_column_type_kind(&self, typ: &syn::Type) -> (TypeKind, bool) {
+        match option_inner_type(typ) {
+            Some(inner) => (self.get_type_kind(inner), true),
+            None => (self.get_type_kind(typ), false),
+        }
+    }
     pub fn find_interning_table(&self, name: &syn::Ident) -> Option<&InterningTable> {
         for table in &self.interning_tables {
             if &table.name == name {
@@ -265,6 +528,105 @@ impl DatabaseSchema {
         }
         None
     }
+    /// Check that every `references` declaration names an existing column
+    /// of a core relation that has the same incremental id type.
+    pub fn check_references(&self) -> syn::Result<()> {
+        for relation in self.relations.iter().chain(&self.derived_relations) {
+            for parameter in &relation.parameters {
+                for reference in &parameter.references {
+                    if !matches!(
+                        self.get_column_type_kind(&parameter.typ).0,
+                        TypeKind::IncrementalId(_)
+                    ) {
+                        return Err(syn::Error::new(
+                            parameter.name.span(),
+                            "Only columns of incremental id types can reference other columns.",
+                        ));
+                    }
+                    let target = self
+                        .relations
+                        .iter()
+                        .find(|target| target.name == reference.relation)
+                        .ok_or_else(|| {
+                            syn::Error::new(
+                                reference.relation.span(),
+                                format!("Unknown core relation `{}`.", reference.relation),
+                            )
+                        })?;
+                    let target_parameter = target
+                        .parameters
+                        .iter()
+                        .find(|target_parameter| target_parameter.name == reference.column)
+                        .ok_or_else(|| {
+                            syn::Error::new(
+                                reference.column.span(),
+                                format!(
+                                    "Relation `{}` has no column `{}`.",
+                                    reference.relation, reference.column
+                                ),
+                            )
+                        })?;
+                    // An optional column references the column of the id type.
+                    let typ = option_inner_type(&parameter.typ).unwrap_or(&parameter.typ);
+                    let target_typ = &target_parameter.typ;
+                    if quote::quote!(#typ).to_string() != quote::quote!(#target_typ).to_string() {
+                        return Err(syn::Error::new(
+                            reference.column.span(),
+                            format!(
+                                "Column `{}.{}` has a different type than `{}.{}`.",
+                                reference.relation, reference.column, relation.name, parameter.name
+                            ),
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+    /// Check that the indexes refer to existing relations and columns and
+    /// that no index is declared twice.
+    pub fn check_indexes(&self) -> syn::Result<()> {
+        let mut names = std::collections::HashSet::new();
+        for index in &self.indexes {
+            let relation = self.find_relation(&index.relation).ok_or_else(|| {
+                syn::Error::new(
+                    index.relation.span(),
+                    format!("Unknown relation `{}`.", index.relation),
+                )
+            })?;
+            if index.columns.is_empty() {
+                return Err(syn::Error::new(
+                    index.relation.span(),
+                    "An index needs at least one column.",
+                ));
+            }
+            for (i, column) in index.columns.iter().enumerate() {
+                if !relation
+                    .parameters
+                    .iter()
+                    .any(|parameter| &parameter.name == column)
+                {
+                    return Err(syn::Error::new(
+                        column.span(),
+                        format!("Relation `{}` has no column `{}`.", relation.name, column),
+                    ));
+                }
+                if index.columns[..i].contains(column) {
+                    return Err(syn::Error::new(
+                        column.span(),
+                        format!("Column `{}` is indexed twice.", column),
+                    ));
+                }
+            }
+            if !names.insert(index.get_field_name()) {
+                return Err(syn::Error::new(
+                    index.relation.span(),
+                    "The index is declared twice.",
+                ));
+            }
+        }
+        Ok(())
+    }
     pub fn find_relation(&self, name: &syn::Ident) -> Option<&Relation> {
         for relation in self.relations.iter().chain(&self.derived_relations) {
             if &relation.name == name {
@@ -273,4 +635,216 @@ impl DatabaseSchema {
         }
         None
     }
+    /// The current schema version, which is the number of migrations.
+    pub fn schema_version(&self) -> u32 {
+        self.migrations.len() as u32
+    }
+    /// The migrations that were declared after schema `version`, starting
+    /// with the latest one.
+    fn migrations_since(&self, version: u32) -> impl Iterator<Item = &Migration> {
+        self.migrations.iter().skip(version as usize).rev()
+    }
+    /// The variants of the enum in schema `version` in the order of their
+    /// discriminants.
+    pub fn enum_variants_at_version(
+        &self,
+        enum_info: &Enum,
+        version: u32,
+    ) -> syn::Result<Vec<syn::Ident>> {
+        let mut variants: Vec<_> = enum_info
+            .item
+            .variants
+            .iter()
+            .map(|variant| variant.ident.clone())
+            .collect();
+        for migration in self.migrations_since(version) {
+            for step in migration.steps.iter().rev() {
+                if let MigrationStep::AddVariant { enum_name, variant } = step {
+                    if enum_name != &enum_info.item.ident {
+                        continue;
+                    }
+                    let position = variants
+                        .iter()
+                        .position(|current| current == variant)
+                        .ok_or_else(|| {
+                            syn::Error::new(
+                                variant.span(),
+                                format!("Enum `{}` has no variant `{}`.", enum_name, variant),
+                            )
+                        })?;
+                    variants.remove(position);
+                }
+            }
+        }
+        Ok(variants)
+    }
+    /// How to migrate the facts of `relation` stored by schema `version`.
+    /// Returns `None` if the facts do not need to be changed.
+    pub fn migrate_relation<'a>(
+        &'a self,
+        relation: &Relation,
+        version: u32,
+    ) -> syn::Result<Option<MigratedRelation<'a>>> {
+        let mut old = relation.clone();
+        // The index of the current column each old column corresponds to.
+        let mut current_indices: Vec<usize> = (0..relation.parameters.len()).collect();
+        let mut defaults = vec![None; relation.parameters.len()];
+        let mut none_values = vec![None; relation.parameters.len()];
+        let find_column = |old: &Relation, column: &syn::Ident| {
+            old.parameters
+                .iter()
+                .position(|parameter| &parameter.name == column)
+                .ok_or_else(|| {
+                    syn::Error::new(
+                        column.span(),
+                        format!("Relation `{}` has no column `{}`.", old.name, column),
+                    )
+                })
+        };
+        for migration in self.migrations_since(version) {
+            for step in migration.steps.iter().rev() {
+                match step {
+                    MigrationStep::AddColumn {
+                        relation: name,
+                        column,
+                        default,
+                    } if name == &relation.name => {
+                        let position = find_column(&old, column)?;
+                        if old.parameters[position].is_autogenerated {
+                            return Err(syn::Error::new(
+                                column.span(),
+                                "Autogenerated columns cannot be added by migrations.",
+                            ));
+                        }
+                        old.parameters.remove(position);
+                        defaults[current_indices.remove(position)] = Some(default);
+                    }
+                    MigrationStep::RenameColumn {
+                        relation: name,
+                        old_name,
+                        new_name,
+                    } if name == &relation.name => {
+                        let position = find_column(&old, new_name)?;
+                        old.parameters[position].name = old_name.clone();
+                        if let Some(key) = &mut old.key {
+                            for source in &mut key.source {
+                                if source == new_name {
+                                    *source = old_name.clone();
+                                }
+                            }
+                            if key.target.as_ref() == Some(new_name) {
+                                key.target = Some(old_name.clone());
+                            }
+                        }
+                    }
+                    MigrationStep::MakeOptional {
+                        relation: name,
+                        column,
+                        none_value,
+                    } if name == &relation.name => {
+                        let position = find_column(&old, column)?;
+                        let parameter = &mut old.parameters[position];
+                        let inner_type = option_inner_type(&parameter.typ)
+                            .ok_or_else(|| {
+                                syn::Error::new(
+                                    column.span(),
+                                    format!("Column `{}` is not optional.", column),
+                                )
+                            })?
+                            .clone();
+                        parameter.typ = inner_type;
+                        none_values[current_indices[position]] = Some(none_value);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        let mut is_identity = old.parameters.len() == relation.parameters.len();
+        let mut sources = Vec::new();
+        for (current_index, parameter) in relation.parameters.iter().enumerate() {
+            let source = match current_indices
+                .iter()
+                .position(|&index| index == current_index)
+            {
+                Some(index) => {
+                    let mut variants = None;
+                    if let TypeKind::Enum = self.get_type_kind(&parameter.typ) {
+                        let enum_info = self.find_enum(&parameter.typ).unwrap();
+                        let old_variants = self.enum_variants_at_version(enum_info, version)?;
+                        if old_variants.len() != enum_info.item.variants.len() {
+                            variants = Some((enum_info.item.ident.clone(), old_variants));
+                        }
+                    }
+                    let none_value = none_values[current_index];
+                    is_identity &= index == current_index
+                        && variants.is_none()
+                        && none_value.is_none()
+                        && old.parameters[index].name == parameter.name;
+                    ColumnSource::Old {
+                        index,
+                        variants,
+                        none_value,
+                    }
+                }
+                None => {
+                    is_identity = false;
+                    ColumnSource::Default(
+                        defaults[current_index].unwrap(),
+                        none_values[current_index],
+                    )
+                }
+            };
+            sources.push(source);
+        }
+        if is_identity {
+            Ok(None)
+        } else {
+            Ok(Some(MigratedRelation { old, sources }))
+        }
+    }
+    pub fn find_enum(&self, typ: &syn::Type) -> Option<&Enum> {
+        if let syn::Type::Path(syn::TypePath { qself: None, path }) = typ {
+            if let Some(ident) = path.get_ident() {
+                return self.enums.iter().find(|info| &info.item.ident == ident);
+            }
+        }
+        None
+    }
+    /// Check that the migrations are numbered consecutively from 1 and that
+    /// they can be applied to the declared relations and enums.
+    pub fn check_migrations(&self) -> syn::Result<()> {
+        for (index, migration) in self.migrations.iter().enumerate() {
+            if migration.version.base10_parse::<usize>()? != index + 1 {
+                return Err(syn::Error::new(
+                    migration.version.span(),
+                    format!("Expected migration {}.", index + 1),
+                ));
+            }
+            for step in &migration.steps {
+                if let Some(relation) = step.relation() {
+                    if !self.relations.iter().any(|r| &r.name == relation) {
+                        return Err(syn::Error::new(
+                            relation.span(),
+                            format!("Unknown core relation `{}`.", relation),
+                        ));
+                    }
+                }
+                if let MigrationStep::AddVariant { enum_name, .. } = step {
+                    if !self.enums.iter().any(|e| &e.item.ident == enum_name) {
+                        return Err(syn::Error::new(
+                            enum_name.span(),
+                            format!("Unknown enum `{}`.", enum_name),
+                        ));
+                    }
+                }
+            }
+        }
+        for relation in &self.relations {
+            self.migrate_relation(relation, 0)?;
+        }
+        for enum_info in &self.enums {
+            self.enum_variants_at_version(enum_info, 0)?;
+        }
+        Ok(())
+    }
 }
@@ -50,7 +50,7 @@ pub(super) fn generate_counters(schema: &ast::DatabaseSchema) -> (TokenStream, T
         });
     }
     let counters = quote! {
-        #[derive(Deserialize, Serialize)]
+        #[derive(Clone, Deserialize, Serialize)]
         /// Counters for generating unique identifiers.
         pub struct Counters {
             #fields
@@ -0,0 +1,277 @@
+use super::resolution::{load_resolution_tables, resolved_columns, ResolvedColumn, ResolvedValue};
+use super::utils::{interning_table_load_expression, tuple_column_name};
+use crate::ast;
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use std::collections::BTreeSet;
+
+pub(super) fn generate_dump_functions(schema: &ast::DatabaseSchema) -> TokenStream {
+    let dump_values = generate_dump_values(schema);
+    let mut match_arms = TokenStream::new();
+    let mut dump_functions = TokenStream::new();
+    for relation in schema.relations.iter().chain(&schema.derived_relations) {
+        let name = relation.name.to_string();
+        let fn_name = syn::Ident::new(&format!("dump_relation_{}", name), Span::call_site());
+        match_arms.extend(quote! {
+            #name => self.#fn_name(resolve, limit, format, output),
+        });
+        dump_functions.extend(generate_relation_dump(relation, &fn_name, schema));
+    }
+    for table in &schema.interning_tables {
+        let name = table.name.to_string();
+        let fn_name = syn::Ident::new(&format!("dump_interning_{}", name), Span::call_site());
+        match_arms.extend(quote! {
+            #name => self.#fn_name(resolve, limit, format, output),
+        });
+        dump_functions.extend(generate_interning_table_dump(table, &fn_name, schema));
+    }
+    quote! {
+        #dump_values
+
+        impl Loader {
+            /// Write the core or derived relation, or the interning table
+            /// `name` into `output`. At most `limit` rows are written. If
+            /// `resolve` is true, additional columns are added that resolve
+            /// interned strings and the elements of interned tuples such as
+            /// `DefPath` and `Build`.
+            pub fn dump_relation(
+                &self,
+                name: &str,
+                resolve: bool,
+                limit: Option<usize>,
+                format: crate::dump::DumpFormat,
+                output: &mut dyn std::io::Write,
+            ) -> Result<()> {
+                match name {
+                    #match_arms
+                    _ => Err(anyhow::anyhow!("Unknown relation or interning table: {}", name)),
+                }
+            }
+            #dump_functions
+        }
+    }
+}
+
+/// Generate `DumpValue` implementations for ids and enums.
+fn generate_dump_values(schema: &ast::DatabaseSchema) -> TokenStream {
+    let mut tokens = TokenStream::new();
+    let id_types = schema
+        .custom_ids
+        .iter()
+        .map(|id| &id.name)
+        .chain(schema.incremental_ids.iter().map(|id| &id.name))
+        .chain(schema.interning_tables.iter().map(|table| &table.key.name));
+    for name in id_types {
+        tokens.extend(quote! {
+            impl crate::dump::DumpValue for #name {
+                fn to_dump_value(&self) -> serde_json::Value {
+                    self.0.to_dump_value()
+                }
+            }
+        });
+    }
+    for ast::Enum { item, .. } in &schema.enums {
+        let name = &item.ident;
+        tokens.extend(quote! {
+            impl crate::dump::DumpValue for #name {
+                fn to_dump_value(&self) -> serde_json::Value {
+                    serde_json::Value::String(self.to_string())
+                }
+            }
+        });
+    }
+    tokens
+}
+
+/// Generate the code that computes the resolved columns of `column` and
+/// appends their names to `names` and their values to `row`. `value` is the
+/// expression that evaluates to the value of `column`.
+fn generate_resolved_values(
+    column: &str,
+    typ: &syn::Type,
+    value: TokenStream,
+    schema: &ast::DatabaseSchema,
+    tables: &mut BTreeSet<String>,
+    names: &mut Vec<String>,
+    row: &mut TokenStream,
+) {
+    for ResolvedColumn {
+        name,
+        value: resolved,
+    } in resolved_columns(column, typ, schema, tables)
+    {
+        let resolved = match resolved {
+            ResolvedValue::Str(resolved) => quote! { (#resolved).to_dump_value() },
+            ResolvedValue::Hex(resolved) => {
+                quote! { serde_json::Value::String(format!("{:x}", #resolved)) }
+            }
+            ResolvedValue::Native(_, resolved) => quote! { #resolved.to_dump_value() },
+        };
+        names.push(name);
+        row.extend(quote! {
+            {
+                let value = #value;
+                row.push(#resolved);
+            }
+        });
+    }
+}
+
+/// Generate the body of a dump function that writes `rows` with the given
+/// columns.
+fn generate_dump_body(
+    load: TokenStream,
+    rows: TokenStream,
+    columns: Vec<String>,
+    resolved_columns: Vec<String>,
+    tables: BTreeSet<String>,
+    values: TokenStream,
+    resolved_values: TokenStream,
+) -> TokenStream {
+    let (load_tables, unwrap_tables) = load_resolution_tables(&tables);
+    let resolve_values = if tables.is_empty() {
+        TokenStream::new()
+    } else {
+        quote! {
+            if resolve {
+                #unwrap_tables
+                #resolved_values
+            }
+        }
+    };
+    quote! {
+        use crate::dump::DumpValue;
+        #load
+        #load_tables
+        let mut columns = vec![#(#columns.to_string()),*];
+        if resolve {
+            columns.extend(vec![#(#resolved_columns.to_string()),*]);
+        }
+        let mut writer = crate::dump::RowWriter::new(format, columns, output)?;
+        for row_value in #rows.take(limit.unwrap_or(usize::MAX)) {
+            #[allow(unused_mut)]
+            let mut row = vec![#values];
+            #resolve_values
+            writer.write(row)?;
+        }
+        writer.finish()
+    }
+}
+
+fn generate_relation_dump(
+    relation: &ast::Relation,
+    fn_name: &syn::Ident,
+    schema: &ast::DatabaseSchema,
+) -> TokenStream {
+    let read_fn_name = relation.get_loader_read_function_name();
+    let mut columns = Vec::new();
+    let mut values = TokenStream::new();
+    let mut resolved_columns = Vec::new();
+    let mut resolved_values = TokenStream::new();
+    let mut tables = BTreeSet::new();
+    for ast::RelationParameter { name, typ, .. } in &relation.parameters {
+        let column = name.to_string();
+        values.extend(quote! { row_value.#name.to_dump_value(), });
+        generate_resolved_values(
+            &column,
+            typ,
+            quote! { row_value.#name },
+            schema,
+            &mut tables,
+            &mut resolved_columns,
+            &mut resolved_values,
+        );
+        columns.push(column);
+    }
+    let body = generate_dump_body(
+        quote! { let facts = self.#read_fn_name()?; },
+        quote! { facts.iter() },
+        columns,
+        resolved_columns,
+        tables,
+        values,
+        resolved_values,
+    );
+    quote! {
+        fn #fn_name(
+            &self,
+            resolve: bool,
+            limit: Option<usize>,
+            format: crate::dump::DumpFormat,
+            output: &mut dyn std::io::Write,
+        ) -> Result<()> {
+            #body
+        }
+    }
+}
+
+fn generate_interning_table_dump(
+    table: &ast::InterningTable,
+    fn_name: &syn::Ident,
+    schema: &ast::DatabaseSchema,
+) -> TokenStream {
+    let load = interning_table_load_expression(table);
+    let key_type = &table.key.name;
+    let mut columns = vec!["id".to_string()];
+    let mut values = quote! { row_value.0.to_dump_value(), };
+    let mut resolved_columns = Vec::new();
+    let mut resolved_values = TokenStream::new();
+    let mut tables = BTreeSet::new();
+    match &table.value {
+        syn::Type::Tuple(syn::TypeTuple { elems, .. }) => {
+            for (i, elem) in elems.iter().enumerate() {
+                let column = tuple_column_name(elems.iter(), i, elem);
+                let index = syn::Index::from(i);
+                values.extend(quote! { row_value.1.#index.to_dump_value(), });
+                generate_resolved_values(
+                    &column,
+                    elem,
+                    quote! { row_value.1.#index },
+                    schema,
+                    &mut tables,
+                    &mut resolved_columns,
+                    &mut resolved_values,
+                );
+                columns.push(column);
+            }
+        }
+        value => {
+            values.extend(quote! { row_value.1.to_dump_value(), });
+            generate_resolved_values(
+                "value",
+                value,
+                quote! { *row_value.1 },
+                schema,
+                &mut tables,
+                &mut resolved_columns,
+                &mut resolved_values,
+            );
+            columns.push("value".to_string());
+        }
+    }
+    let body = generate_dump_body(
+        quote! { let table = #load; },
+        quote! {
+            table
+                .iter_values()
+                .enumerate()
+                .map(|(index, value)| (#key_type::from(index), value))
+        },
+        columns,
+        resolved_columns,
+        tables,
+        values,
+        resolved_values,
+    );
+    quote! {
+        fn #fn_name(
+            &self,
+            resolve: bool,
+            limit: Option<usize>,
+            format: crate::dump::DumpFormat,
+            output: &mut dyn std::io::Write,
+        ) -> Result<()> {
+            #body
+        }
+    }
+}
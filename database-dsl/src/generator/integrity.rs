@@ -0,0 +1,353 @@
+use super::utils::interning_table_load_expression;
+use crate::ast;
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use std::collections::BTreeMap;
+
+pub(super) fn generate_integrity_checks(schema: &ast::DatabaseSchema) -> TokenStream {
+    let (load_tables, check_tables) = generate_interning_table_checks(schema);
+    let mut check_relations = TokenStream::new();
+    for relation in &schema.relations {
+        check_relations.extend(generate_relation_check(relation, schema));
+    }
+    for relation in &schema.derived_relations {
+        let check = generate_relation_check(relation, schema);
+        let file_name = format!("relations/{}", relation.name);
+        check_relations.extend(quote! {
+            let path = self.database_root.join(#file_name);
+            if path.with_extension("rc").exists() || path.with_extension("rcc").exists() {
+                #check
+            }
+        });
+    }
+    let foreign_keys = generate_foreign_keys(schema);
+    let tables_reference_checks = generate_reference_checks(schema, false, |relation| {
+        let name = &relation.name;
+        quote! { self.relations.#name.iter() }
+    });
+    let loader_reference_checks = generate_reference_checks(schema, true, |relation| {
+        let read_fn_name = relation.get_loader_read_function_name();
+        let types = relation.parameters.iter().map(|parameter| &parameter.typ);
+        quote! { self.#read_fn_name()?.iter().map(|&row| <(#(#types,)*)>::from(row)) }
+    });
+    quote! {
+        /// The columns that are declared to reference columns of other
+        /// relations.
+        pub const FOREIGN_KEYS: &[crate::integrity::ForeignKey] = &[#foreign_keys];
+
+        impl Tables {
+            /// Check that the columns declared with `references` contain only
+            /// ids that are present in the referenced columns.
+            pub fn check_references(&self) -> crate::integrity::IntegrityReport {
+                let mut report = crate::integrity::IntegrityReport::default();
+                #tables_reference_checks
+                report
+            }
+        }
+
+        impl Loader {
+            /// Same as `Tables::check_references`, but also checks the derived
+            /// relations that were already computed.
+            pub(crate) fn check_references(
+                &self,
+                report: &mut crate::integrity::IntegrityReport,
+            ) -> Result<()> {
+                #loader_reference_checks
+                Ok(())
+            }
+            /// Check that all interned ids are valid indices into their
+            /// interning tables and that all incremental ids are smaller than
+            /// their counters.
+            pub(crate) fn check_id_bounds(
+                &self,
+                report: &mut crate::integrity::IntegrityReport,
+            ) -> Result<()> {
+                let counters = self.read_counters()?;
+                #load_tables
+                #check_tables
+                #check_relations
+                Ok(())
+            }
+        }
+    }
+}
+
+fn table_length_variable(table: &str) -> syn::Ident {
+    syn::Ident::new(&format!("{}_len", table), Span::call_site())
+}
+
+/// Returns the expression that evaluates to the exclusive upper bound of
+/// values of type `typ` and the description of the constraint, if values of
+/// `typ` are bounded.
+fn id_bound(
+    column: &str,
+    typ: &syn::Type,
+    schema: &ast::DatabaseSchema,
+) -> Option<(TokenStream, String)> {
+    match schema.get_type_kind(typ) {
+        ast::TypeKind::InternedId(table) => {
+            let table_name = table.name.to_string();
+            let length = table_length_variable(&table_name);
+            Some((
+                quote! { #length },
+                format!("`{}` is a valid index into `{}`", column, table_name),
+            ))
+        }
+        ast::TypeKind::IncrementalId(id) => {
+            let field_name = id.get_field_name();
+            Some((
+                quote! { counters.#field_name as usize },
+                format!("`{}` is smaller than the `{}` counter", column, field_name),
+            ))
+        }
+        _ => None,
+    }
+}
+
+/// Generate the code that checks the bounded columns with the given names
+/// and types. `fact` is the variable that holds a row.
+fn generate_column_checks<'a>(
+    relation: &str,
+    rows: TokenStream,
+    columns: impl Iterator<Item = (String, TokenStream, &'a syn::Type)>,
+    schema: &ast::DatabaseSchema,
+) -> TokenStream {
+    let mut tokens = TokenStream::new();
+    for (column, value, typ) in columns {
+        // Only the present values of optional columns are checked.
+        let (typ, values) = match ast::option_inner_type(typ) {
+            Some(inner_type) => (
+                inner_type,
+                quote! { #rows.filter_map(|fact| #value.map(|id| id.index())) },
+            ),
+            None => (typ, quote! { #rows.map(|fact| #value.index()) }),
+        };
+        if let Some((bound, constraint)) = id_bound(&column, typ, schema) {
+            tokens.extend(quote! {
+                report.check(
+                    #relation,
+                    #constraint.to_string(),
+                    #values,
+                    |value| value < #bound,
+                );
+            });
+        }
+    }
+    tokens
+}
+
+/// Generate the code that loads the interning tables, remembers their
+/// lengths, and checks the ids stored in them.
+fn generate_interning_table_checks(schema: &ast::DatabaseSchema) -> (TokenStream, TokenStream) {
+    let mut load_tables = TokenStream::new();
+    let mut check_tables = TokenStream::new();
+    for table in &schema.interning_tables {
+        let table_name = table.name.to_string();
+        let variable = syn::Ident::new(&format!("table_{}", table_name), Span::call_site());
+        let length = table_length_variable(&table_name);
+        let load = interning_table_load_expression(table);
+        let columns: Vec<_> = match &table.value {
+            syn::Type::Tuple(syn::TypeTuple { elems, .. }) => elems
+                .iter()
+                .enumerate()
+                .map(|(i, elem)| {
+                    let index = syn::Index::from(i);
+                    (format!("value.{}", i), quote! { fact.#index }, elem)
+                })
+                .collect(),
+            value => vec![("value".to_string(), quote! { fact }, value)],
+        };
+        let checks = generate_column_checks(
+            &table_name,
+            quote! { #variable.iter_values() },
+            columns.into_iter(),
+            schema,
+        );
+        if checks.is_empty() {
+            load_tables.extend(quote! {
+                let #length = #load.len();
+            });
+            check_tables.extend(quote! {
+                report.add_relation(#table_name);
+            });
+        } else {
+            load_tables.extend(quote! {
+                let #variable = #load;
+                let #length = #variable.len();
+            });
+            check_tables.extend(quote! {
+                report.add_relation(#table_name);
+                #checks
+            });
+        }
+    }
+    (load_tables, check_tables)
+}
+
+fn generate_relation_check(relation: &ast::Relation, schema: &ast::DatabaseSchema) -> TokenStream {
+    let name = relation.name.to_string();
+    let read_fn_name = relation.get_loader_read_function_name();
+    let columns = relation.parameters.iter().map(|parameter| {
+        let field_name = &parameter.name;
+        (
+            parameter.name.to_string(),
+            quote! { fact.#field_name },
+            &parameter.typ,
+        )
+    });
+    let checks = generate_column_checks(&name, quote! { facts.iter() }, columns, schema);
+    if checks.is_empty() {
+        quote! {
+            report.add_relation(#name);
+        }
+    } else {
+        quote! {
+            report.add_relation(#name);
+            {
+                let facts = self.#read_fn_name()?;
+                #checks
+            }
+        }
+    }
+}
+
+/// A column declared with `references`.
+struct Reference<'a> {
+    relation: &'a ast::Relation,
+    index: usize,
+    is_derived: bool,
+}
+
+/// Group the columns declared with `references` by the referenced column.
+fn collect_references(
+    schema: &ast::DatabaseSchema,
+) -> BTreeMap<(String, String), Vec<Reference<'_>>> {
+    let mut references: BTreeMap<_, Vec<_>> = BTreeMap::new();
+    let relations = schema
+        .relations
+        .iter()
+        .map(|relation| (relation, false))
+        .chain(
+            schema
+                .derived_relations
+                .iter()
+                .map(|relation| (relation, true)),
+        );
+    for (relation, is_derived) in relations {
+        for (index, parameter) in relation.parameters.iter().enumerate() {
+            for reference in &parameter.references {
+                let target = (reference.relation.to_string(), reference.column.to_string());
+                references.entry(target).or_default().push(Reference {
+                    relation,
+                    index,
+                    is_derived,
+                });
+            }
+        }
+    }
+    references
+}
+
+fn generate_foreign_keys(schema: &ast::DatabaseSchema) -> TokenStream {
+    let mut tokens = TokenStream::new();
+    for ((target_relation, target_column), references) in collect_references(schema) {
+        for Reference {
+            relation, index, ..
+        } in references
+        {
+            let relation_name = relation.name.to_string();
+            let column = relation.parameters[index].name.to_string();
+            tokens.extend(quote! {
+                crate::integrity::ForeignKey {
+                    relation: #relation_name,
+                    column: #column,
+                    target_relation: #target_relation,
+                    target_column: #target_column,
+                },
+            });
+        }
+    }
+    tokens
+}
+
+/// Generate the code that checks the columns declared with `references`.
+/// `rows` returns the expression that iterates over the facts of the given
+/// relation. If `include_derived` is false, the columns of derived relations
+/// are not checked.
+fn generate_reference_checks(
+    schema: &ast::DatabaseSchema,
+    include_derived: bool,
+    rows: impl Fn(&ast::Relation) -> TokenStream,
+) -> TokenStream {
+    let mut tokens = TokenStream::new();
+    for ((target_relation, target_column), references) in collect_references(schema) {
+        let target = schema
+            .relations
+            .iter()
+            .find(|relation| relation.name == target_relation)
+            .unwrap();
+        let target_index = target
+            .parameters
+            .iter()
+            .position(|parameter| parameter.name == target_column)
+            .unwrap();
+        let target_rows = rows(target);
+        let target_index = syn::Index::from(target_index);
+        let mut checks = TokenStream::new();
+        for Reference {
+            relation,
+            index,
+            is_derived,
+        } in references
+        {
+            if is_derived && !include_derived {
+                continue;
+            }
+            let parameter = &relation.parameters[index];
+            let relation_name = relation.name.to_string();
+            let constraint = format!(
+                "`{}` references `{}.{}`",
+                parameter.name, target_relation, target_column
+            );
+            let is_optional = schema.get_column_type_kind(&parameter.typ).1;
+            let source_rows = rows(relation);
+            let index = syn::Index::from(index);
+            let values = if is_optional {
+                quote! { #source_rows.filter_map(|fact| fact.#index.map(|id| id.index())) }
+            } else {
+                quote! { #source_rows.map(|fact| fact.#index.index()) }
+            };
+            let check = quote! {
+                report.check(
+                    #relation_name,
+                    #constraint.to_string(),
+                    #values,
+                    |value| targets.contains(&value),
+                );
+            };
+            if is_derived {
+                let file_name = format!("relations/{}", relation.name);
+                checks.extend(quote! {
+                    let path = self.database_root.join(#file_name);
+                    if path.with_extension("rc").exists() || path.with_extension("rcc").exists() {
+                        #check
+                    }
+                });
+            } else {
+                checks.extend(check);
+            }
+        }
+        if checks.is_empty() {
+            continue;
+        }
+        tokens.extend(quote! {
+            {
+                let targets: std::collections::HashSet<usize> = #target_rows
+                    .map(|fact| fact.#target_index.index())
+                    .collect();
+                #checks
+            }
+        });
+    }
+    tokens
+}
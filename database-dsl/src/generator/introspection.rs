@@ -0,0 +1,239 @@
+use super::utils::tuple_column_name;
+use crate::ast;
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+
+pub(super) fn generate_introspection(schema: &ast::DatabaseSchema) -> TokenStream {
+    let dyn_values = generate_dyn_values(schema);
+    let core_relation_count = schema.relations.len();
+    let mut relation_infos = TokenStream::new();
+    let mut match_arms = TokenStream::new();
+    let relations = schema.relations.iter().chain(&schema.derived_relations);
+    for (index, relation) in relations.enumerate() {
+        let name = relation.name.to_string();
+        let derived = index >= core_relation_count;
+        let columns = relation.parameters.iter().map(|parameter| {
+            generate_column_info(
+                &parameter.name.to_string(),
+                &parameter.typ,
+                parameter.is_autogenerated,
+                schema,
+            )
+        });
+        let key = match &relation.key {
+            Some(ast::RelationKey { source, target }) => {
+                let source = source.iter().map(|column| column.to_string());
+                let target = match target {
+                    Some(target) => {
+                        let target = target.to_string();
+                        quote! { Some(#target) }
+                    }
+                    None => quote! { None },
+                };
+                quote! {
+                    Some(crate::introspection::RelationKeyInfo {
+                        source: &[#(#source),*],
+                        target: #target,
+                    })
+                }
+            }
+            None => quote! { None },
+        };
+        let doc = &relation.doc;
+        relation_infos.extend(quote! {
+            crate::introspection::RelationInfo {
+                name: #name,
+                derived: #derived,
+                columns: &[#(#columns),*],
+                key: #key,
+                doc: #doc,
+            },
+        });
+        let load_fn_name = syn::Ident::new(&format!("load_{}_rows", name), Span::call_site());
+        let values = relation.parameters.iter().map(|parameter| {
+            let field_name = &parameter.name;
+            quote! { fact.#field_name.to_dyn_value() }
+        });
+        match_arms.extend(quote! {
+            #name => {
+                let facts = self.#load_fn_name();
+                let rows = (0..facts.len()).map(move |i| {
+                    let fact = facts.get(i).unwrap();
+                    vec![#(#values),*]
+                });
+                Ok(crate::introspection::DynRelation::new(SCHEMA.relations[#index].columns, rows))
+            }
+        });
+    }
+    let mut table_infos = TokenStream::new();
+    for (index, table) in schema.interning_tables.iter().enumerate() {
+        let name = table.name.to_string();
+        let key_type = &table.key.name;
+        let key_column =
+            generate_column_info("id", &syn::parse_quote! { #key_type }, false, schema);
+        let mut columns = vec![key_column];
+        let mut values = vec![quote! { #key_type::from(index).to_dyn_value() }];
+        match &table.value {
+            syn::Type::Tuple(syn::TypeTuple { elems, .. }) => {
+                for (i, elem) in elems.iter().enumerate() {
+                    let column = tuple_column_name(elems.iter(), i, elem);
+                    columns.push(generate_column_info(&column, elem, false, schema));
+                    let i = syn::Index::from(i);
+                    values.push(quote! { value.#i.to_dyn_value() });
+                }
+            }
+            value => {
+                columns.push(generate_column_info("value", value, false, schema));
+                values.push(quote! { value.to_dyn_value() });
+            }
+        }
+        let doc = &table.doc;
+        table_infos.extend(quote! {
+            crate::introspection::InterningTableInfo {
+                name: #name,
+                columns: &[#(#columns),*],
+                doc: #doc,
+            },
+        });
+        let load_fn_name = syn::Ident::new(&format!("load_{}", name), Span::call_site());
+        match_arms.extend(quote! {
+            #name => {
+                let rows = self
+                    .#load_fn_name()
+                    .iter_values()
+                    .enumerate()
+                    .map(|(index, value)| vec![#(#values),*]);
+                Ok(crate::introspection::DynRelation::new(
+                    SCHEMA.interning_tables[#index].columns,
+                    rows,
+                ))
+            }
+        });
+    }
+    quote! {
+        #dyn_values
+
+        /// The relations and interning tables of the database.
+        pub static SCHEMA: crate::introspection::Schema = crate::introspection::Schema {
+            relations: &[#relation_infos],
+            interning_tables: &[#table_infos],
+        };
+
+        impl Tables {
+            /// The relations and interning tables of the database.
+            pub fn schema() -> &'static crate::introspection::Schema {
+                &SCHEMA
+            }
+        }
+
+        impl Loader {
+            /// The relations and interning tables of the database.
+            pub fn schema() -> &'static crate::introspection::Schema {
+                &SCHEMA
+            }
+            /// The rows of the core or derived relation, or the interning
+            /// table `name`. The relation is loaded as by `load_*`.
+            pub fn dyn_relation(&self, name: &str) -> Result<crate::introspection::DynRelation<'_>> {
+                use crate::introspection::ToDynValue;
+                match name {
+                    #match_arms
+                    _ => Err(anyhow::anyhow!("Unknown relation or interning table: {}", name)),
+                }
+            }
+        }
+    }
+}
+
+/// Generate the `ColumnInfo` of a column of type `typ`.
+fn generate_column_info(
+    name: &str,
+    typ: &syn::Type,
+    auto: bool,
+    schema: &ast::DatabaseSchema,
+) -> TokenStream {
+    let type_name = quote! { #typ }.to_string();
+    let (kind, optional) = schema.get_column_type_kind(typ);
+    let kind = match kind {
+        ast::TypeKind::CustomId => quote! { CustomId },
+        ast::TypeKind::IncrementalId(_) => quote! { IncrementalId },
+        ast::TypeKind::InternedId(table) => {
+            let table = table.name.to_string();
+            quote! { InternedId { table: #table } }
+        }
+        ast::TypeKind::Enum => {
+            let item = &schema.find_enum(typ).unwrap().item;
+            let variants = item
+                .variants
+                .iter()
+                .map(|variant| variant.ident.to_string());
+            quote! { Enum { variants: &[#(#variants),*] } }
+        }
+        ast::TypeKind::RustType => quote! { Primitive },
+        ast::TypeKind::Optional => unreachable!("options are not nested"),
+    };
+    quote! {
+        crate::introspection::ColumnInfo {
+            name: #name,
+            typ: #type_name,
+            kind: crate::introspection::ColumnKind::#kind,
+            optional: #optional,
+            auto: #auto,
+        }
+    }
+}
+
+/// Generate `ToDynValue` implementations for ids and enums.
+fn generate_dyn_values(schema: &ast::DatabaseSchema) -> TokenStream {
+    let mut tokens = TokenStream::new();
+    for id in &schema.custom_ids {
+        let name = &id.name;
+        tokens.extend(quote! {
+            impl crate::introspection::ToDynValue for #name {
+                fn to_dyn_value(&self) -> crate::introspection::DynValue {
+                    crate::introspection::ToDynValue::to_dyn_value(&self.0)
+                }
+            }
+        });
+    }
+    let id_types = schema
+        .incremental_ids
+        .iter()
+        .map(|id| &id.name)
+        .chain(schema.interning_tables.iter().map(|table| &table.key.name));
+    for name in id_types {
+        let type_name = name.to_string();
+        tokens.extend(quote! {
+            impl crate::introspection::ToDynValue for #name {
+                fn to_dyn_value(&self) -> crate::introspection::DynValue {
+                    crate::introspection::DynValue::Id {
+                        typ: #type_name,
+                        index: self.0 as u64,
+                    }
+                }
+            }
+        });
+    }
+    for ast::Enum { item, .. } in &schema.enums {
+        let name = &item.ident;
+        let type_name = name.to_string();
+        let variants = item.variants.iter().map(|variant| &variant.ident);
+        let variant_names = item
+            .variants
+            .iter()
+            .map(|variant| variant.ident.to_string());
+        tokens.extend(quote! {
+            impl crate::introspection::ToDynValue for #name {
+                fn to_dyn_value(&self) -> crate::introspection::DynValue {
+                    let variant = match self {
+                        #(#name::#variants => #variant_names,)*
+                    };
+                    crate::introspection::DynValue::Enum {
+                        typ: #type_name,
+                        variant,
+                    }
+                }
+            }
+        });
+    }
+    tokens
+}
@@ -8,56 +8,116 @@ pub(super) fn generate_loader_functions(
 ) -> (TokenStream, TokenStream) {
     let mut function_tokens = TokenStream::new();
     let mut cache_field_tokens = TokenStream::new();
-    for relation in schema.relations.iter().chain(&schema.derived_relations) {
+    let mut relation_caches = TokenStream::new();
+    let mut pinned_memory_usage = TokenStream::new();
+    let core_relation_count = schema.relations.len();
+    let relations = schema.relations.iter().chain(&schema.derived_relations);
+    for (index, relation) in relations.enumerate() {
         let ast::Relation {
             ref name,
             ref parameters,
             ..
         } = relation;
-        let relation_hash = relation.get_hash();
+        let read_fn_name = relation.get_read_function_name();
+        let loader_read_fn_name = relation.get_loader_read_function_name();
+        let write_fn_name = relation.get_write_function_name();
         let file_name = format!("relations/{}", name);
         let load_fn_name = syn::Ident::new(&format!("load_{}", name), Span::call_site());
         let store_fn_name = syn::Ident::new(&format!("store_{}", name), Span::call_site());
+        let unload_fn_name = syn::Ident::new(&format!("unload_{}", name), Span::call_site());
+        let load_rows_fn_name = syn::Ident::new(&format!("load_{}_rows", name), Span::call_site());
+        let row_struct_name = relation.get_row_struct_name();
+        let name_str = name.to_string();
+        relation_caches.extend(quote! {
+            (#name_str, &self.#name),
+        });
         let mut types = TokenStream::new();
         for ast::RelationParameter { typ, .. } in parameters {
             types.extend(quote! {#typ,});
         }
         cache_field_tokens.extend(quote! {
-            #name: std::cell::RefCell<Option<Vec<(#types)>>>,
+            #name: crate::cache::CachedFacts<super::rows::#row_struct_name>,
         });
-        function_tokens.extend(quote! {
-            pub fn #load_fn_name(&self) -> std::cell::Ref<Vec<(#types)>> {
-                if self.#name.borrow().is_none() {
-                    let relation: Relation<(#types)> = unsafe { Relation::load(
-                        #relation_hash,
-                        self.database_root.join(#file_name)
-                    ) }.unwrap();
-                    *self.#name.borrow_mut() = Some(relation.into());
+        let read_from_crates = if index < core_relation_count {
+            quote! {
+                if let Some(crates) = &self.crates {
+                    let facts = &crates.tables()?.relations.#name.facts;
+                    let rows: Vec<_> = facts.iter().map(|&fact| fact.into()).collect();
+                    return Ok(rows.into());
+                }
+            }
+        } else {
+            quote! {
+                if self.crates.is_some() {
+                    return #read_fn_name(self.database_root.join(#file_name));
                 }
-                std::cell::Ref::map(self.#name.borrow(), |option| option.as_ref().unwrap())
+            }
+        };
+        function_tokens.extend(quote! {
+            /// Read the facts from the base and all segments of the database.
+            pub(crate) fn #loader_read_fn_name(
+                &self,
+            ) -> Result<Facts<super::rows::#row_struct_name>> {
+                #read_from_crates
+                crate::migration::check_schema_version(&self.database_root, SCHEMA_VERSION)?;
+                crate::segments::read_relation(&self.database_root, #file_name, #read_fn_name)
+            }
+            /// Load the facts as structs with named fields on the first call.
+            /// The facts stored in the raw format are memory-mapped. If
+            /// several threads call this concurrently, the facts are read only
+            /// once.
+            pub fn #load_rows_fn_name(&self) -> crate::cache::LoadedFacts<super::rows::#row_struct_name> {
+                let rows = self.#name.get_or_load(&self.clock, || {
+                    self.#loader_read_fn_name().unwrap()
+                });
+                self.enforce_memory_budget();
+                rows
+            }
+            /// Copy the loaded facts into tuples, for example, for the
+            /// `datapond_query!` queries. Prefer `load_*_rows`, which does not
+            /// copy the facts.
+            pub fn #load_fn_name(&self) -> Vec<(#types)> {
+                self.#load_rows_fn_name().iter().map(|&row| row.into()).collect()
+            }
+            /// Drop the cached facts unless they are still borrowed. Returns
+            /// whether the facts are not cached anymore.
+            pub fn #unload_fn_name(&self) -> bool {
+                crate::cache::CachedRelation::unload(&self.#name)
             }
             pub fn #store_fn_name(&self, facts: Vec<(#types)>) {
-                assert!(self.#name.borrow().is_none());
-                let relation: Relation<(#types)> = facts.into();
-                unsafe { relation.save(#relation_hash, self.database_root.join(#file_name)); }
-                *self.#name.borrow_mut() = Some(relation.into());
+                assert!(!self.#name.is_loaded());
+                std::fs::create_dir_all(self.database_root.join("relations")).unwrap();
+                #write_fn_name(
+                    &facts,
+                    self.database_root.join(#file_name),
+                    StorageFormat::Raw,
+                ).unwrap();
+                let rows: Vec<super::rows::#row_struct_name> =
+                    facts.into_iter().map(|fact| fact.into()).collect();
+                self.#name.set(&self.clock, rows.into());
+                self.enforce_memory_budget();
             }
         });
 
         if let [key, value] = parameters.as_slice() {
             let load_fn_name_as_map =
                 syn::Ident::new(&format!("load_{}_as_map", name), Span::call_site());
-            let key = &key.typ;
-            let value = &value.typ;
+            let (key_name, key) = (&key.name, &key.typ);
+            let (value_name, value) = (&value.name, &value.typ);
             function_tokens.extend(quote! {
                 pub fn #load_fn_name_as_map(&self) -> std::collections::HashMap<#key, #value> {
-                    self.#load_fn_name().iter().copied().collect()
+                    self.#load_rows_fn_name()
+                        .iter()
+                        .map(|row| (row.#key_name, row.#value_name))
+                        .collect()
                 }
             });
         }
     }
     for table in &schema.interning_tables {
-        let ast::InterningTable { name, key, value } = table;
+        let ast::InterningTable {
+            name, key, value, ..
+        } = table;
         let fn_name = syn::Ident::new(&format!("load_{}", name), Span::call_site());
         let fn_name_as_vec = syn::Ident::new(&format!("load_{}_as_vec", name), Span::call_site());
         let key_type = &key.name;
@@ -74,39 +134,202 @@ pub(super) fn generate_loader_functions(
             }
         }
         let load = if is_copy_type(value, schema) {
-            let table_hash = table.get_hash();
+            let file_schema = table.get_file_schema_name();
             let file_name = format!("interning/{}", name);
             quote! {
-                unsafe {
-                    InterningTable::load(
-                        #table_hash,
-                        self.database_root.join(#file_name)
-                    ).unwrap()
-                }
+                unsafe { InterningTable::load_mapped(&#file_schema, root.join(#file_name)) }
             }
         } else {
             let file_name = format!("interning/{}.bincode", name);
             quote! {
-                crate::storage::load(
-                    &self.database_root.join(#file_name)
-                ).unwrap()
+                crate::storage::load(&root.join(#file_name))
             }
         };
+        let read_fn_name = table.get_loader_read_function_name();
+        let name_str = name.to_string();
+        pinned_memory_usage.extend(quote! {
+            usage.extend(self.#name.get().map(|table| (#name_str, table.memory_usage())));
+        });
         cache_field_tokens.extend(quote! {
-            #name: std::cell::RefCell<Option<InterningTable<#key_type, #value>>>,
+            #name: std::sync::OnceLock<InterningTable<#key_type, #value>>,
         });
         function_tokens.extend(quote! {
-            pub fn #fn_name(&self) -> std::cell::Ref<InterningTable<#key_type, #value>> {
-                if self.#name.borrow().is_none() {
-                    *self.#name.borrow_mut() = Some(#load);
+            /// Read the table from the base and all segments of the database.
+            pub(crate) fn #read_fn_name(&self) -> Result<InterningTable<#key_type, #value>> {
+                if let Some(crates) = &self.crates {
+                    return Ok(Facts::shared(crates.tables()?.clone(), |tables| {
+                        &tables.interning_tables.#name.contents[..]
+                    }).into());
                 }
-                std::cell::Ref::map(self.#name.borrow(), |option| option.as_ref().unwrap())
+                crate::migration::check_schema_version(&self.database_root, SCHEMA_VERSION)?;
+                crate::segments::read_interning_table(&self.database_root, |root| #load)
+            }
+            pub fn #fn_name(&self) -> &InterningTable<#key_type, #value> {
+                self.#name.get_or_init(|| self.#read_fn_name().unwrap())
             }
             pub fn #fn_name_as_vec(&self) -> Vec<(#types)> {
-                let table: InterningTable<#key_type, #value> = #load;
-                table.into()
+                self.#read_fn_name().unwrap().into()
             }
         });
     }
+    for index in &schema.indexes {
+        let (field_tokens, index_function_tokens) = generate_index_functions(schema, index);
+        let field_name = index.get_field_name();
+        let field_name_str = field_name.to_string();
+        pinned_memory_usage.extend(quote! {
+            usage.extend(self.#field_name.get().map(|&(_, size)| {
+                let usage = crate::cache::RelationMemoryUsage { owned: size, ..Default::default() };
+                (#field_name_str, usage)
+            }));
+        });
+        cache_field_tokens.extend(field_tokens);
+        function_tokens.extend(index_function_tokens);
+    }
+    function_tokens.extend(quote! {
+        /// Read the current counters, which are stored in the last segment of
+        /// the database.
+        pub(crate) fn read_counters(&self) -> Result<Counters> {
+            if let Some(crates) = &self.crates {
+                return Ok(crates.tables()?.counters.clone());
+            }
+            crate::migration::check_schema_version(&self.database_root, SCHEMA_VERSION)?;
+            load_counters(&crate::segments::counters_path(&self.database_root)?)
+        }
+        fn relation_caches(&self) -> Vec<(&'static str, &dyn crate::cache::CachedRelation)> {
+            vec![#relation_caches]
+        }
+        /// The memory used by the loaded interning tables and indexes.
+        fn pinned_memory_usage(&self) -> Vec<(&'static str, crate::cache::RelationMemoryUsage)> {
+            let mut usage = Vec::new();
+            #pinned_memory_usage
+            usage
+        }
+        /// The memory used by the loaded relations, interning tables, and
+        /// indexes.
+        pub fn memory_usage(&self) -> crate::cache::MemoryUsage {
+            crate::cache::MemoryUsage::new(&self.relation_caches(), self.pinned_memory_usage())
+        }
+        /// Drop all cached relations that are not borrowed, for example,
+        /// after running the queries that needed them.
+        pub fn unload_relations(&self) {
+            for (_, relation) in self.relation_caches() {
+                relation.unload();
+            }
+        }
+        fn enforce_memory_budget(&self) {
+            if let Some(budget) = self.memory_budget {
+                let pinned: usize = self
+                    .pinned_memory_usage()
+                    .iter()
+                    .map(|(_, usage)| usage.owned)
+                    .sum();
+                crate::cache::evict_relations(&self.relation_caches(), budget.saturating_sub(pinned));
+            }
+        }
+    });
     (function_tokens, cache_field_tokens)
 }
+
+fn generate_index_functions(
+    schema: &ast::DatabaseSchema,
+    index: &ast::Index,
+) -> (TokenStream, TokenStream) {
+    let relation = schema.find_relation(&index.relation).unwrap();
+    let relation_name = &relation.name;
+    let load_rows_fn_name =
+        syn::Ident::new(&format!("load_{}_rows", relation_name), Span::call_site());
+    let field_name = index.get_field_name();
+    let group_fn_name = index.get_group_function_name();
+    let lookup_fn_name = index.get_lookup_function_name();
+    let types: Vec<_> = relation
+        .parameters
+        .iter()
+        .map(|parameter| &parameter.typ)
+        .collect();
+    let mut key_types = Vec::new();
+    let mut key_values = Vec::new();
+    for column in &index.columns {
+        let position = relation
+            .parameters
+            .iter()
+            .position(|parameter| &parameter.name == column)
+            .unwrap();
+        let index = syn::Index::from(position);
+        key_types.push(&relation.parameters[position].typ);
+        key_values.push(quote! { fact.#index });
+    }
+    let (key_type, key_value, lookup_key) = match key_types.as_slice() {
+        [key_type] => (quote! { #key_type }, quote! { #(#key_values)* }, {
+            let column = &index.columns[0];
+            quote! { #column }
+        }),
+        _ => {
+            let columns = &index.columns;
+            (
+                quote! { (#(#key_types,)*) },
+                quote! { (#(#key_values,)*) },
+                quote! { (#(#columns,)*) },
+            )
+        }
+    };
+    let columns = &index.columns;
+    let index_type = quote! {
+        crate::index::RelationIndex<#key_type, (#(#types,)*)>
+    };
+    let build = quote! {
+        crate::index::RelationIndex::new(
+            self.#load_rows_fn_name().iter().map(|&row| <(#(#types,)*)>::from(row)),
+            |fact| #key_value,
+        )
+    };
+    let build = if index.persist {
+        let file_name = format!("relations/{}", relation_name);
+        let index_file = index.get_file_name();
+        quote! {
+            if self.crates.is_some() {
+                #build
+            } else {
+                crate::index::load_or_build_index(
+                    &self.database_root,
+                    #file_name,
+                    #index_file,
+                    || #build,
+                ).unwrap()
+            }
+        }
+    } else {
+        build
+    };
+    let column_names = index
+        .columns
+        .iter()
+        .map(|column| format!("`{}`", column))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let group_doc = format!(
+        " Group the facts of `{}` by {}. The index is built on the first call and kept until the loader is dropped.",
+        relation_name, column_names
+    );
+    let lookup_doc = format!(
+        " The facts of `{}` with the given {}.",
+        relation_name, column_names
+    );
+    let field_tokens = quote! {
+        #field_name: std::sync::OnceLock<(#index_type, usize)>,
+    };
+    let function_tokens = quote! {
+        #[doc = #group_doc]
+        pub fn #group_fn_name(&self) -> &#index_type {
+            &self.#field_name.get_or_init(|| {
+                let index = #build;
+                let size = index.memory_usage();
+                (index, size)
+            }).0
+        }
+        #[doc = #lookup_doc]
+        pub fn #lookup_fn_name(&self, #(#columns: #key_types),*) -> &[(#(#types,)*)] {
+            self.#group_fn_name().lookup(&#lookup_key)
+        }
+    };
+    (field_tokens, function_tokens)
+}
@@ -11,6 +11,7 @@ pub(super) fn generate_merge_functions(schema: &ast::DatabaseSchema) -> TokenStr
     let mut tokens = TokenStream::new();
     let mut field_tokens = TokenStream::new();
     let mut field_init_tokens = TokenStream::new();
+    let mut segment_field_init_tokens = TokenStream::new();
     for relation in &schema.relations {
         if let Some(ast::RelationKey { source, target }) = &relation.key {
             let mut key_types = TokenStream::new();
@@ -40,6 +41,7 @@ pub(super) fn generate_merge_functions(schema: &ast::DatabaseSchema) -> TokenStr
             });
 
             let mut parameter_tokens = TokenStream::new();
+            let mut field_tokens_of_row = TokenStream::new();
             for parameter in &relation.parameters {
                 let parameter_name = &parameter.name;
                 if source.contains(parameter_name)
@@ -49,6 +51,7 @@ pub(super) fn generate_merge_functions(schema: &ast::DatabaseSchema) -> TokenStr
                         .unwrap_or(false)
                 {
                     parameter_tokens.extend(quote! {#parameter_name,});
+                    field_tokens_of_row.extend(quote! {#parameter_name,});
                 } else {
                     parameter_tokens.extend(quote! {_,});
                 }
@@ -62,8 +65,39 @@ pub(super) fn generate_merge_functions(schema: &ast::DatabaseSchema) -> TokenStr
                     |&(#parameter_tokens)| ((#ident_tokens), #target_name)
                 ).collect(),
             });
+            let read_fn_name = relation.get_loader_read_function_name();
+            let row_struct_name = relation.get_row_struct_name();
+            segment_field_init_tokens.extend(quote! {
+                #map_name: loader.#read_fn_name()?.iter().map(
+                    |&super::rows::#row_struct_name { #field_tokens_of_row .. }| {
+                        ((#ident_tokens), #target_name)
+                    }
+                ).collect(),
+            });
         }
     }
+    let mut load_interning_tables = TokenStream::new();
+    let mut segment_interning_tables = TokenStream::new();
+    for table in &schema.interning_tables {
+        let name = &table.name;
+        let offset_name = table.get_segment_offset_name();
+        let read_fn_name = table.get_loader_read_function_name();
+        field_tokens.extend(quote! {
+            #offset_name: usize,
+        });
+        field_init_tokens.extend(quote! {
+            #offset_name: 0,
+        });
+        segment_field_init_tokens.extend(quote! {
+            #offset_name: tables.interning_tables.#name.len(),
+        });
+        load_interning_tables.extend(quote! {
+            #name: loader.#read_fn_name()?,
+        });
+        segment_interning_tables.extend(quote! {
+            #name: self.tables.interning_tables.#name.values_from(self.#offset_name),
+        });
+    }
     tokens.extend(merge_interning_tables(schema, &mut name_generator));
     tokens.extend(merge_relations(schema, &mut name_generator));
     tokens.extend(merge_counters(schema));
@@ -81,12 +115,49 @@ pub(super) fn generate_merge_functions(schema: &ast::DatabaseSchema) -> TokenStr
                     tables,
                 }
             }
+            /// Create a merger that collects the facts for a new segment of
+            /// the database at `database_root`. Only the interning tables and
+            /// the counters of the database are loaded; the relations start
+            /// empty.
+            pub fn new_segment(database_root: &Path) -> Result<Self> {
+                let loader = Loader::new(database_root.to_path_buf());
+                let tables = Tables {
+                    relations: Relations::default(),
+                    counters: loader.read_counters()?,
+                    interning_tables: InterningTables {
+                        #load_interning_tables
+                    },
+                };
+                Ok(Self {
+                    #segment_field_init_tokens
+                    tables,
+                })
+            }
+            /// Store the merged facts, the newly interned values, and the
+            /// counters as a segment in `segment_root`. If the merger was
+            /// created with `new`, this stores the complete database.
+            pub fn store_segment(&self, segment_root: &Path, format: StorageFormat) -> Result<()> {
+                let relations_path = segment_root.join("relations");
+                std::fs::create_dir_all(&relations_path)?;
+                store_multifile_relations(&self.tables.relations, &relations_path, format)?;
+                store_counters(&self.tables.counters, &segment_root.join("counters.bincode"));
+                let interning_tables = InterningTables {
+                    #segment_interning_tables
+                };
+                let interning_tables_path = segment_root.join("interning");
+                std::fs::create_dir_all(&interning_tables_path)?;
+                store_multifile_interning_tables(&interning_tables, &interning_tables_path);
+                Ok(())
+            }
             pub fn merge(&mut self, other: super::tables::Tables) {
                 #tokens
             }
             pub fn tables(&mut self) -> &mut Tables {
                 &mut self.tables
             }
+            pub fn into_tables(self) -> Tables {
+                self.tables
+            }
         }
     }
 }
@@ -198,7 +269,9 @@ fn merge_relations(
 
     // TODO: The current algorithm relies on the order in which keyed
     // relations are merged (all dependencies must be merged before merging
-    // dependents). This should be fixed.
+    // dependents). This should be fixed. This is the order of the relations
+    // in the schema; the ids of the merged tables are assigned in the order
+    // of the tables, which `merge_in_parallel` preserves.
     for relation in &schema.relations {
         let name = &relation.name;
         let mut params = TokenStream::new();
@@ -217,42 +290,10 @@ fn merge_relations(
             if relation.is_in_relation_key_source(param_name) {
                 new_source.extend(quote! {#new_name,});
             }
-            match schema.get_type_kind(&param.typ) {
-                ast::TypeKind::CustomId | ast::TypeKind::Enum | ast::TypeKind::RustType => {
-                    params_remap.extend(quote! {
-                        let #new_name = *#param_name;
-                    });
-                }
-                ast::TypeKind::IncrementalId(id) => {
-                    let counter_name = id.get_field_name();
-                    let constant_count = id.constants.len();
-                    let typ = &id.typ;
-                    let shift = quote! {
-                        #param_name.shift(
-                            self.tables.counters.#counter_name-(#constant_count as #typ)
-                        )
-                    };
-                    if constant_count > 0 {
-                        params_remap.extend(quote! {
-                            let #new_name = if #param_name.index() >= #constant_count {
-                                #shift
-                            } else {
-                                *#param_name
-                            };
-                        });
-                    } else {
-                        params_remap.extend(quote! {
-                            let #new_name = #shift;
-                        });
-                    }
-                }
-                ast::TypeKind::InternedId(table) => {
-                    let map = &table.name;
-                    params_remap.extend(quote! {
-                        let #new_name = #map[#param_name];
-                    });
-                }
-            }
+            let remap = remap_expression(quote! { #param_name }, &param.typ, schema);
+            params_remap.extend(quote! {
+                let #new_name = #remap;
+            });
             if let Some(map_name) = updatable_fields.get(&param.typ) {
                 let new_tmp_name = name_generator.get_fresh_ident();
                 params_remap.extend(quote! {
@@ -263,6 +304,13 @@ fn merge_relations(
                     };
                 });
             }
+            if let Some(map_name) = ast::option_inner_type(&param.typ)
+                .and_then(|inner_type| updatable_fields.get(inner_type))
+            {
+                params_remap.extend(quote! {
+                    let #new_name = #new_name.map(|value| #map_name.get(&value).copied().unwrap_or(value));
+                });
+            }
         }
         let filter_tokens = if relation.key.is_some() {
             let map_name = relation.get_merge_map_name();
@@ -317,6 +365,56 @@ fn merge_relations(
     tokens
 }
 
+/// Generate the expression that maps the value of type `typ` referenced by
+/// `value` from the merged database to the corresponding value in
+/// `self.tables`.
+fn remap_expression(
+    value: TokenStream,
+    typ: &syn::Type,
+    schema: &ast::DatabaseSchema,
+) -> TokenStream {
+    match schema.get_type_kind(typ) {
+        ast::TypeKind::CustomId | ast::TypeKind::Enum | ast::TypeKind::RustType => {
+            quote! { *#value }
+        }
+        ast::TypeKind::IncrementalId(id) => {
+            let counter_name = id.get_field_name();
+            let constant_count = id.constants.len();
+            let typ = &id.typ;
+            let shift = quote! {
+                #value.shift(
+                    self.tables.counters.#counter_name-(#constant_count as #typ)
+                )
+            };
+            if constant_count > 0 {
+                quote! {
+                    if #value.index() >= #constant_count {
+                        #shift
+                    } else {
+                        *#value
+                    }
+                }
+            } else {
+                shift
+            }
+        }
+        ast::TypeKind::InternedId(table) => {
+            let map = &table.name;
+            quote! { #map[#value] }
+        }
+        ast::TypeKind::Optional => {
+            let inner_type = ast::option_inner_type(typ).unwrap();
+            match schema.get_type_kind(inner_type) {
+                ast::TypeKind::RustType => quote! { *#value },
+                _ => {
+                    let remap = remap_expression(quote! { value }, inner_type, schema);
+                    quote! { #value.as_ref().map(|value| #remap) }
+                }
+            }
+        }
+    }
+}
+
 fn merge_counters(schema: &ast::DatabaseSchema) -> TokenStream {
     let mut tokens = TokenStream::new();
     for id in &schema.incremental_ids {
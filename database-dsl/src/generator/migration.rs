@@ -0,0 +1,225 @@
+use super::storage::{file_schema_expression, read_facts_expression};
+use crate::ast;
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use std::collections::HashMap;
+
+pub(super) fn generate_migration_functions(schema: &ast::DatabaseSchema) -> TokenStream {
+    let schema_version = schema.schema_version();
+    let mut functions = TokenStream::new();
+    let mut migrate_relations = TokenStream::new();
+    for relation in &schema.relations {
+        let name = relation.name.to_string();
+        let write_fn_name = relation.get_write_function_name();
+        // Versions that store the relation in the same way share the function.
+        let mut function_names: HashMap<String, syn::Ident> = HashMap::new();
+        let mut arms: Vec<(Vec<u32>, syn::Ident)> = Vec::new();
+        for version in 0..schema_version {
+            let migrated = match schema.migrate_relation(relation, version) {
+                Ok(Some(migrated)) => migrated,
+                Ok(None) => continue,
+                Err(_) => unreachable!("checked by DatabaseSchema::check_migrations"),
+            };
+            let body = generate_migrate_relation_body(relation, &migrated, schema);
+            let function_name = function_names
+                .entry(body.to_string())
+                .or_insert_with(|| {
+                    let function_name = syn::Ident::new(
+                        &format!("migrate_{}_from_{}", relation.name, version),
+                        Span::call_site(),
+                    );
+                    functions.extend(quote! {
+                        /// ``path`` and ``target`` – the paths **without** the
+                        /// extension.
+                        fn #function_name(path: PathBuf, target: PathBuf) -> Result<()> {
+                            #body
+                        }
+                    });
+                    function_name
+                })
+                .clone();
+            match arms.iter_mut().find(|(_, name)| name == &function_name) {
+                Some((versions, _)) => versions.push(version),
+                None => arms.push((vec![version], function_name)),
+            }
+        }
+        let create_empty = quote! {
+            #write_fn_name(&[], target, StorageFormat::Raw)?;
+            migrated.push(#name);
+        };
+        let migrate = if arms.is_empty() {
+            quote! {
+                if is_base && !exists {
+                    #create_empty
+                }
+            }
+        } else {
+            let arms = arms.into_iter().map(|(versions, function_name)| {
+                quote! {
+                    if #(version == #versions)||* {
+                        #function_name(path, target)?;
+                        migrated.push(#name);
+                    }
+                }
+            });
+            quote! {
+                if exists {
+                    #(#arms)else*
+                } else if is_base {
+                    #create_empty
+                }
+            }
+        };
+        migrate_relations.extend(quote! {
+            {
+                let path = segment_root.join("relations").join(#name);
+                let target = target_root.join("relations").join(#name);
+                let exists = path.with_extension("rc").exists() || path.with_extension("rcc").exists();
+                #migrate
+            }
+        });
+    }
+    quote! {
+        /// The current schema version, which is the number of migrations
+        /// declared in the schema.
+        pub const SCHEMA_VERSION: u32 = #schema_version;
+
+        /// Migrate the relations stored in `segment_root` by the schema
+        /// `version` and write the migrated relations into the same paths
+        /// inside `target_root`. Relations that did not change are not
+        /// written. If `is_base` is true, the relations that are missing
+        /// because they were added to the schema are created empty. Returns
+        /// the names of the written relations.
+        pub(crate) fn migrate_relations(
+            segment_root: &Path,
+            target_root: &Path,
+            version: u32,
+            is_base: bool,
+        ) -> Result<Vec<&'static str>> {
+            std::fs::create_dir_all(target_root.join("relations"))?;
+            let mut migrated = Vec::new();
+            if version == SCHEMA_VERSION {
+                return Ok(migrated);
+            }
+            #migrate_relations
+            Ok(migrated)
+        }
+
+        #functions
+    }
+}
+
+/// Generate the body of the function that reads the facts of `relation`
+/// stored by an older schema and writes them in the current one by using the
+/// format of the old file.
+fn generate_migrate_relation_body(
+    relation: &ast::Relation,
+    migrated: &ast::MigratedRelation,
+    schema: &ast::DatabaseSchema,
+) -> TokenStream {
+    let write_fn_name = relation.get_write_function_name();
+    let old_file_schema = file_schema_expression(&migrated.old);
+    let read_old_facts =
+        read_facts_expression(&migrated.old, quote! { OLD_FILE_SCHEMA }, quote! { OldRow });
+    let old_types: Vec<_> = migrated
+        .old
+        .parameters
+        .iter()
+        .map(|parameter| &parameter.typ)
+        .collect();
+    let old_indices = (0..old_types.len()).map(syn::Index::from);
+    let types = relation.parameters.iter().map(|parameter| &parameter.typ);
+    let values = migrated.sources.iter().zip(&relation.parameters).map(|(source, parameter)| match source {
+        ast::ColumnSource::Old {
+            index,
+            variants: None,
+            none_value: None,
+        } => {
+            let index = syn::Index::from(*index);
+            quote! { fact.#index }
+        }
+        ast::ColumnSource::Old {
+            index,
+            variants: None,
+            none_value: Some(none_value),
+        } => {
+            let none_value =
+                none_value_expression(&migrated.old.parameters[*index].typ, none_value, schema);
+            let index = syn::Index::from(*index);
+            quote! {
+                if fact.#index == #none_value {
+                    None
+                } else {
+                    Some(fact.#index)
+                }
+            }
+        }
+        ast::ColumnSource::Old {
+            index,
+            variants: Some((enum_name, variants)),
+            ..
+        } => {
+            // The stored discriminants are the indices of the old variants.
+            let index = syn::Index::from(*index);
+            let discriminants = (0..variants.len()).map(|discriminant| discriminant as u8);
+            quote! {
+                match fact.#index as u8 {
+                    #(#discriminants => #enum_name::#variants,)*
+                    value => unreachable!("Invalid {} value: {}", stringify!(#enum_name), value),
+                }
+            }
+        }
+        ast::ColumnSource::Default(default, None) => quote! { #default },
+        ast::ColumnSource::Default(default, Some(none_value)) => {
+            let inner_type = ast::option_inner_type(&parameter.typ).unwrap();
+            let none_value = none_value_expression(inner_type, none_value, schema);
+            quote! {
+                {
+                    let value: #inner_type = #default;
+                    if value == #none_value {
+                        None
+                    } else {
+                        Some(value)
+                    }
+                }
+            }
+        }
+    });
+    quote! {
+        const OLD_FILE_SCHEMA: FileSchema<'static> = #old_file_schema;
+        let format = if path.with_extension("rcc").exists() {
+            StorageFormat::Columnar
+        } else {
+            StorageFormat::Raw
+        };
+        /// The row struct of the old relation, whose fields are accessed
+        /// like the fields of a tuple.
+        #[repr(C)]
+        #[derive(Clone, Copy)]
+        struct OldRow(#(#old_types,)*);
+        impl From<(#(#old_types,)*)> for OldRow {
+            fn from(fact: (#(#old_types,)*)) -> Self {
+                OldRow(#(fact.#old_indices,)*)
+            }
+        }
+        let old_facts: Result<Facts<OldRow>> = #read_old_facts;
+        let facts: Vec<(#(#types,)*)> = old_facts?
+            .iter()
+            .map(|fact| (#(#values,)*))
+            .collect();
+        #write_fn_name(&facts, target, format)
+    }
+}
+
+/// The value of type `typ` given by the `none_value` of `make_optional`,
+/// which is the underlying value for ids.
+fn none_value_expression(
+    typ: &syn::Type,
+    none_value: &syn::Expr,
+    schema: &ast::DatabaseSchema,
+) -> TokenStream {
+    match schema.get_type_kind(typ) {
+        ast::TypeKind::RustType => quote! { #none_value },
+        _ => quote! { <#typ>::from(#none_value) },
+    }
+}
@@ -4,11 +4,22 @@ use quote::quote;
 
 mod counters;
 mod debug;
+mod dump;
+mod integrity;
 mod interning_tables;
+mod introspection;
 mod loader;
 mod merge;
+mod migration;
+mod ownership;
+mod parquet;
 mod registration;
 mod relations;
+mod removal;
+mod resolution;
+mod resolver;
+mod rows;
+mod sqlite;
 mod storage;
 mod types;
 mod utils;
@@ -17,22 +28,40 @@ pub(crate) fn generate_tokens(schema: ast::DatabaseSchema) -> TokenStream {
     let types = types::generate_types(&schema);
     let tables = interning_tables::generate_interning_tables(&schema);
     let relations = relations::generate_relations(&schema);
+    let rows = rows::generate_rows(&schema);
     let (counters, counter_functions) = counters::generate_counters(&schema);
     let registration_functions = registration::generate_registration_functions(&schema);
     let load_save_functions = storage::generate_load_save_functions(&schema);
     let (loader_functions, loader_cache_fields) = loader::generate_loader_functions(&schema);
     let merge_functions = merge::generate_merge_functions(&schema);
     let debug_functions = debug::generate_status_functions(&schema);
+    let sqlite_export = sqlite::generate_sqlite_export(&schema);
+    let parquet_export = parquet::generate_parquet_export(&schema);
+    let dump_functions = dump::generate_dump_functions(&schema);
+    let integrity_checks = integrity::generate_integrity_checks(&schema);
+    let removal_functions = removal::generate_removal_functions(&schema);
+    let ownership_functions = ownership::generate_ownership_functions(&schema);
+    let migration_functions = migration::generate_migration_functions(&schema);
+    let introspection = introspection::generate_introspection(&schema);
+    let resolve_impls = resolver::generate_resolve_impls(&schema);
     quote! {
         pub mod types {
             use serde_derive::{Deserialize, Serialize};
             #types
         }
+        /// Structs with named fields that store the facts of relations.
+        pub mod rows {
+            use serde_derive::{Deserialize, Serialize};
+            #rows
+        }
         pub mod tables {
             use std::path::{Path, PathBuf};
             use std::collections::HashMap;
             use anyhow::Result;
             use serde_derive::{Deserialize, Serialize};
+            use crate::data_structures::Facts;
+            use crate::storage::FileSchema;
+            pub use crate::storage::StorageFormat;
             use super::types::*;
             #tables
             #relations
@@ -64,9 +93,22 @@ pub(crate) fn generate_tokens(schema: ast::DatabaseSchema) -> TokenStream {
 
             #load_save_functions
 
+            /// Loads the relations and interning tables of a database on
+            /// demand and caches them. The loader is `Sync`, so parallel
+            /// queries can share the loaded relations.
             #[derive(Default)]
             pub struct Loader {
+                /// The database, or the directory that stores the derived
+                /// relations if the loader reads the crates in `crates`.
                 pub(crate) database_root: PathBuf,
+                /// The crates saved by the extractor that are queried
+                /// instead of the core relations of a database.
+                pub(crate) crates: Option<crate::crate_set::CrateSet<Tables>>,
+                /// The maximum size of the cached relations in bytes.
+                memory_budget: Option<usize>,
+                /// The logical time used for finding the least recently used
+                /// relations.
+                clock: std::sync::atomic::AtomicU64,
                 #loader_cache_fields
             }
 
@@ -74,8 +116,36 @@ pub(crate) fn generate_tokens(schema: ast::DatabaseSchema) -> TokenStream {
                 pub fn new(database_root: PathBuf) -> Self {
                     Self { database_root, ..Loader::default() }
                 }
+                /// Keep the cached relations within `budget` bytes by
+                /// evicting the least recently used relations that are not
+                /// borrowed after every load. Borrowed relations are never
+                /// evicted, so the budget may be exceeded temporarily.
+                /// Memory-mapped facts do not count toward the budget. The
+                /// loaded interning tables and indexes count toward it, but
+                /// are never evicted.
+                pub fn with_memory_budget(self, budget: usize) -> Self {
+                    Self { memory_budget: Some(budget), ..self }
+                }
                 #loader_functions
             }
+
+            #sqlite_export
+
+            #parquet_export
+
+            #dump_functions
+
+            #integrity_checks
+
+            #removal_functions
+
+            #ownership_functions
+
+            #migration_functions
+
+            #introspection
+
+            #resolve_impls
         }
     }
 }
@@ -0,0 +1,201 @@
+use super::utils::referenced_interning_tables;
+use crate::ast;
+use proc_macro2::TokenStream;
+use quote::quote;
+
+pub(super) fn generate_ownership_functions(schema: &ast::DatabaseSchema) -> TokenStream {
+    let mut fields = TokenStream::new();
+    let mut new_fields = TokenStream::new();
+    for id in &schema.incremental_ids {
+        let field_name = id.get_field_name();
+        fields.extend(quote! {
+            pub(crate) #field_name: Vec<Option<O>>,
+        });
+        new_fields.extend(quote! {
+            #field_name: vec![None; counters.#field_name as usize],
+        });
+    }
+    for table in referenced_interning_tables(schema) {
+        let name = &table.name;
+        let key_type = &table.key.name;
+        fields.extend(quote! {
+            pub(crate) #name: HashMap<#key_type, O>,
+        });
+        new_fields.extend(quote! {
+            #name: HashMap::new(),
+        });
+    }
+    let mut propagate = TokenStream::new();
+    let mut count = TokenStream::new();
+    let mut remove = TokenStream::new();
+    for relation in &schema.relations {
+        let name = &relation.name;
+        let name_str = name.to_string();
+        let owner = generate_fact_owner(relation, schema);
+        let has_incremental_ids = relation.parameters.iter().any(|parameter| {
+            matches!(
+                schema.get_column_type_kind(&parameter.typ).0,
+                ast::TypeKind::IncrementalId(_)
+            )
+        });
+        if !has_incremental_ids {
+            remove.extend(quote! {
+                {
+                    let facts = &mut self.relations.#name.facts;
+                    let len = facts.len();
+                    facts.retain(|fact| #owner != Some(owner));
+                    removed_count += len - facts.len();
+                }
+            });
+        }
+        let mut assignments = TokenStream::new();
+        for (i, parameter) in relation.parameters.iter().enumerate() {
+            if let (ast::TypeKind::IncrementalId(id), is_optional) =
+                schema.get_column_type_kind(&parameter.typ)
+            {
+                let field_name = id.get_field_name();
+                let index = syn::Index::from(i);
+                // Constants such as `NO_BLOCK` are shared by all owners.
+                let constant_count = id.constants.len();
+                let is_unowned = if constant_count > 0 {
+                    quote! { id >= #constant_count && owners.#field_name[id].is_none() }
+                } else {
+                    quote! { owners.#field_name[id].is_none() }
+                };
+                let assignment = quote! {
+                    if #is_unowned {
+                        owners.#field_name[id] = Some(owner);
+                        changed = true;
+                    }
+                };
+                if is_optional {
+                    assignments.extend(quote! {
+                        if let Some(id) = fact.#index {
+                            let id = id.index();
+                            #assignment
+                        }
+                    });
+                } else {
+                    assignments.extend(quote! {
+                        let id = fact.#index.index();
+                        #assignment
+                    });
+                }
+            }
+        }
+        if !assignments.is_empty() {
+            propagate.extend(quote! {
+                for fact in self.relations.#name.iter() {
+                    if let Some(owner) = #owner {
+                        #assignments
+                    }
+                }
+            });
+        }
+        count.extend(quote! {
+            {
+                let mut counts = HashMap::new();
+                for fact in self.relations.#name.iter() {
+                    *counts.entry(#owner).or_insert(0) += 1;
+                }
+                result.push((#name_str, counts));
+            }
+        });
+    }
+    quote! {
+        /// The owners of the ids mentioned by facts, for example, the builds
+        /// that produced them.
+        pub(crate) struct IdOwners<O> {
+            #fields
+        }
+
+        impl<O: Copy> IdOwners<O> {
+            /// Create the owners of a database with the given counters. No id
+            /// has an owner yet.
+            pub(crate) fn new(counters: &Counters) -> Self {
+                Self {
+                    #new_fields
+                }
+            }
+        }
+
+        impl Tables {
+            /// Assign owners to the incremental ids that do not have one. The
+            /// owner of a fact is the owner of its first owned incremental id
+            /// or, if there is none, of its first owned interned id. The
+            /// incremental ids mentioned by the fact get the owner of the fact;
+            /// this is repeated until no new owners are assigned.
+            pub(crate) fn propagate_owners<O: Copy>(&self, owners: &mut IdOwners<O>) {
+                loop {
+                    let mut changed = false;
+                    #propagate
+                    if !changed {
+                        break;
+                    }
+                }
+            }
+            /// Count the facts of each core relation by their owners. Facts
+            /// without an owner are counted under `None`.
+            pub(crate) fn count_facts_by_owner<O: Copy + Eq + std::hash::Hash>(
+                &self,
+                owners: &IdOwners<O>,
+            ) -> Vec<(&'static str, HashMap<Option<O>, usize>)> {
+                let mut result = Vec::new();
+                #count
+                result
+            }
+            /// Remove the facts of the relations without incremental ids
+            /// whose owner, the owner of their first owned interned id, is
+            /// `owner`. Returns the number of removed facts.
+            pub(crate) fn remove_interned_facts_owned_by<O: Copy + Eq>(
+                &mut self,
+                owners: &IdOwners<O>,
+                owner: O,
+            ) -> usize {
+                let mut removed_count = 0;
+                #remove
+                removed_count
+            }
+        }
+    }
+}
+
+/// Generate the expression that evaluates to the owner of `fact`.
+fn generate_fact_owner(relation: &ast::Relation, schema: &ast::DatabaseSchema) -> TokenStream {
+    let mut incremental = Vec::new();
+    let mut interned = Vec::new();
+    for (i, parameter) in relation.parameters.iter().enumerate() {
+        let index = syn::Index::from(i);
+        let (kind, is_optional) = schema.get_column_type_kind(&parameter.typ);
+        match kind {
+            ast::TypeKind::IncrementalId(id) => {
+                let field_name = id.get_field_name();
+                incremental.push(if is_optional {
+                    quote! {
+                        .or_else(|| fact.#index.and_then(|id| owners.#field_name.get(id.index()).copied().flatten()))
+                    }
+                } else {
+                    quote! {
+                        .or_else(|| owners.#field_name.get(fact.#index.index()).copied().flatten())
+                    }
+                });
+            }
+            ast::TypeKind::InternedId(table) => {
+                let table_name = &table.name;
+                interned.push(if is_optional {
+                    quote! {
+                        .or_else(|| fact.#index.and_then(|id| owners.#table_name.get(&id).copied()))
+                    }
+                } else {
+                    quote! {
+                        .or_else(|| owners.#table_name.get(&fact.#index).copied())
+                    }
+                });
+            }
+            _ => {}
+        }
+    }
+    quote! {
+        None #(#incremental)* #(#interned)*
+    }
+}
@@ -0,0 +1,175 @@
+use super::resolution::{load_resolution_tables, resolved_columns, ResolvedColumn, ResolvedValue};
+use crate::ast;
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use std::collections::BTreeSet;
+
+pub(super) fn generate_parquet_export(schema: &ast::DatabaseSchema) -> TokenStream {
+    let arrow_values = generate_arrow_values(schema);
+    let mut match_arms = TokenStream::new();
+    let mut export_functions = TokenStream::new();
+    for relation in schema.relations.iter().chain(&schema.derived_relations) {
+        let name = relation.name.to_string();
+        let fn_name = syn::Ident::new(&format!("export_parquet_{}", name), Span::call_site());
+        match_arms.extend(quote! {
+            #name => self.#fn_name(path, resolve),
+        });
+        export_functions.extend(generate_relation_export(relation, &fn_name, schema));
+    }
+    quote! {
+        #arrow_values
+
+        #[cfg(feature = "parquet")]
+        impl Loader {
+            /// Export the core or derived relation `relation` into the Parquet
+            /// file `path`. If `resolve` is true, additional columns are added
+            /// that resolve interned strings and the elements of interned
+            /// tuples such as `DefPath` and `Build`.
+            pub fn export_parquet(&self, relation: &str, path: &Path, resolve: bool) -> Result<()> {
+                match relation {
+                    #match_arms
+                    _ => Err(anyhow::anyhow!("Unknown relation: {}", relation)),
+                }
+            }
+            #export_functions
+        }
+    }
+}
+
+/// Generate `ArrowValue` implementations for ids and enums.
+fn generate_arrow_values(schema: &ast::DatabaseSchema) -> TokenStream {
+    let mut tokens = TokenStream::new();
+    let id_types = schema
+        .custom_ids
+        .iter()
+        .map(|id| (&id.name, &id.typ))
+        .chain(schema.incremental_ids.iter().map(|id| (&id.name, &id.typ)))
+        .chain(
+            schema
+                .interning_tables
+                .iter()
+                .map(|table| (&table.key.name, &table.key.typ)),
+        );
+    for (name, typ) in id_types {
+        tokens.extend(quote! {
+            #[cfg(feature = "parquet")]
+            impl crate::parquet::ArrowValue for #name {
+                type Builder = <#typ as crate::parquet::ArrowValue>::Builder;
+                fn data_type() -> arrow_schema::DataType {
+                    <#typ as crate::parquet::ArrowValue>::data_type()
+                }
+                fn append_to(&self, builder: &mut Self::Builder) {
+                    self.0.append_to(builder)
+                }
+            }
+        });
+    }
+    for ast::Enum { item, .. } in &schema.enums {
+        let name = &item.ident;
+        tokens.extend(quote! {
+            #[cfg(feature = "parquet")]
+            impl crate::parquet::ArrowValue for #name {
+                type Builder = arrow_array::builder::StringBuilder;
+                fn data_type() -> arrow_schema::DataType {
+                    arrow_schema::DataType::Utf8
+                }
+                fn append_to(&self, builder: &mut Self::Builder) {
+                    builder.append_value(self.to_string())
+                }
+            }
+        });
+    }
+    tokens
+}
+
+fn generate_relation_export(
+    relation: &ast::Relation,
+    fn_name: &syn::Ident,
+    schema: &ast::DatabaseSchema,
+) -> TokenStream {
+    let read_fn_name = relation.get_loader_read_function_name();
+    let mut fields = TokenStream::new();
+    let mut builders = TokenStream::new();
+    let mut appends = TokenStream::new();
+    let mut finished = TokenStream::new();
+    let mut resolved_fields = TokenStream::new();
+    let mut resolved_builders = TokenStream::new();
+    let mut tables = BTreeSet::new();
+    for (i, ast::RelationParameter { name, typ, .. }) in relation.parameters.iter().enumerate() {
+        let column = name.to_string();
+        let field_name = name;
+        let builder = syn::Ident::new(&format!("column_{}", i), Span::call_site());
+        fields.extend(quote! { crate::parquet::field::<#typ>(#column), });
+        builders.extend(quote! {
+            let mut #builder = <#typ as ArrowValue>::Builder::default();
+        });
+        appends.extend(quote! { fact.#field_name.append_to(&mut #builder); });
+        finished.extend(quote! { crate::parquet::finish_column(#builder), });
+        for resolved in resolved_columns(&column, typ, schema, &mut tables) {
+            let ResolvedColumn { name, value } = resolved;
+            let (field, builder_type, append) = match value {
+                ResolvedValue::Str(value) => (
+                    quote! { crate::parquet::string_field(#name) },
+                    quote! { arrow_array::builder::StringBuilder },
+                    quote! { builder.append_value(&#value); },
+                ),
+                ResolvedValue::Hex(value) => (
+                    quote! { crate::parquet::string_field(#name) },
+                    quote! { arrow_array::builder::StringBuilder },
+                    quote! { builder.append_value(format!("{:x}", #value)); },
+                ),
+                ResolvedValue::Native(typ, value) => (
+                    quote! { crate::parquet::field::<#typ>(#name) },
+                    quote! { <#typ as ArrowValue>::Builder },
+                    quote! { #value.append_to(&mut builder); },
+                ),
+            };
+            resolved_fields.extend(quote! { #field, });
+            resolved_builders.extend(quote! {
+                let mut builder = #builder_type::default();
+                for fact in chunk {
+                    let value = fact.#field_name;
+                    #append
+                }
+                columns.push(crate::parquet::finish_column(builder));
+            });
+        }
+    }
+    let (load_tables, unwrap_tables) = load_resolution_tables(&tables);
+    let resolve_columns = if tables.is_empty() {
+        TokenStream::new()
+    } else {
+        quote! {
+            if resolve {
+                #unwrap_tables
+                #resolved_builders
+            }
+        }
+    };
+    quote! {
+        fn #fn_name(&self, path: &Path, resolve: bool) -> Result<()> {
+            use crate::parquet::ArrowValue;
+            let facts = self.#read_fn_name()?;
+            let mut fields = vec![#fields];
+            if resolve {
+                fields.extend(vec![#resolved_fields]);
+            }
+            #load_tables
+            let mut writer = crate::parquet::ParquetWriter::create(path, fields)?;
+            let batches = facts
+                .slices()
+                .flat_map(|slice| slice.chunks(crate::parquet::BATCH_SIZE));
+            for chunk in batches {
+                #builders
+                for fact in chunk {
+                    #appends
+                }
+                #[allow(unused_mut)]
+                let mut columns = vec![#finished];
+                #resolve_columns
+                writer.write(columns)?;
+            }
+            writer.close()
+        }
+    }
+}
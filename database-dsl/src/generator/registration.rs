@@ -88,6 +88,7 @@ fn generate_relation_registration(
         name,
         typ,
         is_autogenerated,
+        ..
     } in &relation.parameters
     {
         if *is_autogenerated {
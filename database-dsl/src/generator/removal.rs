@@ -0,0 +1,385 @@
+use super::utils::referenced_interning_tables;
+use crate::ast;
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+
+pub(super) fn generate_removal_functions(schema: &ast::DatabaseSchema) -> TokenStream {
+    let mut fields = TokenStream::new();
+    for id in &schema.incremental_ids {
+        let field_name = id.get_field_name();
+        let typ = &id.name;
+        fields.extend(quote! {
+            pub(crate) #field_name: std::collections::HashSet<#typ>,
+        });
+    }
+    // Only the tables whose ids are stored in relations can remove facts.
+    for table in referenced_interning_tables(schema) {
+        let name = &table.name;
+        let key_type = &table.key.name;
+        fields.extend(quote! {
+            pub(crate) #name: std::collections::HashSet<#key_type>,
+        });
+    }
+    let remove_facts = generate_remove_facts(schema);
+    let collect_garbage = generate_collect_interning_garbage(schema);
+    let compact_ids = generate_compact_incremental_ids(schema);
+    let derived_relation_paths = schema
+        .derived_relations
+        .iter()
+        .map(|relation| format!("relations/{}", relation.name));
+    quote! {
+        /// The paths, without the extension, of the files that store derived
+        /// relations.
+        pub(crate) const DERIVED_RELATION_PATHS: &[&str] = &[#(#derived_relation_paths),*];
+
+        /// The ids whose facts are being removed from the database.
+        #[derive(Default)]
+        pub(crate) struct RemovedIds {
+            #fields
+        }
+
+        impl Tables {
+            /// Remove the facts of core relations that mention any of the
+            /// `removed` ids. Incremental ids are never shared between builds,
+            /// so all incremental ids mentioned by a removed fact are removed
+            /// too; this is repeated until no new ids are found. Returns the
+            /// number of removed facts.
+            pub(crate) fn remove_facts(&mut self, removed: &mut RemovedIds) -> usize {
+                let mut removed_count = 0;
+                loop {
+                    let mut changed = false;
+                    #remove_facts
+                    if !changed {
+                        break;
+                    }
+                }
+                removed_count
+            }
+            #collect_garbage
+            #compact_ids
+        }
+    }
+}
+
+fn generate_remove_facts(schema: &ast::DatabaseSchema) -> TokenStream {
+    let mut tokens = TokenStream::new();
+    for relation in &schema.relations {
+        let name = &relation.name;
+        let mut conditions = Vec::new();
+        let mut inserts = TokenStream::new();
+        for (i, parameter) in relation.parameters.iter().enumerate() {
+            let index = syn::Index::from(i);
+            let (kind, is_optional) = schema.get_column_type_kind(&parameter.typ);
+            // Optional ids are checked only if they are present.
+            let value = if is_optional {
+                quote! { id }
+            } else {
+                quote! { fact.#index }
+            };
+            let (condition, insert) = match kind {
+                ast::TypeKind::IncrementalId(id) => {
+                    let field_name = id.get_field_name();
+                    let constant_count = id.constants.len();
+                    let insert = quote! {
+                        changed |= removed.#field_name.insert(#value);
+                    };
+                    let insert = if constant_count > 0 {
+                        // Constants such as `NO_BLOCK` are shared by all builds.
+                        quote! {
+                            if #value.index() >= #constant_count {
+                                #insert
+                            }
+                        }
+                    } else {
+                        insert
+                    };
+                    (quote! { removed.#field_name.contains(&#value) }, insert)
+                }
+                ast::TypeKind::InternedId(table) => {
+                    let table_name = &table.name;
+                    (
+                        quote! { removed.#table_name.contains(&#value) },
+                        TokenStream::new(),
+                    )
+                }
+                _ => continue,
+            };
+            if is_optional {
+                conditions.push(quote! { fact.#index.is_some_and(|id| #condition) });
+                if !insert.is_empty() {
+                    inserts.extend(quote! {
+                        if let Some(id) = fact.#index {
+                            #insert
+                        }
+                    });
+                }
+            } else {
+                conditions.push(condition);
+                inserts.extend(insert);
+            }
+        }
+        if conditions.is_empty() {
+            continue;
+        }
+        tokens.extend(quote! {
+            self.relations.#name.facts.retain(|fact| {
+                if #(#conditions)||* {
+                    #inserts
+                    removed_count += 1;
+                    false
+                } else {
+                    true
+                }
+            });
+        });
+    }
+    tokens
+}
+
+fn used_variable(table: &ast::InterningTable) -> syn::Ident {
+    syn::Ident::new(&format!("used_{}", table.name), Span::call_site())
+}
+
+fn remap_variable(table: &ast::InterningTable) -> syn::Ident {
+    syn::Ident::new(&format!("remap_{}", table.name), Span::call_site())
+}
+
+/// The elements of the value of an interning table together with the
+/// expressions that access them in `value`.
+fn value_elements(table: &ast::InterningTable) -> Vec<(TokenStream, &syn::Type)> {
+    match &table.value {
+        syn::Type::Tuple(syn::TypeTuple { elems, .. }) => elems
+            .iter()
+            .enumerate()
+            .map(|(i, elem)| {
+                let index = syn::Index::from(i);
+                (quote! { value.#index }, elem)
+            })
+            .collect(),
+        value => vec![(quote! { value }, value)],
+    }
+}
+
+fn generate_collect_interning_garbage(schema: &ast::DatabaseSchema) -> TokenStream {
+    // Tuple tables refer only to non-tuple tables, and non-tuple tables refer
+    // only to the tables declared before them. Therefore, the values are
+    // marked in the reverse of this order and remapped in this order.
+    let (tuple_tables, other_tables): (Vec<_>, Vec<_>) = schema
+        .interning_tables
+        .iter()
+        .partition(|table| matches!(table.value, syn::Type::Tuple(_)));
+    let remap_order: Vec<_> = other_tables.iter().chain(&tuple_tables).collect();
+
+    let mut init_used = TokenStream::new();
+    for table in &schema.interning_tables {
+        let name = &table.name;
+        let used = used_variable(table);
+        init_used.extend(quote! {
+            let mut #used = vec![false; self.interning_tables.#name.len()];
+        });
+    }
+
+    let mut mark_relations = TokenStream::new();
+    let mut remap_relations = TokenStream::new();
+    for relation in schema.relations.iter() {
+        let name = &relation.name;
+        let mut marks = TokenStream::new();
+        let mut remaps = TokenStream::new();
+        for (i, parameter) in relation.parameters.iter().enumerate() {
+            if let (ast::TypeKind::InternedId(table), is_optional) =
+                schema.get_column_type_kind(&parameter.typ)
+            {
+                let index = syn::Index::from(i);
+                let used = used_variable(table);
+                let remap = remap_variable(table);
+                if is_optional {
+                    marks.extend(quote! {
+                        if let Some(id) = fact.#index {
+                            #used[id.index()] = true;
+                        }
+                    });
+                    remaps.extend(quote! {
+                        fact.#index = fact.#index.map(|id| #remap[id.index()].unwrap());
+                    });
+                } else {
+                    marks.extend(quote! { #used[fact.#index.index()] = true; });
+                    remaps.extend(quote! { fact.#index = #remap[fact.#index.index()].unwrap(); });
+                }
+            }
+        }
+        if marks.is_empty() {
+            continue;
+        }
+        mark_relations.extend(quote! {
+            for fact in self.relations.#name.iter() {
+                #marks
+            }
+        });
+        remap_relations.extend(quote! {
+            for fact in self.relations.#name.facts.iter_mut() {
+                #remaps
+            }
+        });
+    }
+
+    let mut mark_tables = TokenStream::new();
+    for table in remap_order.iter().rev() {
+        let name = &table.name;
+        let used = used_variable(table);
+        let mut marks = TokenStream::new();
+        for (element, typ) in value_elements(table) {
+            if let ast::TypeKind::InternedId(element_table) = schema.get_type_kind(typ) {
+                let element_used = used_variable(element_table);
+                marks.extend(quote! { #element_used[#element.index()] = true; });
+            }
+        }
+        if marks.is_empty() {
+            continue;
+        }
+        mark_tables.extend(quote! {
+            for (index, value) in self.interning_tables.#name.iter_values().enumerate() {
+                if #used[index] {
+                    #marks
+                }
+            }
+        });
+    }
+
+    let mut sweep_tables = TokenStream::new();
+    for table in &remap_order {
+        let name = &table.name;
+        let key_type = &table.key.name;
+        let used = used_variable(table);
+        let remap = remap_variable(table);
+        let mut remaps = TokenStream::new();
+        for (element, typ) in value_elements(table) {
+            if let ast::TypeKind::InternedId(element_table) = schema.get_type_kind(typ) {
+                let element_remap = remap_variable(element_table);
+                remaps.extend(quote! {
+                    #element = #element_remap[#element.index()].unwrap();
+                });
+            }
+        }
+        let value_pattern = if remaps.is_empty() {
+            quote! { value }
+        } else {
+            quote! { mut value }
+        };
+        sweep_tables.extend(quote! {
+            let mut #remap: Vec<Option<#key_type>> = Vec::with_capacity(#used.len());
+            let mut values = Vec::new();
+            for (key, #value_pattern) in std::mem::take(&mut self.interning_tables.#name).into_iter() {
+                let index: usize = key.into();
+                if #used[index] {
+                    #remaps
+                    #remap.push(Some(values.len().into()));
+                    values.push(value);
+                } else {
+                    #remap.push(None);
+                    removed_count += 1;
+                }
+            }
+            self.interning_tables.#name = values.into();
+        });
+    }
+
+    quote! {
+        /// Remove the interned values that are not mentioned by any core
+        /// relation or by other interned values and renumber the remaining
+        /// ones. Returns the number of removed values.
+        pub(crate) fn collect_interning_garbage(&mut self) -> usize {
+            #init_used
+            #mark_relations
+            #mark_tables
+            let mut removed_count = 0;
+            #sweep_tables
+            #remap_relations
+            removed_count
+        }
+    }
+}
+
+fn generate_compact_incremental_ids(schema: &ast::DatabaseSchema) -> TokenStream {
+    let mut tokens = TokenStream::new();
+    for id in &schema.incremental_ids {
+        let field_name = id.get_field_name();
+        let name = &id.name;
+        let typ = &id.typ;
+        let constant_count = id.constants.len();
+        let mut marks = TokenStream::new();
+        let mut remaps = TokenStream::new();
+        for relation in &schema.relations {
+            let relation_name = &relation.name;
+            let mut relation_marks = TokenStream::new();
+            let mut relation_remaps = TokenStream::new();
+            for (i, parameter) in relation.parameters.iter().enumerate() {
+                let index = syn::Index::from(i);
+                let (kind, is_optional) = schema.get_column_type_kind(&parameter.typ);
+                if !matches!(
+                    kind,
+                    ast::TypeKind::IncrementalId(parameter_id) if parameter_id.name == id.name
+                ) {
+                    continue;
+                }
+                if is_optional {
+                    relation_marks.extend(quote! {
+                        if let Some(id) = fact.#index {
+                            used[id.index()] = true;
+                        }
+                    });
+                    relation_remaps.extend(quote! {
+                        fact.#index = fact.#index.map(|id| remap[id.index()].unwrap());
+                    });
+                } else {
+                    relation_marks.extend(quote! {
+                        used[fact.#index.index()] = true;
+                    });
+                    relation_remaps.extend(quote! {
+                        fact.#index = remap[fact.#index.index()].unwrap();
+                    });
+                }
+            }
+            if relation_marks.is_empty() {
+                continue;
+            }
+            marks.extend(quote! {
+                for fact in self.relations.#relation_name.iter() {
+                    #relation_marks
+                }
+            });
+            remaps.extend(quote! {
+                for fact in self.relations.#relation_name.facts.iter_mut() {
+                    #relation_remaps
+                }
+            });
+        }
+        tokens.extend(quote! {
+            {
+                let mut used = vec![false; self.counters.#field_name as usize];
+                #marks
+                // Constants such as `NO_BLOCK` keep their values.
+                let mut remap: Vec<Option<#name>> =
+                    (0..#constant_count).map(|index| Some(index.into())).collect();
+                let mut next = #constant_count;
+                for used in used.into_iter().skip(#constant_count) {
+                    if used {
+                        remap.push(Some(next.into()));
+                        next += 1;
+                    } else {
+                        remap.push(None);
+                    }
+                }
+                #remaps
+                self.counters.#field_name = next as #typ;
+            }
+        });
+    }
+    quote! {
+        /// Renumber the incremental ids mentioned by the core relations so
+        /// that they are dense and reset the counters to the next free ids.
+        /// The order of the ids is preserved.
+        pub(crate) fn compact_incremental_ids(&mut self) {
+            #tokens
+        }
+    }
+}
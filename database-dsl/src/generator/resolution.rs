@@ -0,0 +1,141 @@
+//! Helpers for generating code that resolves interned values into human
+//! readable columns in the same way as `corpus_database::Resolver` does.
+
+use super::utils::{string_resolution_chain, tuple_column_name};
+use crate::ast;
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use std::collections::BTreeSet;
+
+/// A resolved value. The expressions refer to the interned value as `value`
+/// and to the interning tables by using the variables returned by
+/// `table_variable`.
+pub(super) enum ResolvedValue {
+    /// An expression that evaluates to a `String` place.
+    Str(TokenStream),
+    /// An expression that evaluates to a value that should be printed in
+    /// hexadecimal.
+    Hex(TokenStream),
+    /// An expression that evaluates to a value of the given type.
+    Native(syn::Type, TokenStream),
+}
+
+/// A column added when resolving interned values.
+pub(super) struct ResolvedColumn {
+    pub name: String,
+    pub value: ResolvedValue,
+}
+
+/// The variable that holds the loaded interning table `table`.
+pub(super) fn table_variable(table: &str) -> syn::Ident {
+    syn::Ident::new(&format!("table_{}", table), Span::call_site())
+}
+
+/// The expression that follows `chain` starting from `value`.
+fn chain_expression(value: TokenStream, chain: &[String]) -> TokenStream {
+    chain.iter().fold(value, |value, table| {
+        let table = table_variable(table);
+        quote! { #table[#value] }
+    })
+}
+
+/// Returns true if the custom id implements `LowerHex`, which is how
+/// hashes are printed when resolved.
+pub(super) fn implements_lower_hex(typ: &syn::Type, schema: &ast::DatabaseSchema) -> bool {
+    schema.custom_ids.iter().any(|id| {
+        let name = &id.name;
+        quote!(#name).to_string() == quote!(#typ).to_string()
+            && id.items.iter().any(|item| match item {
+                syn::Item::Impl(syn::ItemImpl {
+                    trait_: Some((_, path, _)),
+                    ..
+                }) => path
+                    .segments
+                    .last()
+                    .map_or(false, |segment| segment.ident == "LowerHex"),
+                _ => false,
+            })
+    })
+}
+
+/// The tuple stored in the interning table with keys of type `typ`, if any.
+fn interned_tuple<'a>(
+    typ: &syn::Type,
+    schema: &'a ast::DatabaseSchema,
+) -> Option<(&'a ast::InterningTable, Vec<&'a syn::Type>)> {
+    if let syn::Type::Path(syn::TypePath { qself: None, path }) = typ {
+        if path.get_ident().is_some() {
+            if let ast::TypeKind::InternedId(table) = schema.get_type_kind(typ) {
+                if let syn::Type::Tuple(syn::TypeTuple { elems, .. }) = &table.value {
+                    return Some((table, elems.iter().collect()));
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Compute the columns that resolve the column `column` of type `typ` and
+/// record the interning tables they need into `tables`. Interned strings are
+/// resolved into a single `{column}_text` column, while interned tuples such
+/// as `DefPath` and `Build` are resolved into one column per element.
+pub(super) fn resolved_columns(
+    column: &str,
+    typ: &syn::Type,
+    schema: &ast::DatabaseSchema,
+    tables: &mut BTreeSet<String>,
+) -> Vec<ResolvedColumn> {
+    if let Some(chain) = string_resolution_chain(typ, schema) {
+        let value = chain_expression(quote! { value }, &chain);
+        tables.extend(chain);
+        return vec![ResolvedColumn {
+            name: format!("{}_text", column),
+            value: ResolvedValue::Str(value),
+        }];
+    }
+    let (table, elems) = match interned_tuple(typ, schema) {
+        Some(tuple) => tuple,
+        None => return Vec::new(),
+    };
+    tables.insert(table.name.to_string());
+    let tuple_table = table_variable(&table.name.to_string());
+    let mut columns = Vec::new();
+    for (i, elem) in elems.iter().enumerate() {
+        let index = syn::Index::from(i);
+        let element = quote! { #tuple_table[value].#index };
+        let name = format!(
+            "{}_{}",
+            column,
+            tuple_column_name(elems.iter().copied(), i, elem)
+        );
+        let value = if let Some(chain) = string_resolution_chain(elem, schema) {
+            let value = chain_expression(element, &chain);
+            tables.extend(chain);
+            ResolvedValue::Str(value)
+        } else if implements_lower_hex(elem, schema) {
+            ResolvedValue::Hex(element)
+        } else {
+            ResolvedValue::Native((*elem).clone(), element)
+        };
+        columns.push(ResolvedColumn { name, value });
+    }
+    columns
+}
+
+/// Generate the code that loads the interning tables needed for resolution
+/// only if `resolve` is true, and the code that unwraps them.
+pub(super) fn load_resolution_tables(tables: &BTreeSet<String>) -> (TokenStream, TokenStream) {
+    let mut load_tables = TokenStream::new();
+    let mut unwrap_tables = TokenStream::new();
+    for table in tables {
+        let variable = table_variable(table);
+        let load_fn_name = syn::Ident::new(&format!("load_{}", table), Span::call_site());
+        load_tables.extend(quote! {
+            let #variable = if resolve { Some(self.#load_fn_name()) } else { None };
+        });
+        unwrap_tables.extend(quote! {
+            let #variable = #variable.as_ref().unwrap();
+        });
+    }
+    (load_tables, unwrap_tables)
+}
@@ -0,0 +1,89 @@
+use super::resolution::implements_lower_hex;
+use crate::ast;
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+
+/// Generate the implementations of `Resolve` for all id and enum types.
+pub(super) fn generate_resolve_impls(schema: &ast::DatabaseSchema) -> TokenStream {
+    let mut tokens = TokenStream::new();
+    for table in &schema.interning_tables {
+        let name = &table.key.name;
+        let value = &table.value;
+        let load_fn_name = syn::Ident::new(&format!("load_{}", table.name), Span::call_site());
+        let is_string = matches!(
+            value,
+            syn::Type::Path(syn::TypePath { qself: None, path }) if path.is_ident("String")
+        );
+        let (output, resolve) = if is_string {
+            (
+                quote! { &'a str },
+                quote! { resolver.loader().#load_fn_name()[*self].as_str() },
+            )
+        } else {
+            (
+                quote! { <#value as crate::Resolve<'a, Loader>>::Output },
+                quote! {
+                    crate::Resolve::resolve(&resolver.loader().#load_fn_name()[*self], resolver)
+                },
+            )
+        };
+        tokens.extend(resolve_impl(name, output, resolve, true));
+    }
+    for ast::CustomId { name, .. } in &schema.custom_ids {
+        let typ = syn::parse_quote! { #name };
+        let (output, resolve) = if implements_lower_hex(&typ, schema) {
+            (quote! { String }, quote! { format!("{:x}", self) })
+        } else {
+            (quote! { #name }, quote! { *self })
+        };
+        tokens.extend(resolve_impl(name, output, resolve, false));
+    }
+    for ast::IncrementalId { name, .. } in &schema.incremental_ids {
+        tokens.extend(resolve_impl(
+            name,
+            quote! { #name },
+            quote! { *self },
+            false,
+        ));
+    }
+    for ast::Enum { item, .. } in &schema.enums {
+        let name = &item.ident;
+        tokens.extend(resolve_impl(
+            name,
+            quote! { String },
+            quote! { self.to_string() },
+            false,
+        ));
+    }
+    tokens
+}
+
+/// The implementation of `Resolve` for `name`. The expression `resolve` can
+/// refer to the resolver as `resolver` if `uses_resolver` is true; only such
+/// implementations are specific to the `Loader` of the schema.
+fn resolve_impl(
+    name: &syn::Ident,
+    output: TokenStream,
+    resolve: TokenStream,
+    uses_resolver: bool,
+) -> TokenStream {
+    if uses_resolver {
+        quote! {
+            impl<'a> crate::Resolve<'a, Loader> for #name {
+                type Output = #output;
+                fn resolve(&self, resolver: &crate::Resolver<'a, Loader>) -> Self::Output {
+                    #resolve
+                }
+            }
+        }
+    } else {
+        quote! {
+            impl<'a, L> crate::Resolve<'a, L> for #name {
+                type Output = #output;
+                fn resolve(&self, _resolver: &crate::Resolver<'a, L>) -> Self::Output {
+                    #resolve
+                }
+            }
+        }
+    }
+}
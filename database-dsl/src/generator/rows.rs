@@ -0,0 +1,83 @@
+use crate::ast;
+use proc_macro2::TokenStream;
+use quote::quote;
+use std::collections::HashSet;
+
+/// Generate a struct with named fields for every core and derived relation.
+pub(super) fn generate_rows(schema: &ast::DatabaseSchema) -> TokenStream {
+    let mut tokens = TokenStream::new();
+    let mut names = HashSet::new();
+    for relation in schema.relations.iter().chain(&schema.derived_relations) {
+        let struct_name = relation.get_row_struct_name();
+        if !names.insert(struct_name.to_string()) {
+            panic!(
+                "Relation `{}` has the same row struct name `{}` as another relation.",
+                relation.name, struct_name
+            );
+        }
+        let mut fields = TokenStream::new();
+        let mut field_names = Vec::new();
+        let mut types = Vec::new();
+        for parameter in &relation.parameters {
+            let name = &parameter.name;
+            let typ = qualified_type(&parameter.typ, schema);
+            fields.extend(quote! {
+                pub #name: #typ,
+            });
+            field_names.push(name);
+            types.push(typ);
+        }
+        let indices = (0..relation.parameters.len()).map(syn::Index::from);
+        let doc = format!(" A fact of `{}`.", relation.name);
+        let relation_doc = if relation.doc.is_empty() {
+            TokenStream::new()
+        } else {
+            let relation_doc = &relation.doc;
+            quote! {
+                #[doc = ""]
+                #[doc = #relation_doc]
+            }
+        };
+        tokens.extend(quote! {
+            #[doc = #doc]
+            #relation_doc
+            #[repr(C)]
+            #[derive(
+                Debug, Eq, PartialEq, Hash, Clone, Copy,
+                Deserialize, Serialize, PartialOrd, Ord
+            )]
+            pub struct #struct_name {
+                #fields
+            }
+
+            impl From<(#(#types,)*)> for #struct_name {
+                fn from(fact: (#(#types,)*)) -> Self {
+                    Self {
+                        #(#field_names: fact.#indices,)*
+                    }
+                }
+            }
+
+            impl From<#struct_name> for (#(#types,)*) {
+                fn from(row: #struct_name) -> Self {
+                    (#(row.#field_names,)*)
+                }
+            }
+        });
+    }
+    tokens
+}
+
+/// Qualify the types declared in the schema with `super::types` because
+/// the row structs may have the same names as them, for example,
+/// `BasicBlock` for `basic_blocks`.
+fn qualified_type(typ: &syn::Type, schema: &ast::DatabaseSchema) -> TokenStream {
+    if let Some(inner_type) = ast::option_inner_type(typ) {
+        let inner_type = qualified_type(inner_type, schema);
+        return quote! { Option<#inner_type> };
+    }
+    match schema.get_type_kind(typ) {
+        ast::TypeKind::RustType => quote! { #typ },
+        _ => quote! { super::types::#typ },
+    }
+}
@@ -0,0 +1,205 @@
+use super::utils::{interning_table_load_expression, string_resolution_chain, tuple_column_name};
+use crate::ast;
+use proc_macro2::TokenStream;
+use quote::quote;
+
+pub(super) fn generate_sqlite_export(schema: &ast::DatabaseSchema) -> TokenStream {
+    let sql_values = generate_sql_values(schema);
+    let export_relations = generate_relation_exports(schema);
+    let export_interning_tables = generate_interning_table_exports(schema);
+    quote! {
+        #sql_values
+
+        #[cfg(feature = "sqlite")]
+        impl Loader {
+            /// Export all relations and interning tables into a fresh SQLite
+            /// database at `path`. Derived relations are exported only if
+            /// they were already computed.
+            pub fn export_sqlite(&self, path: &Path) -> Result<()> {
+                use crate::sqlite::SqlValue;
+                let mut connection = crate::sqlite::create_database(path)?;
+                let transaction = connection.transaction()?;
+                #export_interning_tables
+                #export_relations
+                transaction.commit()?;
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Generate `SqlValue` implementations for ids and enums.
+fn generate_sql_values(schema: &ast::DatabaseSchema) -> TokenStream {
+    let mut tokens = TokenStream::new();
+    let id_types = schema
+        .custom_ids
+        .iter()
+        .map(|id| (&id.name, &id.typ))
+        .chain(schema.incremental_ids.iter().map(|id| (&id.name, &id.typ)))
+        .chain(
+            schema
+                .interning_tables
+                .iter()
+                .map(|table| (&table.key.name, &table.key.typ)),
+        );
+    for (name, typ) in id_types {
+        tokens.extend(quote! {
+            #[cfg(feature = "sqlite")]
+            impl crate::sqlite::SqlValue for #name {
+                const SQL_TYPE: &'static str = <#typ as crate::sqlite::SqlValue>::SQL_TYPE;
+                fn to_sql_value(&self) -> rusqlite::types::Value {
+                    self.0.to_sql_value()
+                }
+            }
+        });
+    }
+    for ast::Enum { item, .. } in &schema.enums {
+        let name = &item.ident;
+        tokens.extend(quote! {
+            #[cfg(feature = "sqlite")]
+            impl crate::sqlite::SqlValue for #name {
+                const SQL_TYPE: &'static str = "TEXT";
+                fn to_sql_value(&self) -> rusqlite::types::Value {
+                    rusqlite::types::Value::Text(self.to_string())
+                }
+            }
+        });
+    }
+    tokens
+}
+
+/// Generate the code that creates the view resolving interned strings of the
+/// given columns, if there are any.
+fn generate_resolving_view<'a>(
+    table_name: &str,
+    columns: impl Iterator<Item = (String, &'a syn::Type)>,
+    schema: &ast::DatabaseSchema,
+) -> TokenStream {
+    let mut resolved_columns = TokenStream::new();
+    let mut is_empty = true;
+    for (column, typ) in columns {
+        if let Some(chain) = string_resolution_chain(typ, schema) {
+            resolved_columns.extend(quote! { (#column, &[#(#chain),*]), });
+            is_empty = false;
+        }
+    }
+    if is_empty {
+        TokenStream::new()
+    } else {
+        quote! {
+            crate::sqlite::create_resolving_view(
+                &transaction,
+                #table_name,
+                &[#resolved_columns],
+            )?;
+        }
+    }
+}
+
+fn generate_relation_exports(schema: &ast::DatabaseSchema) -> TokenStream {
+    let mut tokens = TokenStream::new();
+    for relation in &schema.relations {
+        tokens.extend(generate_relation_export(relation, schema));
+    }
+    for relation in &schema.derived_relations {
+        let export = generate_relation_export(relation, schema);
+        let file_name = format!("relations/{}", relation.name);
+        tokens.extend(quote! {
+            let path = self.database_root.join(#file_name);
+            if path.with_extension("rc").exists() || path.with_extension("rcc").exists() {
+                #export
+            }
+        });
+    }
+    tokens
+}
+
+fn generate_relation_export(relation: &ast::Relation, schema: &ast::DatabaseSchema) -> TokenStream {
+    let name = relation.name.to_string();
+    let read_fn_name = relation.get_loader_read_function_name();
+    let mut columns = TokenStream::new();
+    let mut values = TokenStream::new();
+    for ast::RelationParameter { name, typ, .. } in &relation.parameters {
+        let column = name.to_string();
+        columns.extend(quote! {
+            (
+                #column,
+                <#typ as crate::sqlite::SqlValue>::SQL_TYPE,
+                <#typ as crate::sqlite::SqlValue>::NULLABLE,
+            ),
+        });
+        values.extend(quote! { fact.#name.to_sql_value(), });
+    }
+    let view = generate_resolving_view(
+        &name,
+        relation
+            .parameters
+            .iter()
+            .map(|parameter| (parameter.name.to_string(), &parameter.typ)),
+        schema,
+    );
+    quote! {
+        {
+            let facts = self.#read_fn_name()?;
+            let mut statement = crate::sqlite::create_table(&transaction, #name, &[#columns])?;
+            for fact in facts.iter() {
+                statement.execute(rusqlite::params![#values])?;
+            }
+        }
+        #view
+    }
+}
+
+fn generate_interning_table_exports(schema: &ast::DatabaseSchema) -> TokenStream {
+    let mut tokens = TokenStream::new();
+    for table in &schema.interning_tables {
+        let ast::InterningTable {
+            name, key, value, ..
+        } = table;
+        let table_name = name.to_string();
+        let key_type = &key.name;
+        let load = interning_table_load_expression(table);
+        let mut columns = quote! {
+            ("id", <#key_type as crate::sqlite::SqlValue>::SQL_TYPE, false),
+        };
+        let mut values = quote! { key.to_sql_value(), };
+        let value_columns: Vec<(String, &syn::Type)> = match value {
+            syn::Type::Tuple(syn::TypeTuple { elems, .. }) => elems
+                .iter()
+                .enumerate()
+                .map(|(i, elem)| {
+                    let index = syn::Index::from(i);
+                    values.extend(quote! { value.#index.to_sql_value(), });
+                    (tuple_column_name(elems.iter(), i, elem), elem)
+                })
+                .collect(),
+            _ => {
+                values.extend(quote! { value.to_sql_value(), });
+                vec![("value".to_string(), value)]
+            }
+        };
+        for (column, typ) in &value_columns {
+            columns.extend(quote! {
+                (
+                    #column,
+                    <#typ as crate::sqlite::SqlValue>::SQL_TYPE,
+                    <#typ as crate::sqlite::SqlValue>::NULLABLE,
+                ),
+            });
+        }
+        let view = generate_resolving_view(&table_name, value_columns.into_iter(), schema);
+        tokens.extend(quote! {
+            {
+                let table = #load;
+                let mut statement =
+                    crate::sqlite::create_table(&transaction, #table_name, &[#columns])?;
+                for (index, value) in table.iter_values().enumerate() {
+                    let key: #key_type = index.into();
+                    statement.execute(rusqlite::params![#values])?;
+                }
+            }
+            #view
+        });
+    }
+    tokens
+}
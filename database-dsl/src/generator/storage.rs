@@ -1,6 +1,6 @@
 use super::utils::is_copy_type;
 use crate::ast;
-use proc_macro2::TokenStream;
+use proc_macro2::{Span, TokenStream};
 use quote::quote;
 
 pub(super) fn generate_load_save_functions(schema: &ast::DatabaseSchema) -> TokenStream {
@@ -10,29 +10,52 @@ pub(super) fn generate_load_save_functions(schema: &ast::DatabaseSchema) -> Toke
     let store_multifile_relations = store_multifile_relations_function(schema);
     let store_counters = store_counters_function();
     let store_interning_tables = store_multifle_interning_function(schema);
+    let relation_read_write = relation_read_write_functions(schema);
+    let file_schemas = file_schema_constants(schema);
+    let append_segment = append_segment_function(schema);
     quote! {
         impl Tables {
+            /// Load the base and all segments of the database.
             pub fn load_multifile(
                 database_root: &Path
             ) -> Result<Tables> {
-                let relations = load_multifile_relations(&database_root.join("relations"))?;
-                let counters = load_counters(&database_root.join("counters.bincode"))?;
-                let interning_tables = load_interning_tables(&database_root.join("interning"))?;
+                crate::migration::check_schema_version(database_root, SCHEMA_VERSION)?;
+                let mut tables = Self::load_segment(database_root)?;
+                for segment in crate::segments::segment_paths(database_root)? {
+                    tables.append_segment(Self::load_segment(&segment)?);
+                }
+                Ok(tables)
+            }
+            fn load_segment(
+                segment_root: &Path
+            ) -> Result<Tables> {
+                let relations = load_multifile_relations(&segment_root.join("relations"))?;
+                let counters = load_counters(&segment_root.join("counters.bincode"))?;
+                let interning_tables = load_interning_tables(&segment_root.join("interning"))?;
                 Ok(Tables {
                     relations,
                     counters,
                     interning_tables,
                 })
             }
+            #append_segment
             pub fn load_single_file(
                 tables_file: &Path
             ) -> Result<Tables> {
                 crate::storage::load(tables_file)
             }
             pub fn store_multifile(&self, database_root: &Path) -> Result<()> {
+                self.store_multifile_with_format(database_root, StorageFormat::Raw)
+            }
+            /// Store the database by using the specified format for relations.
+            pub fn store_multifile_with_format(
+                &self,
+                database_root: &Path,
+                format: StorageFormat,
+            ) -> Result<()> {
                 let relations_path = database_root.join("relations");
                 std::fs::create_dir_all(&relations_path)?;
-                store_multifile_relations(&self.relations, &relations_path);
+                store_multifile_relations(&self.relations, &relations_path, format)?;
                 let counters_path = database_root.join("counters.bincode");
                 store_counters(&self.counters, &counters_path);
                 let interning_tables_path = &database_root.join("interning");
@@ -41,6 +64,7 @@ pub(super) fn generate_load_save_functions(schema: &ast::DatabaseSchema) -> Toke
                     &self.interning_tables,
                     &interning_tables_path
                 );
+                crate::migration::write_schema_version(database_root, SCHEMA_VERSION);
                 Ok(())
             }
         }
@@ -50,17 +74,207 @@ pub(super) fn generate_load_save_functions(schema: &ast::DatabaseSchema) -> Toke
         #store_multifile_relations
         #store_counters
         #store_interning_tables
+        #relation_read_write
+        #file_schemas
+    }
+}
+
+/// Generate the function that appends the facts and the interned values of
+/// the following segment.
+fn append_segment_function(schema: &ast::DatabaseSchema) -> TokenStream {
+    let mut append_fields = TokenStream::new();
+    for relation in &schema.relations {
+        let name = &relation.name;
+        append_fields.extend(quote! {
+            self.relations.#name.facts.extend(segment.relations.#name.facts);
+        });
+    }
+    for table in &schema.interning_tables {
+        let name = &table.name;
+        append_fields.extend(quote! {
+            self.interning_tables.#name.append(segment.interning_tables.#name);
+        });
+    }
+    quote! {
+        fn append_segment(&mut self, segment: Tables) {
+            #append_fields
+            self.counters = segment.counters;
+        }
+    }
+}
+
+/// Generate the `FileSchema` constants that are stored in the headers of the
+/// relation and interning table files.
+fn file_schema_constants(schema: &ast::DatabaseSchema) -> TokenStream {
+    let mut tokens = TokenStream::new();
+    for relation in schema.relations.iter().chain(&schema.derived_relations) {
+        let constant_name = relation.get_file_schema_name();
+        let file_schema = file_schema_expression(relation);
+        tokens.extend(quote! {
+            pub(crate) const #constant_name: FileSchema<'static> = #file_schema;
+        });
+    }
+    for table in &schema.interning_tables {
+        let ast::InterningTable {
+            name, key, value, ..
+        } = table;
+        if is_copy_type(value, schema) {
+            let constant_name = table.get_file_schema_name();
+            let name = name.to_string();
+            let hash = table.get_hash();
+            let key_type = key.name.to_string();
+            let value_type = quote!(#value).to_string();
+            tokens.extend(quote! {
+                pub(crate) const #constant_name: FileSchema<'static> = FileSchema {
+                    name: #name,
+                    hash: #hash,
+                    columns: &[("key", #key_type), ("value", #value_type)],
+                };
+            });
+        }
+    }
+    tokens
+}
+
+/// Generate the `FileSchema` of `relation`.
+pub(super) fn file_schema_expression(relation: &ast::Relation) -> TokenStream {
+    let name = relation.name.to_string();
+    let hash = relation.get_hash();
+    let mut columns = TokenStream::new();
+    for ast::RelationParameter { name, typ, .. } in &relation.parameters {
+        let column_name = name.to_string();
+        let column_type = quote!(#typ).to_string();
+        columns.extend(quote! {(#column_name, #column_type),});
+    }
+    quote! {
+        FileSchema {
+            name: #name,
+            hash: #hash,
+            columns: &[#columns],
+        }
+    }
+}
+
+/// Generate functions `read_relation_*` and `write_relation_*` that load and
+/// store relations by using either the raw or the columnar format. The raw
+/// format stores the facts as the row structs so that they can be
+/// memory-mapped as rows.
+fn relation_read_write_functions(schema: &ast::DatabaseSchema) -> TokenStream {
+    let mut tokens = TokenStream::new();
+    for relation in schema.relations.iter().chain(&schema.derived_relations) {
+        let file_schema = relation.get_file_schema_name();
+        let read_fn_name = relation.get_read_function_name();
+        let write_fn_name = relation.get_write_function_name();
+        let row_struct_name = relation.get_row_struct_name();
+        let mut types = TokenStream::new();
+        let mut write_columns = TokenStream::new();
+        for (i, ast::RelationParameter { typ, .. }) in relation.parameters.iter().enumerate() {
+            types.extend(quote! {#typ,});
+            let index = syn::Index::from(i);
+            write_columns.extend(quote! {
+                writer.write_column(facts.iter().map(|fact| fact.#index))?;
+            });
+        }
+        let read_facts = read_facts_expression(
+            relation,
+            quote! { #file_schema },
+            quote! { super::rows::#row_struct_name },
+        );
+        tokens.extend(quote! {
+            /// ``path`` – the path **without** the extension.
+            pub(crate) fn #read_fn_name(
+                path: PathBuf,
+            ) -> Result<Facts<super::rows::#row_struct_name>> {
+                #read_facts
+            }
+            /// ``path`` – the path **without** the extension.
+            pub(crate) fn #write_fn_name(
+                facts: &[(#types)],
+                path: PathBuf,
+                format: StorageFormat,
+            ) -> Result<()> {
+                crate::storage::remove_relation_files(&path)?;
+                match format {
+                    StorageFormat::Raw => {
+                        unsafe {
+                            crate::storage::save_rows::<super::rows::#row_struct_name, _>(
+                                facts,
+                                &#file_schema,
+                                path,
+                            )
+                        };
+                    }
+                    StorageFormat::Columnar => {
+                        let mut writer = crate::columnar::ColumnarWriter::new(facts.len());
+                        #write_columns
+                        writer.finish(&#file_schema, &path.with_extension("rcc"))?;
+                    }
+                }
+                Ok(())
+            }
+        });
+    }
+    tokens
+}
+
+/// Generate the expression that reads the facts of `relation` from `path`
+/// (**without** the extension) in either format as `row_type` and checks that
+/// the file matches `file_schema`. `row_type` must be a `#[repr(C)]` struct
+/// that can be converted from the tuple of the relation.
+pub(super) fn read_facts_expression(
+    relation: &ast::Relation,
+    file_schema: TokenStream,
+    row_type: TokenStream,
+) -> TokenStream {
+    let mut types = TokenStream::new();
+    let mut open_columns = TokenStream::new();
+    let mut next_values = TokenStream::new();
+    for (i, ast::RelationParameter { typ, .. }) in relation.parameters.iter().enumerate() {
+        types.extend(quote! {#typ,});
+        let column = syn::Ident::new(&format!("column_{}", i), Span::call_site());
+        open_columns.extend(quote! {
+            let mut #column = reader.column::<#typ>(#i)?;
+        });
+        next_values.extend(quote! {
+            #column.next_value()?,
+        });
+    }
+    quote! {
+        {
+            let columnar_path = path.with_extension("rcc");
+            if columnar_path.exists() {
+                let reader = crate::columnar::ColumnarReader::open(
+                    &#file_schema,
+                    &columnar_path,
+                )?;
+                #open_columns
+                let mut facts = Vec::with_capacity(reader.len());
+                for _ in 0..reader.len() {
+                    facts.push(<#row_type>::from((#next_values)));
+                }
+                Ok(facts.into())
+            } else {
+                unsafe {
+                    crate::storage::load_mapped_rows::<#row_type, (#types)>(&#file_schema, path)
+                }
+            }
+        }
     }
 }
 
 fn load_multifile_relations_function(schema: &ast::DatabaseSchema) -> TokenStream {
     let mut load_fields = TokenStream::new();
     for relation in &schema.relations {
-        let relation_hash = relation.get_hash();
         let name = &relation.name;
+        let read_fn_name = relation.get_read_function_name();
         let file_name = format!("{}", name);
+        let types = relation.parameters.iter().map(|parameter| &parameter.typ);
         load_fields.extend(quote! {
-            #name: unsafe { Relation::load(#relation_hash, path.join(#file_name)) }?,
+            #name: #read_fn_name(path.join(#file_name))?
+                .iter()
+                .map(|&row| <(#(#types,)*)>::from(row))
+                .collect::<Vec<_>>()
+                .into(),
         });
     }
     quote! {
@@ -76,18 +290,20 @@ fn store_multifile_relations_function(schema: &ast::DatabaseSchema) -> TokenStre
     let mut store_fields = TokenStream::new();
     for relation in &schema.relations {
         let name = &relation.name;
-        let relation_hash = relation.get_hash();
+        let write_fn_name = relation.get_write_function_name();
         let file_name = name.to_string();
         store_fields.extend(quote! {
-            unsafe { relations.#name.save(#relation_hash, path.join(#file_name)) }
+            #write_fn_name(&relations.#name.facts, path.join(#file_name), format)?;
         });
     }
     quote! {
         fn store_multifile_relations(
             relations: &Relations,
-            path: &Path
-        ) {
+            path: &Path,
+            format: StorageFormat,
+        ) -> Result<()> {
             #store_fields
+            Ok(())
         }
     }
 }
@@ -113,10 +329,10 @@ fn load_multifle_interning_function(schema: &ast::DatabaseSchema) -> TokenStream
     for table in &schema.interning_tables {
         let ast::InterningTable { name, value, .. } = table;
         if is_copy_type(value, schema) {
-            let table_hash = table.get_hash();
+            let file_schema = table.get_file_schema_name();
             let file_name = name.to_string();
             load_fields.extend(quote! {
-                #name: unsafe { InterningTable::load(#table_hash, path.join(#file_name))? },
+                #name: unsafe { InterningTable::load(&#file_schema, path.join(#file_name))? },
             });
         } else {
             let file_name = format!("{}.bincode", name);
@@ -139,10 +355,10 @@ fn store_multifle_interning_function(schema: &ast::DatabaseSchema) -> TokenStrea
     for table in &schema.interning_tables {
         let ast::InterningTable { name, value, .. } = table;
         if is_copy_type(value, schema) {
-            let table_hash = table.get_hash();
+            let file_schema = table.get_file_schema_name();
             let file_name = name.to_string();
             store_fields.extend(quote! {
-                unsafe { interning_tables.#name.save(#table_hash, path.join(#file_name)); }
+                unsafe { interning_tables.#name.save(&#file_schema, path.join(#file_name)); }
             });
         } else {
             let file_name = format!("{}.bincode", name);
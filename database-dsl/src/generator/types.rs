@@ -48,6 +48,15 @@ fn generate_id_decl(name: &syn::Ident, typ: &syn::Type) -> TokenStream {
             Deserialize, Serialize, PartialOrd, Ord, Default
         )]
         pub struct #name(pub(super) #typ);
+
+        impl crate::columnar::ColumnValue for #name {
+            fn to_raw(self) -> u128 {
+                crate::columnar::ColumnValue::to_raw(self.0)
+            }
+            fn from_raw(raw: u128) -> Self {
+                Self(crate::columnar::ColumnValue::from_raw(raw))
+            }
+        }
     };
     if is_numeric_type(typ) {
         tokens.extend(quote! {
@@ -93,7 +102,13 @@ fn generate_enum_types(schema: &ast::DatabaseSchema) -> TokenStream {
     {
         let enum_name = item.ident.clone();
         let mut new_item = item.clone();
+        let mut from_raw_arms = TokenStream::new();
         for (variant_id, variant) in new_item.variants.iter_mut().enumerate() {
+            let variant_name = &variant.ident;
+            let raw = variant_id as u128;
+            from_raw_arms.extend(quote! {
+                #raw => #enum_name::#variant_name,
+            });
             assert!(variant.discriminant.is_none());
             variant.discriminant = Some((
                 syn::Token![=](Span::call_site()),
@@ -123,6 +138,18 @@ fn generate_enum_types(schema: &ast::DatabaseSchema) -> TokenStream {
                     write!(f, "{:?}", self)
                 }
             }
+
+            impl crate::columnar::ColumnValue for #enum_name {
+                fn to_raw(self) -> u128 {
+                    self as u128
+                }
+                fn from_raw(raw: u128) -> Self {
+                    match raw {
+                        #from_raw_arms
+                        _ => panic!("Invalid {} value: {}", stringify!(#enum_name), raw),
+                    }
+                }
+            }
         };
         tokens.extend(enum_tokens);
     }
@@ -1,4 +1,6 @@
 use crate::ast;
+use proc_macro2::TokenStream;
+use quote::quote;
 
 #[derive(Debug, Clone)]
 pub struct NameGenerator {
@@ -25,6 +27,22 @@ impl NameGenerator {
     }
 }
 
+/// Convert a `CamelCase` identifier into `snake_case`.
+pub fn to_snake_case(name: &str) -> String {
+    let mut result = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() {
+            if i > 0 {
+                result.push('_');
+            }
+            result.extend(c.to_lowercase());
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
 pub fn is_numeric_type(typ: &syn::Type) -> bool {
     if let syn::Type::Path(syn::TypePath {
         qself: None,
@@ -45,12 +63,13 @@ pub fn is_copy_type(typ: &syn::Type, schema: &ast::DatabaseSchema) -> bool {
     match typ {
         syn::Type::Path(syn::TypePath { qself: None, path }) => match path.get_ident() {
             Some(ident) => match ident.to_string().as_ref() {
-                "bool" | "u32" | "u64" | "u128" => true,
+                "bool" | "u8" | "u32" | "u64" | "u128" | "i64" | "i128" => true,
                 _ => match schema.get_type_kind(typ) {
                     ast::TypeKind::CustomId
                     | ast::TypeKind::IncrementalId(_)
                     | ast::TypeKind::InternedId(_)
-                    | ast::TypeKind::Enum => true,
+                    | ast::TypeKind::Enum
+                    | ast::TypeKind::Optional => true,
                     ast::TypeKind::RustType => false,
                 },
             },
@@ -62,3 +81,77 @@ pub fn is_copy_type(typ: &syn::Type, schema: &ast::DatabaseSchema) -> bool {
         _ => false,
     }
 }
+
+/// Generate the expression that loads the interning table `table` from the
+/// base and all segments of `self.database_root` without caching it in the
+/// `Loader`.
+pub fn interning_table_load_expression(table: &ast::InterningTable) -> TokenStream {
+    let read_fn_name = table.get_loader_read_function_name();
+    quote! { self.#read_fn_name()? }
+}
+
+/// Returns the names of the interning tables that need to be followed to
+/// resolve a value of type `typ` into a string, or `None` if `typ` is not an
+/// interned string.
+pub fn string_resolution_chain(
+    typ: &syn::Type,
+    schema: &ast::DatabaseSchema,
+) -> Option<Vec<String>> {
+    if let syn::Type::Path(syn::TypePath { qself: None, path }) = typ {
+        if path.get_ident().is_some() {
+            if let ast::TypeKind::InternedId(table) = schema.get_type_kind(typ) {
+                let mut chain = vec![table.name.to_string()];
+                if let syn::Type::Path(syn::TypePath { qself: None, path }) = &table.value {
+                    if path.is_ident("String") {
+                        return Some(chain);
+                    }
+                }
+                chain.extend(string_resolution_chain(&table.value, schema)?);
+                return Some(chain);
+            }
+        }
+    }
+    None
+}
+
+/// The name of the column storing the `index`-th element of a tuple: the
+/// snake case name of the element type if it is unique within the tuple.
+pub fn tuple_column_name<'a>(
+    elems: impl Iterator<Item = &'a syn::Type>,
+    index: usize,
+    elem: &syn::Type,
+) -> String {
+    if let syn::Type::Path(syn::TypePath { path, .. }) = elem {
+        if let Some(ident) = path.get_ident() {
+            let column = to_snake_case(&ident.to_string());
+            if elems
+                .filter(|other| quote!(#other).to_string() == quote!(#elem).to_string())
+                .count()
+                == 1
+            {
+                return column;
+            }
+            return format!("{}_{}", column, index);
+        }
+    }
+    format!("value_{}", index)
+}
+
+/// The interning tables whose ids are stored in core relations, in the order
+/// of their declaration.
+pub fn referenced_interning_tables(schema: &ast::DatabaseSchema) -> Vec<&ast::InterningTable> {
+    schema
+        .interning_tables
+        .iter()
+        .filter(|table| {
+            schema.relations.iter().any(|relation| {
+                relation.parameters.iter().any(|parameter| {
+                    matches!(
+                        schema.get_column_type_kind(&parameter.typ).0,
+                        ast::TypeKind::InternedId(referenced) if referenced.name == table.name
+                    )
+                })
+            })
+        })
+        .collect()
+}
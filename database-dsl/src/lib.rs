@@ -28,6 +28,15 @@ pub fn parse_schema(core_schema_path: &Path, derived_relations_path: &Path) -> a
         Err(err) => panic!("Error: {:?} (at {:?}", err, err.span().start()),
     };
     schema.derived_relations = derived_relations.into();
+    if let Err(err) = schema.check_references() {
+        panic!("Error: {:?} (at {:?})", err, err.span().start());
+    }
+    if let Err(err) = schema.check_migrations() {
+        panic!("Error: {:?} (at {:?})", err, err.span().start());
+    }
+    if let Err(err) = schema.check_indexes() {
+        panic!("Error: {:?} (at {:?})", err, err.span().start());
+    }
     schema
 }
 
@@ -11,6 +11,31 @@ mod kw {
     syn::custom_keyword!(relation);
     syn::custom_keyword!(auto);
     syn::custom_keyword!(key);
+    syn::custom_keyword!(references);
+    syn::custom_keyword!(migration);
+    syn::custom_keyword!(add_column);
+    syn::custom_keyword!(rename_column);
+    syn::custom_keyword!(add_variant);
+    syn::custom_keyword!(make_optional);
+    syn::custom_keyword!(index);
+    syn::custom_keyword!(persist);
+}
+
+/// The text of the documentation comments in `attrs` with one line per
+/// comment.
+fn doc_comment(attrs: &[syn::Attribute]) -> String {
+    let lines: Vec<_> = attrs
+        .iter()
+        .filter_map(|attr| match attr.parse_meta() {
+            Ok(syn::Meta::NameValue(syn::MetaNameValue {
+                path,
+                lit: syn::Lit::Str(text),
+                ..
+            })) if path.is_ident("doc") => Some(text.value().trim().to_string()),
+            _ => None,
+        })
+        .collect();
+    lines.join("\n")
 }
 
 impl Parse for ast::CustomId {
@@ -84,7 +109,12 @@ impl Parse for ast::InterningTable {
         let key = input.parse()?;
         input.parse::<Token![>]>()?;
         input.parse::<Token![;]>()?;
-        Ok(Self { name, key, value })
+        Ok(Self {
+            name,
+            key,
+            value,
+            doc: String::new(),
+        })
     }
 }
 
@@ -109,7 +139,7 @@ impl Parse for ast::Enum {
 
 impl Parse for ast::RelationParameter {
     fn parse(input: ParseStream) -> syn::Result<Self> {
-        let name = input.parse()?;
+        let name: syn::Ident = input.parse()?;
         input.parse::<Token![:]>()?;
         let is_autogenerated = if input.peek(kw::auto) {
             input.parse::<kw::auto>()?;
@@ -118,10 +148,25 @@ impl Parse for ast::RelationParameter {
             false
         };
         let typ = input.parse()?;
+        if is_autogenerated && ast::option_inner_type(&typ).is_some() {
+            return Err(syn::Error::new(
+                name.span(),
+                "Autogenerated columns cannot be optional.",
+            ));
+        }
+        let mut references = Vec::new();
+        while input.peek(kw::references) {
+            input.parse::<kw::references>()?;
+            let relation = input.parse()?;
+            input.parse::<Token![.]>()?;
+            let column = input.parse()?;
+            references.push(ast::ColumnReference { relation, column });
+        }
         Ok(Self {
             name,
             typ,
             is_autogenerated,
+            references,
         })
     }
 }
@@ -237,6 +282,96 @@ impl Parse for ast::Relation {
             name,
             parameters,
             key,
+            doc: String::new(),
+        })
+    }
+}
+
+impl Parse for ast::MigrationStep {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let lookahead = input.lookahead1();
+        let step = if lookahead.peek(kw::add_column) {
+            input.parse::<kw::add_column>()?;
+            let relation = input.parse()?;
+            input.parse::<Token![.]>()?;
+            let column = input.parse()?;
+            input.parse::<Token![=]>()?;
+            let default = input.parse()?;
+            ast::MigrationStep::AddColumn {
+                relation,
+                column,
+                default,
+            }
+        } else if lookahead.peek(kw::rename_column) {
+            input.parse::<kw::rename_column>()?;
+            let relation = input.parse()?;
+            input.parse::<Token![.]>()?;
+            let old_name = input.parse()?;
+            input.parse::<Token![->]>()?;
+            let new_name = input.parse()?;
+            ast::MigrationStep::RenameColumn {
+                relation,
+                old_name,
+                new_name,
+            }
+        } else if lookahead.peek(kw::add_variant) {
+            input.parse::<kw::add_variant>()?;
+            let enum_name = input.parse()?;
+            input.parse::<Token![::]>()?;
+            let variant = input.parse()?;
+            ast::MigrationStep::AddVariant { enum_name, variant }
+        } else if lookahead.peek(kw::make_optional) {
+            input.parse::<kw::make_optional>()?;
+            let relation = input.parse()?;
+            input.parse::<Token![.]>()?;
+            let column = input.parse()?;
+            input.parse::<Token![=]>()?;
+            let none_value = input.parse()?;
+            ast::MigrationStep::MakeOptional {
+                relation,
+                column,
+                none_value,
+            }
+        } else {
+            return Err(lookahead.error());
+        };
+        input.parse::<Token![;]>()?;
+        Ok(step)
+    }
+}
+
+impl Parse for ast::Migration {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        input.parse::<kw::migration>()?;
+        let version = input.parse()?;
+        let content;
+        syn::braced!(content in input);
+        let mut steps = Vec::new();
+        while !content.is_empty() {
+            steps.push(content.parse()?);
+        }
+        Ok(Self { version, steps })
+    }
+}
+
+impl Parse for ast::Index {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        input.parse::<kw::index>()?;
+        let relation = input.parse()?;
+        let content;
+        syn::parenthesized!(content in input);
+        let punctuated: Punctuated<_, Token![,]> = content.parse_terminated(syn::Ident::parse)?;
+        let persist = if input.peek(kw::persist) {
+            input.parse::<kw::persist>()?;
+            true
+        } else {
+            false
+        };
+        input.parse::<Token![;]>()?;
+        Ok(Self {
+            relation,
+            columns: punctuated.into_iter().collect(),
+            persist,
         })
     }
 }
@@ -245,8 +380,9 @@ impl Parse for ast::Relations {
     fn parse(input: ParseStream) -> syn::Result<Self> {
         let mut relations = Vec::new();
         while !input.is_empty() {
-            let _attrs = input.call(syn::Attribute::parse_outer)?;
-            let relation: ast::Relation = input.parse()?;
+            let attrs = input.call(syn::Attribute::parse_outer)?;
+            let mut relation: ast::Relation = input.parse()?;
+            relation.doc = doc_comment(&attrs);
             relations.push(relation);
         }
         Ok(Self { relations })
@@ -257,25 +393,33 @@ impl Parse for ast::DatabaseSchema {
     fn parse(input: ParseStream) -> syn::Result<Self> {
         let mut schema = ast::DatabaseSchema::default();
         while !input.is_empty() {
-            let _attrs = input.call(syn::Attribute::parse_outer)?;
+            let attrs = input.call(syn::Attribute::parse_outer)?;
             let lookahead = input.lookahead1();
             if lookahead.peek(kw::custom_id) {
                 let custom_id: ast::CustomId = input.parse()?;
-                // inc_id.comments = _attrs; TODO
+                // inc_id.comments = attrs; TODO
                 schema.custom_ids.push(custom_id);
             } else if lookahead.peek(kw::inc_id) {
                 let inc_id: ast::IncrementalId = input.parse()?;
-                // inc_id.comments = _attrs; TODO
+                // inc_id.comments = attrs; TODO
                 schema.incremental_ids.push(inc_id);
             } else if lookahead.peek(kw::intern) {
-                let intern_table: ast::InterningTable = input.parse()?;
+                let mut intern_table: ast::InterningTable = input.parse()?;
+                intern_table.doc = doc_comment(&attrs);
                 schema.interning_tables.push(intern_table);
             } else if lookahead.peek(Token![enum]) {
                 let item: ast::Enum = input.parse()?;
                 schema.enums.push(item);
             } else if lookahead.peek(kw::relation) {
-                let relation: ast::Relation = input.parse()?;
+                let mut relation: ast::Relation = input.parse()?;
+                relation.doc = doc_comment(&attrs);
                 schema.relations.push(relation);
+            } else if lookahead.peek(kw::migration) {
+                let migration: ast::Migration = input.parse()?;
+                schema.migrations.push(migration);
+            } else if lookahead.peek(kw::index) {
+                let index: ast::Index = input.parse()?;
+                schema.indexes.push(index);
             } else {
                 return Err(lookahead.error());
             }
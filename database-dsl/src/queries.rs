@@ -1,7 +1,8 @@
 use crate::ast;
 use proc_macro2::Span;
-use proc_macro2::TokenStream;
+use proc_macro2::{Delimiter, TokenStream, TokenTree};
 use quote::quote;
+use std::collections::{HashMap, HashSet};
 use syn::parse::{Parse, ParseStream};
 use syn::Token;
 
@@ -10,16 +11,16 @@ pub(crate) fn generate(schema: ast::DatabaseSchema, input: TokenStream) -> Token
         Ok(parsed_result) => parsed_result,
         Err(err) => return err.to_compile_error(),
     };
-    let (mut tokens, datapond_input) = program.to_tokens(schema);
-    let datafrog = datapond::generate_datafrog(datapond_input);
-    tokens.extend(datafrog);
-    let tokens = quote! {
+    let tokens = match program.to_tokens(schema) {
+        Ok(tokens) => tokens,
+        Err(err) => return err.to_compile_error(),
+    };
+    quote! {
         {
             use corpus_database::types::*;
             #tokens
         }
-    };
-    tokens
+    }
 }
 
 mod kw {
@@ -103,26 +104,35 @@ impl Parse for Program {
 }
 
 impl Program {
-    fn to_tokens(self, schema: ast::DatabaseSchema) -> (TokenStream, TokenStream) {
-        let mut pre_tokens = TokenStream::new();
-        let mut datapond_tokens = TokenStream::new();
+    fn to_tokens(self, schema: ast::DatabaseSchema) -> syn::Result<TokenStream> {
+        let mut tokens = TokenStream::new();
+        let mut declarations = Vec::new();
         if let Some(loader) = &self.load_instructions.loader {
             for LoadInstruction { operation, args } in self.load_instructions.instructions {
-                let (pre, datapond) = match operation.to_string().as_ref() {
+                let (pre, loaded) = match operation.to_string().as_ref() {
                     "interning_tables_as_relations" => {
                         load_interning_tables_as_relations(&schema, loader, args)
                     }
                     "relations" => load_relations(&schema, loader, args),
                     _ => unreachable!("Unknown operation: {}", operation),
                 };
-                pre_tokens.extend(pre);
-                datapond_tokens.extend(datapond);
+                tokens.extend(pre);
+                declarations.extend(loaded);
+            }
+        }
+        let datalog = Datalog::parse(self.datapond_program.clone())?;
+        if datalog.is_single_stratum() {
+            // Datapond can evaluate the program as it is.
+            let mut datapond_tokens = TokenStream::new();
+            for declaration in &declarations {
+                datapond_tokens.extend(declaration.to_tokens(DeclarationKind::Input));
             }
             datapond_tokens.extend(self.datapond_program);
-            (pre_tokens, datapond_tokens)
+            tokens.extend(datapond::generate_datafrog(datapond_tokens));
         } else {
-            (TokenStream::new(), self.datapond_program)
+            tokens.extend(datalog.generate_strata(declarations)?);
         }
+        Ok(tokens)
     }
 }
 
@@ -130,9 +140,9 @@ fn load_interning_tables_as_relations(
     schema: &ast::DatabaseSchema,
     loader: &syn::Ident,
     args: Vec<syn::Ident>,
-) -> (TokenStream, TokenStream) {
+) -> (TokenStream, Vec<Declaration>) {
     let mut pre_tokens = TokenStream::new();
-    let mut datapond_tokens = TokenStream::new();
+    let mut declarations = Vec::new();
     for arg in args {
         let table = schema
             .find_interning_table(&arg)
@@ -142,39 +152,38 @@ fn load_interning_tables_as_relations(
         pre_tokens.extend(quote! {
             let #name = #loader.#load_fn_name();
         });
-        let arg = convert_to_named_arg(&table.key.name);
-        let mut args = quote! {#arg,};
+        let mut columns = vec![convert_to_named_arg(&table.key.name)];
         match &table.value {
             syn::Type::Tuple(syn::TypeTuple { elems, .. }) => {
                 for elem in elems {
                     if let syn::Type::Path(syn::TypePath { qself: None, path }) = elem {
-                        let arg = convert_to_named_arg(path.get_ident().unwrap());
-                        args.extend(quote! {#arg,});
+                        columns.push(convert_to_named_arg(path.get_ident().unwrap()));
                     } else {
                         unreachable!();
                     }
                 }
             }
             syn::Type::Path(syn::TypePath { qself: None, path }) => {
-                let arg = convert_to_named_arg(path.get_ident().unwrap());
-                args.extend(quote! {#arg,});
+                columns.push(convert_to_named_arg(path.get_ident().unwrap()));
             }
             _ => unreachable!(),
         }
-        datapond_tokens.extend(quote! {
-            input #name(#args)
+        declarations.push(Declaration {
+            name: name.clone(),
+            kind: DeclarationKind::Input,
+            columns,
         });
     }
-    (pre_tokens, datapond_tokens)
+    (pre_tokens, declarations)
 }
 
 fn load_relations(
     schema: &ast::DatabaseSchema,
     loader: &syn::Ident,
     args: Vec<syn::Ident>,
-) -> (TokenStream, TokenStream) {
+) -> (TokenStream, Vec<Declaration>) {
     let mut pre_tokens = TokenStream::new();
-    let mut datapond_tokens = TokenStream::new();
+    let mut declarations = Vec::new();
     for arg in args {
         let relation = schema.find_relation(&arg).unwrap_or_else(|| {
             unreachable!("Not found relation: {}", arg);
@@ -182,22 +191,26 @@ fn load_relations(
         let name = &relation.name;
         let load_fn_name = syn::Ident::new(&format!("load_{}", name), Span::call_site());
         pre_tokens.extend(quote! {
-            let #name = #loader.#load_fn_name().clone();
+            let #name = #loader.#load_fn_name();
         });
-        let mut args = TokenStream::new();
-        for ast::RelationParameter { name, typ, .. } in &relation.parameters {
-            args.extend(quote! {
-                #name: #typ,
-            });
-        }
-        datapond_tokens.extend(quote! {
-            input #name(#args)
+        let columns = relation
+            .parameters
+            .iter()
+            .map(|ast::RelationParameter { name, typ, .. }| Column {
+                name: name.clone(),
+                typ: typ.clone(),
+            })
+            .collect();
+        declarations.push(Declaration {
+            name: name.clone(),
+            kind: DeclarationKind::Input,
+            columns,
         });
     }
-    (pre_tokens, datapond_tokens)
+    (pre_tokens, declarations)
 }
 
-fn convert_to_named_arg(typ: &syn::Ident) -> TokenStream {
+fn convert_to_named_arg(typ: &syn::Ident) -> Column {
     let mut var_name = String::new();
     let mut first = true;
     for c in typ.to_string().chars() {
@@ -211,6 +224,1352 @@ fn convert_to_named_arg(typ: &syn::Ident) -> TokenStream {
         }
         first = false;
     }
-    let var = syn::Ident::new(&var_name, Span::call_site());
-    quote! {#var: #typ}
+    Column {
+        name: syn::Ident::new(&var_name, Span::call_site()),
+        typ: syn::parse_quote! { #typ },
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum DeclarationKind {
+    Input,
+    Output,
+    Internal,
+}
+
+#[derive(Clone)]
+struct Column {
+    name: syn::Ident,
+    typ: syn::Type,
+}
+
+impl Parse for Column {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let name = input.parse()?;
+        input.parse::<Token![:]>()?;
+        let typ = input.parse()?;
+        Ok(Column { name, typ })
+    }
+}
+
+/// A declaration of a relation such as `output non_tree_types(typ: Type)`.
+struct Declaration {
+    name: syn::Ident,
+    kind: DeclarationKind,
+    columns: Vec<Column>,
+}
+
+impl Declaration {
+    fn to_tokens(&self, kind: DeclarationKind) -> TokenStream {
+        let name = &self.name;
+        let keyword = syn::Ident::new(
+            match kind {
+                DeclarationKind::Input => "input",
+                DeclarationKind::Output => "output",
+                DeclarationKind::Internal => "internal",
+            },
+            Span::call_site(),
+        );
+        let mut columns = TokenStream::new();
+        for Column { name, typ } in &self.columns {
+            columns.extend(quote! {
+                #name: #typ,
+            });
+        }
+        quote! {
+            #keyword #name(#columns)
+        }
+    }
+    fn find_column(&self, name: &syn::Ident) -> Option<&Column> {
+        self.columns.iter().find(|column| &column.name == name)
+    }
+}
+
+/// An argument of an atom in a rule body: either `value` or `.column=value`.
+#[derive(Clone)]
+enum AtomArg {
+    Positional(TokenStream),
+    Named(syn::Ident, TokenStream),
+}
+
+/// An atom in a rule body such as `types_raw_ptr(.typ=typ)` or, if it is
+/// negated, `!unsafe_block_calls(.unsafe_scope=scope)`.
+#[derive(Clone)]
+struct Atom {
+    relation: syn::Ident,
+    args: Vec<AtomArg>,
+    negated: bool,
+    /// The tokens of the atom without the negation.
+    tokens: TokenStream,
+}
+
+impl Atom {
+    /// The variables bound by the atom together with the types of the
+    /// columns they are bound to.
+    fn variables<'a>(
+        &self,
+        declaration: &'a Declaration,
+    ) -> syn::Result<Vec<(syn::Ident, &'a syn::Type)>> {
+        let mut variables = Vec::new();
+        for (i, arg) in self.args.iter().enumerate() {
+            let (column, value) = match arg {
+                AtomArg::Positional(value) => (declaration.columns.get(i), value),
+                AtomArg::Named(column, value) => (declaration.find_column(column), value),
+            };
+            let column = column.ok_or_else(|| {
+                syn::Error::new(
+                    self.relation.span(),
+                    format!("Relation `{}` has no such column.", self.relation),
+                )
+            })?;
+            if let Some(variable) = as_variable(value) {
+                variables.push((variable, &column.typ));
+            }
+        }
+        Ok(variables)
+    }
+    /// The same atom with named arguments for all columns of the relation.
+    /// The columns given as `_` or not mentioned are bound to fresh
+    /// variables named after the position of the atom in the rule body so
+    /// that the facts that differ only in these columns stay distinct.
+    fn bind_all_columns(&self, declaration: &Declaration, position: usize) -> syn::Result<Atom> {
+        let mut values = vec![None; declaration.columns.len()];
+        for (i, arg) in self.args.iter().enumerate() {
+            let (column, value) = match arg {
+                AtomArg::Positional(value) => (Some(i).filter(|&i| i < values.len()), value),
+                AtomArg::Named(column, value) => (
+                    declaration
+                        .columns
+                        .iter()
+                        .position(|other| &other.name == column),
+                    value,
+                ),
+            };
+            let column = column.ok_or_else(|| {
+                syn::Error::new(
+                    self.relation.span(),
+                    format!("Relation `{}` has no such column.", self.relation),
+                )
+            })?;
+            if !is_wildcard(value) {
+                values[column] = Some(value.clone());
+            }
+        }
+        let args: Vec<_> = declaration
+            .columns
+            .iter()
+            .zip(values)
+            .map(|(column, value)| {
+                let value = value.unwrap_or_else(|| {
+                    let variable = syn::Ident::new(
+                        &format!("__{}_{}", position, column.name),
+                        self.relation.span(),
+                    );
+                    quote! { #variable }
+                });
+                (column.name.clone(), value)
+            })
+            .collect();
+        let relation = &self.relation;
+        let named_args = args
+            .iter()
+            .map(|(column, value)| quote! { .#column=#value });
+        Ok(Atom {
+            relation: relation.clone(),
+            tokens: quote! { #relation(#(#named_args),*) },
+            args: args
+                .into_iter()
+                .map(|(column, value)| AtomArg::Named(column, value))
+                .collect(),
+            negated: false,
+        })
+    }
+}
+
+#[derive(Clone, Copy)]
+enum AggregateKind {
+    Count,
+    Sum,
+    Min,
+    Max,
+}
+
+/// An argument of a rule head.
+enum HeadArg {
+    /// A variable, which groups the facts if the rule computes aggregates.
+    Variable(syn::Ident),
+    /// An aggregate such as `count()` or `sum(size)`.
+    Aggregate(AggregateKind, Option<syn::Ident>, Span),
+}
+
+struct Rule {
+    head: syn::Ident,
+    head_args: Vec<TokenStream>,
+    body: TokenStream,
+    atoms: Vec<Atom>,
+}
+
+impl Rule {
+    fn to_tokens(&self) -> TokenStream {
+        let Rule {
+            head,
+            head_args,
+            body,
+            ..
+        } = self;
+        quote! {
+            #head(#(#head_args),*) :- #body.
+        }
+    }
+    fn parse_head_args(&self) -> syn::Result<Vec<HeadArg>> {
+        self.head_args
+            .iter()
+            .map(|arg| {
+                if let Some(variable) = as_variable(arg) {
+                    return Ok(HeadArg::Variable(variable));
+                }
+                let tokens: Vec<_> = arg.clone().into_iter().collect();
+                if let [TokenTree::Ident(function), TokenTree::Group(group)] = tokens.as_slice() {
+                    let kind = match function.to_string().as_ref() {
+                        "count" => Some(AggregateKind::Count),
+                        "sum" => Some(AggregateKind::Sum),
+                        "min" => Some(AggregateKind::Min),
+                        "max" => Some(AggregateKind::Max),
+                        _ => None,
+                    };
+                    if let (Some(kind), Delimiter::Parenthesis) = (kind, group.delimiter()) {
+                        let variable = as_variable(&group.stream());
+                        match (kind, &variable) {
+                            (AggregateKind::Count, None) if group.stream().is_empty() => {}
+                            (AggregateKind::Count, _) => {
+                                return Err(syn::Error::new(
+                                    function.span(),
+                                    "`count()` takes no arguments.",
+                                ))
+                            }
+                            (_, None) => {
+                                return Err(syn::Error::new(
+                                    function.span(),
+                                    format!("`{}` takes a single variable.", function),
+                                ))
+                            }
+                            _ => {}
+                        }
+                        return Ok(HeadArg::Aggregate(kind, variable, function.span()));
+                    }
+                }
+                Err(syn::Error::new(
+                    tokens[0].span(),
+                    "Expected a variable or an aggregate: `count()`, `sum(x)`, `min(x)`, or `max(x)`.",
+                ))
+            })
+            .collect()
+    }
+    fn is_aggregation(&self) -> bool {
+        self.head_args.iter().any(|arg| as_variable(arg).is_none())
+    }
+}
+
+/// A rule that computes aggregates. The body of the rule is evaluated by
+/// Datapond into the relation `body_relation`, whose columns are the
+/// variables of the body, and the aggregates are computed from its facts.
+struct Aggregation {
+    head: syn::Ident,
+    head_args: Vec<HeadArg>,
+    body_relation: syn::Ident,
+}
+
+/// The facts of `body_relation` that do not match any of the `negated`
+/// atoms. `body_relation` is computed by Datapond from the atoms of a rule
+/// that are not negated, and its columns are the variables of these atoms.
+struct Antijoin {
+    head: syn::Ident,
+    /// The head of the rule that contains the negated atoms.
+    rule_head: syn::Ident,
+    body_relation: syn::Ident,
+    negated: Vec<Atom>,
+}
+
+/// A relation that is computed by Rust code from relations that are fully
+/// computed by the previous strata.
+enum Computation {
+    Aggregation(Aggregation),
+    Antijoin(Antijoin),
+}
+
+impl Computation {
+    fn head(&self) -> &syn::Ident {
+        match self {
+            Computation::Aggregation(aggregation) => &aggregation.head,
+            Computation::Antijoin(antijoin) => &antijoin.head,
+        }
+    }
+    /// The relations that must be fully computed before this one.
+    fn dependencies(&self) -> Vec<&syn::Ident> {
+        match self {
+            Computation::Aggregation(aggregation) => vec![&aggregation.body_relation],
+            Computation::Antijoin(antijoin) => std::iter::once(&antijoin.body_relation)
+                .chain(antijoin.negated.iter().map(|atom| &atom.relation))
+                .collect(),
+        }
+    }
+    /// The error that is reported if `dependency` recursively depends on
+    /// this relation.
+    fn recursion_error(&self, dependency: &syn::Ident) -> syn::Error {
+        match self {
+            Computation::Aggregation(aggregation) => syn::Error::new(
+                aggregation.head.span(),
+                format!(
+                    "Relation `{}` recursively depends on its own aggregates.",
+                    aggregation.head
+                ),
+            ),
+            Computation::Antijoin(antijoin) => {
+                let head = &antijoin.rule_head;
+                match antijoin
+                    .negated
+                    .iter()
+                    .find(|atom| &atom.relation == dependency)
+                {
+                    Some(atom) if &atom.relation == head => syn::Error::new(
+                        atom.relation.span(),
+                        format!("Relation `{}` cannot depend on its own negation.", head),
+                    ),
+                    Some(atom) => syn::Error::new(
+                        atom.relation.span(),
+                        format!(
+                            "Relation `{}` cannot depend on the negation of `{}`, which depends on `{}`.",
+                            head, atom.relation, head
+                        ),
+                    ),
+                    None => syn::Error::new(
+                        head.span(),
+                        format!(
+                            "Relation `{}` cannot be recursive because its rule contains a negation.",
+                            head
+                        ),
+                    ),
+                }
+            }
+        }
+    }
+    fn to_tokens(&self, declarations: &[Declaration]) -> syn::Result<TokenStream> {
+        match self {
+            Computation::Aggregation(aggregation) => aggregation.to_tokens(declarations),
+            Computation::Antijoin(antijoin) => antijoin.to_tokens(declarations),
+        }
+    }
+}
+
+/// The part of a `datapond_query!` after the load instructions.
+struct Datalog {
+    declarations: Vec<Declaration>,
+    rules: Vec<Rule>,
+}
+
+impl Datalog {
+    fn parse(tokens: TokenStream) -> syn::Result<Self> {
+        let mut declarations = Vec::new();
+        let mut rules = Vec::new();
+        let mut tokens = tokens.into_iter().peekable();
+        while let Some(token) = tokens.next() {
+            let name = match token {
+                TokenTree::Ident(name) => name,
+                token => {
+                    return Err(syn::Error::new(
+                        token.span(),
+                        "Expected a declaration or a rule.",
+                    ))
+                }
+            };
+            let kind = match name.to_string().as_ref() {
+                "input" => Some(DeclarationKind::Input),
+                "output" => Some(DeclarationKind::Output),
+                "internal" => Some(DeclarationKind::Internal),
+                _ => None,
+            };
+            if let Some(kind) = kind {
+                let name = match tokens.next() {
+                    Some(TokenTree::Ident(name)) => name,
+                    _ => return Err(syn::Error::new(name.span(), "Expected a relation name.")),
+                };
+                let columns = parenthesized(tokens.next(), &name)?;
+                let columns = syn::parse::Parser::parse2(
+                    syn::punctuated::Punctuated::<Column, Token![,]>::parse_terminated,
+                    columns,
+                )?;
+                declarations.push(Declaration {
+                    name,
+                    kind,
+                    columns: columns.into_iter().collect(),
+                });
+                continue;
+            }
+            let head_args = split_at_commas(parenthesized(tokens.next(), &name)?);
+            let mut body = TokenStream::new();
+            let mut found_end = false;
+            let mut found_implication = false;
+            while let Some(token) = tokens.next() {
+                match &token {
+                    TokenTree::Punct(punct) if punct.as_char() == '.' => {
+                        found_end = true;
+                        break;
+                    }
+                    TokenTree::Punct(punct)
+                        if punct.as_char() == ':' && body.is_empty() && !found_implication =>
+                    {
+                        match tokens.next() {
+                            Some(TokenTree::Punct(punct)) if punct.as_char() == '-' => {
+                                found_implication = true;
+                            }
+                            _ => return Err(syn::Error::new(punct.span(), "Expected `:-`.")),
+                        }
+                    }
+                    _ => body.extend(std::iter::once(token)),
+                }
+            }
+            if !found_end || !found_implication {
+                return Err(syn::Error::new(
+                    name.span(),
+                    "Expected a rule of the form `head(...) :- body.`.",
+                ));
+            }
+            let atoms = split_at_commas(body.clone())
+                .into_iter()
+                .map(parse_atom)
+                .collect::<syn::Result<_>>()?;
+            rules.push(Rule {
+                head: name,
+                head_args,
+                body,
+                atoms,
+            });
+        }
+        Ok(Datalog {
+            declarations,
+            rules,
+        })
+    }
+    /// Returns true if Datapond can evaluate the program at once, that is,
+    /// the program does not compute aggregates or use negations.
+    fn is_single_stratum(&self) -> bool {
+        !self
+            .rules
+            .iter()
+            .any(|rule| rule.is_aggregation() || rule.atoms.iter().any(|atom| atom.negated))
+    }
+    /// Split the program into strata so that every relation that is
+    /// aggregated or negated is fully computed before it is used. Each
+    /// stratum is evaluated by a separate Datapond program that takes the
+    /// relations computed by the previous strata as inputs.
+    fn generate_strata(self, loaded: Vec<Declaration>) -> syn::Result<TokenStream> {
+        let Datalog {
+            mut declarations,
+            rules,
+        } = self;
+        let outputs: HashSet<_> = declarations
+            .iter()
+            .filter(|declaration| declaration.kind == DeclarationKind::Output)
+            .map(|declaration| declaration.name.clone())
+            .collect();
+        declarations.extend(loaded);
+
+        // Replace the aggregations with rules that compute their bodies.
+        let mut aggregations = Vec::new();
+        let mut normal_rules = Vec::new();
+        for rule in rules {
+            if !rule.is_aggregation() {
+                normal_rules.push(rule);
+                continue;
+            }
+            let head_args = rule.parse_head_args()?;
+            let body_relation = syn::Ident::new(&format!("__{}_body", rule.head), rule.head.span());
+            // The body relation is a set, so it keeps all columns of the
+            // atoms; otherwise, the facts that differ only in the columns
+            // that are not bound to variables would be aggregated only once.
+            let atoms = rule
+                .atoms
+                .iter()
+                .enumerate()
+                .map(|(position, atom)| {
+                    if atom.negated {
+                        return Ok(atom.clone());
+                    }
+                    let declaration = find_declaration(&declarations, &atom.relation)?;
+                    atom.bind_all_columns(declaration, position)
+                })
+                .collect::<syn::Result<Vec<_>>>()?;
+            let body_atoms = atoms.iter().map(|atom| {
+                let tokens = &atom.tokens;
+                if atom.negated {
+                    quote! { !#tokens }
+                } else {
+                    tokens.clone()
+                }
+            });
+            let body = quote! { #(#body_atoms),* };
+            let columns = bound_variables(&declarations, &atoms)?;
+            for arg in &head_args {
+                let (variable, span) = match arg {
+                    HeadArg::Variable(variable) => (variable, variable.span()),
+                    HeadArg::Aggregate(_, Some(variable), span) => (variable, *span),
+                    HeadArg::Aggregate(_, None, _) => continue,
+                };
+                if !columns.iter().any(|column| &column.name == variable) {
+                    return Err(syn::Error::new(
+                        span,
+                        format!("Variable `{}` is not bound by the rule body.", variable),
+                    ));
+                }
+            }
+            normal_rules.push(Rule {
+                head: body_relation.clone(),
+                head_args: column_names(&columns),
+                body,
+                atoms,
+            });
+            declarations.push(Declaration {
+                name: body_relation.clone(),
+                kind: DeclarationKind::Internal,
+                columns,
+            });
+            aggregations.push(Aggregation {
+                head: rule.head,
+                head_args,
+                body_relation,
+            });
+        }
+        for aggregation in &aggregations {
+            let declaration = find_declaration(&declarations, &aggregation.head)?;
+            if declaration.kind == DeclarationKind::Input {
+                return Err(syn::Error::new(
+                    aggregation.head.span(),
+                    format!("Input relation `{}` cannot be computed.", aggregation.head),
+                ));
+            }
+            if declaration.columns.len() != aggregation.head_args.len() {
+                return Err(syn::Error::new(
+                    aggregation.head.span(),
+                    format!(
+                        "Relation `{}` has {} columns.",
+                        aggregation.head,
+                        declaration.columns.len()
+                    ),
+                ));
+            }
+            let rule_count = normal_rules
+                .iter()
+                .filter(|rule| rule.head == aggregation.head)
+                .count()
+                + aggregations
+                    .iter()
+                    .filter(|other| other.head == aggregation.head)
+                    .count();
+            if rule_count > 1 {
+                return Err(syn::Error::new(
+                    aggregation.head.span(),
+                    format!(
+                        "Relation `{}` computes aggregates and cannot have other rules.",
+                        aggregation.head
+                    ),
+                ));
+            }
+        }
+        let mut computations: Vec<_> = aggregations
+            .into_iter()
+            .map(Computation::Aggregation)
+            .collect();
+
+        // Replace the rules with negations with rules that compute the
+        // facts matched by their other atoms and rules that use the facts
+        // that remain after removing the ones matched by the negated atoms.
+        let mut rules = Vec::new();
+        let mut negation_count = 0;
+        for rule in normal_rules {
+            if !rule.atoms.iter().any(|atom| atom.negated) {
+                rules.push(rule);
+                continue;
+            }
+            let (negated, positive): (Vec<_>, Vec<_>) =
+                rule.atoms.into_iter().partition(|atom| atom.negated);
+            if positive.is_empty() {
+                return Err(syn::Error::new(
+                    rule.head.span(),
+                    format!(
+                        "A rule of `{}` must have an atom that is not negated.",
+                        rule.head
+                    ),
+                ));
+            }
+            let columns = bound_variables(&declarations, &positive)?;
+            for atom in &negated {
+                atom.variables(find_declaration(&declarations, &atom.relation)?)?;
+                for arg in &atom.args {
+                    let value = match arg {
+                        AtomArg::Positional(value) | AtomArg::Named(_, value) => value,
+                    };
+                    match as_variable(value) {
+                        Some(variable) => {
+                            if !columns.iter().any(|column| column.name == variable) {
+                                return Err(syn::Error::new(
+                                    variable.span(),
+                                    format!(
+                                        "Variable `{}` must be bound by an atom that is not negated.",
+                                        variable
+                                    ),
+                                ));
+                            }
+                        }
+                        None if value.to_string() == "_" => {}
+                        None => {
+                            return Err(syn::Error::new(
+                                atom.relation.span(),
+                                "Negated atoms can contain only variables and `_`.",
+                            ))
+                        }
+                    }
+                }
+            }
+            let index = negation_count;
+            negation_count += 1;
+            // The rule computes the body of an aggregation if its head is
+            // hidden.
+            let rule_head = computations
+                .iter()
+                .find_map(|computation| match computation {
+                    Computation::Aggregation(aggregation)
+                        if aggregation.body_relation == rule.head =>
+                    {
+                        Some(aggregation.head.clone())
+                    }
+                    _ => None,
+                })
+                .unwrap_or_else(|| rule.head.clone());
+            let body_relation = syn::Ident::new(
+                &format!("__{}_negation_{}_body", rule_head, index),
+                rule.head.span(),
+            );
+            let head = syn::Ident::new(
+                &format!("__{}_negation_{}", rule_head, index),
+                rule.head.span(),
+            );
+            let variables = column_names(&columns);
+            let positive_tokens = positive.iter().map(|atom| &atom.tokens);
+            rules.push(Rule {
+                head: body_relation.clone(),
+                head_args: variables.clone(),
+                body: quote! { #(#positive_tokens),* },
+                atoms: positive,
+            });
+            let body = quote! { #head(#(#variables),*) };
+            rules.push(Rule {
+                head: rule.head,
+                head_args: rule.head_args,
+                atoms: vec![parse_atom(body.clone())?],
+                body,
+            });
+            declarations.push(Declaration {
+                name: body_relation.clone(),
+                kind: DeclarationKind::Internal,
+                columns: columns.clone(),
+            });
+            declarations.push(Declaration {
+                name: head.clone(),
+                kind: DeclarationKind::Internal,
+                columns,
+            });
+            computations.push(Computation::Antijoin(Antijoin {
+                head,
+                rule_head,
+                body_relation,
+                negated,
+            }));
+        }
+
+        // Compute the strata: a relation is in the same or a later stratum
+        // than the relations in the bodies of its rules, and in a later
+        // stratum than the relations its computation depends on.
+        let derived: HashSet<_> = rules
+            .iter()
+            .map(|rule| rule.head.clone())
+            .chain(
+                computations
+                    .iter()
+                    .map(|computation| computation.head().clone()),
+            )
+            .collect();
+        let mut dependencies = Vec::new();
+        for rule in &rules {
+            for atom in &rule.atoms {
+                if derived.contains(&atom.relation) {
+                    dependencies.push((&rule.head, &atom.relation, 0));
+                }
+            }
+        }
+        for computation in &computations {
+            for dependency in computation.dependencies() {
+                if derived.contains(dependency) {
+                    dependencies.push((computation.head(), dependency, 1));
+                }
+            }
+        }
+        for computation in &computations {
+            for dependency in computation.dependencies() {
+                if depends_on(&dependencies, dependency, computation.head()) {
+                    return Err(computation.recursion_error(dependency));
+                }
+            }
+        }
+        let mut strata: HashMap<syn::Ident, usize> =
+            derived.iter().map(|name| (name.clone(), 0)).collect();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &(relation, dependency, offset) in &dependencies {
+                let stratum = strata[dependency] + offset;
+                if strata[relation] < stratum {
+                    strata.insert(relation.clone(), stratum);
+                    changed = true;
+                }
+            }
+        }
+        let stratum_count = strata.values().max().map_or(0, |max| max + 1);
+
+        // The relations that are used outside of the stratum that computes
+        // them.
+        let mut escaping = HashSet::new();
+        for computation in &computations {
+            for dependency in computation.dependencies() {
+                if derived.contains(dependency) {
+                    escaping.insert(dependency.clone());
+                }
+            }
+        }
+        for rule in &rules {
+            for atom in &rule.atoms {
+                if derived.contains(&atom.relation) && strata[&atom.relation] != strata[&rule.head]
+                {
+                    escaping.insert(atom.relation.clone());
+                }
+            }
+        }
+
+        let mut tokens = TokenStream::new();
+        for declaration in &declarations {
+            let name = &declaration.name;
+            let is_computed = computations
+                .iter()
+                .any(|computation| computation.head() == name);
+            if (escaping.contains(name) || is_computed) && !outputs.contains(name) {
+                tokens.extend(quote! {
+                    let #name;
+                });
+            }
+        }
+        for stratum in 0..stratum_count {
+            for computation in &computations {
+                if strata[computation.head()] == stratum {
+                    tokens.extend(computation.to_tokens(&declarations)?);
+                }
+            }
+            let stratum_rules: Vec<_> = rules
+                .iter()
+                .filter(|rule| strata[&rule.head] == stratum)
+                .collect();
+            if stratum_rules.is_empty() {
+                continue;
+            }
+            let mut pre_tokens = TokenStream::new();
+            let mut datapond_tokens = TokenStream::new();
+            let mut inputs = Vec::new();
+            for atom in stratum_rules.iter().flat_map(|rule| &rule.atoms) {
+                let relation = &atom.relation;
+                if inputs.contains(&relation)
+                    || stratum_rules.iter().any(|rule| &rule.head == relation)
+                {
+                    continue;
+                }
+                inputs.push(relation);
+                if derived.contains(relation) {
+                    // Datapond takes the inputs as vectors.
+                    pre_tokens.extend(quote! {
+                        let #relation = #relation.to_vec();
+                    });
+                } else if rules.iter().any(|rule| {
+                    strata[&rule.head] > stratum
+                        && rule.atoms.iter().any(|atom| &atom.relation == relation)
+                }) || computations.iter().any(|computation| {
+                    strata[computation.head()] > stratum
+                        && computation.dependencies().contains(&relation)
+                }) {
+                    // The input is needed by a later stratum.
+                    pre_tokens.extend(quote! {
+                        let #relation = #relation.clone();
+                    });
+                }
+                let declaration = find_declaration(&declarations, relation)?;
+                datapond_tokens.extend(declaration.to_tokens(DeclarationKind::Input));
+            }
+            for declaration in &declarations {
+                let name = &declaration.name;
+                if stratum_rules.iter().any(|rule| &rule.head == name) {
+                    let kind = if outputs.contains(name) || escaping.contains(name) {
+                        DeclarationKind::Output
+                    } else {
+                        DeclarationKind::Internal
+                    };
+                    datapond_tokens.extend(declaration.to_tokens(kind));
+                }
+            }
+            for rule in &stratum_rules {
+                datapond_tokens.extend(rule.to_tokens());
+            }
+            let datafrog = datapond::generate_datafrog(datapond_tokens);
+            tokens.extend(quote! {
+                {
+                    #pre_tokens
+                    #datafrog
+                }
+            });
+        }
+        Ok(tokens)
+    }
+}
+
+impl Aggregation {
+    /// Generate the code that groups the facts of `body_relation` by the
+    /// variables in the head and computes the aggregates of each group.
+    fn to_tokens(&self, declarations: &[Declaration]) -> syn::Result<TokenStream> {
+        let head = &self.head;
+        let body_relation = &self.body_relation;
+        let head_declaration = find_declaration(declarations, head)?;
+        let body_declaration = find_declaration(declarations, body_relation)?;
+        let mut group_variables = Vec::new();
+        let mut used_variables = Vec::new();
+        let mut initial_values = Vec::new();
+        let mut updates = TokenStream::new();
+        let mut values = Vec::new();
+        for (arg, column) in self.head_args.iter().zip(&head_declaration.columns) {
+            let typ = &column.typ;
+            match arg {
+                HeadArg::Variable(variable) => {
+                    group_variables.push(variable);
+                    used_variables.push(variable);
+                    values.push(quote! { #variable });
+                }
+                HeadArg::Aggregate(kind, variable, _) => {
+                    let index = syn::Index::from(initial_values.len());
+                    used_variables.extend(variable);
+                    // Aggregates that do not fit into the type of the column
+                    // are reported instead of being truncated.
+                    let overflow = format!(
+                        "The aggregate in the column `{}` of `{}` does not fit into `{}`.",
+                        column.name,
+                        head,
+                        quote! { #typ }
+                    );
+                    let value = quote! {
+                        std::convert::TryInto::<#typ>::try_into(#variable).expect(#overflow)
+                    };
+                    let (initial_value, update, result) = match kind {
+                        AggregateKind::Count => (
+                            quote! { <#typ as Default>::default() },
+                            quote! {
+                                __state.#index = __state.#index.checked_add(1).expect(#overflow);
+                            },
+                            quote! { __state.#index },
+                        ),
+                        AggregateKind::Sum => (
+                            quote! { <#typ as Default>::default() },
+                            quote! {
+                                __state.#index =
+                                    __state.#index.checked_add(#value).expect(#overflow);
+                            },
+                            quote! { __state.#index },
+                        ),
+                        AggregateKind::Min | AggregateKind::Max => {
+                            let select = match kind {
+                                AggregateKind::Min => quote! { std::cmp::min },
+                                _ => quote! { std::cmp::max },
+                            };
+                            (
+                                quote! { None::<#typ> },
+                                quote! {
+                                    let __value = #value;
+                                    __state.#index = Some(
+                                        __state.#index.map_or(__value, |__current| {
+                                            #select(__current, __value)
+                                        })
+                                    );
+                                },
+                                quote! { __state.#index.unwrap() },
+                            )
+                        }
+                    };
+                    initial_values.push(initial_value);
+                    updates.extend(update);
+                    values.push(result);
+                }
+            }
+        }
+        let patterns = body_declaration.columns.iter().map(|column| {
+            let name = &column.name;
+            if used_variables.contains(&name) {
+                quote! { #name }
+            } else {
+                quote! { _ }
+            }
+        });
+        Ok(quote! {
+            {
+                let mut __groups = std::collections::BTreeMap::new();
+                for &(#(#patterns,)*) in #body_relation.iter() {
+                    let __state = __groups
+                        .entry((#(#group_variables,)*))
+                        .or_insert((#(#initial_values,)*));
+                    #updates
+                }
+                let __facts: Vec<_> = __groups
+                    .into_iter()
+                    .map(|((#(#group_variables,)*), __state)| (#(#values,)*))
+                    .collect();
+                #head = datafrog::Relation::from_vec(__facts);
+            }
+        })
+    }
+}
+
+impl Antijoin {
+    /// Generate the code that collects the values of the columns of each
+    /// negated relation that are bound to variables and removes the facts
+    /// of `body_relation` whose variables have these values.
+    fn to_tokens(&self, declarations: &[Declaration]) -> syn::Result<TokenStream> {
+        let head = &self.head;
+        let body_relation = &self.body_relation;
+        let body_declaration = find_declaration(declarations, body_relation)?;
+        let mut sets = TokenStream::new();
+        let mut conditions = Vec::new();
+        let mut used_variables = Vec::new();
+        for (index, atom) in self.negated.iter().enumerate() {
+            let relation = &atom.relation;
+            let declaration = find_declaration(declarations, relation)?;
+            let mut patterns = vec![quote! { _ }; declaration.columns.len()];
+            let mut keys = Vec::new();
+            let mut variables = Vec::new();
+            for (i, arg) in atom.args.iter().enumerate() {
+                let (position, value) = match arg {
+                    AtomArg::Positional(value) => (i, value),
+                    AtomArg::Named(column, value) => (
+                        declaration
+                            .columns
+                            .iter()
+                            .position(|other| &other.name == column)
+                            .unwrap(),
+                        value,
+                    ),
+                };
+                if let Some(variable) = as_variable(value) {
+                    let key = syn::Ident::new(&format!("__key_{}", keys.len()), Span::call_site());
+                    patterns[position] = quote! { #key };
+                    keys.push(key);
+                    used_variables.push(variable.clone());
+                    variables.push(variable);
+                }
+            }
+            let set = syn::Ident::new(&format!("__negated_{}", index), Span::call_site());
+            sets.extend(quote! {
+                let #set: std::collections::HashSet<_> = #relation
+                    .iter()
+                    .map(|&(#(#patterns,)*)| (#(#keys,)*))
+                    .collect();
+            });
+            conditions.push(quote! {
+                !#set.contains(&(#(#variables,)*))
+            });
+        }
+        let patterns = body_declaration.columns.iter().map(|column| {
+            let name = &column.name;
+            if used_variables.contains(name) {
+                quote! { #name }
+            } else {
+                quote! { _ }
+            }
+        });
+        Ok(quote! {
+            {
+                #sets
+                let __facts: Vec<_> = #body_relation
+                    .iter()
+                    .filter(|&&(#(#patterns,)*)| #(#conditions)&&*)
+                    .cloned()
+                    .collect();
+                #head = datafrog::Relation::from_vec(__facts);
+            }
+        })
+    }
+}
+
+/// The variables bound by the atoms that are not negated together with
+/// their types in the order of their first occurrence.
+fn bound_variables(declarations: &[Declaration], atoms: &[Atom]) -> syn::Result<Vec<Column>> {
+    let mut columns: Vec<Column> = Vec::new();
+    for atom in atoms.iter().filter(|atom| !atom.negated) {
+        let declaration = find_declaration(declarations, &atom.relation)?;
+        for (variable, typ) in atom.variables(declaration)? {
+            if !columns.iter().any(|column| column.name == variable) {
+                columns.push(Column {
+                    name: variable,
+                    typ: typ.clone(),
+                });
+            }
+        }
+    }
+    Ok(columns)
+}
+
+fn column_names(columns: &[Column]) -> Vec<TokenStream> {
+    columns
+        .iter()
+        .map(|column| {
+            let name = &column.name;
+            quote! { #name }
+        })
+        .collect()
+}
+
+/// Returns true if `relation` depends on `other` directly or transitively.
+fn depends_on(
+    dependencies: &[(&syn::Ident, &syn::Ident, usize)],
+    relation: &syn::Ident,
+    other: &syn::Ident,
+) -> bool {
+    let mut visited = HashSet::new();
+    let mut stack = vec![relation];
+    while let Some(current) = stack.pop() {
+        if current == other {
+            return true;
+        }
+        if visited.insert(current) {
+            stack.extend(
+                dependencies
+                    .iter()
+                    .filter(|(dependent, _, _)| *dependent == current)
+                    .map(|(_, dependency, _)| *dependency),
+            );
+        }
+    }
+    false
+}
+
+fn find_declaration<'a>(
+    declarations: &'a [Declaration],
+    name: &syn::Ident,
+) -> syn::Result<&'a Declaration> {
+    declarations
+        .iter()
+        .find(|declaration| &declaration.name == name)
+        .ok_or_else(|| {
+            syn::Error::new(
+                name.span(),
+                format!(
+                    "Unknown relation `{}`: it must be loaded or declared with `input`, `output`, or `internal`.",
+                    name
+                ),
+            )
+        })
+}
+
+/// The contents of a parenthesized group that follows `name`.
+fn parenthesized(token: Option<TokenTree>, name: &syn::Ident) -> syn::Result<TokenStream> {
+    match token {
+        Some(TokenTree::Group(group)) if group.delimiter() == Delimiter::Parenthesis => {
+            Ok(group.stream())
+        }
+        _ => Err(syn::Error::new(
+            name.span(),
+            format!("Expected the arguments of `{}`.", name),
+        )),
+    }
+}
+
+fn split_at_commas(tokens: TokenStream) -> Vec<TokenStream> {
+    let mut parts = Vec::new();
+    let mut current = TokenStream::new();
+    for token in tokens {
+        match &token {
+            TokenTree::Punct(punct) if punct.as_char() == ',' => {
+                parts.push(std::mem::take(&mut current));
+            }
+            _ => current.extend(std::iter::once(token)),
+        }
+    }
+    if !current.is_empty() {
+        parts.push(current);
+    }
+    parts
+}
+
+/// The variable if `tokens` consist of a single identifier other than `_`.
+/// Returns true if `tokens` is `_`, which matches any value.
+fn is_wildcard(tokens: &TokenStream) -> bool {
+    let mut tokens = tokens.clone().into_iter();
+    matches!(
+        (tokens.next(), tokens.next()),
+        (Some(TokenTree::Ident(ident)), None) if ident == "_"
+    )
+}
+
+fn as_variable(tokens: &TokenStream) -> Option<syn::Ident> {
+    let mut tokens = tokens.clone().into_iter();
+    match (tokens.next(), tokens.next()) {
+        (Some(TokenTree::Ident(ident)), None) if ident != "_" => Some(ident),
+        _ => None,
+    }
+}
+
+fn parse_atom(tokens: TokenStream) -> syn::Result<Atom> {
+    let mut tokens: Vec<_> = tokens.into_iter().collect();
+    let negated = matches!(tokens.first(), Some(TokenTree::Punct(punct)) if punct.as_char() == '!');
+    if negated {
+        tokens.remove(0);
+    }
+    match tokens.as_slice() {
+        [TokenTree::Ident(relation), TokenTree::Group(group)]
+            if group.delimiter() == Delimiter::Parenthesis =>
+        {
+            let args = split_at_commas(group.stream())
+                .into_iter()
+                .map(|arg| {
+                    let arg_tokens: Vec<_> = arg.clone().into_iter().collect();
+                    match arg_tokens.as_slice() {
+                        [TokenTree::Punct(dot), TokenTree::Ident(column), TokenTree::Punct(equals), value @ ..]
+                            if dot.as_char() == '.' && equals.as_char() == '=' =>
+                        {
+                            AtomArg::Named(column.clone(), value.iter().cloned().collect())
+                        }
+                        _ => AtomArg::Positional(arg),
+                    }
+                })
+                .collect();
+            Ok(Atom {
+                relation: relation.clone(),
+                args,
+                negated,
+                tokens: tokens.iter().cloned().collect(),
+            })
+        }
+        _ => Err(syn::Error::new(
+            tokens
+                .first()
+                .map_or_else(Span::call_site, |token| token.span()),
+            "Expected an atom such as `relation(x, .column=y)`.",
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::generate;
+    use crate::parse_schema;
+    use proc_macro2::TokenStream;
+    use quote::quote;
+    use std::path::Path;
+
+    /// Expand the query `input` by using the core schema.
+    fn expand(input: TokenStream) -> String {
+        let database_src = Path::new(env!("CARGO_MANIFEST_DIR")).join("../database/src");
+        let schema = parse_schema(
+            &database_src.join("schema.dl"),
+            &database_src.join("derived.dl"),
+        );
+        generate(schema, input).to_string()
+    }
+
+    fn assert_compile_error(input: TokenStream, message: &str) {
+        let expanded = expand(input);
+        assert!(
+            expanded.starts_with("compile_error"),
+            "Expected an error, got: {}",
+            expanded
+        );
+        assert!(
+            expanded.contains(message),
+            "Expected `{}`, got: {}",
+            message,
+            expanded
+        );
+    }
+
+    #[test]
+    fn test_aggregates() {
+        let expanded = expand(quote! {
+            input edges(from: u32, to: u32, weight: u32)
+            output stats(from: u32, count: u16, total: u8, lightest: u32, heaviest: u32)
+            stats(from, count(), sum(weight), min(weight), max(weight)) :-
+                edges(from, _, weight).
+        });
+        assert!(!expanded.contains("compile_error"), "{}", expanded);
+        // The aggregates are grouped by `from` and computed from the
+        // bindings of `from` and `weight`.
+        assert!(expanded.contains("__stats_body"));
+        assert!(expanded.contains(". entry ((from ,))"));
+        // The column given as `_` is kept in the body relation so that the
+        // edges with the same weight but different targets are all counted.
+        assert!(expanded.contains(
+            "__stats_body (from , __0_to , weight) : - \
+             edges (. from = from , . to = __0_to , . weight = weight)"
+        ));
+        assert!(expanded.contains("std :: cmp :: min"));
+        assert!(expanded.contains("std :: cmp :: max"));
+        // The aggregates that do not fit into their columns are reported.
+        assert!(expanded.contains("checked_add"));
+        assert!(expanded.contains(
+            "\"The aggregate in the column `count` of `stats` does not fit into `u16`.\""
+        ));
+        assert!(expanded.contains(
+            "\"The aggregate in the column `total` of `stats` does not fit into `u8`.\""
+        ));
+    }
+
+    #[test]
+    fn test_aggregation_with_other_rules() {
+        assert_compile_error(
+            quote! {
+                input edges(from: u32, to: u32)
+                output degrees(from: u32, degree: u32)
+                degrees(from, count()) :- edges(from, _).
+                degrees(from, to) :- edges(from, to).
+            },
+            "Relation `degrees` computes aggregates and cannot have other rules.",
+        );
+    }
+
+    #[test]
+    fn test_recursive_aggregation() {
+        assert_compile_error(
+            quote! {
+                input edges(from: u32, to: u32)
+                output degrees(from: u32, degree: u32)
+                degrees(from, count()) :- edges(from, to), degrees(to, _).
+            },
+            "Relation `degrees` recursively depends on its own aggregates.",
+        );
+    }
+
+    #[test]
+    fn test_unbound_aggregate_variables() {
+        assert_compile_error(
+            quote! {
+                input edges(from: u32, to: u32)
+                output weights(from: u32, weight: u32)
+                weights(from, sum(weight)) :- edges(from, _).
+            },
+            "Variable `weight` is not bound by the rule body.",
+        );
+        assert_compile_error(
+            quote! {
+                input edges(from: u32, to: u32)
+                output degrees(node: u32, degree: u32)
+                degrees(node, count()) :- edges(from, _).
+            },
+            "Variable `node` is not bound by the rule body.",
+        );
+    }
+
+    #[test]
+    fn test_negations() {
+        let expanded = expand(quote! {
+            input nodes(node: u32)
+            input edges(from: u32, to: u32)
+            output sinks(node: u32)
+            output loops(node: u32)
+            sinks(node) :- nodes(node), !edges(.from=node).
+            loops(node) :- nodes(node), !edges(node, node), !edges(_, node).
+        });
+        assert!(!expanded.contains("compile_error"), "{}", expanded);
+        // The named argument selects the column `from`.
+        assert!(expanded.contains(
+            "let __negated_0 : std :: collections :: HashSet < _ > = edges . iter () \
+             . map (| & (__key_0 , _ ,) | (__key_0 ,))"
+        ));
+        assert!(expanded.contains(
+            "__sinks_negation_0_body . iter () \
+             . filter (| && (node ,) | ! __negated_0 . contains (& (node ,)))"
+        ));
+        // The repeated variable matches only the facts whose columns are
+        // equal, and `_` matches any value.
+        assert!(expanded.contains(
+            "let __negated_0 : std :: collections :: HashSet < _ > = edges . iter () \
+             . map (| & (__key_0 , __key_1 ,) | (__key_0 , __key_1 ,))"
+        ));
+        assert!(expanded.contains(
+            "let __negated_1 : std :: collections :: HashSet < _ > = edges . iter () \
+             . map (| & (_ , __key_0 ,) | (__key_0 ,))"
+        ));
+        assert!(expanded.contains(
+            "! __negated_0 . contains (& (node , node ,)) && ! __negated_1 . contains (& (node ,))"
+        ));
+    }
+
+    #[test]
+    fn test_unbound_negated_variables() {
+        assert_compile_error(
+            quote! {
+                input nodes(node: u32)
+                input edges(from: u32, to: u32)
+                output sources(node: u32)
+                sources(node) :- nodes(node), !edges(from, node).
+            },
+            "Variable `from` must be bound by an atom that is not negated.",
+        );
+        assert_compile_error(
+            quote! {
+                input edges(from: u32, to: u32)
+                output unknown(node: u32)
+                unknown(node) :- !edges(node, _).
+            },
+            "A rule of `unknown` must have an atom that is not negated.",
+        );
+        assert_compile_error(
+            quote! {
+                input nodes(node: u32)
+                input edges(from: u32, to: u32)
+                output sources(node: u32)
+                sources(node) :- nodes(node), !edges(1, node).
+            },
+            "Negated atoms can contain only variables and `_`.",
+        );
+    }
+
+    #[test]
+    fn test_unstratified_negations() {
+        assert_compile_error(
+            quote! {
+                input nodes(node: u32)
+                output unmarked(node: u32)
+                unmarked(node) :- nodes(node), !unmarked(node).
+            },
+            "Relation `unmarked` cannot depend on its own negation.",
+        );
+        assert_compile_error(
+            quote! {
+                input nodes(node: u32)
+                output even(node: u32)
+                output odd(node: u32)
+                even(node) :- nodes(node), !odd(node).
+                odd(node) :- even(node).
+            },
+            "Relation `even` cannot depend on the negation of `odd`, which depends on `even`.",
+        );
+        assert_compile_error(
+            quote! {
+                input edges(from: u32, to: u32)
+                input blocked(node: u32)
+                output reachable(node: u32)
+                reachable(node) :- edges(0, node).
+                reachable(node) :- reachable(from), edges(from, node), !blocked(node).
+            },
+            "Relation `reachable` cannot be recursive because its rule contains a negation.",
+        );
+    }
 }
@@ -5,9 +5,17 @@ use std::path::Path;
 fn main() {
     println!("cargo:rerun-if-changed=src/schema.dl");
     println!("cargo:rerun-if-changed=src/derived.dl");
+    println!("cargo:rerun-if-changed=src/test_schema");
 
-    let definition = parse_schema(Path::new("src/schema.dl"), Path::new("src/derived.dl"));
     let out_dir = env::var("OUT_DIR").unwrap();
-    let dest_path = Path::new(&out_dir).join("schema.rs");
-    generate_definition(&dest_path, definition);
+    let definition = parse_schema(Path::new("src/schema.dl"), Path::new("src/derived.dl"));
+    generate_definition(&Path::new(&out_dir).join("schema.rs"), definition);
+    // The schemas used by the tests.
+    for (schema, file_name) in [
+        ("src/test_schema/schema.dl", "test_schema.rs"),
+        ("src/test_schema/schema_v0.dl", "test_schema_v0.rs"),
+    ] {
+        let definition = parse_schema(Path::new(schema), Path::new("src/test_schema/derived.dl"));
+        generate_definition(&Path::new(&out_dir).join(file_name), definition);
+    }
 }
@@ -0,0 +1,417 @@
+// Licensed under the MIT license <LICENSE or
+// http://opensource.org/licenses/MIT>. This file may not be copied,
+// modified, or distributed except according to those terms.
+
+//! The cache of relations loaded by the `Loader`.
+//!
+//! A loaded relation is shared between the cache and the `LoadedFacts`
+//! handles returned by `Loader::load_*`. A relation can be unloaded only when
+//! no handle is alive; the memory is freed when the last handle is dropped.
+//! Every load records a logical timestamp so that, when the loader has a
+//! memory budget, the least recently used relations are evicted first.
+//!
+//! The interning tables and indexes are kept until the loader is dropped.
+//! They cannot be evicted, but count toward the memory budget, so fewer
+//! relations stay cached once they are loaded.
+
+use crate::data_structures::Facts;
+use std::fmt;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// The facts of a relation loaded by the `Loader`. The relation is kept in
+/// memory at least until the handle is dropped.
+///
+/// The facts of a relation that has segments are stored in several chunks.
+/// The methods of the handle use the chunks directly; only dereferencing the
+/// handle into a slice concatenates them into memory.
+pub struct LoadedFacts<T> {
+    facts: Arc<Facts<T>>,
+}
+
+impl<T: Clone> LoadedFacts<T> {
+    pub fn len(&self) -> usize {
+        self.facts.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.facts.is_empty()
+    }
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.facts.get(index)
+    }
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.facts.iter()
+    }
+    /// The facts as consecutive slices, one for each chunk.
+    pub fn slices(&self) -> impl Iterator<Item = &[T]> {
+        self.facts.slices()
+    }
+    /// Copy the facts into a vector, for example, to pass them to Datapond.
+    pub fn to_vec(&self) -> Vec<T> {
+        self.iter().cloned().collect()
+    }
+}
+
+impl<T: Clone> Deref for LoadedFacts<T> {
+    type Target = [T];
+    fn deref(&self) -> &[T] {
+        &self.facts
+    }
+}
+
+/// A relation that is loaded on demand.
+pub(crate) struct CachedFacts<T> {
+    facts: Mutex<Option<Arc<Facts<T>>>>,
+    /// Held while loading the facts, so that the lock of `facts`, which is
+    /// also taken for evicting and reporting the memory usage, is not held
+    /// during the load.
+    loading: Mutex<()>,
+    last_use: AtomicU64,
+}
+
+impl<T> Default for CachedFacts<T> {
+    fn default() -> Self {
+        Self {
+            facts: Mutex::new(None),
+            loading: Mutex::new(()),
+            last_use: AtomicU64::new(0),
+        }
+    }
+}
+
+impl<T> CachedFacts<T> {
+    /// Get the cached facts, loading them with `load` if needed. If several
+    /// threads call this concurrently, the facts are loaded only once.
+    pub(crate) fn get_or_load(
+        &self,
+        clock: &AtomicU64,
+        load: impl FnOnce() -> Facts<T>,
+    ) -> LoadedFacts<T> {
+        let _loading = self.loading.lock().unwrap();
+        let cached = self.facts.lock().unwrap().clone();
+        let facts = cached.unwrap_or_else(|| {
+            let facts = Arc::new(load());
+            *self.facts.lock().unwrap() = Some(facts.clone());
+            facts
+        });
+        self.touch(clock);
+        LoadedFacts { facts }
+    }
+    /// Cache the facts that were just stored.
+    ///
+    /// **Panics** if the facts are already loaded.
+    pub(crate) fn set(&self, clock: &AtomicU64, new_facts: Facts<T>) {
+        let _loading = self.loading.lock().unwrap();
+        let mut facts = self.facts.lock().unwrap();
+        assert!(facts.is_none(), "the relation is already loaded");
+        *facts = Some(Arc::new(new_facts));
+        self.touch(clock);
+    }
+    pub(crate) fn is_loaded(&self) -> bool {
+        self.facts.lock().unwrap().is_some()
+    }
+    fn touch(&self, clock: &AtomicU64) {
+        let now = clock.fetch_add(1, Ordering::Relaxed) + 1;
+        self.last_use.store(now, Ordering::Relaxed);
+    }
+}
+
+/// The memory used by a relation cached by a `Loader`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RelationMemoryUsage {
+    /// The size in bytes of the facts held in memory.
+    pub owned: usize,
+    /// The size in bytes of the facts borrowed from memory-mapped files.
+    /// These pages are managed by the operating system and do not count
+    /// toward the memory budget of the loader.
+    pub mapped: usize,
+    /// The size in bytes of the facts borrowed from the tables merged from
+    /// the crates queried by the loader. The merged tables are kept until the
+    /// loader is dropped, so these facts do not count toward the budget
+    /// either.
+    pub shared: usize,
+    /// Whether the facts are currently borrowed.
+    pub borrowed: bool,
+}
+
+/// The type-erased interface of `CachedFacts` used for evicting relations.
+pub(crate) trait CachedRelation: Sync {
+    /// The memory used by the facts, or `None` if they are not loaded.
+    fn memory_usage(&self) -> Option<RelationMemoryUsage>;
+    /// The logical time of the last load.
+    fn last_use(&self) -> u64;
+    /// Drop the facts from the cache unless they are borrowed. Returns
+    /// whether the facts are not cached anymore.
+    fn unload(&self) -> bool;
+}
+
+impl<T: Clone + Send + Sync> CachedRelation for CachedFacts<T> {
+    fn memory_usage(&self) -> Option<RelationMemoryUsage> {
+        self.facts
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|facts| RelationMemoryUsage {
+                borrowed: Arc::strong_count(facts) > 1,
+                ..facts.memory_usage()
+            })
+    }
+    fn last_use(&self) -> u64 {
+        self.last_use.load(Ordering::Relaxed)
+    }
+    fn unload(&self) -> bool {
+        let mut facts = self.facts.lock().unwrap();
+        match &*facts {
+            Some(loaded) if Arc::strong_count(loaded) > 1 => false,
+            _ => {
+                *facts = None;
+                true
+            }
+        }
+    }
+}
+
+/// Evict the least recently used relations that are not borrowed until the
+/// facts held in memory fit into `budget` bytes. Memory-mapped and shared
+/// facts do not count toward the budget, so relations that own no facts are
+/// never evicted.
+pub(crate) fn evict_relations(relations: &[(&'static str, &dyn CachedRelation)], budget: usize) {
+    let mut loaded: Vec<_> = relations
+        .iter()
+        .filter_map(|&(name, relation)| {
+            relation
+                .memory_usage()
+                .filter(|usage| usage.owned > 0)
+                .map(|usage| (relation.last_use(), name, relation, usage))
+        })
+        .collect();
+    let mut total: usize = loaded.iter().map(|(_, _, _, usage)| usage.owned).sum();
+    loaded.sort_by_key(|&(last_use, ..)| last_use);
+    for (_, name, relation, usage) in loaded {
+        if total <= budget {
+            break;
+        }
+        if !usage.borrowed && relation.unload() {
+            log::info!("Evicting {} ({} bytes) from the loader", name, usage.owned);
+            total -= usage.owned;
+        }
+    }
+}
+
+/// The memory used by the relations, interning tables, and indexes cached by
+/// a `Loader`.
+#[derive(Debug, Default)]
+pub struct MemoryUsage {
+    /// The loaded relations with their memory usage, in the order of the
+    /// schema.
+    pub relations: Vec<(&'static str, RelationMemoryUsage)>,
+    /// The loaded interning tables and indexes with their memory usage. They
+    /// are kept until the loader is dropped.
+    pub pinned: Vec<(&'static str, RelationMemoryUsage)>,
+}
+
+impl MemoryUsage {
+    pub(crate) fn new(
+        relations: &[(&'static str, &dyn CachedRelation)],
+        pinned: Vec<(&'static str, RelationMemoryUsage)>,
+    ) -> Self {
+        Self {
+            relations: relations
+                .iter()
+                .filter_map(|&(name, relation)| relation.memory_usage().map(|usage| (name, usage)))
+                .collect(),
+            pinned,
+        }
+    }
+    fn all(&self) -> impl Iterator<Item = &RelationMemoryUsage> {
+        self.relations
+            .iter()
+            .chain(&self.pinned)
+            .map(|(_, usage)| usage)
+    }
+    /// The total size in bytes of the facts held in memory.
+    pub fn total(&self) -> usize {
+        self.all().map(|usage| usage.owned).sum()
+    }
+    /// The total size in bytes of the facts borrowed from memory-mapped
+    /// files.
+    pub fn mapped(&self) -> usize {
+        self.all().map(|usage| usage.mapped).sum()
+    }
+    /// The total size in bytes of the facts borrowed from the tables merged
+    /// from crates.
+    pub fn shared(&self) -> usize {
+        self.all().map(|usage| usage.shared).sum()
+    }
+}
+
+impl fmt::Display for MemoryUsage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (name, usage) in self.relations.iter().chain(&self.pinned) {
+            write!(f, "{}: {} bytes", name, usage.owned)?;
+            if usage.mapped > 0 {
+                write!(f, ", {} bytes mapped", usage.mapped)?;
+            }
+            if usage.shared > 0 {
+                write!(f, ", {} bytes shared", usage.shared)?;
+            }
+            if usage.borrowed {
+                write!(f, " (borrowed)")?;
+            }
+            writeln!(f)?;
+        }
+        writeln!(
+            f,
+            "Total: {} bytes, {} bytes mapped, {} bytes shared",
+            self.total(),
+            self.mapped(),
+            self.shared()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tables;
+    use crate::test_support::{create_build_tables, test_dir};
+
+    #[test]
+    fn test_parallel_loader() {
+        fn assert_sync<T: Sync>() {}
+        assert_sync::<tables::Loader>();
+        let database_root = test_dir("rust-corpus-parallel-loader-test");
+        create_build_tables("package", "1.0.0")
+            .store_multifile(&database_root)
+            .unwrap();
+        let loader = tables::Loader::new(database_root);
+        // All threads get the same facts, which are loaded only once.
+        let loaded: Vec<_> = std::thread::scope(|scope| {
+            let threads: Vec<_> = (0..4)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let statements = loader.load_statements_rows();
+                        let strings = loader.load_strings();
+                        (
+                            statements.as_ptr() as usize,
+                            statements.len(),
+                            strings as *const _ as usize,
+                        )
+                    })
+                })
+                .collect();
+            threads
+                .into_iter()
+                .map(|thread| thread.join().unwrap())
+                .collect()
+        });
+        assert!(loaded.iter().all(|&facts| facts == loaded[0]));
+        assert_eq!(loaded[0].1, 1);
+    }
+
+    #[test]
+    fn test_loader_memory_budget() {
+        let database_root = test_dir("rust-corpus-loader-memory-budget-test");
+        let mapped_root = database_root.join("mapped");
+        let owned_root = database_root.join("owned");
+        let tables = create_build_tables("package", "1.0.0");
+        tables.store_multifile(&mapped_root).unwrap();
+        tables
+            .store_multifile_with_format(&owned_root, tables::StorageFormat::Columnar)
+            .unwrap();
+        let loaded_relations = |loader: &tables::Loader| {
+            loader
+                .memory_usage()
+                .relations
+                .into_iter()
+                .map(|(name, usage)| (name, usage.borrowed))
+                .collect::<Vec<_>>()
+        };
+
+        // Memory-mapped facts do not count toward the budget, so they are
+        // not evicted.
+        let loader = tables::Loader::new(mapped_root).with_memory_budget(0);
+        let statements = loader.load_statements_rows();
+        let size = statements.len() * std::mem::size_of_val(&statements[0]);
+        drop(statements);
+        loader.load_basic_blocks_rows();
+        assert_eq!(
+            loaded_relations(&loader),
+            vec![("basic_blocks", false), ("statements", false)]
+        );
+        assert_eq!(loader.memory_usage().total(), 0);
+        assert!(loader.memory_usage().mapped() >= size);
+
+        let loader = tables::Loader::new(owned_root.clone());
+        let statements = loader.load_statements_rows();
+        assert_eq!(loaded_relations(&loader), vec![("statements", true)]);
+        assert_eq!(loader.memory_usage().total(), size);
+        assert_eq!(loader.memory_usage().mapped(), 0);
+        // Borrowed relations cannot be unloaded.
+        assert!(!loader.unload_statements());
+        drop(statements);
+        assert_eq!(loaded_relations(&loader), vec![("statements", false)]);
+        assert!(loader.unload_statements());
+        assert!(loaded_relations(&loader).is_empty());
+        assert_eq!(loader.load_statements_rows().len(), 1);
+        let basic_blocks = loader.load_basic_blocks_rows();
+        loader.unload_relations();
+        assert_eq!(loaded_relations(&loader), vec![("basic_blocks", true)]);
+        drop(basic_blocks);
+
+        // The least recently used relations that are not borrowed are
+        // evicted when the budget is exceeded.
+        let loader = tables::Loader::new(owned_root.clone()).with_memory_budget(0);
+        let statements = loader.load_statements_rows();
+        let basic_blocks = loader.load_basic_blocks_rows();
+        assert_eq!(
+            loaded_relations(&loader),
+            vec![("basic_blocks", true), ("statements", true)]
+        );
+        drop(statements);
+        let mir_cfgs = loader.load_mir_cfgs_rows();
+        assert_eq!(
+            loaded_relations(&loader),
+            vec![("mir_cfgs", true), ("basic_blocks", true)]
+        );
+        drop(basic_blocks);
+        drop(mir_cfgs);
+        // The relation that was just loaded is borrowed while evicting.
+        loader.load_statements_rows();
+        assert_eq!(loaded_relations(&loader), vec![("statements", false)]);
+
+        // The interning tables and indexes are never evicted, but count
+        // toward the budget.
+        let index_size = loader.group_basic_blocks_by_block().memory_usage();
+        let strings_size = loader.load_strings().memory_usage().owned;
+        assert!(index_size > 0 && strings_size > 0);
+        let usage = loader.memory_usage();
+        assert_eq!(
+            usage
+                .pinned
+                .iter()
+                .map(|&(name, usage)| (name, usage.owned))
+                .collect::<Vec<_>>(),
+            vec![
+                ("strings", strings_size),
+                ("basic_blocks_by_block_index", index_size)
+            ]
+        );
+        assert_eq!(loaded_relations(&loader), vec![("basic_blocks", false)]);
+        let basic_blocks_size = usage.relations[0].1.owned;
+        assert_eq!(usage.total(), basic_blocks_size + index_size + strings_size);
+
+        // Relations that fit into the budget alone are evicted once the
+        // interning tables are loaded.
+        let loader = tables::Loader::new(owned_root).with_memory_budget(size);
+        drop(loader.load_statements_rows());
+        loader.load_terminators_call_rows();
+        assert_eq!(
+            loaded_relations(&loader),
+            vec![("statements", false), ("terminators_call", false)]
+        );
+        loader.load_strings();
+        loader.load_terminators_call_rows();
+        assert_eq!(loaded_relations(&loader), vec![("terminators_call", false)]);
+    }
+}
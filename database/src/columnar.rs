@@ -0,0 +1,422 @@
+// Licensed under the MIT license <LICENSE or
+// http://opensource.org/licenses/MIT>. This file may not be copied,
+// modified, or distributed except according to those terms.
+
+//! A compressed columnar storage format for relations.
+//!
+//! Each column of a relation is stored separately. The values of a column
+//! are delta encoded (consecutive ids usually differ only by a small amount),
+//! written as variable length integers, and compressed with zstd.
+//!
+//! The `.rcc` file consists of the same header as the `.rc` files (see
+//! `storage::FileHeader`), the number of columns, the compressed size of each
+//! column, and the compressed columns.
+
+use crate::storage::{FileHeader, FileSchema, COLUMNAR_MAGIC};
+use anyhow::{bail, Context, Result};
+use log::trace;
+use std::io::{BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// A value that can be stored in a column of the columnar format.
+pub trait ColumnValue: Copy {
+    fn to_raw(self) -> u128;
+    fn from_raw(raw: u128) -> Self;
+}
+
+macro_rules! impl_column_value {
+    ($($typ:ty),*) => {
+        $(
+            impl ColumnValue for $typ {
+                fn to_raw(self) -> u128 {
+                    self as u128
+                }
+                fn from_raw(raw: u128) -> Self {
+                    raw as $typ
+                }
+            }
+        )*
+    };
+}
+
+impl_column_value!(u8, u16, u32, u64, u128);
+
+macro_rules! impl_signed_column_value {
+    ($($typ:ty),*) => {
+        $(
+            impl ColumnValue for $typ {
+                fn to_raw(self) -> u128 {
+                    // Sign extend so that small negative values have small
+                    // deltas.
+                    self as i128 as u128
+                }
+                fn from_raw(raw: u128) -> Self {
+                    raw as $typ
+                }
+            }
+        )*
+    };
+}
+
+impl_signed_column_value!(i64, i128);
+
+impl ColumnValue for bool {
+    fn to_raw(self) -> u128 {
+        self as u128
+    }
+    fn from_raw(raw: u128) -> Self {
+        raw != 0
+    }
+}
+
+impl ColumnValue for (u64, u64) {
+    fn to_raw(self) -> u128 {
+        ((self.0 as u128) << 64) | (self.1 as u128)
+    }
+    fn from_raw(raw: u128) -> Self {
+        ((raw >> 64) as u64, raw as u64)
+    }
+}
+
+/// `None` is stored as zero and `Some(value)` as the raw value plus one.
+/// Only ids and unsigned integers up to `u64` can be optional, so the raw
+/// value never overflows.
+impl<T: ColumnValue> ColumnValue for Option<T> {
+    fn to_raw(self) -> u128 {
+        match self {
+            Some(value) => value.to_raw() + 1,
+            None => 0,
+        }
+    }
+    fn from_raw(raw: u128) -> Self {
+        raw.checked_sub(1).map(T::from_raw)
+    }
+}
+
+/// Writes the columns of a relation one by one.
+pub struct ColumnarWriter {
+    len: usize,
+    columns: Vec<Vec<u8>>,
+}
+
+impl ColumnarWriter {
+    pub fn new(len: usize) -> Self {
+        Self {
+            len,
+            columns: Vec::new(),
+        }
+    }
+    /// Compress and add the next column.
+    pub fn write_column<T: ColumnValue>(&mut self, values: impl Iterator<Item = T>) -> Result<()> {
+        let mut encoder = zstd::Encoder::new(Vec::new(), zstd::DEFAULT_COMPRESSION_LEVEL)?;
+        let mut previous = 0u128;
+        let mut count = 0;
+        let mut buf = Vec::with_capacity(19);
+        for value in values {
+            let raw = value.to_raw();
+            buf.clear();
+            write_varint(&mut buf, zigzag_encode(raw.wrapping_sub(previous)));
+            encoder.write_all(&buf)?;
+            previous = raw;
+            count += 1;
+        }
+        assert_eq!(count, self.len, "All columns must have the same length");
+        self.columns.push(encoder.finish()?);
+        Ok(())
+    }
+    /// Write the columns into `path` (**with** the extension).
+    pub fn finish(self, schema: &FileSchema, path: &Path) -> Result<()> {
+        trace!("[enter] save_columnar({:?})", path);
+        let mut data = Vec::new();
+        data.extend_from_slice(&(self.columns.len() as u64).to_le_bytes());
+        for column in &self.columns {
+            data.extend_from_slice(&(column.len() as u64).to_le_bytes());
+        }
+        for column in &self.columns {
+            data.extend_from_slice(column);
+        }
+        let header =
+            FileHeader::encode(COLUMNAR_MAGIC, schema, 0, self.len, crc32fast::hash(&data));
+        let mut file = std::io::BufWriter::new(
+            std::fs::File::create(path).with_context(|| format!("Unable to create {:?}", path))?,
+        );
+        file.write_all(&header)?;
+        file.write_all(&data)?;
+        file.flush()?;
+        trace!("[exit] save_columnar({:?})", path);
+        Ok(())
+    }
+}
+
+/// Reads the columns of a relation.
+pub struct ColumnarReader {
+    path: PathBuf,
+    len: usize,
+    data: memmap2::Mmap,
+    /// The byte ranges of columns in `data`.
+    columns: Vec<(usize, usize)>,
+}
+
+impl ColumnarReader {
+    /// Open the columnar file `path` (**with** the extension).
+    pub fn open(schema: &FileSchema, path: &Path) -> Result<Self> {
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("Failed to open file: {:?}", path))?;
+        // SAFETY: the file is only read while decoding the columns.
+        let data = unsafe { memmap2::Mmap::map(&file) }
+            .with_context(|| format!("Failed to map file: {:?}", path))?;
+        let header = FileHeader::read(&mut &data[..], COLUMNAR_MAGIC, schema, 0, path)?;
+        header.check_checksum(&data[header.size..], schema, path)?;
+        let mut column_table = &data[header.size..];
+        let mut read_u64 = || -> Result<u64> {
+            let mut buf = [0u8; 8];
+            column_table.read_exact(&mut buf).with_context(|| {
+                format!(
+                    "The file {:?} of `{}` is truncated: the column table is incomplete",
+                    path, schema.name
+                )
+            })?;
+            Ok(u64::from_le_bytes(buf))
+        };
+        let column_count = read_u64()? as usize;
+        if column_count != schema.columns.len() {
+            bail!(
+                "The file {:?} of `{}` is corrupt: expected {} columns, found {}",
+                path,
+                schema.name,
+                schema.columns.len(),
+                column_count
+            );
+        }
+        let mut column_sizes = Vec::with_capacity(column_count);
+        for _ in 0..column_count {
+            column_sizes.push(read_u64()? as usize);
+        }
+        let mut start = header.size + 8 * (1 + column_count);
+        let mut columns = Vec::with_capacity(column_count);
+        for size in column_sizes {
+            columns.push((start, start.saturating_add(size)));
+            start = start.saturating_add(size);
+        }
+        header.check_data_size(data.len() - header.size, start - header.size, schema, path)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            len: header.len,
+            data,
+            columns,
+        })
+    }
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    /// Start decoding the column with the given index.
+    pub fn column<T: ColumnValue>(&self, index: usize) -> Result<ColumnDecoder<'_, T>> {
+        let (start, end) = self.columns[index];
+        let decoder = zstd::Decoder::with_buffer(&self.data[start..end])
+            .with_context(|| format!("Failed to decode column {} of {:?}", index, self.path))?;
+        Ok(ColumnDecoder {
+            decoder: BufReader::new(decoder),
+            previous: 0,
+            _marker: std::marker::PhantomData,
+        })
+    }
+}
+
+/// An iterator-like decoder of a single column.
+pub struct ColumnDecoder<'a, T> {
+    decoder: BufReader<zstd::Decoder<'a, &'a [u8]>>,
+    previous: u128,
+    _marker: std::marker::PhantomData<T>,
+}
+
+impl<'a, T: ColumnValue> ColumnDecoder<'a, T> {
+    pub fn next_value(&mut self) -> Result<T> {
+        let delta = zigzag_decode(read_varint(&mut self.decoder)?);
+        self.previous = self.previous.wrapping_add(delta);
+        Ok(T::from_raw(self.previous))
+    }
+}
+
+fn zigzag_encode(value: u128) -> u128 {
+    let value = value as i128;
+    ((value << 1) ^ (value >> 127)) as u128
+}
+
+fn zigzag_decode(value: u128) -> u128 {
+    (value >> 1) ^ (value & 1).wrapping_neg()
+}
+
+fn write_varint(buf: &mut Vec<u8>, mut value: u128) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn read_varint(reader: &mut impl Read) -> Result<u128> {
+    let mut value = 0u128;
+    let mut shift = 0;
+    loop {
+        let mut byte = [0u8];
+        reader
+            .read_exact(&mut byte)
+            .context("Unexpected end of a column")?;
+        if shift >= 128 {
+            bail!("Invalid variable length integer in a column");
+        }
+        value |= u128::from(byte[0] & 0x7f) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(value);
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{create_cleanup_tables, test_dir, test_schema};
+    use crate::{tables, types};
+
+    #[test]
+    fn test_zigzag() {
+        for value in [0u128, 1, 2, 5, u128::MAX, u128::MAX - 3, 1 << 100] {
+            assert_eq!(zigzag_decode(zigzag_encode(value)), value);
+        }
+        assert_eq!(zigzag_encode(1), 2);
+        assert_eq!(zigzag_encode(0u128.wrapping_sub(1)), 1);
+    }
+
+    #[test]
+    fn test_saving_and_loading_columns() {
+        let facts: Vec<(u64, bool, u16, u128)> = (0..10000u64)
+            .map(|i| {
+                (
+                    i,
+                    i % 3 == 0,
+                    (i % 17) as u16,
+                    u128::MAX - u128::from(i * i),
+                )
+            })
+            .collect();
+        let mut test_file = std::env::temp_dir();
+        test_file.push("rust-corpus-columnar-test.rcc");
+        let mut writer = ColumnarWriter::new(facts.len());
+        writer
+            .write_column(facts.iter().map(|fact| fact.0))
+            .unwrap();
+        writer
+            .write_column(facts.iter().map(|fact| fact.1))
+            .unwrap();
+        writer
+            .write_column(facts.iter().map(|fact| fact.2))
+            .unwrap();
+        writer
+            .write_column(facts.iter().map(|fact| fact.3))
+            .unwrap();
+        let schema = FileSchema {
+            name: "test_relation",
+            hash: 7,
+            columns: &[("a", "u64"), ("b", "bool"), ("c", "u16"), ("d", "u128")],
+        };
+        writer.finish(&schema, &test_file).unwrap();
+        let reader = ColumnarReader::open(&schema, &test_file).unwrap();
+        assert_eq!(reader.len(), facts.len());
+        let mut column0 = reader.column::<u64>(0).unwrap();
+        let mut column1 = reader.column::<bool>(1).unwrap();
+        let mut column2 = reader.column::<u16>(2).unwrap();
+        let mut column3 = reader.column::<u128>(3).unwrap();
+        for fact in &facts {
+            let loaded = (
+                column0.next_value().unwrap(),
+                column1.next_value().unwrap(),
+                column2.next_value().unwrap(),
+                column3.next_value().unwrap(),
+            );
+            assert_eq!(fact, &loaded);
+        }
+        assert!(column0.next_value().is_err());
+    }
+
+    #[test]
+    fn test_signed_and_optional_column_values() {
+        for value in [0i64, 1, -1, i64::MIN, i64::MAX] {
+            assert_eq!(i64::from_raw(value.to_raw()), value);
+        }
+        for value in [0i128, -5, i128::MIN, i128::MAX] {
+            assert_eq!(i128::from_raw(value.to_raw()), value);
+        }
+        assert_eq!((-1i64).to_raw(), u128::MAX);
+        for value in [None, Some(0u64), Some(7), Some(u64::MAX)] {
+            assert_eq!(Option::<u64>::from_raw(value.to_raw()), value);
+        }
+        assert_eq!(None::<u64>.to_raw(), 0);
+    }
+
+    #[test]
+    fn test_store_multifile_columnar() {
+        let mut tables = tables::Tables::default();
+        let build = tables.register_builds(
+            String::from("package1"),
+            String::from("version1"),
+            String::from("crate1"),
+            1usize.into(),
+            String::from("edition1"),
+        );
+        tables.register_build_crate_types(build, String::from("lib"));
+        let (root_module,) = tables.register_root_modules(build);
+        for i in 0..100u64 {
+            tables.register_spans(
+                types::Span::from(i as usize),
+                types::SpanExpansionKind::MacroBang,
+                String::from("expansion"),
+                String::from("file.rs"),
+                i as u16,
+                (i * 2) as u16,
+            );
+        }
+        let database_root = test_dir("rust-corpus-columnar-database-test");
+        tables
+            .store_multifile_with_format(&database_root, tables::StorageFormat::Columnar)
+            .unwrap();
+        assert!(database_root.join("relations/spans.rcc").exists());
+        assert!(!database_root.join("relations/spans.rc").exists());
+        let loaded = tables::Tables::load_multifile(&database_root).unwrap();
+        assert_eq!(
+            loaded.relations.root_modules.iter().collect::<Vec<_>>(),
+            vec![&(build, root_module)]
+        );
+        assert_eq!(loaded.relations.spans.len(), 100);
+        for (actual, expected) in loaded
+            .relations
+            .spans
+            .iter()
+            .zip(tables.relations.spans.iter())
+        {
+            assert_eq!(actual, expected);
+        }
+        let loader = tables::Loader::new(database_root);
+        assert_eq!(loader.load_build_crate_types().len(), 1);
+    }
+
+    #[test]
+    fn test_store_optional_ids() {
+        let tables = create_cleanup_tables();
+        let expected: Vec<_> = tables.relations.terminators.iter().collect();
+        for format in [tables::StorageFormat::Raw, tables::StorageFormat::Columnar] {
+            let database_root = test_dir(&format!("rust-corpus-optional-ids-test-{:?}", format));
+            tables
+                .store_multifile_with_format(&database_root, format)
+                .unwrap();
+            let loaded = test_schema::tables::Tables::load_multifile(&database_root).unwrap();
+            let actual: Vec<_> = loaded.relations.terminators.iter().collect();
+            assert_eq!(actual, expected);
+            let loader = test_schema::tables::Loader::new(database_root);
+            assert_eq!(
+                loader.load_terminators().iter().collect::<Vec<_>>(),
+                expected
+            );
+        }
+    }
+}
@@ -0,0 +1,130 @@
+// Licensed under the MIT license <LICENSE or
+// http://opensource.org/licenses/MIT>. This file may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Querying the crates saved by the extractor without building a database.
+//!
+//! The crates are loaded and merged in memory, exactly as `update-database`
+//! would merge them, when the first relation or interning table is loaded.
+//! The loaded relations and interning tables borrow their facts from the
+//! merged tables instead of copying them. The derived relations stored by
+//! queries are written into a separate directory.
+
+use crate::merge::{load_in_parallel, merge_in_parallel};
+use crate::tables::{Loader, Tables};
+use anyhow::{Context, Result};
+use log::info;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// The crates queried by a `Loader` and their merged tables.
+pub(crate) struct CrateSet<T> {
+    crate_files: Vec<PathBuf>,
+    /// Loads and merges the crates.
+    merge: fn(&[PathBuf]) -> Result<T>,
+    tables: OnceLock<Arc<T>>,
+    /// Ensures that the crates are merged only once.
+    merge_lock: Mutex<()>,
+}
+
+impl<T> CrateSet<T> {
+    /// The merged tables of all crates.
+    pub(crate) fn tables(&self) -> Result<&Arc<T>> {
+        let _guard = self.merge_lock.lock().unwrap();
+        if let Some(tables) = self.tables.get() {
+            return Ok(tables);
+        }
+        let tables = (self.merge)(&self.crate_files)?;
+        Ok(self.tables.get_or_init(|| Arc::new(tables)))
+    }
+}
+
+/// Load the crates saved by the extractor into `crate_files` and merge them.
+fn merge_crates(crate_files: &[PathBuf]) -> Result<Tables> {
+    info!("Merging {} crates", crate_files.len());
+    let jobs = std::thread::available_parallelism().map_or(1, |jobs| jobs.get());
+    let mut loaded = Vec::with_capacity(crate_files.len());
+    for (path, tables) in crate_files
+        .iter()
+        .zip(load_in_parallel(crate_files.to_vec(), jobs))
+    {
+        loaded.push(tables.with_context(|| format!("Failed to load the crate {:?}", path))?);
+    }
+    Ok(merge_in_parallel(loaded, jobs))
+}
+
+impl Loader {
+    /// Create a loader that queries the crates saved by the extractor into
+    /// `crate_files` instead of a database. The crates are merged in memory
+    /// on the first load; the derived relations are stored into and loaded
+    /// from `derived_root`.
+    ///
+    /// Note that the merged tables are kept in memory until the loader is
+    /// dropped; the loaded relations borrow their facts and thus do not count
+    /// toward the memory budget.
+    pub fn from_crates(crate_files: Vec<PathBuf>, derived_root: PathBuf) -> Self {
+        let mut loader = Loader::new(derived_root);
+        loader.crates = Some(CrateSet {
+            crate_files,
+            merge: merge_crates,
+            tables: OnceLock::new(),
+            merge_lock: Mutex::new(()),
+        });
+        loader
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::{create_crate_tables, test_dir};
+    use crate::{tables, types};
+
+    #[test]
+    fn test_crate_set_loader() {
+        let temp_dir = test_dir("rust-corpus-crate-set-test");
+        std::fs::create_dir_all(&temp_dir).unwrap();
+        let mut paths = Vec::new();
+        let mut merger = tables::TableMerger::new(tables::Tables::default());
+        for krate in &["crate1", "crate2", "crate3"] {
+            let path = temp_dir.join(krate);
+            create_crate_tables(krate).save_bincode(path.clone());
+            paths.push(path.with_extension("bincode"));
+            merger.merge(create_crate_tables(krate));
+        }
+        let merged = merger.tables();
+        let derived_root = temp_dir.join("derived");
+        let loader = tables::Loader::from_crates(paths, derived_root.clone());
+        assert_eq!(
+            &*loader.load_statements(),
+            merged.relations.statements.facts.as_slice()
+        );
+        assert_eq!(
+            &*loader.load_basic_blocks(),
+            merged.relations.basic_blocks.facts.as_slice()
+        );
+        assert_eq!(
+            loader.load_strings().iter_values().collect::<Vec<_>>(),
+            merged
+                .interning_tables
+                .strings
+                .iter_values()
+                .collect::<Vec<_>>()
+        );
+        assert!(loader.check_integrity().unwrap().is_ok());
+        // The interning tables borrow the values of the merged tables, while
+        // the relations are copied into row structs.
+        let memory_usage = loader.memory_usage();
+        assert!(memory_usage.total() > 0);
+        assert!(memory_usage.shared() > 0);
+        // The derived relations are stored next to the crates.
+        let selected_modules = vec![(types::Build::from(0usize), types::Module::from(1usize))];
+        loader.store_selected_modules(selected_modules.clone());
+        assert!(derived_root.join("relations/selected_modules.rc").exists());
+        let loader = tables::Loader::from_crates(Vec::new(), derived_root);
+        assert_eq!(
+            &*loader.load_selected_modules(),
+            selected_modules.as_slice()
+        );
+        assert!(loader.load_statements().is_empty());
+    }
+}
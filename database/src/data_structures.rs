@@ -4,8 +4,14 @@
 
 //! The implementation of interning tables and relations.
 
+use crate::cache::RelationMemoryUsage;
+use crate::storage::MappedFacts;
+use serde::{Serialize, Serializer};
 use serde_derive::{Deserialize, Serialize};
+use std::any::Any;
 use std::collections::HashMap;
+use std::ops::Deref;
+use std::sync::{Arc, OnceLock};
 
 #[derive(Deserialize, Serialize)]
 /// A table that expresses a relation between elements.
@@ -46,6 +52,191 @@ impl<T> From<Vec<T>> for Relation<T> {
     }
 }
 
+/// Facts that are either owned by us or borrowed from a memory-mapped file.
+pub enum Facts<T> {
+    Owned(Vec<T>),
+    Mapped(MappedFacts<T>),
+    Chunked(ChunkedFacts<T>),
+    Shared(SharedFacts<T>),
+}
+
+/// Facts that are stored in several chunks, for example, in the base and the
+/// segments of a database. Iterating over the facts with `Facts::iter` or
+/// `Facts::slices`, indexing them with `Facts::get`, and taking their length
+/// use the chunks directly. Only accessing the facts as a single slice
+/// concatenates the chunks into memory.
+pub struct ChunkedFacts<T> {
+    /// The chunks, none of which is chunked itself.
+    chunks: Vec<Facts<T>>,
+    concatenated: OnceLock<Vec<T>>,
+}
+
+/// Facts borrowed from a value that is shared with other users, for example,
+/// from the tables merged from the crates queried by a `Loader`.
+pub struct SharedFacts<T> {
+    /// Keeps the facts alive.
+    _owner: Arc<dyn Any + Send + Sync>,
+    facts: *const [T],
+}
+
+// SAFETY: `SharedFacts` gives only shared access to the facts, which are kept
+// alive by the owner.
+unsafe impl<T: Sync> Send for SharedFacts<T> {}
+unsafe impl<T: Sync> Sync for SharedFacts<T> {}
+
+impl<T> Default for Facts<T> {
+    fn default() -> Self {
+        Facts::Owned(Vec::new())
+    }
+}
+
+impl<T> Facts<T> {
+    /// Borrow the facts selected by `select` from `owner` without copying
+    /// them. The owner is kept alive until the facts are dropped.
+    pub fn shared<O: Any + Send + Sync>(owner: Arc<O>, select: impl FnOnce(&O) -> &[T]) -> Self {
+        let facts: *const [T] = select(&owner);
+        Facts::Shared(SharedFacts {
+            _owner: owner,
+            facts,
+        })
+    }
+}
+
+impl<T: Clone> Deref for Facts<T> {
+    type Target = [T];
+    fn deref(&self) -> &[T] {
+        match self {
+            Facts::Owned(facts) => facts,
+            Facts::Mapped(facts) => facts,
+            Facts::Chunked(facts) => facts.concatenated.get_or_init(|| {
+                facts
+                    .chunks
+                    .iter()
+                    .flat_map(|chunk| chunk.iter())
+                    .cloned()
+                    .collect()
+            }),
+            // SAFETY: the facts were borrowed from the owner, which is
+            // immutable and kept alive by `facts`.
+            Facts::Shared(facts) => unsafe { &*facts.facts },
+        }
+    }
+}
+
+impl<T: Clone> Facts<T> {
+    /// Concatenate the facts without copying them. Empty chunks are dropped
+    /// and chunked facts are flattened.
+    pub fn concat(chunks: Vec<Facts<T>>) -> Self {
+        let mut chunks: Vec<_> = chunks
+            .into_iter()
+            .flat_map(|chunk| match chunk {
+                Facts::Chunked(facts) => facts.chunks,
+                chunk => vec![chunk],
+            })
+            .filter(|chunk| !chunk.is_empty())
+            .collect();
+        match chunks.len() {
+            0 => Facts::default(),
+            1 => chunks.pop().unwrap(),
+            _ => Facts::Chunked(ChunkedFacts {
+                chunks,
+                concatenated: OnceLock::new(),
+            }),
+        }
+    }
+    /// The chunks of the facts. Only chunked facts have more than one chunk.
+    fn chunks(&self) -> &[Facts<T>] {
+        match self {
+            Facts::Chunked(facts) => &facts.chunks,
+            _ => std::slice::from_ref(self),
+        }
+    }
+    pub fn len(&self) -> usize {
+        match self {
+            Facts::Chunked(facts) => facts.chunks.iter().map(|chunk| chunk.len()).sum(),
+            _ => self.deref().len(),
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Get the fact at `index` without concatenating the chunks.
+    pub fn get(&self, mut index: usize) -> Option<&T> {
+        for chunk in self.chunks() {
+            if index < chunk.len() {
+                return chunk.deref().get(index);
+            }
+            index -= chunk.len();
+        }
+        None
+    }
+    /// Iterate over the facts without concatenating the chunks.
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.slices().flat_map(|slice| slice.iter())
+    }
+    /// The facts as consecutive slices, one for each chunk.
+    pub fn slices(&self) -> impl Iterator<Item = &[T]> {
+        self.chunks().iter().map(|chunk| chunk.deref())
+    }
+    /// The memory used by the facts. The facts are not borrowed by anyone.
+    pub fn memory_usage(&self) -> RelationMemoryUsage {
+        let size = self.len() * std::mem::size_of::<T>();
+        let mut usage = RelationMemoryUsage::default();
+        match self {
+            Facts::Owned(_) => usage.owned = size,
+            Facts::Mapped(_) => usage.mapped = size,
+            Facts::Shared(_) => usage.shared = size,
+            Facts::Chunked(facts) => {
+                usage.owned = facts
+                    .concatenated
+                    .get()
+                    .map_or(0, |facts| facts.len() * std::mem::size_of::<T>());
+                for chunk in &facts.chunks {
+                    let chunk_usage = chunk.memory_usage();
+                    usage.owned += chunk_usage.owned;
+                    usage.mapped += chunk_usage.mapped;
+                    usage.shared += chunk_usage.shared;
+                }
+            }
+        }
+        usage
+    }
+    /// Get a mutable vector of facts. If the facts are not owned by us, they
+    /// are copied into memory first.
+    pub fn make_mut(&mut self) -> &mut Vec<T> {
+        if !matches!(self, Facts::Owned(_)) {
+            *self = Facts::Owned(std::mem::take(self).into_vec());
+        }
+        match self {
+            Facts::Owned(facts) => facts,
+            _ => unreachable!(),
+        }
+    }
+    pub fn into_vec(self) -> Vec<T> {
+        match self {
+            Facts::Owned(facts) => facts,
+            Facts::Mapped(facts) => facts.to_vec(),
+            Facts::Shared(_) => self.to_vec(),
+            Facts::Chunked(facts) => match facts.concatenated.into_inner() {
+                Some(concatenated) => concatenated,
+                None => facts.chunks.into_iter().flat_map(Facts::into_vec).collect(),
+            },
+        }
+    }
+}
+
+impl<T> From<Vec<T>> for Facts<T> {
+    fn from(facts: Vec<T>) -> Self {
+        Facts::Owned(facts)
+    }
+}
+
+impl<T: Clone + Serialize> Serialize for Facts<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.deref().serialize(serializer)
+    }
+}
+
 pub trait InterningTableKey: Copy + Eq + std::hash::Hash + From<usize> + Into<usize> {}
 impl<T> InterningTableKey for T where T: Copy + Eq + std::hash::Hash + From<usize> + Into<usize> {}
 pub trait InterningTableValue: Eq + std::hash::Hash + Clone {}
@@ -59,9 +250,11 @@ where
     K: InterningTableKey,
     V: InterningTableValue,
 {
-    pub(crate) contents: Vec<V>,
+    pub(crate) contents: Facts<V>,
+    /// The inverse of `contents`. It is computed only when needed because
+    /// read-only users such as queries typically do not need it.
     #[serde(skip_serializing)]
-    inv_contents: HashMap<V, K>,
+    inv_contents: OnceLock<HashMap<V, K>>,
 }
 
 impl<K, V> Default for InterningTable<K, V>
@@ -71,8 +264,8 @@ where
 {
     fn default() -> Self {
         Self {
-            contents: Vec::new(),
-            inv_contents: HashMap::new(),
+            contents: Facts::default(),
+            inv_contents: OnceLock::new(),
         }
     }
 }
@@ -83,14 +276,19 @@ where
     V: InterningTableValue,
 {
     fn from(contents: Vec<V>) -> Self {
-        let inv_contents = contents
-            .iter()
-            .enumerate()
-            .map(|(k, v)| (v.clone(), k.into()))
-            .collect();
+        Facts::Owned(contents).into()
+    }
+}
+
+impl<K, V> From<Facts<V>> for InterningTable<K, V>
+where
+    K: InterningTableKey,
+    V: InterningTableValue,
+{
+    fn from(contents: Facts<V>) -> Self {
         Self {
             contents,
-            inv_contents,
+            inv_contents: OnceLock::new(),
         }
     }
 }
@@ -100,21 +298,42 @@ where
     K: InterningTableKey,
     V: InterningTableValue,
 {
+    fn inv_contents(&self) -> &HashMap<V, K> {
+        self.inv_contents.get_or_init(|| {
+            self.contents
+                .iter()
+                .enumerate()
+                .map(|(k, v)| (v.clone(), k.into()))
+                .collect()
+        })
+    }
     pub(crate) fn intern(&mut self, value: V) -> K {
-        if self.inv_contents.contains_key(&value) {
-            self.inv_contents[&value]
+        self.inv_contents();
+        let inv_contents = self.inv_contents.get_mut().unwrap();
+        if let Some(key) = inv_contents.get(&value) {
+            *key
         } else {
             let new_key = self.contents.len().into();
-            self.inv_contents.insert(value.clone(), new_key);
-            self.contents.push(value);
+            inv_contents.insert(value.clone(), new_key);
+            self.contents.make_mut().push(value);
             new_key
         }
     }
     pub fn iter_values(&self) -> impl Iterator<Item = &V> {
         self.contents.iter()
     }
+    /// The memory used by the values and, if it was computed, the inverse
+    /// table.
+    pub fn memory_usage(&self) -> RelationMemoryUsage {
+        let mut usage = self.contents.memory_usage();
+        if let Some(inv_contents) = self.inv_contents.get() {
+            usage.owned += inv_contents.len() * std::mem::size_of::<(V, K)>();
+        }
+        usage
+    }
     pub fn into_iter(self) -> impl Iterator<Item = (K, V)> {
         self.contents
+            .into_vec()
             .into_iter()
             .enumerate()
             .map(|(k, v)| (k.into(), v))
@@ -122,6 +341,16 @@ where
     pub fn len(&self) -> usize {
         self.contents.len()
     }
+    /// Append the values of `other` that were interned after the values of
+    /// `self`, for example, by a later segment of the database.
+    pub(crate) fn append(&mut self, other: Self) {
+        self.contents = Facts::concat(vec![std::mem::take(&mut self.contents), other.contents]);
+        self.inv_contents = OnceLock::new();
+    }
+    /// A table that contains only the values starting with index `start`.
+    pub(crate) fn values_from(&self, start: usize) -> Self {
+        self.contents[start..].to_vec().into()
+    }
 }
 
 impl<K> InterningTable<K, String>
@@ -129,7 +358,7 @@ where
     K: InterningTableKey,
 {
     pub fn lookup_str(&self, value: &str) -> Option<K> {
-        self.inv_contents.get(value).cloned()
+        self.inv_contents().get(value).cloned()
     }
 }
 
@@ -139,7 +368,7 @@ where
     V: InterningTableValue,
 {
     pub fn lookup(&self, value: &V) -> Option<K> {
-        self.inv_contents.get(value).cloned()
+        self.inv_contents().get(value).cloned()
     }
 }
 
@@ -151,7 +380,9 @@ where
     type Output = V;
     fn index(&self, key: K) -> &Self::Output {
         let index: usize = key.into();
-        &self.contents[index]
+        self.contents
+            .get(index)
+            .unwrap_or_else(|| panic!("The key {} is not interned", index))
     }
 }
 
@@ -162,9 +393,38 @@ where
 {
     fn into(self) -> Vec<(K, V)> {
         self.contents
+            .into_vec()
             .into_iter()
             .enumerate()
             .map(|(i, v)| (i.into(), v))
             .collect()
     }
 }
+
+/// Implement the conversion of interning tables whose values are tuples into
+/// vectors of tuples that start with the key.
+macro_rules! impl_into_flat_vec {
+    ($($value:ident: $type_arg:ident),*) => {
+        impl<K, $($type_arg),*> Into<Vec<(K, $($type_arg),*)>> for InterningTable<K, ($($type_arg,)*)>
+        where
+            K: InterningTableKey,
+            $($type_arg: InterningTableValue),*
+        {
+            fn into(self) -> Vec<(K, $($type_arg),*)> {
+                self.contents
+                    .into_vec()
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, ($($value,)*))| (i.into(), $($value),*))
+                    .collect()
+            }
+        }
+    };
+}
+
+impl_into_flat_vec!(v0: V0);
+impl_into_flat_vec!(v0: V0, v1: V1);
+impl_into_flat_vec!(v0: V0, v1: V1, v2: V2);
+impl_into_flat_vec!(v0: V0, v1: V1, v2: V2, v3: V3);
+impl_into_flat_vec!(v0: V0, v1: V1, v2: V2, v3: V3, v4: V4);
+impl_into_flat_vec!(v0: V0, v1: V1, v2: V2, v3: V3, v4: V4, v5: V5);
@@ -0,0 +1,329 @@
+// Licensed under the MIT license <LICENSE or
+// http://opensource.org/licenses/MIT>. This file may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Comparing two databases.
+//!
+//! The interned ids of two databases are unrelated, so the builds and the
+//! definitions are compared by their resolved names. The facts of each core
+//! relation are attributed to builds by following the ownership chain encoded
+//! in the schema and counted per build.
+
+use crate::dump::{DumpFormat, RowWriter};
+use crate::tables::{IdOwners, Tables};
+use crate::types::{Build, DefPath};
+use anyhow::{Context, Result};
+use log::info;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::path::Path;
+
+/// A build identified by its resolved package, version, crate name, and
+/// crate hash.
+type BuildKey = (String, String, String, u64);
+
+/// A definition identified by the package, version, and crate name of its
+/// build and its resolved relative definition path. The crate hash is not
+/// part of the key so that the definitions of the same package version
+/// compiled by different toolchains are compared with each other.
+type DefinitionKey = (String, String, String, String);
+
+/// The size of the MIR body of a definition.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct BodySize {
+    basic_blocks: usize,
+    statements: usize,
+}
+
+/// The information about a database that is compared.
+struct Summary {
+    builds: BTreeSet<BuildKey>,
+    /// The number of facts of each relation per build. Facts that could not
+    /// be attributed to any build are counted under `None`.
+    fact_counts: Vec<(&'static str, HashMap<Option<BuildKey>, usize>)>,
+    definitions: HashMap<DefinitionKey, BodySize>,
+}
+
+impl Summary {
+    fn new(database_root: &Path) -> Result<Self> {
+        info!("Loading {:?}", database_root);
+        let tables = Tables::load_multifile(database_root)?;
+        let strings = &tables.interning_tables.strings;
+        let package_names = &tables.interning_tables.package_names;
+        let package_versions = &tables.interning_tables.package_versions;
+        let crate_names = &tables.interning_tables.crate_names;
+        let relative_def_paths = &tables.interning_tables.relative_def_paths;
+        let mut build_keys = Vec::new();
+        let mut crate_builds = HashMap::new();
+        for (build, &(package, version, krate, crate_hash, _edition)) in
+            tables.interning_tables.builds.iter_values().enumerate()
+        {
+            build_keys.push((
+                strings[package_names[package]].clone(),
+                strings[package_versions[version]].clone(),
+                strings[crate_names[krate]].clone(),
+                crate_hash.index() as u64,
+            ));
+            crate_builds
+                .entry((krate, crate_hash))
+                .or_insert_with(|| Build::from(build));
+        }
+
+        let mut owners = IdOwners::new(&tables.counters);
+        for build in 0..build_keys.len() {
+            owners.builds.insert(Build::from(build), Build::from(build));
+        }
+        tables.propagate_owners(&mut owners);
+        // Def paths are shared between builds, so they are used only for the
+        // facts that could not be attributed by following incremental ids.
+        // Such facts belong to the build of the crate that defines the def
+        // path.
+        let mut def_path_builds = HashMap::new();
+        for (def_path, &(krate, crate_hash, _, _, _)) in
+            tables.interning_tables.def_paths.iter_values().enumerate()
+        {
+            if let Some(&build) = crate_builds.get(&(krate, crate_hash)) {
+                def_path_builds.insert(DefPath::from(def_path), build);
+            }
+        }
+        owners.def_paths = def_path_builds.clone();
+        tables.propagate_owners(&mut owners);
+        let fact_counts = tables
+            .count_facts_by_owner(&owners)
+            .into_iter()
+            .map(|(relation, counts)| {
+                let counts = counts
+                    .into_iter()
+                    .map(|(build, count)| {
+                        (build.map(|build| build_keys[build.index()].clone()), count)
+                    })
+                    .collect();
+                (relation, counts)
+            })
+            .collect();
+
+        let mut body_sizes: HashMap<DefPath, BodySize> = HashMap::new();
+        let mut block_bodies = HashMap::new();
+        for &(block, mir, _kind) in tables.relations.basic_blocks.iter() {
+            body_sizes.entry(mir).or_default().basic_blocks += 1;
+            block_bodies.insert(block, mir);
+        }
+        for &(_stmt, block, _index, _kind, _scope) in tables.relations.statements.iter() {
+            if let Some(mir) = block_bodies.get(&block) {
+                body_sizes.entry(*mir).or_default().statements += 1;
+            }
+        }
+        let mut definitions = HashMap::new();
+        for (def_path, &(_, _, relative_def_path, _, _)) in
+            tables.interning_tables.def_paths.iter_values().enumerate()
+        {
+            let def_path = DefPath::from(def_path);
+            if let Some(build) = def_path_builds.get(&def_path) {
+                let (package, version, krate, _) = build_keys[build.index()].clone();
+                let key = (
+                    package,
+                    version,
+                    krate,
+                    strings[relative_def_paths[relative_def_path]].clone(),
+                );
+                let size = body_sizes.get(&def_path).copied().unwrap_or_default();
+                definitions.entry(key).or_insert(size);
+            }
+        }
+        Ok(Self {
+            builds: build_keys.into_iter().collect(),
+            fact_counts,
+            definitions,
+        })
+    }
+}
+
+fn create_csv_file(output_dir: &Path, name: &str) -> Result<fs::File> {
+    let path = output_dir.join(format!("{}.csv", name));
+    fs::File::create(&path).with_context(|| format!("Unable to create {:?}", path))
+}
+
+fn build_values(build: &Option<BuildKey>) -> Vec<Value> {
+    match build {
+        Some((package, version, krate, crate_hash)) => vec![
+            Value::from(package.as_str()),
+            Value::from(version.as_str()),
+            Value::from(krate.as_str()),
+            Value::from(format!("{:016x}", crate_hash)),
+        ],
+        None => vec![Value::from(""); 4],
+    }
+}
+
+fn build_columns() -> Vec<String> {
+    ["package", "version", "crate", "crate_hash"]
+        .iter()
+        .map(|column| column.to_string())
+        .collect()
+}
+
+/// Compare the databases at `database_a` and `database_b` and write the
+/// differences as CSV files into `output_dir`:
+///
+/// * `builds.csv` lists the builds that were added, removed, or are present
+///   in both databases;
+/// * `<relation>.csv` contains, for each core relation, the number of facts
+///   of each build in both databases;
+/// * `definitions.csv` lists the definitions that were added, removed, or
+///   whose MIR body changed its number of basic blocks or statements.
+pub fn diff_databases(database_a: &Path, database_b: &Path, output_dir: &Path) -> Result<()> {
+    let summary_a = Summary::new(database_a)?;
+    let summary_b = Summary::new(database_b)?;
+    fs::create_dir_all(output_dir).with_context(|| format!("Unable to create {:?}", output_dir))?;
+
+    let mut file = create_csv_file(output_dir, "builds")?;
+    let mut columns = build_columns();
+    columns.push("status".to_string());
+    let mut writer = RowWriter::new(DumpFormat::Csv, columns, &mut file)?;
+    for build in summary_a.builds.union(&summary_b.builds) {
+        let status = match (
+            summary_a.builds.contains(build),
+            summary_b.builds.contains(build),
+        ) {
+            (true, true) => "unchanged",
+            (true, false) => "removed",
+            _ => "added",
+        };
+        let mut row = build_values(&Some(build.clone()));
+        row.push(Value::from(status));
+        writer.write(row)?;
+    }
+    writer.finish()?;
+
+    for ((relation, counts_a), (_, counts_b)) in
+        summary_a.fact_counts.iter().zip(&summary_b.fact_counts)
+    {
+        let mut counts: BTreeMap<_, (usize, usize)> = BTreeMap::new();
+        for (build, count) in counts_a {
+            counts.entry(build).or_default().0 = *count;
+        }
+        for (build, count) in counts_b {
+            counts.entry(build).or_default().1 = *count;
+        }
+        let mut file = create_csv_file(output_dir, relation)?;
+        let mut columns = build_columns();
+        columns.push("facts_a".to_string());
+        columns.push("facts_b".to_string());
+        let mut writer = RowWriter::new(DumpFormat::Csv, columns, &mut file)?;
+        for (build, (count_a, count_b)) in counts {
+            let mut row = build_values(build);
+            row.push(Value::from(count_a));
+            row.push(Value::from(count_b));
+            writer.write(row)?;
+        }
+        writer.finish()?;
+    }
+
+    let mut file = create_csv_file(output_dir, "definitions")?;
+    let columns = [
+        "package",
+        "version",
+        "crate",
+        "def_path",
+        "status",
+        "basic_blocks_a",
+        "basic_blocks_b",
+        "statements_a",
+        "statements_b",
+    ]
+    .iter()
+    .map(|column| column.to_string())
+    .collect();
+    let mut writer = RowWriter::new(DumpFormat::Csv, columns, &mut file)?;
+    let keys: BTreeSet<_> = summary_a
+        .definitions
+        .keys()
+        .chain(summary_b.definitions.keys())
+        .collect();
+    let mut changed_count = 0;
+    for key in keys {
+        let size_a = summary_a.definitions.get(key);
+        let size_b = summary_b.definitions.get(key);
+        let status = match (size_a, size_b) {
+            (Some(a), Some(b)) if a == b => continue,
+            (Some(_), Some(_)) => "changed",
+            (Some(_), None) => "removed",
+            _ => "added",
+        };
+        changed_count += 1;
+        let size_a = size_a.copied().unwrap_or_default();
+        let size_b = size_b.copied().unwrap_or_default();
+        let (package, version, krate, def_path) = key;
+        writer.write(vec![
+            Value::from(package.as_str()),
+            Value::from(version.as_str()),
+            Value::from(krate.as_str()),
+            Value::from(def_path.as_str()),
+            Value::from(status),
+            Value::from(size_a.basic_blocks),
+            Value::from(size_b.basic_blocks),
+            Value::from(size_a.statements),
+            Value::from(size_b.statements),
+        ])?;
+    }
+    writer.finish()?;
+    info!("{} definitions differ", changed_count);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::diff_databases;
+    use crate::tables;
+    use crate::test_support::{create_build_tables, test_dir};
+
+    #[test]
+    fn test_diff_databases() {
+        let temp_dir = test_dir("rust-corpus-diff-test");
+        let store = |name: &str, builds: &[(&str, &str)]| {
+            let mut merger = tables::TableMerger::new(tables::Tables::default());
+            for (package, version) in builds {
+                merger.merge(create_build_tables(package, version));
+            }
+            let database_root = temp_dir.join(name);
+            merger
+                .store_segment(&database_root, tables::StorageFormat::Raw)
+                .unwrap();
+            crate::store_schema_version(&database_root);
+            database_root
+        };
+        let database_a = store("a", &[("kept", "1.0.0"), ("removed", "1.0.0")]);
+        let database_b = store("b", &[("added", "1.0.0"), ("kept", "1.0.0")]);
+        let output_dir = temp_dir.join("diff");
+        diff_databases(&database_a, &database_b, &output_dir).unwrap();
+        let read = |name: &str| std::fs::read_to_string(output_dir.join(name)).unwrap();
+        assert_eq!(
+            read("builds.csv"),
+            "package,version,crate,crate_hash,status\n\
+             added,1.0.0,added_crate,0000000000000001,added\n\
+             kept,1.0.0,kept_crate,0000000000000001,unchanged\n\
+             removed,1.0.0,removed_crate,0000000000000001,removed\n"
+        );
+        // All facts are attributed to builds, including the basic blocks
+        // that are reachable only through statements and the facts that
+        // mention only def paths.
+        for relation in &["statements", "basic_blocks", "function_unsafe_use", "spans"] {
+            assert!(!read(&format!("{}.csv", relation)).contains(",,,"));
+        }
+        assert_eq!(
+            read("basic_blocks.csv"),
+            "package,version,crate,crate_hash,facts_a,facts_b\n\
+             added,1.0.0,added_crate,0000000000000001,0,1\n\
+             kept,1.0.0,kept_crate,0000000000000001,1,1\n\
+             removed,1.0.0,removed_crate,0000000000000001,1,0\n"
+        );
+        assert_eq!(
+            read("definitions.csv"),
+            "package,version,crate,def_path,status,\
+             basic_blocks_a,basic_blocks_b,statements_a,statements_b\n\
+             added,1.0.0,added_crate,added::f,added,0,1,0,1\n\
+             removed,1.0.0,removed_crate,removed::f,removed,1,0,1,0\n"
+        );
+    }
+}
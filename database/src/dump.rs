@@ -0,0 +1,209 @@
+// Licensed under the MIT license <LICENSE or
+// http://opensource.org/licenses/MIT>. This file may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Helper functions for dumping relations and interning tables in a human
+//! readable form.
+//!
+//! Integers are printed as numbers, enums as the names of their variants,
+//! and `u128` and `(u64, u64)` values as zero-padded hexadecimal strings.
+
+use anyhow::{bail, Result};
+use serde_json::Value;
+use std::io::Write;
+
+/// The format in which the rows are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DumpFormat {
+    /// Comma separated values with a header row.
+    Csv,
+    /// One JSON object per line.
+    JsonLines,
+}
+
+impl std::str::FromStr for DumpFormat {
+    type Err = anyhow::Error;
+    fn from_str(format: &str) -> Result<Self> {
+        match format {
+            "csv" => Ok(DumpFormat::Csv),
+            "jsonl" => Ok(DumpFormat::JsonLines),
+            _ => bail!(
+                "Unknown dump format `{}`; expected `csv` or `jsonl`",
+                format
+            ),
+        }
+    }
+}
+
+/// A value that can be dumped.
+pub trait DumpValue {
+    fn to_dump_value(&self) -> Value;
+}
+
+macro_rules! impl_dump_number {
+    ($($typ:ty),*) => {
+        $(
+            impl DumpValue for $typ {
+                fn to_dump_value(&self) -> Value {
+                    Value::from(*self)
+                }
+            }
+        )*
+    };
+}
+
+impl_dump_number!(u8, u16, u32, u64, i64, bool);
+
+impl DumpValue for u128 {
+    fn to_dump_value(&self) -> Value {
+        Value::String(format!("{:032x}", self))
+    }
+}
+
+impl DumpValue for i128 {
+    fn to_dump_value(&self) -> Value {
+        Value::String(self.to_string())
+    }
+}
+
+impl DumpValue for (u64, u64) {
+    fn to_dump_value(&self) -> Value {
+        Value::String(format!("{:016x}{:016x}", self.0, self.1))
+    }
+}
+
+impl DumpValue for String {
+    fn to_dump_value(&self) -> Value {
+        Value::String(self.clone())
+    }
+}
+
+impl<T: DumpValue> DumpValue for Option<T> {
+    fn to_dump_value(&self) -> Value {
+        match self {
+            Some(value) => value.to_dump_value(),
+            None => Value::Null,
+        }
+    }
+}
+
+/// Writes rows with the given columns in the specified format.
+pub(crate) struct RowWriter<'a> {
+    format: DumpFormat,
+    columns: Vec<String>,
+    output: &'a mut dyn Write,
+}
+
+impl<'a> RowWriter<'a> {
+    /// Create a writer and write the header, if the format has one.
+    pub(crate) fn new(
+        format: DumpFormat,
+        columns: Vec<String>,
+        output: &'a mut dyn Write,
+    ) -> Result<Self> {
+        let writer = Self {
+            format,
+            columns,
+            output,
+        };
+        if format == DumpFormat::Csv {
+            let header: Vec<_> = writer
+                .columns
+                .iter()
+                .map(|column| csv_field(column))
+                .collect();
+            writeln!(writer.output, "{}", header.join(","))?;
+        }
+        Ok(writer)
+    }
+    pub(crate) fn write(&mut self, row: Vec<Value>) -> Result<()> {
+        assert_eq!(row.len(), self.columns.len());
+        match self.format {
+            DumpFormat::Csv => {
+                let fields: Vec<_> = row
+                    .iter()
+                    .map(|value| match value {
+                        Value::String(value) => csv_field(value),
+                        Value::Null => String::new(),
+                        value => value.to_string(),
+                    })
+                    .collect();
+                writeln!(self.output, "{}", fields.join(","))?;
+            }
+            DumpFormat::JsonLines => {
+                // Written by hand to preserve the order of the columns.
+                let fields: Vec<_> = self
+                    .columns
+                    .iter()
+                    .zip(&row)
+                    .map(|(column, value)| format!("{}:{}", Value::from(column.as_str()), value))
+                    .collect();
+                writeln!(self.output, "{{{}}}", fields.join(","))?;
+            }
+        }
+        Ok(())
+    }
+    pub(crate) fn finish(self) -> Result<()> {
+        self.output.flush()?;
+        Ok(())
+    }
+}
+
+/// Quote the field if it contains characters that have a special meaning in
+/// CSV.
+fn csv_field(value: &str) -> String {
+    if value.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DumpFormat;
+    use crate::tables;
+    use crate::test_support::{create_single_build_tables, test_dir};
+
+    #[test]
+    fn test_dump_relation() {
+        let (mut tables, build) = create_single_build_tables();
+        tables.register_build_crate_types(build, String::from("lib, bin"));
+        tables.register_build_crate_types(build, String::from("proc-macro"));
+        let database_root = test_dir("rust-corpus-dump-relation-test");
+        tables.store_multifile(&database_root).unwrap();
+        let loader = tables::Loader::new(database_root);
+        let dump = |name: &str, resolve: bool, limit: Option<usize>, format: DumpFormat| {
+            let mut output = Vec::new();
+            loader
+                .dump_relation(name, resolve, limit, format, &mut output)
+                .map(|()| String::from_utf8(output).unwrap())
+        };
+        assert_eq!(
+            dump("build_crate_types", false, None, DumpFormat::Csv).unwrap(),
+            "build,crate_type\n0,4\n0,5\n"
+        );
+        assert_eq!(
+            dump("build_crate_types", true, Some(1), DumpFormat::Csv).unwrap(),
+            "build,crate_type,build_package,build_package_version,build_krate,\
+             build_crate_hash,build_edition,crate_type_text\n\
+             0,4,package1,version1,crate1,abc,edition1,\"lib, bin\"\n"
+        );
+        assert_eq!(
+            dump("build_crate_types", true, None, DumpFormat::JsonLines)
+                .unwrap()
+                .lines()
+                .nth(1)
+                .unwrap(),
+            "{\"build\":0,\"crate_type\":5,\"build_package\":\"package1\",\
+             \"build_package_version\":\"version1\",\"build_krate\":\"crate1\",\
+             \"build_crate_hash\":\"abc\",\"build_edition\":\"edition1\",\
+             \"crate_type_text\":\"proc-macro\"}"
+        );
+        assert_eq!(
+            dump("crate_names", true, None, DumpFormat::Csv).unwrap(),
+            "id,value,value_text\n0,2,crate1\n"
+        );
+        assert!(dump("no_such_relation", false, None, DumpFormat::Csv).is_err());
+    }
+}
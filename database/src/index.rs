@@ -0,0 +1,149 @@
+// Licensed under the MIT license <LICENSE or
+// http://opensource.org/licenses/MIT>. This file may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Secondary indexes of relations declared with `index` in `schema.dl`.
+//!
+//! An index groups the facts of a relation by the values of the indexed
+//! columns. The `Loader` builds an index on the first lookup and keeps it
+//! until the loader is dropped. An index declared with `persist` is stored
+//! next to the relation file together with the numbers of facts and the
+//! checksums stored in the headers of the relation files of the base and all
+//! segments; it is rebuilt when any of them changes.
+
+use crate::segments::relation_files;
+use crate::storage::relation_file_stamp;
+use anyhow::Result;
+use log::{info, warn};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::path::{Path, PathBuf};
+
+/// The facts of a relation grouped by the values of the indexed columns.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RelationIndex<K: Eq + Hash, T> {
+    groups: HashMap<K, Vec<T>>,
+}
+
+impl<K: Eq + Hash, T: Copy> RelationIndex<K, T> {
+    pub(crate) fn new(facts: impl IntoIterator<Item = T>, key: impl Fn(&T) -> K) -> Self {
+        let mut groups: HashMap<K, Vec<T>> = HashMap::new();
+        for fact in facts {
+            groups.entry(key(&fact)).or_default().push(fact);
+        }
+        Self { groups }
+    }
+    /// The facts whose indexed columns have the value `key` in the order in
+    /// which they are stored in the relation.
+    pub fn lookup(&self, key: &K) -> &[T] {
+        self.groups.get(key).map_or(&[], Vec::as_slice)
+    }
+    /// All groups of facts by the values of the indexed columns.
+    pub fn groups(&self) -> &HashMap<K, Vec<T>> {
+        &self.groups
+    }
+    /// The size in bytes of the keys and the grouped facts.
+    pub fn memory_usage(&self) -> usize {
+        let facts: usize = self.groups.values().map(Vec::len).sum();
+        self.groups.len() * std::mem::size_of::<(K, Vec<T>)>() + facts * std::mem::size_of::<T>()
+    }
+}
+
+/// The number of facts and the checksum of every file that stores the
+/// relation, relative to the database root.
+type RelationStamp = Vec<(PathBuf, u64, u32)>;
+
+#[derive(Deserialize, Serialize)]
+struct PersistedIndex<K: Eq + Hash, T> {
+    stamp: RelationStamp,
+    index: RelationIndex<K, T>,
+}
+
+fn relation_stamp(database_root: &Path, file_name: &str) -> Result<RelationStamp> {
+    let mut stamp = Vec::new();
+    for path in relation_files(database_root, file_name)? {
+        let (len, checksum) = relation_file_stamp(&path)?;
+        let relative_path = path.strip_prefix(database_root)?.to_path_buf();
+        stamp.push((relative_path, len, checksum));
+    }
+    Ok(stamp)
+}
+
+/// Load the index stored in `index_file` if the relation stored in
+/// `file_name` did not change since, otherwise build it with `build` and
+/// store it. Both paths are relative to the database root.
+pub(crate) fn load_or_build_index<K, T>(
+    database_root: &Path,
+    file_name: &str,
+    index_file: &str,
+    build: impl FnOnce() -> RelationIndex<K, T>,
+) -> Result<RelationIndex<K, T>>
+where
+    K: Eq + Hash + serde::Serialize + for<'de> serde::Deserialize<'de>,
+    T: serde::Serialize + for<'de> serde::Deserialize<'de>,
+{
+    let stamp = relation_stamp(database_root, file_name)?;
+    let index_path = database_root.join(index_file);
+    if index_path.exists() {
+        match crate::storage::load::<PersistedIndex<K, T>>(&index_path) {
+            Ok(persisted) if persisted.stamp == stamp => return Ok(persisted.index),
+            Ok(_) => info!("The index {:?} is outdated", index_path),
+            Err(e) => warn!("Failed to load the index {:?}: {:?}", index_path, e),
+        }
+    }
+    let persisted = PersistedIndex {
+        stamp,
+        index: build(),
+    };
+    // The database may be read-only, in which case the index is only kept in
+    // memory.
+    match std::fs::File::create(&index_path) {
+        Ok(file) => {
+            if let Err(e) = bincode::serialize_into(file, &persisted) {
+                warn!("Failed to store the index {:?}: {}", index_path, e);
+            }
+        }
+        Err(e) => warn!("Failed to store the index {:?}: {}", index_path, e),
+    }
+    Ok(persisted.index)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::{create_build_tables, test_dir};
+    use crate::{tables, types};
+
+    #[test]
+    fn test_relation_index() {
+        let temp_dir = test_dir("rust-corpus-index-test");
+        let mut tables = create_build_tables("indexed", "1.0.0");
+        tables.store_multifile(&temp_dir).unwrap();
+        let (block, def_path, kind) = tables.relations.basic_blocks.facts[0];
+
+        let loader = tables::Loader::new(temp_dir.clone());
+        assert_eq!(
+            loader.lookup_basic_blocks_by_block(block),
+            &[(block, def_path, kind)]
+        );
+        assert!(loader
+            .lookup_basic_blocks_by_block(tables.get_fresh_basicblock())
+            .is_empty());
+        assert_eq!(loader.group_basic_blocks_by_block().groups().len(), 1);
+        assert!(loader
+            .lookup_terminators_call_by_call(0usize.into())
+            .is_empty());
+        let index_path = temp_dir.join("relations/basic_blocks.by_block.bincode");
+        assert!(index_path.exists());
+
+        // The persisted index is rebuilt when the relation changes.
+        let (new_block,) = tables.register_basic_blocks(def_path, types::BasicBlockKind::CleanUp);
+        tables.store_multifile(&temp_dir).unwrap();
+        let loader = tables::Loader::new(temp_dir);
+        assert_eq!(
+            loader.lookup_basic_blocks_by_block(new_block),
+            &[(new_block, def_path, types::BasicBlockKind::CleanUp)]
+        );
+        assert_eq!(loader.group_basic_blocks_by_block().groups().len(), 2);
+    }
+}
@@ -0,0 +1,260 @@
+// Licensed under the MIT license <LICENSE or
+// http://opensource.org/licenses/MIT>. This file may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Checks of the referential integrity of the database.
+//!
+//! The checks are generated by `database-dsl` from the schema: interned ids
+//! must be valid indices into their interning tables, incremental ids must be
+//! smaller than their counters, and columns declared with `references` must
+//! contain only ids that are present in the referenced columns. Besides,
+//! the data of every relation and interning table file must match the
+//! checksum stored in its header.
+
+use crate::storage::check_file_checksum;
+use crate::tables::Loader;
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// The maximum number of offending values that are kept for each violation.
+const MAX_EXAMPLES: usize = 5;
+
+/// A column declared with `references target_relation.target_column` in the
+/// schema.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ForeignKey {
+    pub relation: &'static str,
+    pub column: &'static str,
+    pub target_relation: &'static str,
+    pub target_column: &'static str,
+}
+
+/// A constraint that does not hold for some rows of a relation.
+#[derive(Debug)]
+pub struct Violation {
+    /// A human readable description of the constraint.
+    pub constraint: String,
+    /// The number of rows that violate the constraint.
+    pub count: usize,
+    /// The first few offending values.
+    pub examples: Vec<usize>,
+}
+
+/// The violations found in each checked relation and interning table.
+#[derive(Debug, Default)]
+pub struct IntegrityReport {
+    relations: BTreeMap<String, Vec<Violation>>,
+    /// The files whose data does not match their checksums.
+    corrupt_files: Vec<PathBuf>,
+}
+
+impl IntegrityReport {
+    /// Returns true if no constraint is violated.
+    pub fn is_ok(&self) -> bool {
+        self.corrupt_files.is_empty()
+            && self
+                .relations
+                .values()
+                .all(|violations| violations.is_empty())
+    }
+    /// The files whose data does not match their checksums.
+    pub fn corrupt_files(&self) -> &[PathBuf] {
+        &self.corrupt_files
+    }
+    /// The violations found in `relation`.
+    pub fn violations(&self, relation: &str) -> &[Violation] {
+        self.relations
+            .get(relation)
+            .map_or(&[], |violations| violations.as_slice())
+    }
+    /// Record that `relation` was checked even if it has no constrained
+    /// columns.
+    pub(crate) fn add_relation(&mut self, relation: &str) {
+        self.relations.entry(relation.to_string()).or_default();
+    }
+    /// Check that `is_valid` holds for all `values` of `relation`.
+    pub(crate) fn check(
+        &mut self,
+        relation: &str,
+        constraint: String,
+        values: impl IntoIterator<Item = usize>,
+        is_valid: impl Fn(usize) -> bool,
+    ) {
+        let mut count = 0;
+        let mut examples = Vec::new();
+        for value in values {
+            if !is_valid(value) {
+                count += 1;
+                if examples.len() < MAX_EXAMPLES {
+                    examples.push(value);
+                }
+            }
+        }
+        let violations = self.relations.entry(relation.to_string()).or_default();
+        if count > 0 {
+            violations.push(Violation {
+                constraint,
+                count,
+                examples,
+            });
+        }
+    }
+}
+
+impl fmt::Display for IntegrityReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for path in &self.corrupt_files {
+            writeln!(f, "{}: checksum mismatch", path.display())?;
+        }
+        for (relation, violations) in &self.relations {
+            if violations.is_empty() {
+                writeln!(f, "{}: ok", relation)?;
+                continue;
+            }
+            writeln!(
+                f,
+                "{}: {} violated constraint(s)",
+                relation,
+                violations.len()
+            )?;
+            for violation in violations {
+                let examples: Vec<_> = violation.examples.iter().map(|v| v.to_string()).collect();
+                writeln!(
+                    f,
+                    "  {}: {} row(s), for example {}",
+                    violation.constraint,
+                    violation.count,
+                    examples.join(", ")
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Loader {
+    /// Check the referential integrity of the database.
+    pub fn check_integrity(&self) -> Result<IntegrityReport> {
+        let mut report = IntegrityReport::default();
+        // A loader of crates merged in memory may have no database files.
+        if self.database_root.exists() {
+            check_checksums(&self.database_root, &mut report.corrupt_files)?;
+        }
+        self.check_id_bounds(&mut report)?;
+        self.check_references(&mut report)?;
+        Ok(report)
+    }
+}
+
+/// Collect the relation and interning table files in `dir` and its
+/// subdirectories whose data does not match their checksums.
+fn check_checksums(dir: &Path, corrupt_files: &mut Vec<PathBuf>) -> Result<()> {
+    let entries = std::fs::read_dir(dir).with_context(|| format!("Failed to read {:?}", dir))?;
+    let mut paths = entries
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<Vec<_>>>()
+        .with_context(|| format!("Failed to read {:?}", dir))?;
+    paths.sort();
+    for path in paths {
+        if path.is_dir() {
+            check_checksums(&path, corrupt_files)?;
+        } else if matches!(
+            path.extension().and_then(|extension| extension.to_str()),
+            Some("rc" | "rcc")
+        ) && !check_file_checksum(&path)?
+        {
+            corrupt_files.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ForeignKey;
+    use crate::test_support::test_dir;
+    use crate::{tables, types};
+
+    #[test]
+    fn test_check_integrity() {
+        let mut tables = tables::Tables::default();
+        let def_path = tables.register_def_paths(
+            String::from("crate1"),
+            1usize.into(),
+            String::from("relative_def_id1"),
+            (1u64, 2u64).into(),
+            String::from("summary_1"),
+        );
+        let (block,) = tables.register_basic_blocks(def_path, types::BasicBlockKind::Entry);
+        let scope = tables.get_fresh_scope();
+        let statement = tables.get_fresh_statement();
+        tables.register_statements(statement, block, 0usize.into(), String::from("Nop"), scope);
+        // A statement in a block that was never registered.
+        let statement = tables.get_fresh_statement();
+        let missing_block = block.shift(1);
+        tables.register_statements(
+            statement,
+            missing_block,
+            0usize.into(),
+            String::from("Nop"),
+            scope,
+        );
+        // Constants such as `NO_BLOCK` are not stored in any relation.
+        let no_block = tables.get_no_block();
+        tables.register_terminators_goto(block, no_block);
+        let in_memory_report = tables.check_references();
+        assert_eq!(in_memory_report.violations("statements").len(), 1);
+        assert_eq!(in_memory_report.violations("terminators_goto").len(), 1);
+        assert!(in_memory_report.violations("terminators").is_empty());
+        assert!(tables::FOREIGN_KEYS.contains(&ForeignKey {
+            relation: "statements",
+            column: "block",
+            target_relation: "basic_blocks",
+            target_column: "block",
+        }));
+        let database_root = test_dir("rust-corpus-check-integrity-test");
+        tables.store_multifile(&database_root).unwrap();
+        let loader = tables::Loader::new(database_root.clone());
+        let report = loader.check_integrity().unwrap();
+        assert!(!report.is_ok());
+        assert!(report.violations("basic_blocks").is_empty());
+        assert!(report.violations("def_paths").is_empty());
+        let violations = report.violations("statements");
+        assert_eq!(violations.len(), 2);
+        for violation in violations {
+            assert_eq!(violation.count, 1);
+            assert_eq!(violation.examples, vec![missing_block.index()]);
+        }
+        assert_eq!(
+            violations[0].constraint,
+            "`block` is smaller than the `basicblocks` counter"
+        );
+        assert_eq!(
+            violations[1].constraint,
+            "`block` references `basic_blocks.block`"
+        );
+        let text = report.to_string();
+        assert!(text.contains("basic_blocks: ok\n"));
+        assert!(text.contains("statements: 2 violated constraint(s)\n"));
+        assert!(text.contains("terminators_goto: 1 violated constraint(s)\n"));
+        assert!(report.corrupt_files().is_empty());
+
+        // Memory-mapped files are not checksummed when they are loaded, but
+        // the corruption is reported by the check.
+        let statements_path = database_root.join("relations/statements.rc");
+        let mut contents = std::fs::read(&statements_path).unwrap();
+        let last = contents.len() - 1;
+        contents[last] ^= 0xff;
+        std::fs::write(&statements_path, contents).unwrap();
+        let report = tables::Loader::new(database_root)
+            .check_integrity()
+            .unwrap();
+        assert_eq!(report.corrupt_files(), [statements_path.clone()]);
+        assert!(report.to_string().contains(&format!(
+            "{}: checksum mismatch\n",
+            statements_path.display()
+        )));
+    }
+}
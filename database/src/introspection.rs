@@ -0,0 +1,308 @@
+// Licensed under the MIT license <LICENSE or
+// http://opensource.org/licenses/MIT>. This file may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Information about the database schema that is available at runtime.
+//!
+//! `tables::SCHEMA` describes every core and derived relation and every
+//! interning table declared in `schema.dl` and `derived.dl`. Together with
+//! `Loader::dyn_relation`, which yields the rows of a relation or an
+//! interning table as `DynValue`s, it allows generic tools to work with any
+//! relation without knowing its type at compile time.
+
+use std::fmt;
+
+/// The relations and interning tables of the database.
+#[derive(Debug)]
+pub struct Schema {
+    /// The core relations followed by the derived relations in the order of
+    /// their declaration.
+    pub relations: &'static [RelationInfo],
+    pub interning_tables: &'static [InterningTableInfo],
+}
+
+impl Schema {
+    pub fn relation(&self, name: &str) -> Option<&'static RelationInfo> {
+        self.relations.iter().find(|relation| relation.name == name)
+    }
+    pub fn interning_table(&self, name: &str) -> Option<&'static InterningTableInfo> {
+        self.interning_tables
+            .iter()
+            .find(|table| table.name == name)
+    }
+}
+
+/// A core or derived relation.
+#[derive(Debug)]
+pub struct RelationInfo {
+    pub name: &'static str,
+    /// True if the relation is declared in `derived.dl`.
+    pub derived: bool,
+    pub columns: &'static [ColumnInfo],
+    /// The columns used for deduplicating the facts when merging databases.
+    pub key: Option<RelationKeyInfo>,
+    /// The documentation comment of the relation, or an empty string.
+    pub doc: &'static str,
+}
+
+/// The key of a relation declared with `key = [(source) => target]`.
+#[derive(Debug)]
+pub struct RelationKeyInfo {
+    pub source: &'static [&'static str],
+    /// The autogenerated column that is remapped to the value of the first
+    /// fact with the same source.
+    pub target: Option<&'static str>,
+}
+
+/// An interning table. Its columns are the interned id followed by the
+/// elements of the value.
+#[derive(Debug)]
+pub struct InterningTableInfo {
+    pub name: &'static str,
+    pub columns: &'static [ColumnInfo],
+    /// The documentation comment of the table, or an empty string.
+    pub doc: &'static str,
+}
+
+/// A column of a relation or an interning table.
+#[derive(Debug)]
+pub struct ColumnInfo {
+    pub name: &'static str,
+    /// The name of the type as written in the schema.
+    pub typ: &'static str,
+    pub kind: ColumnKind,
+    /// True if the column has the type `Option<T>`, in which case `kind`
+    /// describes `T`.
+    pub optional: bool,
+    /// True if the value is generated when the fact is registered.
+    pub auto: bool,
+}
+
+/// What kind of values a column contains.
+#[derive(Debug)]
+pub enum ColumnKind {
+    /// A Rust type such as `bool`, `i64`, or `String`.
+    Primitive,
+    Enum {
+        variants: &'static [&'static str],
+    },
+    /// An id that is incremented each time an object is created.
+    IncrementalId,
+    /// A key of the interning table `table`.
+    InternedId {
+        table: &'static str,
+    },
+    /// An id taken from the compiler, such as `CrateHash`.
+    CustomId,
+}
+
+/// A value of a column.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum DynValue {
+    Bool(bool),
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    U128(u128),
+    I64(i64),
+    I128(i128),
+    /// A pair of integers such as `DefPathHash`.
+    Pair(u64, u64),
+    String(String),
+    Enum {
+        typ: &'static str,
+        variant: &'static str,
+    },
+    /// An incremental or interned id.
+    Id {
+        typ: &'static str,
+        index: u64,
+    },
+    /// A missing value of an optional column.
+    None,
+}
+
+impl fmt::Display for DynValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DynValue::Bool(value) => write!(f, "{}", value),
+            DynValue::U8(value) => write!(f, "{}", value),
+            DynValue::U16(value) => write!(f, "{}", value),
+            DynValue::U32(value) => write!(f, "{}", value),
+            DynValue::U64(value) => write!(f, "{}", value),
+            DynValue::U128(value) => write!(f, "{:032x}", value),
+            DynValue::I64(value) => write!(f, "{}", value),
+            DynValue::I128(value) => write!(f, "{}", value),
+            DynValue::Pair(first, second) => write!(f, "{:016x}{:016x}", first, second),
+            DynValue::String(value) => write!(f, "{}", value),
+            DynValue::Enum { variant, .. } => write!(f, "{}", variant),
+            DynValue::Id { index, .. } => write!(f, "{}", index),
+            DynValue::None => Ok(()),
+        }
+    }
+}
+
+/// A value that can be converted into a `DynValue`.
+pub trait ToDynValue {
+    fn to_dyn_value(&self) -> DynValue;
+}
+
+macro_rules! impl_to_dyn_value {
+    ($($typ:ty => $variant:ident),*) => {
+        $(
+            impl ToDynValue for $typ {
+                fn to_dyn_value(&self) -> DynValue {
+                    DynValue::$variant(*self)
+                }
+            }
+        )*
+    };
+}
+
+impl_to_dyn_value!(
+    bool => Bool, u8 => U8, u16 => U16, u32 => U32, u64 => U64, u128 => U128,
+    i64 => I64, i128 => I128
+);
+
+impl ToDynValue for (u64, u64) {
+    fn to_dyn_value(&self) -> DynValue {
+        DynValue::Pair(self.0, self.1)
+    }
+}
+
+impl ToDynValue for String {
+    fn to_dyn_value(&self) -> DynValue {
+        DynValue::String(self.clone())
+    }
+}
+
+impl<T: ToDynValue> ToDynValue for Option<T> {
+    fn to_dyn_value(&self) -> DynValue {
+        match self {
+            Some(value) => value.to_dyn_value(),
+            None => DynValue::None,
+        }
+    }
+}
+
+/// The rows of a relation or an interning table returned by
+/// `Loader::dyn_relation`.
+pub struct DynRelation<'a> {
+    columns: &'static [ColumnInfo],
+    rows: Box<dyn Iterator<Item = Vec<DynValue>> + 'a>,
+}
+
+impl<'a> DynRelation<'a> {
+    pub(crate) fn new(
+        columns: &'static [ColumnInfo],
+        rows: impl Iterator<Item = Vec<DynValue>> + 'a,
+    ) -> Self {
+        Self {
+            columns,
+            rows: Box::new(rows),
+        }
+    }
+    /// The columns of the rows.
+    pub fn columns(&self) -> &'static [ColumnInfo] {
+        self.columns
+    }
+}
+
+impl<'a> Iterator for DynRelation<'a> {
+    type Item = Vec<DynValue>;
+    fn next(&mut self) -> Option<Vec<DynValue>> {
+        self.rows.next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ColumnKind, DynValue};
+    use crate::tables;
+    use crate::test_support::{create_build_tables, test_dir};
+
+    #[test]
+    fn test_schema_introspection() {
+        let schema = tables::Loader::schema();
+        let basic_blocks = schema.relation("basic_blocks").unwrap();
+        assert!(!basic_blocks.derived);
+        assert_eq!(
+            basic_blocks.doc,
+            "Basic blocks.\n`is_cleanup` is true if the block is on the unwind path."
+        );
+        let columns: Vec<_> = basic_blocks
+            .columns
+            .iter()
+            .map(|column| (column.name, column.typ, column.auto))
+            .collect();
+        assert_eq!(
+            columns,
+            vec![
+                ("block", "BasicBlock", true),
+                ("mir", "DefPath", false),
+                ("kind", "BasicBlockKind", false)
+            ]
+        );
+        assert!(matches!(
+            basic_blocks.columns[1].kind,
+            ColumnKind::InternedId { table: "def_paths" }
+        ));
+        assert!(matches!(
+            basic_blocks.columns[2].kind,
+            ColumnKind::Enum { variants } if variants.contains(&"CleanUp")
+        ));
+        assert!(schema.relation("selected_scopes").unwrap().derived);
+        assert_eq!(
+            schema.interning_table("strings").unwrap().doc,
+            "Interned strings."
+        );
+
+        let temp_dir = test_dir("rust-corpus-introspection-test");
+        let tables = create_build_tables("introspected", "1.0.0");
+        tables.store_multifile(&temp_dir).unwrap();
+        let loader = tables::Loader::new(temp_dir);
+        let rows: Vec<_> = loader.dyn_relation("basic_blocks").unwrap().collect();
+        let (block, def_path, _) = tables.relations.basic_blocks.facts[0];
+        assert_eq!(
+            rows,
+            vec![vec![
+                DynValue::Id {
+                    typ: "BasicBlock",
+                    index: block.index() as u64
+                },
+                DynValue::Id {
+                    typ: "DefPath",
+                    index: def_path.index() as u64
+                },
+                DynValue::Enum {
+                    typ: "BasicBlockKind",
+                    variant: "Entry"
+                },
+            ]]
+        );
+        let builds = loader.dyn_relation("builds").unwrap();
+        let columns: Vec<_> = builds.columns().iter().map(|column| column.name).collect();
+        assert_eq!(
+            columns,
+            vec![
+                "id",
+                "package",
+                "package_version",
+                "krate",
+                "crate_hash",
+                "edition"
+            ]
+        );
+        let rows: Vec<_> = builds.collect();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0][4], DynValue::U64(1));
+        let strings: Vec<_> = loader
+            .dyn_relation("strings")
+            .unwrap()
+            .map(|row| row[1].to_string())
+            .collect();
+        assert!(strings.contains(&String::from("introspected")));
+        assert!(loader.dyn_relation("missing").is_err());
+    }
+}
@@ -2,15 +2,52 @@
 
 include!(concat!(env!("OUT_DIR"), "/schema.rs"));
 
+mod cache;
+mod columnar;
+mod crate_set;
 mod data_structures;
+mod diff;
+mod dump;
+mod index;
+mod integrity;
+mod introspection;
+mod merge;
+mod migration;
+#[cfg(feature = "parquet")]
+mod parquet;
+mod removal;
+mod resolver;
+mod segments;
+#[cfg(feature = "sqlite")]
+mod sqlite;
 mod storage;
+#[cfg(test)]
+mod test_support;
+mod transaction;
 
-pub use self::data_structures::InterningTable;
+pub use self::cache::{LoadedFacts, MemoryUsage, RelationMemoryUsage};
+pub use self::data_structures::{Facts, InterningTable};
+pub use self::diff::diff_databases;
+pub use self::dump::DumpFormat;
+pub use self::index::RelationIndex;
+pub use self::integrity::{ForeignKey, IntegrityReport, Violation};
+pub use self::introspection::{
+    ColumnInfo, ColumnKind, DynRelation, DynValue, InterningTableInfo, RelationInfo,
+    RelationKeyInfo, Schema,
+};
+pub use self::merge::{load_in_parallel, merge_in_parallel};
+pub use self::migration::{database_schema_version, migrate_database, store_schema_version};
+pub use self::removal::{replace_database, RemovalReport, RemovedBuild};
+pub use self::resolver::{Resolve, Resolver};
+pub use self::segments::{compact_database, next_segment_path, segment_paths};
+pub use self::storage::FileSchema;
+pub use self::transaction::{recover_database, Recovery, Transaction};
 
 #[cfg(test)]
 mod tests {
 
     use super::*;
+    use crate::test_support::{create_build_tables, test_dir};
 
     #[test]
     fn test_crate_names() {
@@ -1357,4 +1394,42 @@ mod tests {
             assert_eq!(actual, expected);
         }
     }
+
+    #[test]
+    fn test_row_structs() {
+        let temp_dir = test_dir("rust-corpus-rows-test");
+        let tables = create_build_tables("rows", "1.0.0");
+        tables.store_multifile(&temp_dir).unwrap();
+        let (block, def_path, kind) = tables.relations.basic_blocks.facts[0];
+
+        let loader = tables::Loader::new(temp_dir);
+        let basic_blocks = loader.load_basic_blocks_rows();
+        assert_eq!(
+            &basic_blocks[..],
+            &[rows::BasicBlock {
+                block,
+                mir: def_path,
+                kind,
+            }]
+        );
+        let fact: (types::BasicBlock, types::DefPath, types::BasicBlockKind) =
+            basic_blocks[0].into();
+        assert_eq!(fact, (block, def_path, kind));
+        // The rows are memory-mapped from the stored relation and the tuples
+        // are copied from them instead of being cached separately.
+        let (name, usage) = loader.memory_usage().relations[0];
+        assert_eq!(name, "basic_blocks");
+        assert!(usage.mapped > 0);
+        assert_eq!(
+            loader.load_basic_blocks(),
+            tables.relations.basic_blocks.facts
+        );
+        assert_eq!(loader.memory_usage().relations[0].1, usage);
+        drop(basic_blocks);
+        assert!(loader.unload_basic_blocks());
+        assert_eq!(
+            loader.load_statements_rows().len(),
+            loader.load_statements().len()
+        );
+    }
 }
@@ -0,0 +1,196 @@
+// Licensed under the MIT license <LICENSE or
+// http://opensource.org/licenses/MIT>. This file may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Loading and merging the tables of many crates in parallel.
+//!
+//! Merging is associative: merging `b` into `a` and then `c` into the result
+//! produces the same tables as merging `c` into `b` and then the result into
+//! `a`. Interned values and facts keep their order and incremental ids are
+//! shifted by the same amounts. Therefore, the tables can be merged pairwise
+//! in a tree whose leaves are in the original order.
+
+use crate::tables::{TableMerger, Tables};
+use anyhow::Result;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Load the tables stored in `paths` by using `jobs` worker threads. The
+/// results are in the same order as `paths`.
+pub fn load_in_parallel(paths: Vec<PathBuf>, jobs: usize) -> Vec<Result<Tables>> {
+    parallel_map(paths, jobs, |path| Tables::load(&path))
+}
+
+/// Merge `tables` into a single `Tables` by merging adjacent pairs by using
+/// `jobs` worker threads until only one is left. Merging the result into a
+/// `TableMerger` produces the same database as merging the tables into it
+/// one by one in order.
+pub fn merge_in_parallel(tables: Vec<Tables>, jobs: usize) -> Tables {
+    let mut level = tables;
+    while level.len() > 1 {
+        let mut pairs = Vec::with_capacity((level.len() + 1) / 2);
+        let mut iter = level.into_iter();
+        while let Some(left) = iter.next() {
+            pairs.push((left, iter.next()));
+        }
+        level = parallel_map(pairs, jobs, |(left, right)| match right {
+            Some(right) => {
+                let mut merger = TableMerger::new(left);
+                merger.merge(right);
+                merger.into_tables()
+            }
+            None => left,
+        });
+    }
+    level.pop().unwrap_or_default()
+}
+
+/// Apply `function` to all `items` by using `jobs` worker threads and return
+/// the results in the order of the items.
+fn parallel_map<T, R>(items: Vec<T>, jobs: usize, function: impl Fn(T) -> R + Sync) -> Vec<R>
+where
+    T: Send,
+    R: Send,
+{
+    let len = items.len();
+    let queue = Mutex::new(items.into_iter().enumerate());
+    let mut results: Vec<(usize, R)> = std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..jobs.clamp(1, len.max(1)))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results = Vec::new();
+                    loop {
+                        let next = queue.lock().unwrap().next();
+                        match next {
+                            Some((index, item)) => results.push((index, function(item))),
+                            None => break results,
+                        }
+                    }
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap())
+            .collect()
+    });
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{load_in_parallel, merge_in_parallel};
+    use crate::tables;
+    use crate::test_support::{
+        create_build_tables, create_cleanup_tables, create_crate_tables, test_dir, test_schema,
+    };
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_merge_in_parallel() {
+        let temp_dir = test_dir("rust-corpus-parallel-merge-test");
+        std::fs::create_dir_all(&temp_dir).unwrap();
+        let crates: Vec<_> = (0..7).map(|i| format!("crate{}", i % 5)).collect();
+        let mut paths = Vec::new();
+        for krate in &crates {
+            let path = temp_dir.join(format!("{}-{}", krate, paths.len()));
+            create_crate_tables(krate).save_bincode(path.clone());
+            paths.push(path.with_extension("bincode"));
+        }
+
+        let mut sequential = tables::TableMerger::new(tables::Tables::default());
+        for path in &paths {
+            sequential.merge(tables::Tables::load(path).unwrap());
+        }
+        let loaded: Vec<_> = load_in_parallel(paths, 3)
+            .into_iter()
+            .map(|tables| tables.unwrap())
+            .collect();
+        let mut parallel = tables::TableMerger::new(tables::Tables::default());
+        parallel.merge(merge_in_parallel(loaded, 3));
+        // Raw relation files may differ in padding bytes, so the serialized
+        // tables are compared instead.
+        assert_eq!(
+            bincode::serialize(sequential.tables()).unwrap(),
+            bincode::serialize(parallel.tables()).unwrap()
+        );
+        assert_eq!(
+            merge_in_parallel(Vec::new(), 3).relations.statements.len(),
+            0
+        );
+    }
+
+    /// Store a database of the crates in `paths` in the same way as
+    /// `update-database` with `--jobs jobs`: with one job, the crates are
+    /// merged one by one; otherwise, they are loaded and merged in parallel in
+    /// chunks whose results are merged into the database.
+    fn store_database(paths: &[PathBuf], jobs: usize) -> PathBuf {
+        let database_root = test_dir(&format!("rust-corpus-parallel-merge-database-{}", jobs));
+        let mut merger = tables::TableMerger::new(tables::Tables::default());
+        if jobs == 1 {
+            for path in paths {
+                merger.merge(tables::Tables::load(path).unwrap());
+            }
+        } else {
+            for chunk in paths.chunks(jobs * 2) {
+                let loaded = load_in_parallel(chunk.to_vec(), jobs)
+                    .into_iter()
+                    .map(|tables| tables.unwrap())
+                    .collect();
+                merger.merge(merge_in_parallel(loaded, jobs));
+            }
+        }
+        merger
+            .store_segment(&database_root, tables::StorageFormat::Raw)
+            .unwrap();
+        crate::store_schema_version(&database_root);
+        database_root
+    }
+
+    #[test]
+    fn test_parallel_update_database() {
+        let temp_dir = test_dir("rust-corpus-parallel-update-test");
+        std::fs::create_dir_all(&temp_dir).unwrap();
+        let mut paths = Vec::new();
+        for i in 0..11 {
+            // Some crates share def paths and strings with earlier ones.
+            let tables = if i % 2 == 0 {
+                create_crate_tables(&format!("crate{}", i % 3))
+            } else {
+                create_build_tables(&format!("package{}", i % 4), &format!("1.0.{}", i))
+            };
+            let path = temp_dir.join(format!("crate-{}", i));
+            tables.save_bincode(path.clone());
+            paths.push(path.with_extension("bincode"));
+        }
+        let sequential = store_database(&paths, 1);
+        let parallel = store_database(&paths, 4);
+        let sequential = tables::Tables::load_multifile(&sequential).unwrap();
+        let parallel = tables::Tables::load_multifile(&parallel).unwrap();
+        assert_eq!(
+            sequential.relations.statements.len(),
+            paths.len(),
+            "every crate has one statement"
+        );
+        // The stored relations, interning tables, and counters are equal.
+        assert_eq!(
+            bincode::serialize(&sequential).unwrap(),
+            bincode::serialize(&parallel).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_merge_optional_ids() {
+        let mut merger = test_schema::tables::TableMerger::new(create_cleanup_tables());
+        merger.merge(create_cleanup_tables());
+        let terminators: Vec<_> = merger.tables().relations.terminators.iter().collect();
+        assert_eq!(terminators.len(), 4);
+        // The blocks of the second tables are shifted and `Some` cleanup
+        // blocks are shifted with them, while `None` stays `None`.
+        let blocks: Vec<_> = terminators.iter().map(|fact| fact.0).collect();
+        let cleanups: Vec<_> = terminators.iter().map(|fact| fact.3).collect();
+        assert_ne!(blocks[0], blocks[2]);
+        assert_eq!(cleanups, vec![Some(blocks[1]), None, Some(blocks[3]), None]);
+    }
+}
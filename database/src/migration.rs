@@ -0,0 +1,278 @@
+// Licensed under the MIT license <LICENSE or
+// http://opensource.org/licenses/MIT>. This file may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Migrating databases stored by older versions of the schema.
+//!
+//! Every `migration N { ... }` block of the schema increases the schema
+//! version by one. The version with which a database was written is stored in
+//! `schema_version.json` in the database root; databases without this file
+//! were written before the first migration and have the version 0. Reading a
+//! database of a different version fails and the user has to run
+//! `corpus-manager migrate`, which rewrites the changed relations of the base
+//! and all segments.
+
+use crate::removal::remove_derived_relations;
+use crate::segments::segment_paths;
+use crate::tables::{migrate_relations, DERIVED_RELATION_PATHS, SCHEMA_VERSION};
+use crate::transaction::{recover_database, Transaction};
+use anyhow::{bail, Context, Result};
+use log::info;
+use std::path::Path;
+
+/// The file inside the database root that stores the schema version.
+const SCHEMA_VERSION_FILE: &str = "schema_version.json";
+
+/// The schema version of the database at `database_root`.
+pub fn database_schema_version(database_root: &Path) -> Result<u32> {
+    let path = database_root.join(SCHEMA_VERSION_FILE);
+    if path.exists() {
+        crate::storage::load(&path)
+    } else {
+        Ok(0)
+    }
+}
+
+/// Record that the database at `database_root` uses the current schema.
+pub fn store_schema_version(database_root: &Path) {
+    write_schema_version(database_root, SCHEMA_VERSION);
+}
+
+/// Record that the database at `database_root` uses the schema `version`.
+pub(crate) fn write_schema_version(database_root: &Path, version: u32) {
+    crate::storage::save(&version, &database_root.join(SCHEMA_VERSION_FILE));
+}
+
+/// Check that the database at `database_root` uses the schema version
+/// `current_version`.
+pub(crate) fn check_schema_version(database_root: &Path, current_version: u32) -> Result<()> {
+    let version = database_schema_version(database_root)?;
+    if version != current_version {
+        bail!(
+            "The database {:?} uses the schema version {}, but the current version is {}; \
+             please run `corpus-manager migrate`",
+            database_root,
+            version,
+            current_version
+        );
+    }
+    Ok(())
+}
+
+/// Migrate the database at `database_root` to the current schema version.
+/// The relations that changed are rewritten in the format in which they were
+/// stored and the derived relations are removed.
+pub fn migrate_database(database_root: &Path) -> Result<()> {
+    migrate_database_to(
+        database_root,
+        SCHEMA_VERSION,
+        migrate_relations,
+        DERIVED_RELATION_PATHS,
+    )
+}
+
+/// Migrate the database at `database_root` to the schema `current_version`
+/// by using the generated function `migrate_relations` and the derived
+/// relations of that schema.
+pub(crate) fn migrate_database_to(
+    database_root: &Path,
+    current_version: u32,
+    migrate_relations: fn(&Path, &Path, u32, bool) -> Result<Vec<&'static str>>,
+    derived_relation_paths: &[&str],
+) -> Result<()> {
+    recover_database(database_root)?;
+    let version = database_schema_version(database_root)?;
+    if version == current_version {
+        info!("The database already uses the schema version {}", version);
+        return Ok(());
+    }
+    if version > current_version {
+        bail!(
+            "The database {:?} uses the schema version {}, which is newer than the supported \
+             version {}",
+            database_root,
+            version,
+            current_version
+        );
+    }
+    info!(
+        "Migrating the database from the schema version {} to {}",
+        version, current_version
+    );
+    let transaction = Transaction::begin(database_root)?;
+    let migrated = migrate_relations(database_root, transaction.path(), version, true)?;
+    info!("Migrated the relations of the base: {:?}", migrated);
+    for segment in segment_paths(database_root)? {
+        let relative_path = segment
+            .strip_prefix(database_root)
+            .with_context(|| format!("Invalid segment path {:?}", segment))?;
+        let migrated = migrate_relations(
+            &segment,
+            &transaction.path().join(relative_path),
+            version,
+            false,
+        )?;
+        info!(
+            "Migrated the relations of {:?}: {:?}",
+            relative_path, migrated
+        );
+    }
+    remove_derived_relations(&transaction, derived_relation_paths)?;
+    write_schema_version(transaction.path(), current_version);
+    transaction.commit()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{database_schema_version, migrate_database, migrate_database_to};
+    use crate::tables::{self, StorageFormat};
+    use crate::test_support::{create_build_tables, test_dir, test_schema, test_schema_v0};
+
+    #[test]
+    fn test_schema_version() {
+        let database_root = test_dir("rust-corpus-schema-version-test");
+        create_build_tables("package", "1.0.0")
+            .store_multifile(&database_root)
+            .unwrap();
+        assert_eq!(
+            database_schema_version(&database_root).unwrap(),
+            tables::SCHEMA_VERSION
+        );
+        // Migrating a database that uses the current schema does nothing.
+        migrate_database(&database_root).unwrap();
+        assert!(!database_root.join(".staging").exists());
+        tables::Tables::load_multifile(&database_root).unwrap();
+        // A database written by a newer schema can be neither loaded nor
+        // migrated.
+        let version_path = database_root.join("schema_version.json");
+        std::fs::write(&version_path, (tables::SCHEMA_VERSION + 1).to_string()).unwrap();
+        assert!(tables::Tables::load_multifile(&database_root).is_err());
+        assert!(tables::Loader::new(database_root.clone())
+            .read_counters()
+            .is_err());
+        assert!(migrate_database(&database_root).is_err());
+        // Databases written before the version was recorded have version 0.
+        std::fs::remove_file(&version_path).unwrap();
+        assert_eq!(database_schema_version(&database_root).unwrap(), 0);
+    }
+
+    /// Migrate a database stored by `test_schema_v0` to `test_schema`, whose
+    /// migrations add the column `successor_count` to `terminators`, rename
+    /// its column `label` to `name`, make its columns `cleanup` and
+    /// `successor_count` optional, and add the variant `Call` in the middle of
+    /// `TerminatorKind`.
+    fn check_migration(format: StorageFormat) {
+        use test_schema::types::TerminatorKind;
+        let database_root = test_dir(&format!("rust-corpus-migration-test-{:?}", format));
+        let mut old_tables = test_schema_v0::tables::Tables::default();
+        let entry = old_tables.get_fresh_block();
+        let cleanup = old_tables.get_fresh_block();
+        let no_block = old_tables.get_no_block();
+        old_tables.register_terminators(
+            entry,
+            test_schema_v0::types::TerminatorKind::Return,
+            String::from("entry"),
+            cleanup,
+        );
+        old_tables.register_terminators(
+            cleanup,
+            test_schema_v0::types::TerminatorKind::Unknown,
+            String::from("cleanup"),
+            no_block,
+        );
+        old_tables
+            .store_multifile_with_format(&database_root, format)
+            .unwrap();
+        test_schema_v0::tables::Loader::new(database_root.clone())
+            .store_blocks_with_cleanup(vec![(entry, cleanup)]);
+        let relations_path = database_root.join("relations");
+        let derived_path = relations_path.join("blocks_with_cleanup.rc");
+        assert!(derived_path.exists());
+        assert_eq!(database_schema_version(&database_root).unwrap(), 0);
+
+        // The old database cannot be loaded by the current schema.
+        assert!(test_schema::tables::Tables::load_multifile(&database_root).is_err());
+        let migrate = || {
+            migrate_database_to(
+                &database_root,
+                test_schema::tables::SCHEMA_VERSION,
+                test_schema::tables::migrate_relations,
+                test_schema::tables::DERIVED_RELATION_PATHS,
+            )
+        };
+        migrate().unwrap();
+        assert_eq!(database_schema_version(&database_root).unwrap(), 2);
+        assert!(!derived_path.exists());
+        // The relation is rewritten in the format in which it was stored.
+        let (extension, other_extension) = match format {
+            StorageFormat::Raw => ("rc", "rcc"),
+            StorageFormat::Columnar => ("rcc", "rc"),
+        };
+        assert!(relations_path
+            .join("terminators")
+            .with_extension(extension)
+            .exists());
+        assert!(!relations_path
+            .join("terminators")
+            .with_extension(other_extension)
+            .exists());
+
+        let loader = test_schema::tables::Loader::new(database_root.clone());
+        let labels = loader.load_labels();
+        let strings = loader.load_strings();
+        let terminators: Vec<_> = loader
+            .load_terminators()
+            .iter()
+            .map(|&(block, kind, name, cleanup, successor_count)| {
+                (
+                    block.index(),
+                    kind,
+                    strings[labels[name]].clone(),
+                    cleanup.map(|cleanup| cleanup.index()),
+                    successor_count,
+                )
+            })
+            .collect();
+        assert_eq!(
+            terminators,
+            vec![
+                (
+                    entry.index(),
+                    TerminatorKind::Return,
+                    String::from("entry"),
+                    Some(cleanup.index()),
+                    None
+                ),
+                (
+                    cleanup.index(),
+                    TerminatorKind::Unknown,
+                    String::from("cleanup"),
+                    None,
+                    None
+                ),
+            ]
+        );
+        test_schema::tables::Tables::load_multifile(&database_root).unwrap();
+
+        // Migrating again does nothing, but a database written by a newer
+        // schema is rejected.
+        migrate().unwrap();
+        let version_path = database_root.join("schema_version.json");
+        std::fs::write(version_path, "3").unwrap();
+        assert!(test_schema::tables::Tables::load_multifile(&database_root).is_err());
+        assert!(test_schema::tables::Loader::new(database_root.clone())
+            .read_counters()
+            .is_err());
+        assert!(migrate().is_err());
+    }
+
+    #[test]
+    fn test_migrate_raw_database() {
+        check_migration(StorageFormat::Raw);
+    }
+
+    #[test]
+    fn test_migrate_columnar_database() {
+        check_migration(StorageFormat::Columnar);
+    }
+}
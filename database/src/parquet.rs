@@ -0,0 +1,237 @@
+// Licensed under the MIT license <LICENSE or
+// http://opensource.org/licenses/MIT>. This file may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Helper functions for exporting relations to Apache Parquet files.
+//!
+//! Each column of a relation is stored as an Arrow column with the same
+//! name. Integers up to 64 bits keep their types, enums are stored as strings
+//! containing the name of the variant, `u128` and `(u64, u64)` values are
+//! stored as zero-padded hexadecimal strings, and `i128` values as decimal
+//! strings. Optional columns are nullable.
+
+use anyhow::{Context, Result};
+use arrow_array::builder::{
+    ArrayBuilder, BooleanBuilder, Int64Builder, PrimitiveBuilder, StringBuilder, UInt16Builder,
+    UInt32Builder, UInt64Builder, UInt8Builder,
+};
+use arrow_array::{ArrayRef, ArrowPrimitiveType, RecordBatch};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use log::trace;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// The number of facts written into a single record batch.
+pub(crate) const BATCH_SIZE: usize = 1 << 20;
+
+/// A value that can be stored in an Arrow column.
+pub trait ArrowValue {
+    type Builder: ArrayBuilder + Default;
+    /// True if the column may contain nulls.
+    const NULLABLE: bool = false;
+    fn data_type() -> DataType;
+    fn append_to(&self, builder: &mut Self::Builder);
+}
+
+/// A builder of a column that may contain nulls.
+pub trait NullableBuilder {
+    fn append_null_value(&mut self);
+}
+
+impl<T: ArrowPrimitiveType> NullableBuilder for PrimitiveBuilder<T> {
+    fn append_null_value(&mut self) {
+        self.append_null();
+    }
+}
+
+impl NullableBuilder for BooleanBuilder {
+    fn append_null_value(&mut self) {
+        self.append_null();
+    }
+}
+
+impl NullableBuilder for StringBuilder {
+    fn append_null_value(&mut self) {
+        self.append_null();
+    }
+}
+
+macro_rules! impl_arrow_value {
+    ($($typ:ty => ($builder:ty, $data_type:expr)),*) => {
+        $(
+            impl ArrowValue for $typ {
+                type Builder = $builder;
+                fn data_type() -> DataType {
+                    $data_type
+                }
+                fn append_to(&self, builder: &mut Self::Builder) {
+                    builder.append_value(*self);
+                }
+            }
+        )*
+    };
+}
+
+impl_arrow_value!(
+    u8 => (UInt8Builder, DataType::UInt8),
+    u16 => (UInt16Builder, DataType::UInt16),
+    u32 => (UInt32Builder, DataType::UInt32),
+    u64 => (UInt64Builder, DataType::UInt64),
+    i64 => (Int64Builder, DataType::Int64),
+    bool => (BooleanBuilder, DataType::Boolean)
+);
+
+impl ArrowValue for u128 {
+    type Builder = StringBuilder;
+    fn data_type() -> DataType {
+        DataType::Utf8
+    }
+    fn append_to(&self, builder: &mut Self::Builder) {
+        builder.append_value(format!("{:032x}", self));
+    }
+}
+
+impl ArrowValue for i128 {
+    type Builder = StringBuilder;
+    fn data_type() -> DataType {
+        DataType::Utf8
+    }
+    fn append_to(&self, builder: &mut Self::Builder) {
+        builder.append_value(self.to_string());
+    }
+}
+
+impl ArrowValue for (u64, u64) {
+    type Builder = StringBuilder;
+    fn data_type() -> DataType {
+        DataType::Utf8
+    }
+    fn append_to(&self, builder: &mut Self::Builder) {
+        builder.append_value(format!("{:016x}{:016x}", self.0, self.1));
+    }
+}
+
+impl ArrowValue for String {
+    type Builder = StringBuilder;
+    fn data_type() -> DataType {
+        DataType::Utf8
+    }
+    fn append_to(&self, builder: &mut Self::Builder) {
+        builder.append_value(self);
+    }
+}
+
+impl<T: ArrowValue> ArrowValue for Option<T>
+where
+    T::Builder: NullableBuilder,
+{
+    type Builder = T::Builder;
+    const NULLABLE: bool = true;
+    fn data_type() -> DataType {
+        T::data_type()
+    }
+    fn append_to(&self, builder: &mut Self::Builder) {
+        match self {
+            Some(value) => value.append_to(builder),
+            None => builder.append_null_value(),
+        }
+    }
+}
+
+/// A column of type `T`.
+pub(crate) fn field<T: ArrowValue>(name: &str) -> Field {
+    Field::new(name, T::data_type(), T::NULLABLE)
+}
+
+/// A column containing resolved strings.
+pub(crate) fn string_field(name: &str) -> Field {
+    Field::new(name, DataType::Utf8, false)
+}
+
+/// Finish the builder and return the built column.
+pub(crate) fn finish_column(mut builder: impl ArrayBuilder) -> ArrayRef {
+    builder.finish()
+}
+
+/// Writes record batches into a Parquet file.
+pub(crate) struct ParquetWriter {
+    path: PathBuf,
+    schema: SchemaRef,
+    writer: ::parquet::arrow::ArrowWriter<std::fs::File>,
+}
+
+impl ParquetWriter {
+    pub(crate) fn create(path: &Path, fields: Vec<Field>) -> Result<Self> {
+        trace!("[enter] create_parquet({:?})", path);
+        let schema = Arc::new(Schema::new(fields));
+        let file =
+            std::fs::File::create(path).with_context(|| format!("Unable to create {:?}", path))?;
+        let properties = ::parquet::file::properties::WriterProperties::builder()
+            .set_compression(::parquet::basic::Compression::SNAPPY)
+            .build();
+        let writer = ::parquet::arrow::ArrowWriter::try_new(file, schema.clone(), Some(properties))
+            .with_context(|| format!("Unable to create a Parquet writer for {:?}", path))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            schema,
+            writer,
+        })
+    }
+    pub(crate) fn write(&mut self, columns: Vec<ArrayRef>) -> Result<()> {
+        let batch = RecordBatch::try_new(self.schema.clone(), columns)?;
+        self.writer
+            .write(&batch)
+            .with_context(|| format!("Unable to write {:?}", self.path))
+    }
+    pub(crate) fn close(self) -> Result<()> {
+        self.writer
+            .close()
+            .with_context(|| format!("Unable to write {:?}", self.path))?;
+        trace!("[exit] create_parquet({:?})", self.path);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tables;
+    use crate::test_support::{create_single_build_tables, test_dir};
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn test_export_parquet() {
+        use arrow_array::{Array, StringArray, UInt32Array};
+        let (mut tables, build) = create_single_build_tables();
+        tables.register_build_crate_types(build, String::from("lib"));
+        let database_root = test_dir("rust-corpus-parquet-export-test");
+        tables.store_multifile(&database_root).unwrap();
+        let parquet_path = database_root.join("build_crate_types.parquet");
+        let loader = tables::Loader::new(database_root);
+        loader
+            .export_parquet("build_crate_types", &parquet_path, true)
+            .unwrap();
+        let file = std::fs::File::open(&parquet_path).unwrap();
+        let mut reader =
+            ::parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder::try_new(file)
+                .unwrap()
+                .build()
+                .unwrap();
+        let batch = reader.next().unwrap().unwrap();
+        assert_eq!(batch.num_rows(), 1);
+        let string_column = |name: &str| -> String {
+            let column = batch.column_by_name(name).unwrap();
+            let column = column.as_any().downcast_ref::<StringArray>().unwrap();
+            column.value(0).to_string()
+        };
+        let build_column = batch.column_by_name("build").unwrap();
+        let build_column = build_column.as_any().downcast_ref::<UInt32Array>().unwrap();
+        assert_eq!(build_column.value(0), 0);
+        assert_eq!(string_column("crate_type_text"), "lib");
+        assert_eq!(string_column("build_package"), "package1");
+        assert_eq!(string_column("build_krate"), "crate1");
+        assert_eq!(string_column("build_crate_hash"), "abc");
+        assert!(loader
+            .export_parquet("no_such_relation", &parquet_path, false)
+            .is_err());
+    }
+}
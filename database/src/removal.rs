@@ -0,0 +1,257 @@
+// Licensed under the MIT license <LICENSE or
+// http://opensource.org/licenses/MIT>. This file may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Removing builds together with all facts that belong to them.
+//!
+//! The facts of a build are found by following the ownership chain encoded in
+//! the schema: a build owns its root modules, the modules own submodules and
+//! items, the items own MIR bodies, and so on. Since incremental ids are
+//! allocated per crate, every fact that mentions a removed incremental id
+//! belongs to a removed build. The interned values that are no longer used
+//! are garbage-collected afterwards, which renumbers the remaining ones.
+
+use crate::segments::SEGMENTS_DIR;
+use crate::tables::{IdOwners, RemovedIds, StorageFormat, Tables, DERIVED_RELATION_PATHS};
+use crate::transaction::Transaction;
+use anyhow::Result;
+use log::info;
+use std::collections::HashSet;
+use std::path::Path;
+
+/// A build that was removed from the database.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemovedBuild {
+    pub package: String,
+    pub version: String,
+    pub krate: String,
+    pub crate_hash: u64,
+}
+
+impl RemovedBuild {
+    /// The name of the file into which the extractor saved the crate.
+    pub fn crate_file_name(&self) -> String {
+        format!("{}_{:016x}.bincode", self.krate, self.crate_hash)
+    }
+}
+
+/// What `Tables::remove_builds` or `Tables::extract_builds` removed.
+#[derive(Debug, Default)]
+pub struct RemovalReport {
+    pub builds: Vec<RemovedBuild>,
+    /// The number of removed facts.
+    pub facts: usize,
+    /// The number of removed interned values.
+    pub interned_values: usize,
+}
+
+impl Tables {
+    /// Remove the builds of `package` with the given `version` (all versions
+    /// if `None`) together with all facts that belong to them, and
+    /// garbage-collect the interned values that are no longer used.
+    ///
+    /// Note that the interned ids of the remaining facts change.
+    pub fn remove_builds(&mut self, package: &str, version: Option<&str>) -> RemovalReport {
+        self.remove_builds_where(|build_package, build_version| {
+            build_package == package && version.map_or(true, |version| build_version == version)
+        })
+    }
+
+    /// Keep only the builds whose package and version are in `builds`
+    /// together with the facts and interned values they use, and renumber
+    /// all ids densely. The result is a self-contained database that can be
+    /// stored with `store_multifile`. Returns what was removed.
+    pub fn extract_builds(&mut self, builds: &HashSet<(String, String)>) -> RemovalReport {
+        let report = self.remove_builds_where(|package, version| {
+            !builds.contains(&(package.to_string(), version.to_string()))
+        });
+        self.compact_incremental_ids();
+        report
+    }
+
+    /// Remove the builds for whose package and version `is_removed` returns
+    /// true together with all facts that belong to them.
+    fn remove_builds_where(&mut self, is_removed: impl Fn(&str, &str) -> bool) -> RemovalReport {
+        let mut report = RemovalReport::default();
+        let mut removed = RemovedIds::default();
+        let mut removed_crates = HashSet::new();
+        let mut remaining_crates = HashSet::new();
+        {
+            let strings = &self.interning_tables.strings;
+            let package_names = &self.interning_tables.package_names;
+            let package_versions = &self.interning_tables.package_versions;
+            let crate_names = &self.interning_tables.crate_names;
+            for (build, &(build_package, build_version, krate, crate_hash, _edition)) in
+                self.interning_tables.builds.iter_values().enumerate()
+            {
+                let build_package = &strings[package_names[build_package]];
+                let build_version = &strings[package_versions[build_version]];
+                if is_removed(build_package, build_version) {
+                    report.builds.push(RemovedBuild {
+                        package: build_package.clone(),
+                        version: build_version.clone(),
+                        krate: strings[crate_names[krate]].clone(),
+                        crate_hash: crate_hash.index() as u64,
+                    });
+                    removed.builds.insert(build.into());
+                    removed_crates.insert((krate, crate_hash));
+                } else {
+                    remaining_crates.insert((krate, crate_hash));
+                }
+            }
+        }
+        if report.builds.is_empty() {
+            return report;
+        }
+        report.facts = self.remove_facts(&mut removed);
+        // The def paths are shared between builds, so they do not own the
+        // facts that mention incremental ids. The facts without incremental
+        // ids, such as the ones that describe the definitions themselves,
+        // belong to the owner of their first interned id, as in
+        // `diff_databases`: a def path belongs to the removed builds if no
+        // remaining build compiled its crate.
+        let mut owners = IdOwners::new(&self.counters);
+        for build in 0..self.interning_tables.builds.len() {
+            let build = build.into();
+            owners.builds.insert(build, removed.builds.contains(&build));
+        }
+        for (def_path, &(krate, crate_hash, _, _, _)) in
+            self.interning_tables.def_paths.iter_values().enumerate()
+        {
+            let is_removed = removed_crates.contains(&(krate, crate_hash))
+                && !remaining_crates.contains(&(krate, crate_hash));
+            owners.def_paths.insert(def_path.into(), is_removed);
+        }
+        report.facts += self.remove_interned_facts_owned_by(&owners, true);
+        report.interned_values = self.collect_interning_garbage();
+        report
+    }
+}
+
+/// Store `tables` as the new contents of the database updated by
+/// `transaction`. The segments and the derived relations are removed because
+/// the ids of the tables may differ from the stored ones.
+pub fn replace_database(
+    tables: &Tables,
+    transaction: &Transaction,
+    format: StorageFormat,
+) -> Result<()> {
+    tables.store_multifile_with_format(transaction.path(), format)?;
+    transaction.remove(Path::new(SEGMENTS_DIR))?;
+    remove_derived_relations(transaction, DERIVED_RELATION_PATHS)
+}
+
+/// Remove the files of the derived relations when `transaction` is
+/// committed. They have to be computed again from the updated database.
+pub(crate) fn remove_derived_relations(
+    transaction: &Transaction,
+    derived_relation_paths: &[&str],
+) -> Result<()> {
+    for path in derived_relation_paths {
+        for extension in &["rc", "rcc"] {
+            let path = Path::new(path).with_extension(extension);
+            if transaction.database_root().join(&path).exists() {
+                info!("Removing the derived relation {:?}", path);
+                transaction.remove(&path)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tables;
+    use crate::test_support::{create_build_tables, test_dir};
+
+    #[test]
+    fn test_remove_builds() {
+        let mut merger = tables::TableMerger::new(tables::Tables::default());
+        merger.merge(create_build_tables("kept", "1.0.0"));
+        merger.merge(create_build_tables("removed", "1.0.0"));
+        merger.merge(create_build_tables("removed", "2.0.0"));
+        let mut tables = merger.into_tables();
+
+        let report = tables.remove_builds("removed", Some("2.0.0"));
+        assert_eq!(report.builds.len(), 1);
+        assert_eq!(report.builds[0].version, "2.0.0");
+        assert_eq!(
+            report.builds[0].crate_file_name(),
+            "removed_crate_0000000000000001.bincode"
+        );
+        // Both versions have the same crate hash, so the def path of the
+        // removed build is still owned by the other version.
+        assert_eq!(tables.relations.function_unsafe_use.len(), 3);
+        let report = tables.remove_builds("removed", None);
+        assert_eq!(report.builds.len(), 1);
+        assert_eq!(tables.remove_builds("missing", None).builds.len(), 0);
+        assert!(tables.check_references().is_ok());
+
+        // Since the kept build was merged first, removing the other builds
+        // produces the same facts and interned values as if only the kept
+        // build had been loaded.
+        let mut expected = tables::TableMerger::new(tables::Tables::default());
+        expected.merge(create_build_tables("kept", "1.0.0"));
+        let expected = expected.tables();
+        assert_eq!(
+            bincode::serialize(&tables.relations).unwrap(),
+            bincode::serialize(&expected.relations).unwrap()
+        );
+        assert_eq!(
+            bincode::serialize(&tables.interning_tables).unwrap(),
+            bincode::serialize(&expected.interning_tables).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_extract_builds() {
+        let mut merger = tables::TableMerger::new(tables::Tables::default());
+        merger.merge(create_build_tables("removed", "1.0.0"));
+        merger.merge(create_build_tables("kept", "1.0.0"));
+        merger.merge(create_build_tables("kept", "2.0.0"));
+        let mut tables = merger.into_tables();
+
+        let builds = [(String::from("kept"), String::from("1.0.0"))]
+            .into_iter()
+            .collect();
+        let report = tables.extract_builds(&builds);
+        assert_eq!(report.builds.len(), 2);
+
+        // The incremental ids are renumbered as if only the kept build had
+        // been loaded. The interned strings shared with the removed build
+        // keep their order, which differs from the one of the kept build.
+        let mut expected = tables::TableMerger::new(tables::Tables::default());
+        expected.merge(create_build_tables("kept", "1.0.0"));
+        let expected = expected.tables();
+        assert_eq!(
+            tables.relations.root_modules.facts,
+            expected.relations.root_modules.facts
+        );
+        assert_eq!(
+            tables.relations.mir_cfgs.facts,
+            expected.relations.mir_cfgs.facts
+        );
+        assert_eq!(
+            tables.relations.subscopes.facts,
+            expected.relations.subscopes.facts
+        );
+        assert_eq!(
+            tables.relations.basic_blocks.facts,
+            expected.relations.basic_blocks.facts
+        );
+        assert_eq!(
+            tables.interning_tables.strings.len(),
+            expected.interning_tables.strings.len()
+        );
+        assert_eq!(
+            bincode::serialize(&tables.counters).unwrap(),
+            bincode::serialize(&expected.counters).unwrap()
+        );
+
+        let temp_dir = test_dir("rust-corpus-extract-test");
+        tables.store_multifile(&temp_dir).unwrap();
+        let loader = tables::Loader::new(temp_dir);
+        assert_eq!(loader.load_builds().len(), 1);
+        assert!(loader.check_integrity().unwrap().is_ok());
+    }
+}
@@ -0,0 +1,163 @@
+// Licensed under the MIT license <LICENSE or
+// http://opensource.org/licenses/MIT>. This file may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Converting interned ids into human readable values.
+//!
+//! The schema generates an implementation of `Resolve` for every id and
+//! enum type. Interned ids are expanded recursively through the interning
+//! tables until they reach `strings`, hashes are printed in hexadecimal,
+//! enums are printed as the names of their variants, and other ids are kept
+//! as they are. For example, a `Build` is resolved into the package name,
+//! the package version, the crate name, the crate hash, and the edition.
+
+use crate::tables::Loader;
+use serde::Serialize;
+
+/// Resolves values by using the interning tables of `loader`, which are
+/// loaded on the first use. `L` is the loader of the schema that declares the
+/// resolved types.
+pub struct Resolver<'a, L = Loader> {
+    loader: &'a L,
+}
+
+impl<'a, L> Clone for Resolver<'a, L> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, L> Copy for Resolver<'a, L> {}
+
+impl<'a, L> Resolver<'a, L> {
+    pub fn new(loader: &'a L) -> Self {
+        Self { loader }
+    }
+    pub fn loader(&self) -> &'a L {
+        self.loader
+    }
+    /// Convert `value` into a human readable value.
+    pub fn resolve<T: Resolve<'a, L>>(&self, value: T) -> T::Output {
+        value.resolve(self)
+    }
+}
+
+/// A value that can be converted into a human readable value, which can be,
+/// for example, written into a CSV file.
+pub trait Resolve<'a, L = Loader> {
+    type Output: Serialize;
+    fn resolve(&self, resolver: &Resolver<'a, L>) -> Self::Output;
+}
+
+macro_rules! impl_resolve_as_self {
+    ($($typ:ty),*) => {
+        $(
+            impl<'a, L> Resolve<'a, L> for $typ {
+                type Output = $typ;
+                fn resolve(&self, _resolver: &Resolver<'a, L>) -> Self::Output {
+                    self.clone()
+                }
+            }
+        )*
+    };
+}
+
+impl_resolve_as_self!(bool, u8, u16, u32, u64, u128, i64, i128, usize, String);
+
+impl<'a, L, T: Resolve<'a, L>> Resolve<'a, L> for &T {
+    type Output = T::Output;
+    fn resolve(&self, resolver: &Resolver<'a, L>) -> Self::Output {
+        (**self).resolve(resolver)
+    }
+}
+
+impl<'a, L, T: Resolve<'a, L>> Resolve<'a, L> for Option<T> {
+    type Output = Option<T::Output>;
+    fn resolve(&self, resolver: &Resolver<'a, L>) -> Self::Output {
+        self.as_ref().map(|value| value.resolve(resolver))
+    }
+}
+
+macro_rules! impl_resolve_for_tuple {
+    ($($name:ident: $index:tt),*) => {
+        impl<'a, TLoader, $($name: Resolve<'a, TLoader>),*> Resolve<'a, TLoader> for ($($name,)*) {
+            type Output = ($($name::Output,)*);
+            fn resolve(&self, resolver: &Resolver<'a, TLoader>) -> Self::Output {
+                ($(self.$index.resolve(resolver),)*)
+            }
+        }
+    };
+}
+
+impl_resolve_for_tuple!(A: 0);
+impl_resolve_for_tuple!(A: 0, B: 1);
+impl_resolve_for_tuple!(A: 0, B: 1, C: 2);
+impl_resolve_for_tuple!(A: 0, B: 1, C: 2, D: 3);
+impl_resolve_for_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4);
+impl_resolve_for_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5);
+impl_resolve_for_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6);
+impl_resolve_for_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7);
+impl_resolve_for_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7, I: 8);
+impl_resolve_for_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7, I: 8, J: 9);
+impl_resolve_for_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7, I: 8, J: 9, K: 10);
+impl_resolve_for_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7, I: 8, J: 9, K: 10, L: 11);
+impl_resolve_for_tuple!(
+    A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7, I: 8, J: 9, K: 10, L: 11, M: 12
+);
+impl_resolve_for_tuple!(
+    A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7, I: 8, J: 9, K: 10, L: 11, M: 12, N: 13
+);
+impl_resolve_for_tuple!(
+    A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7, I: 8, J: 9, K: 10, L: 11, M: 12, N: 13,
+    O: 14
+);
+impl_resolve_for_tuple!(
+    A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7, I: 8, J: 9, K: 10, L: 11, M: 12, N: 13,
+    O: 14, P: 15
+);
+
+#[cfg(test)]
+mod tests {
+    use super::Resolver;
+    use crate::test_support::{create_build_tables, test_dir};
+    use crate::{tables, types};
+
+    #[test]
+    fn test_resolver() {
+        let temp_dir = test_dir("rust-corpus-resolver-test");
+        let tables = create_build_tables("resolved", "1.0.0");
+        tables.store_multifile(&temp_dir).unwrap();
+        let (build, _) = tables.relations.build_crate_types.facts[0];
+        let (block, def_path, _) = tables.relations.basic_blocks.facts[0];
+        let statement = tables.relations.statements.facts[0];
+
+        let loader = tables::Loader::new(temp_dir);
+        let resolver = Resolver::new(&loader);
+        assert_eq!(
+            resolver.resolve(build),
+            (
+                "resolved",
+                "1.0.0",
+                "resolved_crate",
+                String::from("1"),
+                "2018"
+            )
+        );
+        assert_eq!(
+            resolver.resolve(def_path),
+            (
+                "resolved_crate",
+                String::from("1"),
+                "resolved::f",
+                String::from("12"),
+                "summary"
+            )
+        );
+        let (_, resolved_block, index, kind, _) = resolver.resolve(statement);
+        assert_eq!((resolved_block, index, kind), (block, 0usize.into(), "Nop"));
+        assert_eq!(
+            resolver.resolve((types::BasicBlockKind::Entry, Some(build))),
+            (String::from("Entry"), Some(resolver.resolve(build)))
+        );
+    }
+}
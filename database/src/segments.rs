@@ -0,0 +1,264 @@
+// Licensed under the MIT license <LICENSE or
+// http://opensource.org/licenses/MIT>. This file may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Append-only segments of a multifile database.
+//!
+//! The first update writes the relations, the interning tables, and the
+//! counters directly into the database root; we call these files the base.
+//! Every following update writes a new segment `segments/NNNNNN` that has the
+//! same layout as the base, but contains only the new facts and the values
+//! that were appended to the interning tables. The counters of a segment are
+//! the counters after the update, so the counters of the last segment are the
+//! current ones.
+//!
+//! Readers concatenate the facts and the interning table values of the base
+//! and all segments in order. Compaction merges the segments into the base.
+
+use crate::data_structures::{Facts, InterningTable, InterningTableKey, InterningTableValue};
+use crate::tables::{StorageFormat, Tables};
+use crate::transaction::{recover_database, Transaction};
+use anyhow::{Context, Result};
+use log::info;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The directory inside the database root that contains the segments.
+pub(crate) const SEGMENTS_DIR: &str = "segments";
+
+/// The directories of the segments of the database in the order in which
+/// they were written.
+pub fn segment_paths(database_root: &Path) -> Result<Vec<PathBuf>> {
+    let segments_path = database_root.join(SEGMENTS_DIR);
+    if !segments_path.exists() {
+        return Ok(Vec::new());
+    }
+    let mut segments = Vec::new();
+    for entry in fs::read_dir(&segments_path)
+        .with_context(|| format!("Unable to read {:?}", segments_path))?
+    {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            segments.push(entry.path());
+        }
+    }
+    // The names are zero-padded numbers, so they sort in the order in which
+    // the segments were written.
+    segments.sort();
+    Ok(segments)
+}
+
+/// The path, relative to the database root, of the segment that should be
+/// written by the next update.
+pub fn next_segment_path(database_root: &Path) -> Result<PathBuf> {
+    let mut next = 1;
+    for segment in segment_paths(database_root)? {
+        let number: u32 = segment
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.parse().ok())
+            .with_context(|| format!("Invalid segment name {:?}", segment))?;
+        next = next.max(number + 1);
+    }
+    Ok(Path::new(SEGMENTS_DIR).join(format!("{:06}", next)))
+}
+
+/// Read the facts stored in the relation file `file_name` of the base and all
+/// segments. Segments that do not contain the relation are skipped. The
+/// facts of the base and the segments are kept as separate chunks, so the
+/// base stays memory-mapped.
+pub(crate) fn read_relation<T: Copy>(
+    database_root: &Path,
+    file_name: &str,
+    read: impl Fn(PathBuf) -> Result<Facts<T>>,
+) -> Result<Facts<T>> {
+    let mut chunks = vec![read(database_root.join(file_name))?];
+    for segment in segment_paths(database_root)? {
+        let path = segment.join(file_name);
+        if path.with_extension("rc").exists() || path.with_extension("rcc").exists() {
+            chunks.push(read(path)?);
+        }
+    }
+    Ok(Facts::concat(chunks))
+}
+
+/// The files that store the relation `file_name` in the base and all
+/// segments.
+pub(crate) fn relation_files(database_root: &Path, file_name: &str) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let roots = std::iter::once(database_root.to_path_buf()).chain(segment_paths(database_root)?);
+    for root in roots {
+        for extension in &["rc", "rcc"] {
+            let path = root.join(file_name).with_extension(extension);
+            if path.exists() {
+                files.push(path);
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Read an interning table from the base and append the values stored in all
+/// segments. `load` loads the table from the given root directory.
+pub(crate) fn read_interning_table<K, V>(
+    database_root: &Path,
+    load: impl Fn(&Path) -> Result<InterningTable<K, V>>,
+) -> Result<InterningTable<K, V>>
+where
+    K: InterningTableKey,
+    V: InterningTableValue,
+{
+    let mut table = load(database_root)?;
+    for segment in segment_paths(database_root)? {
+        table.append(load(&segment)?);
+    }
+    Ok(table)
+}
+
+/// The path of the file that stores the current counters.
+pub(crate) fn counters_path(database_root: &Path) -> Result<PathBuf> {
+    let root = segment_paths(database_root)?
+        .pop()
+        .unwrap_or_else(|| database_root.to_path_buf());
+    Ok(root.join("counters.bincode"))
+}
+
+/// Merge all segments of the database into the base. The relations are
+/// rewritten in the specified format.
+pub fn compact_database(database_root: &Path, format: StorageFormat) -> Result<()> {
+    recover_database(database_root)?;
+    let segments = segment_paths(database_root)?;
+    if segments.is_empty() {
+        info!("The database has no segments to compact");
+        return Ok(());
+    }
+    info!("Compacting {} segments", segments.len());
+    let transaction = Transaction::begin(database_root)?;
+    let tables = Tables::load_multifile(database_root)?;
+    tables.store_multifile_with_format(transaction.path(), format)?;
+    // Release the memory-mapped files before they are replaced.
+    drop(tables);
+    transaction.remove(Path::new(SEGMENTS_DIR))?;
+    transaction.commit()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compact_database, next_segment_path, segment_paths};
+    use crate::tables;
+    use crate::test_support::{create_crate_tables, test_dir};
+
+    #[test]
+    fn test_segments() {
+        let database_root = test_dir("rust-corpus-segments-test");
+        // The first update writes the base.
+        let mut merger = tables::TableMerger::new(tables::Tables::default());
+        merger.merge(create_crate_tables("crate1"));
+        merger
+            .store_segment(&database_root, tables::StorageFormat::Raw)
+            .unwrap();
+        crate::store_schema_version(&database_root);
+        assert_eq!(
+            next_segment_path(&database_root).unwrap(),
+            std::path::Path::new("segments/000001")
+        );
+        // The following updates write only the new facts into segments.
+        for krate in &["crate2", "crate3"] {
+            let mut merger = tables::TableMerger::new_segment(&database_root).unwrap();
+            merger.merge(create_crate_tables(krate));
+            let segment = database_root.join(next_segment_path(&database_root).unwrap());
+            merger
+                .store_segment(&segment, tables::StorageFormat::Columnar)
+                .unwrap();
+        }
+        assert_eq!(segment_paths(&database_root).unwrap().len(), 2);
+
+        let mut expected = tables::TableMerger::new(tables::Tables::default());
+        for krate in &["crate1", "crate2", "crate3"] {
+            expected.merge(create_crate_tables(krate));
+        }
+        let expected = expected.tables();
+
+        // The base stays memory-mapped while the facts are iterated over or
+        // indexed, which uses the chunks of the base and the segments
+        // directly. Only accessing the facts as a slice concatenates them.
+        let loader = tables::Loader::new(database_root.clone());
+        let expected_rows: Vec<crate::rows::BasicBlock> = expected
+            .relations
+            .basic_blocks
+            .iter()
+            .map(|&fact| fact.into())
+            .collect();
+        let basic_blocks = loader.load_basic_blocks_rows();
+        let (_, usage) = loader.memory_usage().relations[0];
+        assert!(usage.mapped > 0);
+        assert_eq!(basic_blocks.slices().count(), 3);
+        assert_eq!(basic_blocks.len(), expected_rows.len());
+        assert_eq!(
+            basic_blocks.iter().collect::<Vec<_>>(),
+            expected_rows.iter().collect::<Vec<_>>()
+        );
+        assert_eq!(basic_blocks.to_vec(), expected_rows);
+        assert_eq!(basic_blocks.get(2), expected_rows.get(2));
+        assert_eq!(
+            loader.load_basic_blocks(),
+            expected.relations.basic_blocks.facts
+        );
+        assert_eq!(loader.memory_usage().relations[0].1, usage);
+        let slice: &[_] = &basic_blocks;
+        let (_, concatenated_usage) = loader.memory_usage().relations[0];
+        assert_eq!(
+            concatenated_usage.owned,
+            usage.owned + std::mem::size_of_val(slice)
+        );
+        drop(basic_blocks);
+
+        let check = |database_root: &std::path::Path| {
+            let loaded = tables::Tables::load_multifile(database_root).unwrap();
+            assert_eq!(
+                loaded.relations.statements.iter().collect::<Vec<_>>(),
+                expected.relations.statements.iter().collect::<Vec<_>>()
+            );
+            assert_eq!(
+                loaded
+                    .relations
+                    .build_crate_types
+                    .iter()
+                    .collect::<Vec<_>>(),
+                expected
+                    .relations
+                    .build_crate_types
+                    .iter()
+                    .collect::<Vec<_>>()
+            );
+            assert_eq!(
+                loaded
+                    .interning_tables
+                    .strings
+                    .iter_values()
+                    .collect::<Vec<_>>(),
+                expected
+                    .interning_tables
+                    .strings
+                    .iter_values()
+                    .collect::<Vec<_>>()
+            );
+            assert_eq!(loaded.counters.basicblocks, expected.counters.basicblocks);
+            let loader = tables::Loader::new(database_root.to_path_buf());
+            assert_eq!(
+                &*loader.load_basic_blocks(),
+                expected.relations.basic_blocks.facts.as_slice()
+            );
+            assert_eq!(
+                loader.load_def_paths().len(),
+                expected.interning_tables.def_paths.len()
+            );
+            assert!(loader.check_integrity().unwrap().is_ok());
+        };
+        check(&database_root);
+        compact_database(&database_root, tables::StorageFormat::Raw).unwrap();
+        assert!(segment_paths(&database_root).unwrap().is_empty());
+        assert!(!database_root.join("segments").exists());
+        check(&database_root);
+    }
+}
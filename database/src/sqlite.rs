@@ -0,0 +1,230 @@
+// Licensed under the MIT license <LICENSE or
+// http://opensource.org/licenses/MIT>. This file may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Helper functions for exporting the database to SQLite.
+//!
+//! Each relation and each interning table is exported as a separate table.
+//! The columns of the tables have the same names as the parameters of the
+//! relations. Values that do not fit into a signed 64 bit integer are stored
+//! as follows:
+//!
+//! * `u64` values are stored as `INTEGER` by reinterpreting their bits as
+//!   `i64`, so hashes may appear negative;
+//! * `u128` and `(u64, u64)` values are stored as zero-padded hexadecimal
+//!   `TEXT`.
+//!
+//! Enums are stored as `TEXT` containing the name of the variant.
+
+use anyhow::{Context, Result};
+use log::trace;
+use rusqlite::types::Value;
+use std::path::Path;
+
+/// A value that can be stored in a column of an SQLite table.
+pub trait SqlValue {
+    /// The SQL type of the column.
+    const SQL_TYPE: &'static str;
+    /// True if the column may contain `NULL`.
+    const NULLABLE: bool = false;
+    fn to_sql_value(&self) -> Value;
+}
+
+macro_rules! impl_sql_integer {
+    ($($typ:ty),*) => {
+        $(
+            impl SqlValue for $typ {
+                const SQL_TYPE: &'static str = "INTEGER";
+                fn to_sql_value(&self) -> Value {
+                    Value::Integer(i64::from(*self))
+                }
+            }
+        )*
+    };
+}
+
+impl_sql_integer!(u8, u16, u32, i64, bool);
+
+impl SqlValue for u64 {
+    const SQL_TYPE: &'static str = "INTEGER";
+    fn to_sql_value(&self) -> Value {
+        Value::Integer(*self as i64)
+    }
+}
+
+impl SqlValue for u128 {
+    const SQL_TYPE: &'static str = "TEXT";
+    fn to_sql_value(&self) -> Value {
+        Value::Text(format!("{:032x}", self))
+    }
+}
+
+impl SqlValue for i128 {
+    const SQL_TYPE: &'static str = "TEXT";
+    fn to_sql_value(&self) -> Value {
+        Value::Text(self.to_string())
+    }
+}
+
+impl SqlValue for (u64, u64) {
+    const SQL_TYPE: &'static str = "TEXT";
+    fn to_sql_value(&self) -> Value {
+        Value::Text(format!("{:016x}{:016x}", self.0, self.1))
+    }
+}
+
+impl SqlValue for String {
+    const SQL_TYPE: &'static str = "TEXT";
+    fn to_sql_value(&self) -> Value {
+        Value::Text(self.clone())
+    }
+}
+
+impl<T: SqlValue> SqlValue for Option<T> {
+    const SQL_TYPE: &'static str = T::SQL_TYPE;
+    const NULLABLE: bool = true;
+    fn to_sql_value(&self) -> Value {
+        match self {
+            Some(value) => value.to_sql_value(),
+            None => Value::Null,
+        }
+    }
+}
+
+/// Quote an SQL identifier.
+fn quote(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+/// Create the table `name` with the given columns and return the statement
+/// for inserting rows into it. A column is given by its name, its SQL type,
+/// and whether it is nullable.
+pub(crate) fn create_table<'conn>(
+    transaction: &'conn rusqlite::Transaction,
+    name: &str,
+    columns: &[(&str, &str, bool)],
+) -> Result<rusqlite::Statement<'conn>> {
+    trace!("[enter] create_table({})", name);
+    let column_definitions: Vec<_> = columns
+        .iter()
+        .map(|(column, typ, nullable)| {
+            if *nullable {
+                format!("{} {}", quote(column), typ)
+            } else {
+                format!("{} {} NOT NULL", quote(column), typ)
+            }
+        })
+        .collect();
+    transaction
+        .execute_batch(&format!(
+            "CREATE TABLE {} ({});",
+            quote(name),
+            column_definitions.join(", ")
+        ))
+        .with_context(|| format!("Failed to create table {}", name))?;
+    let placeholders = vec!["?"; columns.len()].join(", ");
+    let statement = transaction
+        .prepare(&format!(
+            "INSERT INTO {} VALUES ({})",
+            quote(name),
+            placeholders
+        ))
+        .with_context(|| format!("Failed to prepare insertion into {}", name))?;
+    trace!("[exit] create_table({})", name);
+    Ok(statement)
+}
+
+/// Create the view `{name}_resolved` that contains all columns of the table
+/// `name` and a column `{column}_text` for each column that is an interned
+/// string id. `resolved_columns` maps a column to the chain of interning
+/// tables that need to be followed to obtain the string.
+pub(crate) fn create_resolving_view(
+    transaction: &rusqlite::Transaction,
+    name: &str,
+    resolved_columns: &[(&str, &[&str])],
+) -> Result<()> {
+    let mut selected = vec!["r.*".to_string()];
+    let mut joins = Vec::new();
+    for (column_index, (column, tables)) in resolved_columns.iter().enumerate() {
+        let mut previous = format!("r.{}", quote(column));
+        for (table_index, table) in tables.iter().enumerate() {
+            let alias = format!("j{}_{}", column_index, table_index);
+            joins.push(format!(
+                "LEFT JOIN {} AS {} ON {}.id = {}",
+                quote(table),
+                alias,
+                alias,
+                previous
+            ));
+            previous = format!("{}.value", alias);
+        }
+        selected.push(format!(
+            "{} AS {}",
+            previous,
+            quote(&format!("{}_text", column))
+        ));
+    }
+    let view_name = format!("{}_resolved", name);
+    transaction
+        .execute_batch(&format!(
+            "CREATE VIEW {} AS SELECT {} FROM {} AS r {};",
+            quote(&view_name),
+            selected.join(", "),
+            quote(name),
+            joins.join(" ")
+        ))
+        .with_context(|| format!("Failed to create view {}", view_name))?;
+    Ok(())
+}
+
+/// Create a fresh SQLite database at `path`, removing the old one if it
+/// exists.
+pub(crate) fn create_database(path: &Path) -> Result<rusqlite::Connection> {
+    if path.exists() {
+        std::fs::remove_file(path).with_context(|| format!("Failed to remove {:?}", path))?;
+    }
+    let connection = rusqlite::Connection::open(path)
+        .with_context(|| format!("Failed to create SQLite database {:?}", path))?;
+    // The database is written only once, so we do not need the journal.
+    connection.execute_batch("PRAGMA journal_mode = OFF; PRAGMA synchronous = OFF;")?;
+    Ok(connection)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::{create_single_build_tables, test_dir};
+    use crate::{tables, types};
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_export_sqlite() {
+        let (mut tables, build) = create_single_build_tables();
+        tables.register_build_crate_types(build, String::from("lib"));
+        tables.register_spans(
+            types::Span::from(0usize),
+            types::SpanExpansionKind::MacroBang,
+            String::from("expansion"),
+            String::from("file.rs"),
+            1,
+            2,
+        );
+        let database_root = test_dir("rust-corpus-sqlite-export-test");
+        tables.store_multifile(&database_root).unwrap();
+        let sqlite_path = database_root.join("database.sqlite");
+        let loader = tables::Loader::new(database_root);
+        loader.export_sqlite(&sqlite_path).unwrap();
+        let connection = rusqlite::Connection::open(&sqlite_path).unwrap();
+        let query =
+            |sql: &str| -> String { connection.query_row(sql, [], |row| row.get(0)).unwrap() };
+        assert_eq!(
+            query("SELECT crate_type_text FROM build_crate_types_resolved"),
+            "lib"
+        );
+        assert_eq!(query("SELECT krate_text FROM builds_resolved"), "crate1");
+        assert_eq!(query("SELECT expansion_kind FROM spans"), "MacroBang");
+        assert_eq!(
+            query("SELECT file_name_text FROM spans_resolved"),
+            "file.rs"
+        );
+    }
+}
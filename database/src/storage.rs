@@ -4,13 +4,17 @@
 
 //! Helper functions for serializing and deserializing.
 
-use crate::data_structures::{InterningTable, InterningTableKey, InterningTableValue, Relation};
+use crate::data_structures::{
+    Facts, InterningTable, InterningTableKey, InterningTableValue, Relation,
+};
 use crate::tables::Tables;
-use anyhow::{Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use log::trace;
 use serde::{Deserialize, Serialize};
-use std::io::{BufReader, Read, Write};
-use std::path::Path;
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
 
 pub fn load<T>(path: &Path) -> Result<T>
 where
@@ -52,9 +56,503 @@ where
     trace!("[exit] save({:?})", path);
 }
 
+/// The on-disk format used for storing relations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageFormat {
+    /// A raw dump of facts (`.rc` files) that can be memory-mapped.
+    Raw,
+    /// Delta-encoded and zstd-compressed columns (`.rcc` files).
+    Columnar,
+}
+
+/// Remove the files storing the relation in any of the formats.
+/// ``path`` – the path **without** the extension.
+pub(crate) fn remove_relation_files(path: &Path) -> Result<()> {
+    for extension in ["rc", "rcc"] {
+        let file_path = path.with_extension(extension);
+        match std::fs::remove_file(&file_path) {
+            Ok(()) => {}
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+            Err(error) => {
+                return Err(error).with_context(|| format!("Failed to remove {:?}", file_path))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The description of a relation or an interning table that is stored in the
+/// header of its file. It is used to give a precise error message when the
+/// file does not match the current schema.
+#[derive(Debug, Clone, Copy)]
+pub struct FileSchema<'a> {
+    pub name: &'a str,
+    /// The hash of the schema as computed by the DSL.
+    pub hash: u64,
+    /// The names and types of the columns.
+    pub columns: &'a [(&'a str, &'a str)],
+}
+
+/// The magic number of raw relation (`.rc`) files.
+pub(crate) const RAW_MAGIC: [u8; 8] = *b"QRATESRC";
+/// The magic number of columnar relation (`.rcc`) files.
+pub(crate) const COLUMNAR_MAGIC: [u8; 8] = *b"QRATESCC";
+/// The version of the relation file format. It must be increased each time
+/// the layout of the files changes.
+const FORMAT_VERSION: u32 = 2;
+/// The first format version whose `.rc` relation files store the facts as
+/// the `#[repr(C)]` row structs instead of tuples. The files written by the
+/// older versions are still read by converting their tuples into rows.
+const ROW_FORMAT_VERSION: u32 = 2;
+/// The size of the fixed part of the header: the magic number, the format
+/// version, and the header size.
+const HEADER_PREFIX_SIZE: usize = 16;
+/// The header size is padded to a multiple of this value so that the facts
+/// following the header are correctly aligned when the file is memory-mapped.
+const HEADER_ALIGNMENT: usize = 16;
+/// The size of the header of the `.rc` files written before the header
+/// contained the magic number: the schema hash, the fact size, and the number
+/// of facts.
+const LEGACY_HEADER_SIZE: usize = 24;
+
+/// The header of a relation file.
+///
+/// The header consists of the magic number, the format version, the size of
+/// the header, the schema hash, the fact size, the number of facts, the
+/// CRC32 checksum of the data following the header, the relation name, and
+/// the names and types of the columns. All integers are little endian and
+/// strings are prefixed by their length.
+///
+/// The `.rc` files written before this header was introduced start directly
+/// with the schema hash, the fact size, and the number of facts. They are
+/// still read, but their data is not checksummed.
+#[derive(Debug)]
+pub(crate) struct FileHeader {
+    /// The number of facts.
+    pub len: usize,
+    /// The checksum of the data following the header, or `None` for files
+    /// with the legacy header.
+    pub checksum: Option<u32>,
+    /// The size of the header in bytes, which is also the offset of the data.
+    pub size: usize,
+}
+
+impl FileHeader {
+    /// Encode the header. ``fact_size`` is 0 for files that do not store
+    /// facts as raw memory.
+    pub(crate) fn encode(
+        magic: [u8; 8],
+        schema: &FileSchema,
+        fact_size: usize,
+        len: usize,
+        checksum: u32,
+    ) -> Vec<u8> {
+        fn push_str(buf: &mut Vec<u8>, value: &str) {
+            buf.extend_from_slice(&(value.len() as u32).to_le_bytes());
+            buf.extend_from_slice(value.as_bytes());
+        }
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&magic);
+        buf.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        buf.extend_from_slice(&0u32.to_le_bytes()); // The header size, patched below.
+        buf.extend_from_slice(&schema.hash.to_le_bytes());
+        buf.extend_from_slice(&(fact_size as u64).to_le_bytes());
+        buf.extend_from_slice(&(len as u64).to_le_bytes());
+        buf.extend_from_slice(&checksum.to_le_bytes());
+        push_str(&mut buf, schema.name);
+        buf.extend_from_slice(&(schema.columns.len() as u32).to_le_bytes());
+        for (name, typ) in schema.columns {
+            push_str(&mut buf, name);
+            push_str(&mut buf, typ);
+        }
+        let padding = (HEADER_ALIGNMENT - buf.len() % HEADER_ALIGNMENT) % HEADER_ALIGNMENT;
+        buf.resize(buf.len() + padding, 0);
+        let size = buf.len() as u32;
+        buf[12..16].copy_from_slice(&size.to_le_bytes());
+        buf
+    }
+    /// Read the header from `reader` and check that it matches `schema`.
+    /// ``fact_size`` is 0 for files that do not store facts as raw memory.
+    pub(crate) fn read(
+        reader: &mut impl Read,
+        magic: [u8; 8],
+        schema: &FileSchema,
+        fact_size: usize,
+        path: &Path,
+    ) -> Result<Self> {
+        if cfg!(target_endian = "big") {
+            unreachable!("We assume little endian machines");
+        }
+        let mut prefix_bytes = [0u8; HEADER_PREFIX_SIZE];
+        reader.read_exact(&mut prefix_bytes).with_context(|| {
+            format!(
+                "The file {:?} of `{}` is truncated: it is too short to contain a header",
+                path, schema.name
+            )
+        })?;
+        let mut prefix = &prefix_bytes[..];
+        if prefix.take_bytes::<8>() != magic {
+            if magic == RAW_MAGIC {
+                return Self::read_legacy(prefix_bytes, reader, schema, fact_size, path);
+            }
+            bail!(
+                "The file {:?} of `{}` was written by an older version of the database or \
+                 is not a relation file; please re-run the extraction",
+                path,
+                schema.name
+            );
+        }
+        let version = u32::from_le_bytes(prefix.take_bytes());
+        if !(1..=FORMAT_VERSION).contains(&version) {
+            bail!(
+                "The file {:?} of `{}` uses the format version {}, but {} is expected; \
+                 please re-run the extraction",
+                path,
+                schema.name,
+                version,
+                FORMAT_VERSION
+            );
+        }
+        let size = u32::from_le_bytes(prefix.take_bytes()) as usize;
+        if size < HEADER_PREFIX_SIZE {
+            bail!(
+                "The file {:?} of `{}` is corrupt: invalid header size",
+                path,
+                schema.name
+            );
+        }
+        let mut body = vec![0u8; size - HEADER_PREFIX_SIZE];
+        reader.read_exact(&mut body).with_context(|| {
+            format!(
+                "The file {:?} of `{}` is truncated: the header is incomplete",
+                path, schema.name
+            )
+        })?;
+        let corrupt = || {
+            anyhow!(
+                "The file {:?} of `{}` is corrupt: invalid header",
+                path,
+                schema.name
+            )
+        };
+        let mut body = &body[..];
+        let hash = u64::from_le_bytes(body.try_take_bytes().ok_or_else(corrupt)?);
+        let actual_fact_size = u64::from_le_bytes(body.try_take_bytes().ok_or_else(corrupt)?);
+        let len = u64::from_le_bytes(body.try_take_bytes().ok_or_else(corrupt)?) as usize;
+        let checksum = u32::from_le_bytes(body.try_take_bytes().ok_or_else(corrupt)?);
+        let name = body.try_take_str().ok_or_else(corrupt)?;
+        let column_count = u32::from_le_bytes(body.try_take_bytes().ok_or_else(corrupt)?);
+        let mut columns = Vec::new();
+        for _ in 0..column_count {
+            let column_name = body.try_take_str().ok_or_else(corrupt)?;
+            let column_type = body.try_take_str().ok_or_else(corrupt)?;
+            columns.push(format!("{}: {}", column_name, column_type));
+        }
+        if name != schema.name {
+            bail!(
+                "The file {:?} contains `{}` instead of `{}`",
+                path,
+                name,
+                schema.name
+            );
+        }
+        if hash != schema.hash {
+            let expected_columns: Vec<_> = schema
+                .columns
+                .iter()
+                .map(|(name, typ)| format!("{}: {}", name, typ))
+                .collect();
+            let details = if columns != expected_columns {
+                format!(
+                    "stored columns ({}), expected columns ({})",
+                    columns.join(", "),
+                    expected_columns.join(", ")
+                )
+            } else {
+                format!("stored hash {}, expected hash {}", hash, schema.hash)
+            };
+            bail!(
+                "The schema of `{}` changed since {:?} was written ({}); \
+                 please re-run the extraction",
+                schema.name,
+                path,
+                details
+            );
+        }
+        if actual_fact_size != fact_size as u64 {
+            bail!(
+                "The file {:?} of `{}` is corrupt: stored fact size {}, expected {}",
+                path,
+                schema.name,
+                actual_fact_size,
+                fact_size
+            );
+        }
+        Ok(Self {
+            len,
+            checksum: Some(checksum),
+            size,
+        })
+    }
+    /// Read the rest of a legacy `.rc` header whose first 16 bytes were
+    /// already read into `prefix` and check that it matches `schema`.
+    fn read_legacy(
+        prefix: [u8; HEADER_PREFIX_SIZE],
+        reader: &mut impl Read,
+        schema: &FileSchema,
+        fact_size: usize,
+        path: &Path,
+    ) -> Result<Self> {
+        let mut prefix = &prefix[..];
+        let hash = u64::from_le_bytes(prefix.take_bytes());
+        let actual_fact_size = u64::from_le_bytes(prefix.take_bytes());
+        if hash != schema.hash {
+            bail!(
+                "The file {:?} of `{}` was written by an older version of the database with \
+                 a different schema or is not a relation file; please re-run the extraction",
+                path,
+                schema.name
+            );
+        }
+        if actual_fact_size != fact_size as u64 {
+            bail!(
+                "The file {:?} of `{}` is corrupt: stored fact size {}, expected {}",
+                path,
+                schema.name,
+                actual_fact_size,
+                fact_size
+            );
+        }
+        let mut len = [0u8; 8];
+        reader.read_exact(&mut len).with_context(|| {
+            format!(
+                "The file {:?} of `{}` is truncated: the header is incomplete",
+                path, schema.name
+            )
+        })?;
+        Ok(Self {
+            len: u64::from_le_bytes(len) as usize,
+            checksum: None,
+            size: LEGACY_HEADER_SIZE,
+        })
+    }
+    /// Check that the data following the header has the expected size.
+    pub(crate) fn check_data_size(
+        &self,
+        actual_size: usize,
+        expected_size: usize,
+        schema: &FileSchema,
+        path: &Path,
+    ) -> Result<()> {
+        if actual_size < expected_size {
+            bail!(
+                "The file {:?} of `{}` is truncated: expected {} bytes of data, found {}",
+                path,
+                schema.name,
+                expected_size,
+                actual_size
+            );
+        }
+        if actual_size > expected_size {
+            bail!(
+                "The file {:?} of `{}` is corrupt: expected {} bytes of data, found {}",
+                path,
+                schema.name,
+                expected_size,
+                actual_size
+            );
+        }
+        Ok(())
+    }
+    /// Check that `data` matches the checksum stored in the header.
+    pub(crate) fn check_checksum(
+        &self,
+        data: &[u8],
+        schema: &FileSchema,
+        path: &Path,
+    ) -> Result<()> {
+        if self
+            .checksum
+            .map_or(false, |checksum| crc32fast::hash(data) != checksum)
+        {
+            bail!(
+                "The file {:?} of `{}` is corrupt: checksum mismatch",
+                path,
+                schema.name
+            );
+        }
+        Ok(())
+    }
+}
+
+/// The number of facts and the checksum of the data stored in the relation
+/// file `path`, which identify its contents. They are read from the header;
+/// only the data of the files with the legacy header is checksummed here.
+pub(crate) fn relation_file_stamp(path: &Path) -> Result<(u64, u32)> {
+    let corrupt = || anyhow!("The file {:?} is corrupt: invalid header", path);
+    let mut file =
+        std::fs::File::open(path).with_context(|| format!("Failed to open file: {:?}", path))?;
+    // The header prefix, the schema hash, the fact size, the number of facts,
+    // and the checksum.
+    let mut header = [0u8; HEADER_PREFIX_SIZE + 28];
+    file.read_exact(&mut header[..LEGACY_HEADER_SIZE])
+        .map_err(|_| corrupt())?;
+    let mut bytes = &header[..];
+    let magic = bytes.take_bytes::<8>();
+    if magic != RAW_MAGIC && magic != COLUMNAR_MAGIC {
+        let len = u64::from_le_bytes(header[16..LEGACY_HEADER_SIZE].try_into().unwrap());
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)
+            .with_context(|| format!("Failed to read {:?}", path))?;
+        return Ok((len, crc32fast::hash(&data)));
+    }
+    file.read_exact(&mut header[LEGACY_HEADER_SIZE..])
+        .map_err(|_| corrupt())?;
+    let mut body = &header[HEADER_PREFIX_SIZE + 16..];
+    let len = u64::from_le_bytes(body.take_bytes());
+    let checksum = u32::from_le_bytes(body.take_bytes());
+    Ok((len, checksum))
+}
+
+/// Check that the data of the relation or interning table file `path`
+/// matches the checksum stored in its header. The files with the legacy
+/// header have no checksum and always match. Memory-mapped files are not
+/// checksummed when they are loaded because that would read all their pages.
+pub(crate) fn check_file_checksum(path: &Path) -> Result<bool> {
+    let corrupt = || anyhow!("The file {:?} is corrupt: invalid header", path);
+    let contents = std::fs::read(path).with_context(|| format!("Failed to read {:?}", path))?;
+    let mut prefix = contents.get(..HEADER_PREFIX_SIZE).ok_or_else(corrupt)?;
+    let magic = prefix.take_bytes::<8>();
+    if magic != RAW_MAGIC && magic != COLUMNAR_MAGIC {
+        return Ok(true);
+    }
+    let _version = u32::from_le_bytes(prefix.take_bytes());
+    let size = u32::from_le_bytes(prefix.take_bytes()) as usize;
+    // The checksum follows the schema hash, the fact size, and the number of
+    // facts.
+    let mut body = contents
+        .get(HEADER_PREFIX_SIZE + 24..size)
+        .ok_or_else(corrupt)?;
+    let checksum = u32::from_le_bytes(body.try_take_bytes().ok_or_else(corrupt)?);
+    Ok(crc32fast::hash(&contents[size..]) == checksum)
+}
+
+/// Helpers for decoding the header.
+trait HeaderBytes<'a> {
+    fn try_take_bytes<const N: usize>(&mut self) -> Option<[u8; N]>;
+    fn take_bytes<const N: usize>(&mut self) -> [u8; N] {
+        self.try_take_bytes().unwrap()
+    }
+    fn try_take_str(&mut self) -> Option<&'a str>;
+}
+
+impl<'a> HeaderBytes<'a> for &'a [u8] {
+    fn try_take_bytes<const N: usize>(&mut self) -> Option<[u8; N]> {
+        if self.len() < N {
+            return None;
+        }
+        let (bytes, rest) = self.split_at(N);
+        *self = rest;
+        bytes.try_into().ok()
+    }
+    fn try_take_str(&mut self) -> Option<&'a str> {
+        let len = u32::from_le_bytes(self.try_take_bytes()?) as usize;
+        if self.len() < len {
+            return None;
+        }
+        let (bytes, rest) = self.split_at(len);
+        *self = rest;
+        std::str::from_utf8(bytes).ok()
+    }
+}
+
+/// Save the tuples `facts` as the row structs `R`. The tuples are converted
+/// in chunks so that the relation is not copied as a whole.
+///
+/// This function is safe only when R does not contain references or pointers.
+/// ``path`` – the path **without** the extension.
+pub(crate) unsafe fn save_rows<R: Copy, T: Copy + Into<R>>(
+    facts: &[T],
+    schema: &FileSchema,
+    mut path: PathBuf,
+) {
+    const CHUNK_SIZE: usize = 1 << 16;
+    path.set_extension("rc");
+    trace!("[enter] save_rows({:?})", path);
+    let mut file = std::fs::File::create(&path)
+        .unwrap_or_else(|e| panic!("Unable to create {:?}: {}", path, e));
+    let fact_size = std::mem::size_of::<R>();
+    // The checksum is not known until all rows are written, so the header is
+    // written again at the end. Its size does not depend on the checksum.
+    let header = FileHeader::encode(RAW_MAGIC, schema, fact_size, facts.len(), 0);
+    file.write_all(&header).unwrap();
+    let mut hasher = crc32fast::Hasher::new();
+    let mut rows: Vec<R> = Vec::with_capacity(CHUNK_SIZE.min(facts.len()));
+    for chunk in facts.chunks(CHUNK_SIZE) {
+        rows.clear();
+        rows.extend(chunk.iter().map(|&fact| fact.into()));
+        let data = facts_as_bytes(&rows);
+        hasher.update(data);
+        file.write_all(data).unwrap();
+    }
+    let header = FileHeader::encode(RAW_MAGIC, schema, fact_size, facts.len(), hasher.finalize());
+    file.seek(SeekFrom::Start(0)).unwrap();
+    file.write_all(&header).unwrap();
+    trace!("[exit] save_rows({:?})", path);
+}
+
+/// Read the facts of a relation stored in `path` as the row structs `R`.
+/// The rows are memory-mapped when possible (see `unsafe_map_vec`). The files
+/// written before `ROW_FORMAT_VERSION` store the tuples `T`, which are
+/// converted into rows.
+///
+/// This function is safe only when R and T do not contain references or
+/// pointers. ``path`` – the path **without** the extension.
+pub(crate) unsafe fn load_mapped_rows<R: Copy, T: Copy + Into<R>>(
+    schema: &FileSchema,
+    mut path: PathBuf,
+) -> Result<Facts<R>> {
+    path.set_extension("rc");
+    if raw_format_version(&path)? >= ROW_FORMAT_VERSION {
+        unsafe { unsafe_map_vec(schema, path) }
+    } else {
+        let facts: Facts<T> = unsafe { unsafe_map_vec(schema, path) }?;
+        Ok(Facts::Owned(
+            facts.iter().map(|&fact| fact.into()).collect(),
+        ))
+    }
+}
+
+/// The format version of the `.rc` file `path` without checking the rest of
+/// its header, or 0 for files with the legacy header.
+fn raw_format_version(path: &Path) -> Result<u32> {
+    let mut file =
+        std::fs::File::open(path).with_context(|| format!("Failed to open file: {:?}", path))?;
+    let mut prefix_bytes = [0u8; 12];
+    if file.read_exact(&mut prefix_bytes).is_err() {
+        // Too short to contain a header, which `FileHeader::read` reports.
+        return Ok(0);
+    }
+    let mut prefix = &prefix_bytes[..];
+    if prefix.take_bytes::<8>() != RAW_MAGIC {
+        return Ok(0);
+    }
+    Ok(u32::from_le_bytes(prefix.take_bytes()))
+}
+
+/// View the facts as raw bytes.
+///
 /// **Note:** this function is not marked unsafe just so that we could use unsafe blocks
 /// to mark precisely where we are performing unsafe operations.
-fn unsafe_save_vec<T: Copy>(vec: &Vec<T>, schema_hash: u64, mut path: std::path::PathBuf) {
+fn facts_as_bytes<T: Copy>(vec: &[T]) -> &[u8] {
+    let begin_ptr = vec.as_ptr() as *const u8;
+    // TODO: Is there a safe and equally fast way of doing this?
+    unsafe { std::slice::from_raw_parts(begin_ptr, std::mem::size_of_val(vec)) }
+}
+
+/// **Note:** this function is not marked unsafe just so that we could use unsafe blocks
+/// to mark precisely where we are performing unsafe operations.
+fn unsafe_save_vec<T: Copy>(vec: &[T], schema: &FileSchema, mut path: PathBuf) {
     path.set_extension("rc");
     trace!("[enter] save({:?})", path);
     let mut file = std::fs::File::create(&path)
@@ -62,56 +560,59 @@ fn unsafe_save_vec<T: Copy>(vec: &Vec<T>, schema_hash: u64, mut path: std::path:
     if cfg!(target_endian = "big") {
         unreachable!("We assume little endian machines");
     }
-    let element_size = std::mem::size_of::<T>();
-    let len = vec.len();
-    file.write_all(&(schema_hash as u64).to_le_bytes()).unwrap();
-    file.write_all(&(element_size as u64).to_le_bytes())
-        .unwrap();
-    file.write_all(&(len as u64).to_le_bytes()).unwrap();
-    let begin_ptr = vec.as_ptr() as *const u8;
-    // TODO: Is there a safe and equally fast way of doing this?
-    let data = unsafe { std::slice::from_raw_parts(begin_ptr, element_size * len) };
+    let data = facts_as_bytes(vec);
+    let header = FileHeader::encode(
+        RAW_MAGIC,
+        schema,
+        std::mem::size_of::<T>(),
+        vec.len(),
+        crc32fast::hash(data),
+    );
+    file.write_all(&header).unwrap();
     file.write_all(data).unwrap();
     trace!("[exit] save({:?})", path);
 }
 
-unsafe fn unsafe_load_vec<T: Copy>(
-    expected_relation_hash: u64,
-    mut path: std::path::PathBuf,
-) -> Result<Vec<T>> {
+unsafe fn unsafe_load_vec<T: Copy>(schema: &FileSchema, mut path: PathBuf) -> Result<Vec<T>> {
     path.set_extension("rc");
     trace!("[enter] load({:?})", path);
     let file =
         std::fs::File::open(&path).with_context(|| format!("Failed to open file: {:?}", path))?;
     let mut buf_reader = BufReader::new(file);
-    if cfg!(target_endian = "big") {
-        unreachable!("We assume little endian machines");
-    }
-    let mut buf: [u8; 8] = [0u8; 8];
-    assert_eq!(buf_reader.read(&mut buf)?, 8);
-    let actual_relation_hash = u64::from_le_bytes(buf);
-    assert_eq!(actual_relation_hash, expected_relation_hash);
-    let expected_fact_size = std::mem::size_of::<T>();
-    assert_eq!(buf_reader.read(&mut buf)?, 8);
-    let actual_fact_size = u64::from_le_bytes(buf);
-    assert_eq!(expected_fact_size, (actual_fact_size as usize));
-    assert_eq!(buf_reader.read(&mut buf)?, 8);
-    let len = u64::from_le_bytes(buf) as usize;
-    let mut vec: Vec<T> = Vec::with_capacity(len);
-    assert_eq!(vec.capacity(), len);
+    let fact_size = std::mem::size_of::<T>();
+    let header = FileHeader::read(&mut buf_reader, RAW_MAGIC, schema, fact_size, &path)?;
+    let len = header.len;
+    let mut vec: Vec<T> = Vec::new();
+    vec.try_reserve_exact(len).map_err(|_| {
+        anyhow!(
+            "The file {:?} of `{}` is corrupt: invalid number of facts {}",
+            path,
+            schema.name,
+            len
+        )
+    })?;
     let begin_ptr = vec.as_ptr() as *mut u8;
     // TODO: Is there a safe and equally fast way of doing this?
-    let data = unsafe { std::slice::from_raw_parts_mut(begin_ptr, expected_fact_size * len) };
+    let data = unsafe { std::slice::from_raw_parts_mut(begin_ptr, fact_size * len) };
     let mut total_bytes_read = 0;
     loop {
         let remainder = &mut data[total_bytes_read..];
-        let bytes_read = buf_reader.read(remainder)?;
+        if remainder.is_empty() {
+            break;
+        }
+        let bytes_read = buf_reader
+            .read(remainder)
+            .with_context(|| format!("Failed to read {:?}", path))?;
         if bytes_read == 0 {
             break;
         }
         total_bytes_read += bytes_read;
     }
-    assert_eq!(total_bytes_read, expected_fact_size * len);
+    let trailing_bytes = buf_reader
+        .read(&mut [0u8])
+        .with_context(|| format!("Failed to read {:?}", path))?;
+    header.check_data_size(total_bytes_read + trailing_bytes, data.len(), schema, &path)?;
+    header.check_checksum(data, schema, &path)?;
     unsafe {
         vec.set_len(len);
     }
@@ -119,18 +620,83 @@ unsafe fn unsafe_load_vec<T: Copy>(
     Ok(vec)
 }
 
+/// Facts that are memory-mapped from an `.rc` file.
+pub struct MappedFacts<T> {
+    mmap: memmap2::Mmap,
+    /// The offset of the facts in `mmap`.
+    offset: usize,
+    len: usize,
+    _marker: PhantomData<T>,
+}
+
+impl<T> Deref for MappedFacts<T> {
+    type Target = [T];
+    fn deref(&self) -> &[T] {
+        let begin_ptr = self.mmap[self.offset..].as_ptr() as *const T;
+        // SAFETY: `unsafe_map_vec` checked that the mapped region is large
+        // enough and correctly aligned for `len` facts of type `T`.
+        unsafe { std::slice::from_raw_parts(begin_ptr, self.len) }
+    }
+}
+
+/// Memory-map the facts stored in `path` instead of reading them into a
+/// freshly allocated vector. If the facts cannot be mapped (for example,
+/// because they would not be correctly aligned), they are read into memory.
+/// The data is not checksummed so that only the pages that are accessed are
+/// read; `check-database` verifies the checksums (see `check_file_checksum`).
+///
+/// **Note:** the caller must ensure that the file is not modified while it
+/// is mapped.
+unsafe fn unsafe_map_vec<T: Copy>(schema: &FileSchema, mut path: PathBuf) -> Result<Facts<T>> {
+    path.set_extension("rc");
+    trace!("[enter] map({:?})", path);
+    let file =
+        std::fs::File::open(&path).with_context(|| format!("Failed to open file: {:?}", path))?;
+    let mmap = unsafe { memmap2::Mmap::map(&file) }
+        .with_context(|| format!("Failed to map file: {:?}", path))?;
+    let fact_size = std::mem::size_of::<T>();
+    let header = FileHeader::read(&mut &mmap[..], RAW_MAGIC, schema, fact_size, &path)?;
+    let data = &mmap[header.size..];
+    let expected_size = header.len.checked_mul(fact_size).ok_or_else(|| {
+        anyhow!(
+            "The file {:?} of `{}` is corrupt: invalid number of facts {}",
+            path,
+            schema.name,
+            header.len
+        )
+    })?;
+    header.check_data_size(data.len(), expected_size, schema, &path)?;
+    if data.as_ptr().align_offset(std::mem::align_of::<T>()) != 0 {
+        trace!("[exit] map({:?}): misaligned, loading instead", path);
+        return unsafe { unsafe_load_vec(schema, path) }.map(Facts::Owned);
+    }
+    trace!("[exit] map({:?})", path);
+    Ok(Facts::Mapped(MappedFacts {
+        offset: header.size,
+        len: header.len,
+        mmap,
+        _marker: PhantomData,
+    }))
+}
+
 impl<T: Copy> Relation<T> {
     /// This function is safe only when T does not contain references or pointers.
-    /// ``relation_hash`` – the hash of the relation schema. It is used to prevent
-    /// loading relations that were saved with a different schema.
+    /// ``schema`` – the schema of the relation. It is stored in the file header
+    /// and used to prevent loading relations that were saved with a different
+    /// schema.
     /// ``path`` – the path **without** the extension.
-    pub unsafe fn save(&self, relation_hash: u64, path: std::path::PathBuf) {
-        unsafe_save_vec(&self.facts, relation_hash, path);
+    pub unsafe fn save(&self, schema: &FileSchema, path: PathBuf) {
+        unsafe_save_vec(&self.facts, schema, path);
     }
     /// This function is safe only when T does not contain references or pointers.
-    /// Also, ``relation_hash`` must be correctly initialized.
-    pub unsafe fn load(expected_relation_hash: u64, path: std::path::PathBuf) -> Result<Self> {
-        unsafe { unsafe_load_vec(expected_relation_hash, path).map(|vec| vec.into()) }
+    /// Also, ``schema`` must be correctly initialized.
+    pub unsafe fn load(schema: &FileSchema, path: PathBuf) -> Result<Self> {
+        unsafe { unsafe_load_vec(schema, path).map(|vec| vec.into()) }
+    }
+    /// Same as `load`, but memory-maps the file instead of reading it into
+    /// memory. The mapped file must not be modified while the facts are alive.
+    pub unsafe fn load_mapped(schema: &FileSchema, path: PathBuf) -> Result<Facts<T>> {
+        unsafe { unsafe_map_vec(schema, path) }
     }
 }
 
@@ -140,16 +706,22 @@ where
     V: InterningTableValue + Copy,
 {
     /// This function is safe only when K and V do not contain references or pointers.
-    /// ``table_hash`` – the hash of the interning table schema. It is used to prevent
-    /// loading an interning table that was saved with a different schema.
+    /// ``schema`` – the schema of the interning table. It is stored in the file
+    /// header and used to prevent loading an interning table that was saved with
+    /// a different schema.
     /// ``path`` – the path **without** the extension.
-    pub unsafe fn save(&self, table_hash: u64, path: std::path::PathBuf) {
-        unsafe_save_vec(&self.contents, table_hash, path);
+    pub unsafe fn save(&self, schema: &FileSchema, path: PathBuf) {
+        unsafe_save_vec(&self.contents, schema, path);
     }
-    /// This function is safe only when T does not contain references or pointers.
-    /// Also, ``relation_hash`` must be correctly initialized.
-    pub unsafe fn load(expected_relation_hash: u64, path: std::path::PathBuf) -> Result<Self> {
-        unsafe { unsafe_load_vec(expected_relation_hash, path).map(|vec| vec.into()) }
+    /// This function is safe only when K and V do not contain references or pointers.
+    /// Also, ``schema`` must be correctly initialized.
+    pub unsafe fn load(schema: &FileSchema, path: PathBuf) -> Result<Self> {
+        unsafe { unsafe_load_vec(schema, path).map(|vec| vec.into()) }
+    }
+    /// Same as `load`, but memory-maps the file instead of reading it into
+    /// memory. The mapped file must not be modified while the table is alive.
+    pub unsafe fn load_mapped(schema: &FileSchema, path: PathBuf) -> Result<Self> {
+        unsafe { unsafe_map_vec(schema, path).map(|facts| facts.into()) }
     }
 }
 
@@ -173,7 +745,14 @@ impl Tables {
 #[cfg(test)]
 mod tests {
 
-    use crate::data_structures::Relation;
+    use super::FileSchema;
+    use crate::data_structures::{Facts, Relation};
+
+    const TEST_SCHEMA: FileSchema = FileSchema {
+        name: "test_relation",
+        hash: 5,
+        columns: &[("x", "u32")],
+    };
 
     fn checker<T>(test_count: u32, facts: &Vec<T>)
     where
@@ -182,11 +761,11 @@ mod tests {
         let relation: Relation<T> = facts.clone().into();
         let mut test_file = std::env::temp_dir();
         test_file.push(format!("rust-corpus-relation-saving-test-{}", test_count));
-        let hash = 5;
         unsafe {
-            relation.save(hash, test_file.clone());
+            relation.save(&TEST_SCHEMA, test_file.clone());
         }
-        let loaded_relation: Relation<T> = unsafe { Relation::load(hash, test_file) }.unwrap();
+        let loaded_relation: Relation<T> =
+            unsafe { Relation::load(&TEST_SCHEMA, test_file) }.unwrap();
         let loaded_facts: Vec<T> = loaded_relation.into();
         assert_eq!(facts.len(), loaded_facts.len());
         for (x, y) in facts.iter().zip(&loaded_facts) {
@@ -194,6 +773,27 @@ mod tests {
         }
     }
 
+    /// Save `[1, 2, 3, 4, 5]` into the test file `name` and return its path
+    /// **without** the extension.
+    fn save_test_relation(name: &str) -> std::path::PathBuf {
+        let relation: Relation<u32> = vec![1, 2, 3, 4, 5].into();
+        let mut test_file = std::env::temp_dir();
+        test_file.push(name);
+        unsafe {
+            relation.save(&TEST_SCHEMA, test_file.clone());
+        }
+        test_file
+    }
+
+    fn load_error(schema: &FileSchema, path: std::path::PathBuf) -> String {
+        let result: Result<Relation<u32>, _> = unsafe { Relation::load(schema, path.clone()) };
+        let load_error = format!("{:#}", result.err().unwrap());
+        let result: Result<Facts<u32>, _> = unsafe { Relation::load_mapped(schema, path) };
+        let map_error = format!("{:#}", result.err().unwrap());
+        assert_eq!(load_error, map_error);
+        load_error
+    }
+
     #[test]
     fn test_saving_and_loading_relations1() {
         checker(1, &vec![1u8, 2u8, 3u8]);
@@ -229,14 +829,171 @@ mod tests {
         checker(5, &facts);
     }
     #[test]
-    #[should_panic]
-    fn test_saving_and_loading_relations6() {
-        let relation: Relation<u32> = vec![1, 2, 3, 4, 5].into();
+    fn test_mapping_relations() {
+        let facts: Vec<(u8, u64, u8, u16)> = (0..1000u16)
+            .map(|i| (i as u8, u64::from(i) * 3, (i / 7) as u8, i))
+            .collect();
+        let relation: Relation<_> = facts.clone().into();
         let mut test_file = std::env::temp_dir();
-        test_file.push("rust-corpus-relation-saving-test-6");
+        test_file.push("rust-corpus-relation-mapping-test");
         unsafe {
-            relation.save(5, test_file.clone());
+            relation.save(&TEST_SCHEMA, test_file.clone());
         }
-        let _: Result<Relation<u32>, _> = unsafe { Relation::load(6, test_file) };
+        let mapped: Facts<(u8, u64, u8, u16)> =
+            unsafe { Relation::load_mapped(&TEST_SCHEMA, test_file) }.unwrap();
+        assert!(matches!(mapped, Facts::Mapped(_)));
+        assert_eq!(&mapped[..], &facts[..]);
+    }
+    #[test]
+    fn test_saving_and_loading_rows() {
+        /// The row struct of `(u8, u64, u16)`, which is larger than the tuple
+        /// because its fields are not reordered.
+        #[repr(C)]
+        #[derive(Debug, Clone, Copy, PartialEq)]
+        struct Row {
+            a: u8,
+            b: u64,
+            c: u16,
+        }
+        impl From<(u8, u64, u16)> for Row {
+            fn from((a, b, c): (u8, u64, u16)) -> Self {
+                Self { a, b, c }
+            }
+        }
+        // More facts than fit into a single chunk of `save_rows`.
+        let facts: Vec<(u8, u64, u16)> = (0..100_000u32)
+            .map(|i| (i as u8, u64::from(i) * 3, (i / 7) as u16))
+            .collect();
+        let rows: Vec<Row> = facts.iter().map(|&fact| fact.into()).collect();
+        let mut test_file = std::env::temp_dir();
+        test_file.push("rust-corpus-relation-rows-test");
+        let rc_file = test_file.with_extension("rc");
+        unsafe { super::save_rows::<Row, _>(&facts, &TEST_SCHEMA, test_file.clone()) };
+        let mapped: Facts<Row> = unsafe {
+            super::load_mapped_rows::<Row, (u8, u64, u16)>(&TEST_SCHEMA, test_file.clone())
+        }
+        .unwrap();
+        assert!(matches!(mapped, Facts::Mapped(_)));
+        assert_eq!(&mapped[..], &rows[..]);
+        drop(mapped);
+        assert!(super::check_file_checksum(&rc_file).unwrap());
+
+        // The files of the format version 1 store the tuples.
+        let relation: Relation<_> = facts.into();
+        unsafe { relation.save(&TEST_SCHEMA, test_file.clone()) };
+        let mut contents = std::fs::read(&rc_file).unwrap();
+        contents[8..12].copy_from_slice(&1u32.to_le_bytes());
+        std::fs::write(&rc_file, &contents).unwrap();
+        let loaded: Facts<Row> =
+            unsafe { super::load_mapped_rows::<Row, (u8, u64, u16)>(&TEST_SCHEMA, test_file) }
+                .unwrap();
+        assert!(matches!(loaded, Facts::Owned(_)));
+        assert_eq!(&loaded[..], &rows[..]);
+    }
+    #[test]
+    fn test_saving_and_loading_relations6() {
+        let test_file = save_test_relation("rust-corpus-relation-saving-test-6");
+        let schema = FileSchema {
+            hash: 6,
+            columns: &[("x", "u64")],
+            ..TEST_SCHEMA
+        };
+        let error = load_error(&schema, test_file);
+        assert!(
+            error.contains("The schema of `test_relation` changed"),
+            "{}",
+            error
+        );
+        assert!(error.contains("stored columns (x: u32)"), "{}", error);
+        assert!(error.contains("re-run the extraction"), "{}", error);
+    }
+    #[test]
+    fn test_loading_wrong_relation() {
+        let test_file = save_test_relation("rust-corpus-relation-wrong-name-test");
+        let schema = FileSchema {
+            name: "other_relation",
+            ..TEST_SCHEMA
+        };
+        let error = load_error(&schema, test_file);
+        assert!(
+            error.contains("contains `test_relation` instead of `other_relation`"),
+            "{}",
+            error
+        );
+    }
+    #[test]
+    fn test_loading_truncated_relation() {
+        let test_file = save_test_relation("rust-corpus-relation-truncated-test");
+        let rc_file = test_file.with_extension("rc");
+        let contents = std::fs::read(&rc_file).unwrap();
+        std::fs::write(&rc_file, &contents[..contents.len() - 3]).unwrap();
+        let error = load_error(&TEST_SCHEMA, test_file.clone());
+        assert!(error.contains("is truncated"), "{}", error);
+        std::fs::write(&rc_file, &contents[..10]).unwrap();
+        let error = load_error(&TEST_SCHEMA, test_file);
+        assert!(error.contains("too short to contain a header"), "{}", error);
+    }
+    #[test]
+    fn test_loading_corrupt_relation() {
+        let test_file = save_test_relation("rust-corpus-relation-corrupt-test");
+        let rc_file = test_file.with_extension("rc");
+        let mut contents = std::fs::read(&rc_file).unwrap();
+        let last = contents.len() - 1;
+        contents[last] ^= 0xff;
+        std::fs::write(&rc_file, &contents).unwrap();
+        let result: Result<Relation<u32>, _> =
+            unsafe { Relation::load(&TEST_SCHEMA, test_file.clone()) };
+        let error = format!("{:#}", result.err().unwrap());
+        assert!(error.contains("checksum mismatch"), "{}", error);
+        // Mapping the file does not read its data, so the corruption is
+        // detected only by an explicit check.
+        let mapped: Facts<u32> =
+            unsafe { Relation::load_mapped(&TEST_SCHEMA, test_file.clone()) }.unwrap();
+        drop(mapped);
+        assert!(!super::check_file_checksum(&rc_file).unwrap());
+        contents[0] = b'X';
+        std::fs::write(&rc_file, &contents).unwrap();
+        let error = load_error(&TEST_SCHEMA, test_file);
+        assert!(error.contains("older version of the database"), "{}", error);
+    }
+    #[test]
+    fn test_loading_legacy_relation() {
+        let mut test_file = std::env::temp_dir();
+        test_file.push("rust-corpus-relation-legacy-test");
+        let rc_file = test_file.with_extension("rc");
+        let facts = [1u32, 2, 3, 4, 5];
+        let legacy_file = |hash: u64, fact_size: u64| {
+            let mut contents = Vec::new();
+            contents.extend_from_slice(&hash.to_le_bytes());
+            contents.extend_from_slice(&fact_size.to_le_bytes());
+            contents.extend_from_slice(&(facts.len() as u64).to_le_bytes());
+            contents.extend_from_slice(super::facts_as_bytes(&facts));
+            contents
+        };
+        std::fs::write(&rc_file, legacy_file(TEST_SCHEMA.hash, 4)).unwrap();
+        let loaded: Relation<u32> =
+            unsafe { Relation::load(&TEST_SCHEMA, test_file.clone()) }.unwrap();
+        assert_eq!(loaded.facts, facts);
+        let mapped: Facts<u32> =
+            unsafe { Relation::load_mapped(&TEST_SCHEMA, test_file.clone()) }.unwrap();
+        assert_eq!(&mapped[..], &facts[..]);
+        drop(mapped);
+
+        std::fs::write(&rc_file, legacy_file(TEST_SCHEMA.hash + 1, 4)).unwrap();
+        let error = load_error(&TEST_SCHEMA, test_file.clone());
+        assert!(error.contains("with a different schema"), "{}", error);
+        assert!(error.contains("re-run the extraction"), "{}", error);
+        std::fs::write(&rc_file, legacy_file(TEST_SCHEMA.hash, 8)).unwrap();
+        let error = load_error(&TEST_SCHEMA, test_file.clone());
+        assert!(
+            error.contains("stored fact size 8, expected 4"),
+            "{}",
+            error
+        );
+        let mut contents = legacy_file(TEST_SCHEMA.hash, 4);
+        contents.pop();
+        std::fs::write(&rc_file, contents).unwrap();
+        let error = load_error(&TEST_SCHEMA, test_file);
+        assert!(error.contains("is truncated"), "{}", error);
     }
 }
@@ -0,0 +1,151 @@
+// Licensed under the MIT license <LICENSE or
+// http://opensource.org/licenses/MIT>. This file may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Helpers shared by the tests of the database modules.
+
+use crate::{tables, types};
+use std::path::PathBuf;
+
+/// The code generated from `test_schema/schema.dl`, which uses the features
+/// that the core schema does not use yet. The schema is too small to use all
+/// generated functions.
+#[allow(dead_code, unused_mut, unused_variables, clippy::all)]
+pub(crate) mod test_schema {
+    include!(concat!(env!("OUT_DIR"), "/test_schema.rs"));
+}
+
+/// The code generated from `test_schema/schema_v0.dl`, the version 0 of
+/// `test_schema`, which is used for testing its migrations.
+#[allow(dead_code, unused_mut, unused_variables, clippy::all)]
+pub(crate) mod test_schema_v0 {
+    include!(concat!(env!("OUT_DIR"), "/test_schema_v0.rs"));
+}
+
+/// The path of the test directory `name` in the temporary directory. If the
+/// directory is left over from a previous run, it is removed.
+pub(crate) fn test_dir(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(name);
+    if path.exists() {
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+    path
+}
+
+/// Create tables with a single build of `crate1`, which is the version
+/// `version1` of `package1` and has the crate hash `0xabc`. The strings of
+/// the build are interned in the order of the columns of `builds`.
+pub(crate) fn create_single_build_tables() -> (tables::Tables, types::Build) {
+    let mut tables = tables::Tables::default();
+    let build = tables.register_builds(
+        String::from("package1"),
+        String::from("version1"),
+        String::from("crate1"),
+        0xabcusize.into(),
+        String::from("edition1"),
+    );
+    (tables, build)
+}
+
+/// Create tables with a single build of `krate` that has one basic block
+/// containing one statement.
+pub(crate) fn create_crate_tables(krate: &str) -> tables::Tables {
+    let mut tables = tables::Tables::default();
+    let build = tables.register_builds(
+        format!("{}-package", krate),
+        String::from("1.0.0"),
+        krate.to_string(),
+        1usize.into(),
+        String::from("2018"),
+    );
+    tables.register_build_crate_types(build, String::from("lib"));
+    let def_path = tables.register_def_paths(
+        krate.to_string(),
+        1usize.into(),
+        String::from("relative_def_id1"),
+        (1u64, 2u64).into(),
+        format!("summary_{}", krate),
+    );
+    let (block,) = tables.register_basic_blocks(def_path, types::BasicBlockKind::Entry);
+    let scope = tables.get_fresh_scope();
+    let statement = tables.get_fresh_statement();
+    tables.register_statements(statement, block, 0usize.into(), String::from("Nop"), scope);
+    tables
+}
+
+/// Create the tables of a build whose facts follow the whole ownership
+/// chain from the build to the statements of a function body.
+pub(crate) fn create_build_tables(package: &str, version: &str) -> tables::Tables {
+    let mut tables = tables::Tables::default();
+    let krate = format!("{}_crate", package);
+    let build = tables.register_builds(
+        package.to_string(),
+        version.to_string(),
+        krate.clone(),
+        1usize.into(),
+        String::from("2018"),
+    );
+    tables.register_build_crate_types(build, String::from("lib"));
+    let (root_module,) = tables.register_root_modules(build);
+    let def_path = tables.register_def_paths(
+        krate,
+        1usize.into(),
+        format!("{}::f", package),
+        (1u64, 2u64).into(),
+        String::from("summary"),
+    );
+    let (module,) = tables.register_submodules(
+        def_path,
+        root_module,
+        String::from("m"),
+        types::TyVisibility::Public,
+        String::from("Rust"),
+    );
+    let return_ty = tables.get_fresh_type();
+    let (item,) = tables.register_function_definitions(
+        def_path,
+        module,
+        types::TyVisibility::Public,
+        types::Unsafety::Normal,
+        String::from("Rust"),
+        return_ty,
+    );
+    tables.register_function_unsafe_use(def_path, false);
+    let (root_scope,) = tables.register_mir_cfgs(item, def_path);
+    let span = tables.get_fresh_span();
+    let (scope,) = tables.register_subscopes(
+        root_scope,
+        types::ScopeSafety::Safe,
+        types::BlockCheckMode::DefaultBlock,
+        0,
+        span,
+    );
+    let (block,) = tables.register_basic_blocks(def_path, types::BasicBlockKind::Entry);
+    let statement = tables.get_fresh_statement();
+    tables.register_statements(statement, block, 0usize.into(), String::from("Nop"), scope);
+    tables
+}
+
+/// Create `test_schema` tables with a terminator whose cleanup block is
+/// `Some` and a terminator without a cleanup block.
+pub(crate) fn create_cleanup_tables() -> test_schema::tables::Tables {
+    use test_schema::types::TerminatorKind;
+    let mut tables = test_schema::tables::Tables::default();
+    let entry = tables.get_fresh_block();
+    let cleanup = tables.get_fresh_block();
+    tables.register_terminators(
+        entry,
+        TerminatorKind::Call,
+        String::from("entry"),
+        Some(cleanup),
+        Some(1),
+    );
+    tables.register_terminators(
+        cleanup,
+        TerminatorKind::Return,
+        String::from("cleanup"),
+        None,
+        None,
+    );
+    tables
+}
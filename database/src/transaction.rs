@@ -0,0 +1,312 @@
+// Licensed under the MIT license <LICENSE or
+// http://opensource.org/licenses/MIT>. This file may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Crash-safe updates of a multifile database.
+//!
+//! An update is first written into the staging directory `.staging` inside
+//! the database root. When all files are written, they are synced to disk and
+//! the staging directory is renamed to `.committed`; this rename is the commit
+//! point. Afterwards, the committed files are moved over the files of the
+//! database one by one, the paths that were marked for removal are deleted,
+//! and the `.committed` directory is removed.
+//!
+//! If the process crashes before the commit point, the database is left
+//! unchanged and recovery deletes the staging directory. If it crashes after
+//! the commit point, recovery moves the remaining committed files.
+
+use anyhow::{Context, Result};
+use log::{info, trace};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+const STAGING_DIR: &str = ".staging";
+const COMMITTED_DIR: &str = ".committed";
+/// The file inside the staging directory that lists the paths that should be
+/// removed from the database when the transaction is committed.
+const REMOVED_FILE: &str = ".removed";
+
+/// What `recover_database` had to do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Recovery {
+    /// The last update either completed or was never started.
+    Clean,
+    /// The last update crashed before it was committed and was discarded.
+    RolledBack,
+    /// The last update crashed after it was committed and was completed.
+    RolledForward,
+}
+
+/// Bring the database at `database_root` into a consistent state after a
+/// crash during an update.
+pub fn recover_database(database_root: &Path) -> Result<Recovery> {
+    let committed = database_root.join(COMMITTED_DIR);
+    let staging = database_root.join(STAGING_DIR);
+    let recovery = if committed.exists() {
+        info!("Completing the interrupted update of {:?}", database_root);
+        apply(&committed, database_root)?;
+        Recovery::RolledForward
+    } else if staging.exists() {
+        Recovery::RolledBack
+    } else {
+        Recovery::Clean
+    };
+    if staging.exists() {
+        info!("Discarding the uncommitted update of {:?}", database_root);
+        fs::remove_dir_all(&staging).with_context(|| format!("Unable to remove {:?}", staging))?;
+    }
+    Ok(recovery)
+}
+
+/// An update of the database at `database_root`. The new files are written
+/// into `path()` and replace the files of the database only when the
+/// transaction is committed. Files of the database that are not written into
+/// `path()` are kept.
+pub struct Transaction {
+    database_root: PathBuf,
+    staging: PathBuf,
+}
+
+impl Transaction {
+    /// Start an update of the database at `database_root`, recovering it
+    /// first if the previous update crashed.
+    pub fn begin(database_root: &Path) -> Result<Self> {
+        fs::create_dir_all(database_root)
+            .with_context(|| format!("Unable to create {:?}", database_root))?;
+        recover_database(database_root)?;
+        let staging = database_root.join(STAGING_DIR);
+        fs::create_dir(&staging).with_context(|| format!("Unable to create {:?}", staging))?;
+        Ok(Self {
+            database_root: database_root.to_path_buf(),
+            staging,
+        })
+    }
+    /// The directory into which the new files should be written. It has the
+    /// same layout as the database root.
+    pub fn path(&self) -> &Path {
+        &self.staging
+    }
+    /// The root of the updated database.
+    pub(crate) fn database_root(&self) -> &Path {
+        &self.database_root
+    }
+    /// Remove the file or directory `relative_path` of the database when the
+    /// transaction is committed. The path is relative to the database root
+    /// and must not overlap with the files written into `path()`.
+    pub fn remove(&self, relative_path: &Path) -> Result<()> {
+        let path = relative_path
+            .to_str()
+            .with_context(|| format!("Invalid path {:?}", relative_path))?;
+        let removed_path = self.staging.join(REMOVED_FILE);
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&removed_path)
+            .with_context(|| format!("Unable to open {:?}", removed_path))?;
+        writeln!(file, "{}", path)?;
+        Ok(())
+    }
+    /// Atomically replace the files of the database with the written ones.
+    pub fn commit(self) -> Result<()> {
+        trace!("[enter] commit({:?})", self.database_root);
+        sync_tree(&self.staging)?;
+        let committed = self.database_root.join(COMMITTED_DIR);
+        fs::rename(&self.staging, &committed)
+            .with_context(|| format!("Unable to rename {:?}", self.staging))?;
+        sync_directory(&self.database_root)?;
+        apply(&committed, &self.database_root)?;
+        trace!("[exit] commit({:?})", self.database_root);
+        Ok(())
+    }
+}
+
+/// Move the committed files into the database, remove the paths marked for
+/// removal, and remove the `committed` directory. Moving a file that was
+/// already moved and removing a path that was already removed is skipped, so
+/// this can be safely repeated after a crash.
+fn apply(committed: &Path, database_root: &Path) -> Result<()> {
+    let removed_path = committed.join(REMOVED_FILE);
+    let removed = if removed_path.exists() {
+        fs::read_to_string(&removed_path)
+            .with_context(|| format!("Unable to read {:?}", removed_path))?
+    } else {
+        String::new()
+    };
+    move_files(committed, database_root)?;
+    for path in removed.lines().map(|line| database_root.join(line)) {
+        let result = if path.is_dir() {
+            fs::remove_dir_all(&path)
+        } else if path.exists() {
+            fs::remove_file(&path)
+        } else {
+            continue;
+        };
+        result.with_context(|| format!("Unable to remove {:?}", path))?;
+    }
+    sync_directory(database_root)?;
+    fs::remove_dir_all(committed).with_context(|| format!("Unable to remove {:?}", committed))?;
+    sync_directory(database_root)
+}
+
+fn move_files(source: &Path, target: &Path) -> Result<()> {
+    fs::create_dir_all(target).with_context(|| format!("Unable to create {:?}", target))?;
+    for entry in fs::read_dir(source).with_context(|| format!("Unable to read {:?}", source))? {
+        let entry = entry?;
+        let target_path = target.join(entry.file_name());
+        if entry.file_name() == REMOVED_FILE {
+            // Kept until the whole transaction is applied.
+            continue;
+        }
+        if entry.file_type()?.is_dir() {
+            move_files(&entry.path(), &target_path)?;
+            sync_directory(&target_path)?;
+        } else {
+            // A relation can be stored either in the raw or in the columnar
+            // format. The file in the other format must be removed first so
+            // that it does not shadow the new one.
+            if let Some(other_path) = other_format_path(&target_path) {
+                if other_path.exists() {
+                    fs::remove_file(&other_path)
+                        .with_context(|| format!("Unable to remove {:?}", other_path))?;
+                }
+            }
+            fs::rename(entry.path(), &target_path)
+                .with_context(|| format!("Unable to move {:?}", entry.path()))?;
+        }
+    }
+    Ok(())
+}
+
+/// The path of the same relation file stored in the other format.
+fn other_format_path(path: &Path) -> Option<PathBuf> {
+    match path.extension()?.to_str()? {
+        "rc" => Some(path.with_extension("rcc")),
+        "rcc" => Some(path.with_extension("rc")),
+        _ => None,
+    }
+}
+
+/// Flush all files in the directory tree `path` to disk.
+fn sync_tree(path: &Path) -> Result<()> {
+    for entry in fs::read_dir(path).with_context(|| format!("Unable to read {:?}", path))? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            sync_tree(&entry.path())?;
+        } else {
+            fs::File::open(entry.path())
+                .and_then(|file| file.sync_all())
+                .with_context(|| format!("Unable to sync {:?}", entry.path()))?;
+        }
+    }
+    sync_directory(path)
+}
+
+/// Flush the entries of the directory to disk so that renames are durable.
+fn sync_directory(path: &Path) -> Result<()> {
+    if cfg!(unix) {
+        fs::File::open(path)
+            .and_then(|file| file.sync_all())
+            .with_context(|| format!("Unable to sync {:?}", path))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        recover_database, Recovery, Transaction, COMMITTED_DIR, REMOVED_FILE, STAGING_DIR,
+    };
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    /// Create a database with a raw relation file `relations/a.rc` and the
+    /// file `loaded_crates.json`.
+    fn create_database(name: &str) -> PathBuf {
+        let mut database_root = std::env::temp_dir();
+        database_root.push(name);
+        if database_root.exists() {
+            fs::remove_dir_all(&database_root).unwrap();
+        }
+        fs::create_dir_all(database_root.join("relations")).unwrap();
+        fs::write(database_root.join("relations/a.rc"), "old").unwrap();
+        fs::write(database_root.join("loaded_crates.json"), "old").unwrap();
+        database_root
+    }
+
+    fn read(database_root: &PathBuf, file: &str) -> String {
+        fs::read_to_string(database_root.join(file)).unwrap()
+    }
+
+    #[test]
+    fn test_commit() {
+        let database_root = create_database("rust-corpus-transaction-commit-test");
+        let transaction = Transaction::begin(&database_root).unwrap();
+        fs::create_dir_all(transaction.path().join("relations")).unwrap();
+        fs::write(transaction.path().join("relations/a.rcc"), "new").unwrap();
+        fs::write(transaction.path().join("relations/b.rc"), "new").unwrap();
+        fs::write(transaction.path().join("loaded_crates.json"), "new").unwrap();
+        assert_eq!(read(&database_root, "relations/a.rc"), "old");
+        transaction.commit().unwrap();
+        assert!(!database_root.join("relations/a.rc").exists());
+        assert_eq!(read(&database_root, "relations/a.rcc"), "new");
+        assert_eq!(read(&database_root, "relations/b.rc"), "new");
+        assert_eq!(read(&database_root, "loaded_crates.json"), "new");
+        assert!(!database_root.join(STAGING_DIR).exists());
+        assert!(!database_root.join(COMMITTED_DIR).exists());
+    }
+
+    #[test]
+    fn test_commit_removal() {
+        let database_root = create_database("rust-corpus-transaction-removal-test");
+        fs::create_dir_all(database_root.join("segments/000001")).unwrap();
+        fs::write(
+            database_root.join("segments/000001/counters.bincode"),
+            "old",
+        )
+        .unwrap();
+        let transaction = Transaction::begin(&database_root).unwrap();
+        fs::write(transaction.path().join("loaded_crates.json"), "new").unwrap();
+        transaction.remove(Path::new("segments")).unwrap();
+        transaction.remove(Path::new("missing")).unwrap();
+        transaction.commit().unwrap();
+        assert!(!database_root.join("segments").exists());
+        assert!(!database_root.join(REMOVED_FILE).exists());
+        assert_eq!(read(&database_root, "loaded_crates.json"), "new");
+        assert_eq!(read(&database_root, "relations/a.rc"), "old");
+    }
+
+    #[test]
+    fn test_recover_uncommitted() {
+        let database_root = create_database("rust-corpus-transaction-rollback-test");
+        let transaction = Transaction::begin(&database_root).unwrap();
+        fs::write(transaction.path().join("loaded_crates.json"), "new").unwrap();
+        // Simulate a crash before the commit.
+        drop(transaction);
+        assert_eq!(
+            recover_database(&database_root).unwrap(),
+            Recovery::RolledBack
+        );
+        assert_eq!(read(&database_root, "loaded_crates.json"), "old");
+        assert!(!database_root.join(STAGING_DIR).exists());
+        assert_eq!(recover_database(&database_root).unwrap(), Recovery::Clean);
+    }
+
+    #[test]
+    fn test_recover_committed() {
+        let database_root = create_database("rust-corpus-transaction-roll-forward-test");
+        // Simulate a crash after the commit point when only the loaded crates
+        // list was moved.
+        let committed = database_root.join(COMMITTED_DIR);
+        fs::create_dir_all(committed.join("relations")).unwrap();
+        fs::write(committed.join("relations/a.rc"), "new").unwrap();
+        fs::write(database_root.join("loaded_crates.json"), "new").unwrap();
+        assert_eq!(
+            recover_database(&database_root).unwrap(),
+            Recovery::RolledForward
+        );
+        assert_eq!(read(&database_root, "relations/a.rc"), "new");
+        assert_eq!(read(&database_root, "loaded_crates.json"), "new");
+        assert!(!committed.exists());
+    }
+}
@@ -26,6 +26,7 @@ mod table_filler;
 mod utils;
 
 use lazy_static::lazy_static;
+use log::warn;
 use rustc_data_structures::fx::FxHashSet;
 use rustc_hir::def_id::DefId;
 use rustc_interface::interface::Compiler;
@@ -155,6 +156,13 @@ fn analyse_with_tcx(name: String, tcx: TyCtxt, session: &Session) {
     }
 
     let tables = filler.tables;
+    let report = tables.check_references();
+    if !report.is_ok() {
+        warn!(
+            "The extracted facts of {} contain dangling references:\n{}",
+            file_name, report
+        );
+    }
     let mut path = if let Ok(results_dir_path) = std::env::var("CORPUS_RESULTS_DIR") {
         results_dir_path.into()
     } else {
@@ -154,12 +154,15 @@ impl<'a, 'b, 'tcx> MirVisitor<'a, 'b, 'tcx> {
                             );
                         (stmt, "Assign/ThreadLocalRef")
                     }
-                    mir::Rvalue::Repeat(operand, _len) => {
+                    mir::Rvalue::Repeat(operand, len) => {
                         let interned_operand = self.visit_operand(operand);
+                        // The length is unknown if it depends on a generic
+                        // parameter.
+                        let count = len.try_eval_target_usize(self.tcx, ty::ParamEnv::reveal_all());
                         let (stmt,) = self.filler.tables.register_statements_assign_repeat(
                             interned_target_type,
                             interned_operand,
-                            0, // FIXME: this code panics: len.eval_usize(self.tcx, ty::ParamEnv::reveal_all()),
+                            count,
                         );
                         (stmt, "Assign/Repeat")
                     }
@@ -342,41 +345,38 @@ impl<'a, 'b, 'tcx> MirVisitor<'a, 'b, 'tcx> {
         terminator: &mir::Terminator<'tcx>,
         basic_blocks: &HashMap<mir::BasicBlock, types::BasicBlock>,
     ) -> String {
-        let no_block = self.filler.tables.get_no_block();
         let get_maybe_block = |maybe_mir_block: &Option<_>| {
-            if let Some(ref mir_block) = maybe_mir_block {
-                basic_blocks[mir_block]
-            } else {
-                no_block
-            }
+            maybe_mir_block
+                .as_ref()
+                .map(|mir_block| basic_blocks[mir_block])
         };
         let register_unwind_action = |this: &mut Self, action: &mir::UnwindAction| match action {
             mir::UnwindAction::Continue => {
                 this.filler.tables.register_terminators_unwind_action(
                     block,
                     types::UnwindAction::Continue,
-                    no_block,
+                    None,
                 );
             }
             mir::UnwindAction::Unreachable => {
                 this.filler.tables.register_terminators_unwind_action(
                     block,
                     types::UnwindAction::Unreachable,
-                    no_block,
+                    None,
                 );
             }
             mir::UnwindAction::Terminate => {
                 this.filler.tables.register_terminators_unwind_action(
                     block,
                     types::UnwindAction::Terminate,
-                    no_block,
+                    None,
                 );
             }
             mir::UnwindAction::Cleanup(cleanup_block) => {
                 this.filler.tables.register_terminators_unwind_action(
                     block,
                     types::UnwindAction::Cleanup,
-                    basic_blocks[&cleanup_block],
+                    Some(basic_blocks[&cleanup_block]),
                 );
             }
         };
@@ -430,14 +430,12 @@ impl<'a, 'b, 'tcx> MirVisitor<'a, 'b, 'tcx> {
                 fn_span,
             } => {
                 let interned_func = self.visit_operand(func);
-                let (return_ty, destination_block) = if let Some(target_block) = target {
-                    (
-                        destination.ty(self.body, self.tcx).ty,
-                        basic_blocks[target_block],
-                    )
+                let return_ty = if target.is_some() {
+                    destination.ty(self.body, self.tcx).ty
                 } else {
-                    (self.tcx.mk_unit(), no_block)
+                    self.tcx.mk_unit()
                 };
+                let destination_block = get_maybe_block(target);
                 let interned_return_ty = self.filler.register_type(return_ty);
                 let func_ty = func.ty(self.body, self.tcx);
                 let sig = func_ty.fn_sig(self.tcx);
@@ -4,59 +4,156 @@
 
 //! Module responsible for managing the database.
 
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
 use corpus_database::tables;
 use log::{debug, error, info, trace};
 use log_derive::logfn;
 use std::collections::HashSet;
 use std::path::{Path, PathBuf};
-use std::{ffi, fs, io};
+use std::{ffi, fs};
+
+/// The file that lists the crates whose facts are already in the database.
+const LOADED_CRATES_FILE: &str = "loaded_crates.json";
+/// How many crates each worker thread loads before the loaded crates are
+/// merged into the database when merging in parallel.
+const CRATES_PER_JOB: usize = 16;
 
 pub struct DatabaseManager {
-    loaded_crates_path: PathBuf,
     loaded_crates: HashSet<String>,
     database_root: PathBuf,
+    /// True if the database already contains some crates, so the new facts
+    /// have to be stored as a segment.
+    has_base: bool,
     database: tables::TableMerger,
 }
 
 impl DatabaseManager {
-    pub fn new(database_root: &Path) -> Self {
+    pub fn new(database_root: &Path) -> Result<Self> {
         let database_root = database_root.to_path_buf();
-        let loaded_crates_path = database_root.join("loaded_crates.json");
-        let (loaded_crates, database) = if database_root.exists() {
+        if database_root.exists() {
+            match corpus_database::recover_database(&database_root)
+                .with_context(|| format!("Failed to recover the database {:?}", database_root))?
+            {
+                corpus_database::Recovery::Clean => {}
+                recovery => info!("Recovered the database: {:?}", recovery),
+            }
+        }
+        let loaded_crates_path = database_root.join(LOADED_CRATES_FILE);
+        let (loaded_crates, database) = if loaded_crates_path.exists() {
             // The database already contains some crates.
-            let file = fs::File::open(&loaded_crates_path).unwrap_or_else(|e| {
-                panic!(
-                    "The database state is corrupted. \
-                     Failed to read the list of loaded crates {:?}: {}",
-                    loaded_crates_path, e
+            let file = fs::File::open(&loaded_crates_path).with_context(|| {
+                format!(
+                    "Failed to read the list of loaded crates {:?}",
+                    loaded_crates_path
                 )
-            });
-            let loaded_crates = serde_json::from_reader(file).unwrap_or_else(|e| {
-                panic!(
-                    "The database state is corrupted. The crates list is invalid JSON {:?}: {}",
-                    loaded_crates_path, e
+            })?;
+            let loaded_crates = serde_json::from_reader(file).with_context(|| {
+                format!(
+                    "The list of loaded crates {:?} is invalid JSON",
+                    loaded_crates_path
                 )
-            });
+            })?;
+            // Only the interning tables and the counters are loaded; the new
+            // facts are stored in a separate segment.
+            let database = tables::TableMerger::new_segment(&database_root)
+                .with_context(|| format!("Failed to load the database {:?}", database_root))?;
+            (loaded_crates, database)
+        } else if database_root.join("counters.bincode").exists() {
+            // Databases created before updates became transactional deleted
+            // the list of loaded crates while storing the relations, so an
+            // interrupted update cannot be rolled back by `recover_database`.
+            bail!(
+                "The list of loaded crates {:?} is missing, so an update of the database {:?} \
+                 was interrupted before updates became atomic and its relations may be \
+                 incomplete. Remove the database and run `update-database` again to rebuild \
+                 it, or, if the relations are known to be complete, restore the list from a \
+                 backup.",
+                loaded_crates_path,
+                database_root
+            );
+        } else {
+            fs::create_dir_all(&database_root).with_context(|| {
+                format!(
+                    "Failed to create the directory for the database {:?}",
+                    database_root
+                )
+            })?;
             (
-                loaded_crates,
-                tables::Tables::load_multifile(&database_root).unwrap(),
+                HashSet::new(),
+                tables::TableMerger::new(tables::Tables::default()),
             )
-        } else {
-            fs::create_dir_all(&database_root)
-                .expect("Failed to create the directory for the database");
-            (HashSet::new(), tables::Tables::default())
         };
-        Self {
-            loaded_crates_path,
+        Ok(Self {
+            has_base: loaded_crates_path.exists(),
             loaded_crates,
             database_root,
-            database: tables::TableMerger::new(database),
-        }
+            database,
+        })
     }
+    /// Merge the new crates into the database and store them. If `jobs` is
+    /// larger than one, the crates are loaded and merged in parallel.
     #[logfn(Trace)]
-    pub fn update_database(&mut self, workspace_root: &Path) {
-        let crates = self.scan_crates(&workspace_root.join("rust-corpus"));
+    pub fn update_database(
+        &mut self,
+        workspace_root: &Path,
+        format: tables::StorageFormat,
+        jobs: usize,
+    ) {
+        let crates = scan_crates(&workspace_root.join("rust-corpus"));
+        let (success_counter, fail_counter) = if jobs > 1 {
+            self.merge_crates_in_parallel(crates, jobs)
+        } else {
+            self.merge_crates(crates)
+        };
+        info!("Successfully loaded {} crates", success_counter);
+        if fail_counter > 0 {
+            error!("Failed to load {} crates", fail_counter);
+        }
+        if success_counter == 0 && self.has_base {
+            info!("No new crates; the database is unchanged");
+            return;
+        }
+        // The segment and the list of loaded crates are written into a
+        // staging directory and added to the database only when committed.
+        let transaction = corpus_database::Transaction::begin(&self.database_root)
+            .unwrap_or_else(|e| panic!("Failed to start the update: {:?}", e));
+        // All references are between facts of the same crate, so it is
+        // enough to check the new segment.
+        let report = self.database.tables().check_references();
+        if !report.is_ok() {
+            error!(
+                "The merged database contains dangling references:\n{}",
+                report
+            );
+        }
+        // The first update writes the base of the database.
+        let segment_path = if self.has_base {
+            transaction
+                .path()
+                .join(corpus_database::next_segment_path(&self.database_root).unwrap())
+        } else {
+            transaction.path().to_path_buf()
+        };
+        self.database
+            .store_segment(&segment_path, format)
+            .unwrap_or_else(|e| panic!("Failed to store {:?}: {:?}", segment_path, e));
+        if !self.has_base {
+            corpus_database::store_schema_version(transaction.path());
+        }
+        let loaded_crates_path = transaction.path().join(LOADED_CRATES_FILE);
+        let mut file = fs::File::create(&loaded_crates_path)
+            .unwrap_or_else(|e| panic!("Unable to create {:?}: {}", loaded_crates_path, e));
+        serde_json::to_writer_pretty(&mut file, &self.loaded_crates)
+            .unwrap_or_else(|e| panic!("Unable to write {:?}: {}", loaded_crates_path, e));
+        drop(file);
+        transaction
+            .commit()
+            .unwrap_or_else(|e| panic!("Failed to commit the update: {:?}", e));
+        info!("Successfully updated the database");
+    }
+    /// Load and merge the new crates one by one. Returns the number of
+    /// successfully loaded and failed crates.
+    fn merge_crates(&mut self, crates: impl Iterator<Item = PathBuf>) -> (usize, usize) {
         let mut success_counter = 0;
         let mut fail_counter = 0;
         for path in crates {
@@ -75,37 +172,60 @@ impl DatabaseManager {
                 };
             }
         }
-        info!("Successfully loaded {} crates", success_counter);
-        if fail_counter > 0 {
-            error!("Failed to load {} crates", fail_counter);
-        }
-        // Delete the loaded crates file so that if we crash, we know that we are
-        // in a corrupted state.
-        match fs::remove_file(&self.loaded_crates_path) {
-            Ok(_) => {}
-            Err(error) => {
-                if error.kind() != io::ErrorKind::NotFound {
-                    panic!("Failed to remove the loaded crates file.")
+        (success_counter, fail_counter)
+    }
+    /// Load and merge the new crates in chunks. The crates of a chunk are
+    /// loaded concurrently and merged pairwise in a tree by `jobs` worker
+    /// threads before the result is merged into the database. This produces
+    /// the same database as `merge_crates`. Returns the number of successfully
+    /// loaded and failed crates.
+    fn merge_crates_in_parallel(
+        &mut self,
+        crates: impl Iterator<Item = PathBuf>,
+        jobs: usize,
+    ) -> (usize, usize) {
+        let new_crates: Vec<_> = crates
+            .filter_map(|path| {
+                let file_name = path.file_name().unwrap().to_str().unwrap().to_string();
+                if self.loaded_crates.contains(&file_name) {
+                    debug!("Crate already loaded: {:?} {}", path, file_name);
+                    None
+                } else {
+                    Some((file_name, path))
+                }
+            })
+            .collect();
+        let mut success_counter = 0;
+        let mut fail_counter = 0;
+        for chunk in new_crates.chunks(jobs * CRATES_PER_JOB) {
+            info!(
+                "Loading crates ({}-{} of {})",
+                success_counter + fail_counter,
+                success_counter + fail_counter + chunk.len(),
+                new_crates.len()
+            );
+            let paths = chunk.iter().map(|(_, path)| path.clone()).collect();
+            let mut loaded = Vec::with_capacity(chunk.len());
+            for ((file_name, path), tables) in chunk
+                .iter()
+                .zip(corpus_database::load_in_parallel(paths, jobs))
+            {
+                match tables {
+                    Ok(tables) => {
+                        loaded.push(tables);
+                        self.loaded_crates.insert(file_name.clone());
+                        success_counter += 1;
+                    }
+                    Err(e) => {
+                        fail_counter += 1;
+                        error!("  Error occurred while loading {:?}: {}", path, e)
+                    }
                 }
             }
+            self.database
+                .merge(corpus_database::merge_in_parallel(loaded, jobs));
         }
-        self.database
-            .tables()
-            .store_multifile(&self.database_root)
-            .unwrap();
-        info!("Successfully updated the database");
-        let mut file = fs::File::create(&self.loaded_crates_path)
-            .unwrap_or_else(|e| panic!("Unable to create {:?}: {}", self.loaded_crates_path, e));
-        serde_json::to_writer_pretty(&mut file, &self.loaded_crates)
-            .unwrap_or_else(|e| panic!("Unable to write {:?}: {}", self.loaded_crates_path, e));
-        info!("Successfully saved the loaded crates list");
-    }
-    fn scan_crates(&self, workspace_root: &Path) -> impl Iterator<Item = PathBuf> {
-        walkdir::WalkDir::new(workspace_root.canonicalize().unwrap())
-            .into_iter()
-            .filter_entry(|entry| entry.file_name() != "source")
-            .map(|entry| entry.unwrap().into_path())
-            .filter(|path| path.extension() == Some(ffi::OsStr::new("bincode")))
+        (success_counter, fail_counter)
     }
     #[logfn(Trace)]
     fn load_crate(&mut self, file_name: String, crate_path: PathBuf) -> Result<()> {
@@ -115,3 +235,133 @@ impl DatabaseManager {
         Ok(())
     }
 }
+
+/// The files into which the extractor saved the crates found in
+/// `workspace_root`.
+pub(crate) fn scan_crates(workspace_root: &Path) -> impl Iterator<Item = PathBuf> {
+    walkdir::WalkDir::new(workspace_root.canonicalize().unwrap())
+        .into_iter()
+        .filter_entry(|entry| entry.file_name() != "source")
+        .map(|entry| entry.unwrap().into_path())
+        .filter(|path| path.extension() == Some(ffi::OsStr::new("bincode")))
+}
+
+/// Remove the builds of `package` with the given `version` (all versions if
+/// `None`) and all facts that belong to them from the database. The database
+/// is rewritten without segments and derived relations, and the removed crates
+/// are dropped from the list of loaded crates so that `update-database` loads
+/// them again if they are still in the workspace.
+#[logfn(Trace)]
+pub fn remove_builds(
+    database_root: &Path,
+    package: &str,
+    version: Option<&str>,
+    format: tables::StorageFormat,
+) {
+    corpus_database::recover_database(database_root)
+        .unwrap_or_else(|e| panic!("Failed to recover the database: {:?}", e));
+    let loaded_crates_path = database_root.join(LOADED_CRATES_FILE);
+    let file = fs::File::open(&loaded_crates_path).unwrap_or_else(|e| {
+        panic!(
+            "Failed to read the list of loaded crates {:?}: {}",
+            loaded_crates_path, e
+        )
+    });
+    let mut loaded_crates: HashSet<String> = serde_json::from_reader(file).unwrap_or_else(|e| {
+        panic!(
+            "The database state is corrupted. The crates list is invalid JSON {:?}: {}",
+            loaded_crates_path, e
+        )
+    });
+    let mut tables = tables::Tables::load_multifile(database_root)
+        .unwrap_or_else(|e| panic!("Failed to load the database {:?}: {:?}", database_root, e));
+    let report = tables.remove_builds(package, version);
+    if report.builds.is_empty() {
+        info!("No matching builds; the database is unchanged");
+        return;
+    }
+    for build in &report.builds {
+        info!(
+            "Removing build {} {} (crate {}, hash {:016x})",
+            build.package, build.version, build.krate, build.crate_hash
+        );
+        if !loaded_crates.remove(&build.crate_file_name()) {
+            error!(
+                "The build {} {} is not in the list of loaded crates",
+                build.package, build.version
+            );
+        }
+    }
+    info!(
+        "Removed {} facts and {} interned values",
+        report.facts, report.interned_values
+    );
+    let transaction = corpus_database::Transaction::begin(database_root)
+        .unwrap_or_else(|e| panic!("Failed to start the update: {:?}", e));
+    corpus_database::replace_database(&tables, &transaction, format)
+        .unwrap_or_else(|e| panic!("Failed to store the database: {:?}", e));
+    // Release the memory-mapped files before they are replaced.
+    drop(tables);
+    let loaded_crates_path = transaction.path().join(LOADED_CRATES_FILE);
+    let mut file = fs::File::create(&loaded_crates_path)
+        .unwrap_or_else(|e| panic!("Unable to create {:?}: {}", loaded_crates_path, e));
+    serde_json::to_writer_pretty(&mut file, &loaded_crates)
+        .unwrap_or_else(|e| panic!("Unable to write {:?}: {}", loaded_crates_path, e));
+    drop(file);
+    transaction
+        .commit()
+        .unwrap_or_else(|e| panic!("Failed to commit the update: {:?}", e));
+    info!("Successfully removed {} builds", report.builds.len());
+}
+
+/// Write the builds whose package and version are in `builds` together with
+/// all facts and interned values they use into a new database at
+/// `output_dir`. The ids of the new database are renumbered densely. The
+/// database at `database_root` is not changed.
+#[logfn(Trace)]
+pub fn extract_subset(
+    database_root: &Path,
+    builds: &HashSet<(String, String)>,
+    output_dir: &Path,
+    format: tables::StorageFormat,
+) {
+    if output_dir.exists() {
+        panic!("The output directory {:?} already exists", output_dir);
+    }
+    corpus_database::recover_database(database_root)
+        .unwrap_or_else(|e| panic!("Failed to recover the database: {:?}", e));
+    let loaded_crates_path = database_root.join(LOADED_CRATES_FILE);
+    let file = fs::File::open(&loaded_crates_path).unwrap_or_else(|e| {
+        panic!(
+            "Failed to read the list of loaded crates {:?}: {}",
+            loaded_crates_path, e
+        )
+    });
+    let mut loaded_crates: HashSet<String> = serde_json::from_reader(file).unwrap_or_else(|e| {
+        panic!(
+            "The database state is corrupted. The crates list is invalid JSON {:?}: {}",
+            loaded_crates_path, e
+        )
+    });
+    let mut tables = tables::Tables::load_multifile(database_root)
+        .unwrap_or_else(|e| panic!("Failed to load the database {:?}: {:?}", database_root, e));
+    let report = tables.extract_builds(builds);
+    for build in &report.builds {
+        loaded_crates.remove(&build.crate_file_name());
+    }
+    info!(
+        "Removed {} builds, {} facts, and {} interned values",
+        report.builds.len(),
+        report.facts,
+        report.interned_values
+    );
+    tables
+        .store_multifile_with_format(output_dir, format)
+        .unwrap_or_else(|e| panic!("Failed to store the database: {:?}", e));
+    let loaded_crates_path = output_dir.join(LOADED_CRATES_FILE);
+    let mut file = fs::File::create(&loaded_crates_path)
+        .unwrap_or_else(|e| panic!("Unable to create {:?}: {}", loaded_crates_path, e));
+    serde_json::to_writer_pretty(&mut file, &loaded_crates)
+        .unwrap_or_else(|e| panic!("Unable to write {:?}: {}", loaded_crates_path, e));
+    info!("Successfully extracted the database into {:?}", output_dir);
+}
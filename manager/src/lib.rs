@@ -78,14 +78,158 @@ pub fn move_extracted(workspace: &Path, target_dir: &Path) {
     self::compilation_utils::move_extracted(workspace, target_dir);
 }
 
+fn storage_format(columnar: bool) -> corpus_database::tables::StorageFormat {
+    if columnar {
+        corpus_database::tables::StorageFormat::Columnar
+    } else {
+        corpus_database::tables::StorageFormat::Raw
+    }
+}
+
 /// Update the database with the new information from the downloaded crates.
+/// The facts of the new crates are appended to the database as a new
+/// segment.
+///
+/// If `columnar` is true, the relations are stored in the compressed columnar
+/// format. If `jobs` is larger than one, the crates are loaded and merged by
+/// `jobs` threads.
+#[logfn(Trace)]
+pub fn update_database(
+    workspace: &Path,
+    database_root: &Path,
+    columnar: bool,
+    jobs: usize,
+) -> anyhow::Result<()> {
+    let mut manager = DatabaseManager::new(database_root)?;
+    manager.update_database(workspace, storage_format(columnar), jobs);
+    Ok(())
+}
+
+/// Merge the segments of the database into its base.
+///
+/// If `columnar` is true, the relations are stored in the compressed columnar
+/// format.
+#[logfn(Trace)]
+pub fn compact_database(database_root: &Path, columnar: bool) {
+    corpus_database::compact_database(database_root, storage_format(columnar))
+        .unwrap_or_else(|e| panic!("Failed to compact the database: {:?}", e));
+}
+
+/// Remove the builds of `package` with the given `version` (all versions if
+/// `None`) and all facts that belong to them from the database.
+///
+/// If `columnar` is true, the relations are stored in the compressed columnar
+/// format.
+#[logfn(Trace)]
+pub fn remove_builds(database_root: &Path, package: &str, version: Option<&str>, columnar: bool) {
+    database::remove_builds(database_root, package, version, storage_format(columnar));
+}
+
+/// Write the builds of the crates listed in `crates_list_path` and all facts
+/// that belong to them into a new database at `output_dir`.
+///
+/// If `columnar` is true, the relations are stored in the compressed columnar
+/// format.
+#[logfn(Trace)]
+pub fn extract_subset(
+    database_root: &Path,
+    crates_list_path: &Path,
+    output_dir: &Path,
+    columnar: bool,
+) {
+    let crates_list = CratesList::load(crates_list_path);
+    let builds = crates_list
+        .iter()
+        .map(|krate| (krate.name().to_string(), krate.version().to_string()))
+        .collect();
+    database::extract_subset(database_root, &builds, output_dir, storage_format(columnar));
+}
+
+/// Migrate the database to the current schema version.
+#[logfn(Trace)]
+pub fn migrate_database(database_root: &Path) {
+    corpus_database::migrate_database(database_root)
+        .unwrap_or_else(|e| panic!("Failed to migrate the database: {:?}", e));
+}
+
+/// Export the database into a fresh SQLite database at `sqlite_path`.
+#[logfn(Trace)]
+pub fn export_sqlite(database_root: &Path, sqlite_path: &Path) {
+    let loader = corpus_database::tables::Loader::new(database_root.to_path_buf());
+    loader
+        .export_sqlite(sqlite_path)
+        .unwrap_or_else(|e| panic!("Failed to export the database: {:?}", e));
+}
+
+/// Export the specified relations into `<output_dir>/<relation>.parquet`
+/// files.
+#[logfn(Trace)]
+pub fn export_parquet(
+    database_root: &Path,
+    relations: &[String],
+    resolve: bool,
+    output_dir: &Path,
+) {
+    std::fs::create_dir_all(output_dir).unwrap();
+    let loader = corpus_database::tables::Loader::new(database_root.to_path_buf());
+    for relation in relations {
+        let path = output_dir.join(format!("{}.parquet", relation));
+        loader
+            .export_parquet(relation, &path, resolve)
+            .unwrap_or_else(|e| panic!("Failed to export {}: {:?}", relation, e));
+    }
+}
+
+/// Print at most `limit` rows of the relation or interning table `name` to
+/// the standard output.
+#[logfn(Trace)]
+pub fn dump_relation(
+    database_root: &Path,
+    name: &str,
+    resolve: bool,
+    limit: Option<usize>,
+    format: corpus_database::DumpFormat,
+) {
+    let loader = corpus_database::tables::Loader::new(database_root.to_path_buf());
+    let stdout = std::io::stdout();
+    let mut output = std::io::BufWriter::new(stdout.lock());
+    loader
+        .dump_relation(name, resolve, limit, format, &mut output)
+        .unwrap_or_else(|e| panic!("Failed to dump {}: {:?}", name, e));
+}
+
+/// Check the referential integrity and the checksums of the database and
+/// print a report with the violations found in each relation. Returns false
+/// if some constraint is violated or some file is corrupted.
 #[logfn(Trace)]
-pub fn update_database(workspace: &Path, database_root: &Path) {
-    let mut manager = DatabaseManager::new(database_root);
-    manager.update_database(workspace);
+pub fn check_database(database_root: &Path) -> bool {
+    let loader = corpus_database::tables::Loader::new(database_root.to_path_buf());
+    let report = loader
+        .check_integrity()
+        .unwrap_or_else(|e| panic!("Failed to check the database: {:?}", e));
+    print!("{}", report);
+    report.is_ok()
+}
+
+/// Compare the database at `database_root` with the one at `other_root`
+/// and write the differences as CSV files into `output_dir`.
+#[logfn(Trace)]
+pub fn diff_database(database_root: &Path, other_root: &Path, output_dir: &Path) {
+    corpus_database::diff_databases(database_root, other_root, output_dir)
+        .unwrap_or_else(|e| panic!("Failed to compare the databases: {:?}", e));
 }
 
 /// Run the specified query.
+///
+/// If `memory_budget` is given, the loaded relations, interning tables, and
+/// indexes are kept within that many bytes by evicting the least recently
+/// used relations. Relations that are memory-mapped from the database do not
+/// count toward the budget.
+///
+/// If `crates_dir` is given, the query runs on the crates saved by the
+/// extractor in that directory, which are merged in memory, instead of the
+/// database. The derived relations are then stored in the
+/// `derived-relations` directory of `report_path`.
 #[logfn(Trace)]
 pub fn run_query(
     query_name: &str,
@@ -93,13 +237,25 @@ pub fn run_query(
     report_path: &Path,
     workspace_path: &Path,
     sources_list_path: &Path,
+    memory_budget: Option<usize>,
+    crates_dir: Option<&Path>,
 ) {
     if !report_path.exists() {
         std::fs::create_dir_all(&report_path).unwrap();
     }
+    let mut loader = match crates_dir {
+        Some(crates_dir) => corpus_database::tables::Loader::from_crates(
+            database::scan_crates(crates_dir).collect(),
+            report_path.join("derived-relations"),
+        ),
+        None => corpus_database::tables::Loader::new(database_root.to_path_buf()),
+    };
+    if let Some(budget) = memory_budget {
+        loader = loader.with_memory_budget(budget);
+    }
     queries::run_query(
         query_name,
-        database_root,
+        &loader,
         report_path,
         workspace_path,
         sources_list_path,
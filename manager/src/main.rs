@@ -2,6 +2,7 @@
 // http://opensource.org/licenses/MIT>. This file may not be copied,
 // modified, or distributed except according to those terms.
 
+use log::error;
 use std::fs;
 use std::path::PathBuf;
 use std::time::Duration;
@@ -121,7 +122,153 @@ enum Command {
         name = "update-database",
         about = "Scan the compiled crates and update the database."
     )]
-    UpdateDatabase,
+    UpdateDatabase {
+        #[structopt(
+            long = "columnar",
+            help = "Store relations in the compressed columnar format instead of the raw one."
+        )]
+        columnar: bool,
+        #[structopt(
+            default_value = "1",
+            long = "jobs",
+            help = "The number of threads used for loading and merging crates."
+        )]
+        jobs: usize,
+    },
+    #[structopt(
+        name = "compact",
+        about = "Merge the segments written by update-database into the base of the database."
+    )]
+    Compact {
+        #[structopt(
+            long = "columnar",
+            help = "Store relations in the compressed columnar format instead of the raw one."
+        )]
+        columnar: bool,
+    },
+    #[structopt(
+        name = "remove-builds",
+        about = "Remove the builds of a package and all facts that belong to them from the database."
+    )]
+    RemoveBuilds {
+        #[structopt(long = "package", help = "The name of the package.")]
+        package: String,
+        #[structopt(
+            long = "version",
+            help = "The version of the package. If not given, all versions are removed."
+        )]
+        version: Option<String>,
+        #[structopt(
+            long = "columnar",
+            help = "Store relations in the compressed columnar format instead of the raw one."
+        )]
+        columnar: bool,
+    },
+    #[structopt(
+        name = "extract-subset",
+        about = "Write the builds of the listed crates and all facts that belong to them into a new database."
+    )]
+    ExtractSubset {
+        #[structopt(
+            parse(from_os_str),
+            long = "crates",
+            help = "The file that lists the crates to extract in the format of the crate list."
+        )]
+        crates: PathBuf,
+        #[structopt(
+            parse(from_os_str),
+            long = "out",
+            help = "The directory in which the new database is created."
+        )]
+        out: PathBuf,
+        #[structopt(
+            long = "columnar",
+            help = "Store relations in the compressed columnar format instead of the raw one."
+        )]
+        columnar: bool,
+    },
+    #[structopt(
+        name = "migrate",
+        about = "Migrate the database to the current schema version."
+    )]
+    Migrate,
+    #[structopt(
+        name = "export-sqlite",
+        about = "Export all relations and interning tables into an SQLite database."
+    )]
+    ExportSqlite {
+        #[structopt(parse(from_os_str), help = "The SQLite file to create.")]
+        sqlite_path: PathBuf,
+    },
+    #[structopt(
+        name = "export-parquet",
+        about = "Export the specified relations into Parquet files."
+    )]
+    ExportParquet {
+        #[structopt(
+            long = "relations",
+            use_delimiter = true,
+            required = true,
+            help = "A comma separated list of core or derived relations to export."
+        )]
+        relations: Vec<String>,
+        #[structopt(
+            long = "resolve",
+            help = "Add columns that resolve interned strings, definition paths, and builds."
+        )]
+        resolve: bool,
+        #[structopt(
+            parse(from_os_str),
+            default_value = "../workspace/parquet",
+            long = "output-dir",
+            help = "The directory in which the Parquet files are saved."
+        )]
+        output_dir: PathBuf,
+    },
+    #[structopt(
+        name = "dump-relation",
+        about = "Print the contents of a relation or an interning table."
+    )]
+    DumpRelation {
+        #[structopt(help = "The name of the core or derived relation, or the interning table.")]
+        name: String,
+        #[structopt(
+            long = "resolve",
+            help = "Add columns that resolve interned strings, definition paths, and builds."
+        )]
+        resolve: bool,
+        #[structopt(long = "limit", help = "The maximum number of rows to print.")]
+        limit: Option<usize>,
+        #[structopt(
+            default_value = "csv",
+            long = "format",
+            possible_values = &["csv", "jsonl"],
+            help = "The output format."
+        )]
+        format: corpus_database::DumpFormat,
+    },
+    #[structopt(
+        name = "check-database",
+        about = "Check the referential integrity and the checksums of the database."
+    )]
+    CheckDatabase,
+    #[structopt(
+        name = "diff-database",
+        about = "Compare two databases and write the differences as CSV files."
+    )]
+    DiffDatabase {
+        #[structopt(parse(from_os_str), help = "The old database.")]
+        database_a: PathBuf,
+        #[structopt(parse(from_os_str), help = "The new database.")]
+        database_b: PathBuf,
+        #[structopt(
+            parse(from_os_str),
+            default_value = "../workspace/diff",
+            long = "output-dir",
+            help = "The directory into which the CSV files are written."
+        )]
+        output_dir: PathBuf,
+    },
     #[structopt(name = "query", about = "Run a specific query.")]
     RunQuery {
         #[structopt(help = "The name of the query to run.")]
@@ -133,6 +280,17 @@ enum Command {
             help = "The directory in which the reports are saved."
         )]
         report_path: PathBuf,
+        #[structopt(
+            long = "memory-budget",
+            help = "The maximum size in MiB of the relations kept loaded; the least recently used ones are evicted first."
+        )]
+        memory_budget: Option<usize>,
+        #[structopt(
+            parse(from_os_str),
+            long = "crates-dir",
+            help = "Query the crates saved by the extractor in this directory instead of the database."
+        )]
+        crates_dir: Option<PathBuf>,
     },
 }
 
@@ -242,12 +400,77 @@ fn main() {
         Command::MoveExtracted { target_dir } => {
             corpus_manager::move_extracted(&args.workspace, &target_dir);
         }
-        Command::UpdateDatabase => {
-            corpus_manager::update_database(&args.workspace, &args.database_root);
+        Command::UpdateDatabase { columnar, jobs } => {
+            if let Err(e) = corpus_manager::update_database(
+                &args.workspace,
+                &args.database_root,
+                columnar,
+                jobs,
+            ) {
+                error!("Failed to update the database: {:?}", e);
+                std::process::exit(1);
+            }
+        }
+        Command::Compact { columnar } => {
+            corpus_manager::compact_database(&args.database_root, columnar);
+        }
+        Command::RemoveBuilds {
+            package,
+            version,
+            columnar,
+        } => {
+            corpus_manager::remove_builds(
+                &args.database_root,
+                &package,
+                version.as_deref(),
+                columnar,
+            );
+        }
+        Command::ExtractSubset {
+            crates,
+            out,
+            columnar,
+        } => {
+            corpus_manager::extract_subset(&args.database_root, &crates, &out, columnar);
+        }
+        Command::Migrate => {
+            corpus_manager::migrate_database(&args.database_root);
+        }
+        Command::ExportSqlite { sqlite_path } => {
+            corpus_manager::export_sqlite(&args.database_root, &sqlite_path);
+        }
+        Command::ExportParquet {
+            relations,
+            resolve,
+            output_dir,
+        } => {
+            corpus_manager::export_parquet(&args.database_root, &relations, resolve, &output_dir);
+        }
+        Command::DumpRelation {
+            name,
+            resolve,
+            limit,
+            format,
+        } => {
+            corpus_manager::dump_relation(&args.database_root, &name, resolve, limit, format);
+        }
+        Command::CheckDatabase => {
+            if !corpus_manager::check_database(&args.database_root) {
+                std::process::exit(1);
+            }
+        }
+        Command::DiffDatabase {
+            database_a,
+            database_b,
+            output_dir,
+        } => {
+            corpus_manager::diff_database(&database_a, &database_b, &output_dir);
         }
         Command::RunQuery {
             query_name,
             report_path,
+            memory_budget,
+            crates_dir,
         } => {
             corpus_manager::run_query(
                 &query_name,
@@ -255,6 +478,8 @@ fn main() {
                 &report_path,
                 &args.workspace,
                 &args.crate_list_path,
+                memory_budget.map(|budget| budget * 1024 * 1024),
+                crates_dir.as_deref(),
             );
         }
     }
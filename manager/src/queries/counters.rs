@@ -1,8 +1,8 @@
 //! Compute unsafe blocks and unsafe statements.
 
-use super::utils::{DefPathResolver, GroupByIterator, SpanResolver};
+use super::utils::{GroupByIterator, SpanResolver};
 use crate::write_csv;
-use corpus_database::{tables::Loader, types};
+use corpus_database::{tables::Loader, types, Resolver};
 use corpus_queries_derive::datapond_query;
 use log::info;
 use std::collections::{HashMap, HashSet};
@@ -46,7 +46,7 @@ pub fn query(loader: &Loader, report_path: &Path) {
     let selected_scopes = loader.load_selected_scopes();
 
     let strings = loader.load_strings();
-    let def_path_resolver = DefPathResolver::new(loader);
+    let resolver = Resolver::new(loader);
     let span_resolver = SpanResolver::new(loader);
     let selected_builds: HashSet<_> = loader
         .load_selected_builds()
@@ -132,11 +132,11 @@ pub fn query(loader: &Loader, report_path: &Path) {
                 assert!(
                     selected_builds.contains(&build),
                     "Unsafe block from non-selected build: {:?}",
-                    def_path_resolver.resolve(mir_body_def_path)
+                    resolver.resolve(mir_body_def_path)
                 );
                 unsafe_blocks.push((
                     build,
-                    def_path_resolver.resolve(mir_body_def_path),
+                    resolver.resolve(mir_body_def_path),
                     scope,
                     check_mode.to_string(),
                     span_resolver.resolve(span),
@@ -230,7 +230,7 @@ pub fn query(loader: &Loader, report_path: &Path) {
         |&(build, item, def_path, module, visibility, unsafety, abi, _return_ty, uses_unsafe)| {
             (
                 build,
-                def_path_resolver.resolve(def_path),
+                resolver.resolve(def_path),
                 item,
                 def_path,
                 module,
@@ -1,33 +1,13 @@
 //! Report function sizes in MIR statements.
 
-use super::utils::{BuildResolver, DefPathResolver};
 use crate::write_csv;
-use corpus_database::{tables::Loader, types};
+use corpus_database::{tables::Loader, types, Resolver};
 use std::collections::HashMap;
 use std::path::Path;
 
 // Compute the derived relations `selected_function_sizes` and
 // `selected_build_sizes`.
 fn collect_function_sizes(loader: &Loader) {
-    let function_scopes: HashMap<_, _> = {
-        let selected_scopes = loader.load_selected_scopes();
-        selected_scopes
-            .iter()
-            .map(
-                |&(
-                    build,
-                    mir_body_def_path,
-                    scope,
-                    _parent,
-                    safety,
-                    _explicit_unsafe_group,
-                    check_mode,
-                    _span,
-                )| { (scope, (build, mir_body_def_path, safety, check_mode)) },
-            )
-            .collect()
-    };
-
     let function_definitions: HashMap<_, _> = {
         let selected_function_definitions = loader.load_selected_function_definitions();
         let selected_function_definitions: HashMap<_, _> = selected_function_definitions
@@ -76,7 +56,17 @@ fn collect_function_sizes(loader: &Loader) {
     let mut selected_build_sizes_map: HashMap<_, (u64, u64, u64)> = HashMap::new();
 
     for (_stmt, _block, _index, _kind, scope) in loader.load_statements().iter() {
-        if let Some(&(build, mir_body_def_path, safety, check_mode)) = function_scopes.get(scope) {
+        if let Some(&(
+            build,
+            mir_body_def_path,
+            _scope,
+            _parent,
+            safety,
+            _explicit_unsafe_group,
+            check_mode,
+            _span,
+        )) = loader.lookup_selected_scopes_by_scope(*scope).last()
+        {
             {
                 let (build_stmt, build_unsafe_stmt, build_user_unsafe_stmt) =
                     selected_build_sizes_map.entry(build).or_default();
@@ -155,8 +145,7 @@ fn collect_function_sizes(loader: &Loader) {
 }
 
 fn report_function_sizes(loader: &Loader, report_path: &Path) {
-    let build_resolver = BuildResolver::new(loader);
-    let def_path_resolver = DefPathResolver::new(loader);
+    let resolver = Resolver::new(loader);
     let abis = loader.load_abis();
     let strings = loader.load_strings();
 
@@ -166,12 +155,7 @@ fn report_function_sizes(loader: &Loader, report_path: &Path) {
         selected_build_sizes
             .iter()
             .map(|&(build, stmt, unsafe_stmt, user_unsafe_stmt)| {
-                (
-                    build_resolver.resolve(build),
-                    stmt,
-                    unsafe_stmt,
-                    user_unsafe_stmt,
-                )
+                (resolver.resolve(build), stmt, unsafe_stmt, user_unsafe_stmt)
             });
     write_csv!(report_path, selected_build_sizes);
 
@@ -190,9 +174,9 @@ fn report_function_sizes(loader: &Loader, report_path: &Path) {
             user_unsafe_stmt,
         )| {
             (
-                build_resolver.resolve(build),
+                resolver.resolve(build),
                 item,
-                def_path_resolver.resolve(def_path),
+                resolver.resolve(def_path),
                 visibility.to_string(),
                 unsafety.to_string(),
                 &strings[abis[abi]],
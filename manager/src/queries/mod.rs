@@ -19,157 +19,99 @@ mod unsafe_spans;
 mod unsafe_types;
 mod utils;
 
+/// The queries run by `all` grouped into stages. The queries of a stage do
+/// not depend on each other and run in parallel; they only use the derived
+/// relations stored by the queries of the earlier stages. The relations
+/// loaded by a stage are unloaded when it finishes, so the memory used by
+/// `all` is bounded by its largest stage even without a memory budget.
+const ALL_QUERY_STAGES: &[&[&str]] = &[
+    &[
+        "unsafe-reasons",
+        "prepare-builds",
+        "build-files",
+        "build-meta",
+    ],
+    &["prepare-items"],
+    &["counters", "traits", "types", "unsafe-spans"],
+    &[
+        "size",
+        "function-size",
+        "unsafe-block-groups",
+        "unsafe-types",
+        "non-tree-types",
+    ],
+    &["unsafe-block-calls"],
+];
+
+/// Run the query by using `loader`, which is shared by all queries run by
+/// `prepare-all` and `all`.
 pub fn run_query(
     query_name: &str,
-    database_root: &Path,
+    loader: &corpus_database::tables::Loader,
     report_path: &Path,
     workspace_path: &Path,
     sources_list_path: &Path,
 ) {
     info!("Running query: {}", query_name);
-    let loader = corpus_database::tables::Loader::new(database_root.to_path_buf());
     match query_name {
         "prepare-builds" => prepare_builds::query(
-            &loader,
+            loader,
             &report_path.join("prepare-builds"),
             workspace_path,
             sources_list_path,
         ),
-        "prepare-items" => prepare_items::query(&loader),
+        "prepare-items" => prepare_items::query(loader),
         "prepare-all" => {
-            run_query(
-                "prepare-builds",
-                database_root,
-                report_path,
-                workspace_path,
-                sources_list_path,
-            );
-            run_query(
-                "prepare-items",
-                database_root,
-                report_path,
-                workspace_path,
-                sources_list_path,
-            );
+            for query_name in ["prepare-builds", "prepare-items"] {
+                run_query(
+                    query_name,
+                    loader,
+                    report_path,
+                    workspace_path,
+                    sources_list_path,
+                );
+            }
         }
-        "counters" => counters::query(&loader, &report_path.join("q-counters")),
-        "size" => size::query(&loader, &report_path.join("q-size")),
-        "function-size" => function_size::query(&loader, &report_path.join("function-size")),
-        "build-files" => build_files::query(&loader, &report_path.join("build-files")),
-        "traits" => traits::query(&loader, &report_path.join("traits")),
-        "types" => types::query(&loader, &report_path.join("types")),
-        "resolved-calls" => resolved_calls::query(&loader, &report_path.join("resolved-calls")),
-        "unsafe-types" => unsafe_types::query(&loader, &report_path.join("unsafe-types")),
+        "counters" => counters::query(loader, &report_path.join("q-counters")),
+        "size" => size::query(loader, &report_path.join("q-size")),
+        "function-size" => function_size::query(loader, &report_path.join("function-size")),
+        "build-files" => build_files::query(loader, &report_path.join("build-files")),
+        "traits" => traits::query(loader, &report_path.join("traits")),
+        "types" => types::query(loader, &report_path.join("types")),
+        "resolved-calls" => resolved_calls::query(loader, &report_path.join("resolved-calls")),
+        "unsafe-types" => unsafe_types::query(loader, &report_path.join("unsafe-types")),
         "unsafe-block-groups" => {
-            unsafe_block_groups::query(&loader, &report_path.join("unsafe-block-groups"))
+            unsafe_block_groups::query(loader, &report_path.join("unsafe-block-groups"))
         }
-        "unsafe-reasons" => unsafe_reasons::query(&loader, &report_path.join("unsafe-reasons")),
+        "unsafe-reasons" => unsafe_reasons::query(loader, &report_path.join("unsafe-reasons")),
         "unsafe-block-calls" => {
-            unsafe_block_calls::query(&loader, &report_path.join("unsafe-block-calls"))
+            unsafe_block_calls::query(loader, &report_path.join("unsafe-block-calls"))
         }
-        "unsafe-spans" => unsafe_spans::query(&loader, &report_path.join("unsafe-spans")),
-        "build-meta" => build_meta::query(&loader, &report_path.join("build-meta")),
-        "non-tree-types" => non_tree_types::query(&loader, &report_path.join("non-tree-types")),
+        "unsafe-spans" => unsafe_spans::query(loader, &report_path.join("unsafe-spans")),
+        "build-meta" => build_meta::query(loader, &report_path.join("build-meta")),
+        "non-tree-types" => non_tree_types::query(loader, &report_path.join("non-tree-types")),
         "all" => {
-            run_query(
-                "unsafe-reasons",
-                database_root,
-                report_path,
-                workspace_path,
-                sources_list_path,
-            );
-            run_query(
-                "prepare-all",
-                database_root,
-                report_path,
-                workspace_path,
-                sources_list_path,
-            );
-            run_query(
-                "counters",
-                database_root,
-                report_path,
-                workspace_path,
-                sources_list_path,
-            );
-            run_query(
-                "size",
-                database_root,
-                report_path,
-                workspace_path,
-                sources_list_path,
-            );
-            run_query(
-                "function-size",
-                database_root,
-                report_path,
-                workspace_path,
-                sources_list_path,
-            );
-            run_query(
-                "build-files",
-                database_root,
-                report_path,
-                workspace_path,
-                sources_list_path,
-            );
-            run_query(
-                "build-meta",
-                database_root,
-                report_path,
-                workspace_path,
-                sources_list_path,
-            );
-            run_query(
-                "traits",
-                database_root,
-                report_path,
-                workspace_path,
-                sources_list_path,
-            );
-            run_query(
-                "types",
-                database_root,
-                report_path,
-                workspace_path,
-                sources_list_path,
-            );
-            run_query(
-                "unsafe-types",
-                database_root,
-                report_path,
-                workspace_path,
-                sources_list_path,
-            );
-            run_query(
-                "unsafe-block-groups",
-                database_root,
-                report_path,
-                workspace_path,
-                sources_list_path,
-            );
-            run_query(
-                "unsafe-block-calls",
-                database_root,
-                report_path,
-                workspace_path,
-                sources_list_path,
-            );
-            run_query(
-                "unsafe-spans",
-                database_root,
-                report_path,
-                workspace_path,
-                sources_list_path,
-            );
-            run_query(
-                "non-tree-types",
-                database_root,
-                report_path,
-                workspace_path,
-                sources_list_path,
-            );
+            for stage in ALL_QUERY_STAGES {
+                std::thread::scope(|scope| {
+                    for query_name in stage.iter() {
+                        scope.spawn(move || {
+                            run_query(
+                                query_name,
+                                loader,
+                                report_path,
+                                workspace_path,
+                                sources_list_path,
+                            )
+                        });
+                    }
+                });
+                info!("Loaded relations:\n{}", loader.memory_usage());
+                loader.unload_relations();
+            }
         }
         _ => unreachable!("Unknown query: {}", query_name),
     }
 }
+
+#[cfg(test)]
+mod tests;
@@ -47,19 +47,14 @@ pub fn query(loader: &Loader, report_path: &Path) {
         .map(|(ty, desc, generics)| (ty, (desc, generics)))
         .collect();
 
-    let basic_block_def_paths: HashMap<_, _> = loader
-        .load_basic_blocks()
-        .iter()
-        .map(|&(bb, def_path, _kind)| (bb, def_path))
-        .collect();
-
     let all_calls = loader.load_terminators_call();
     let all_calls = all_calls.iter().filter_map(
         |&(block, call, _func, _unsafety, _abi, _return_ty, _destination, _span)| {
             let target = call_target.get(&call)?; // none for function pointers
             let (target_desc, function_generics, type_generics) = call_target_desc[&call];
 
-            let (caller_crate, _, _, _, _) = def_paths[basic_block_def_paths[&block]];
+            let (_, caller_def_path, _) = loader.lookup_basic_blocks_by_block(block)[0];
+            let (caller_crate, _, _, _, _) = def_paths[caller_def_path];
             let caller_crate_name = &strings[crate_names[caller_crate]];
             let (target_crate, _, _, _, _) = def_paths[*target];
             let target_crate_name = &strings[crate_names[target_crate]];
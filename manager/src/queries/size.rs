@@ -1,15 +1,14 @@
 //! Report unsafe block sizes by MIR statements.
 
-use super::utils::BuildResolver;
 use super::utils::GroupByIterator;
 use crate::write_csv;
-use corpus_database::tables::Loader;
+use corpus_database::{tables::Loader, Resolver};
 use std::collections::{HashMap, HashSet};
 use std::path::Path;
 
 pub fn query(loader: &Loader, report_path: &Path) {
     let mut seen_scopes = HashSet::new();
-    let build_resolver = BuildResolver::new(loader);
+    let resolver = Resolver::new(loader);
     let unsafe_statements = loader.load_unsafe_statements();
     let unsafe_blocks_by_stmts = unsafe_statements.iter().safe_group_by(
         |(build, _stmt, _block, _index, _kind, unsafe_scope, check_mode)| {
@@ -68,7 +67,7 @@ pub fn query(loader: &Loader, report_path: &Path) {
         |(build, unsafe_scope, check_mode, statement_count, terminator_count)| {
             (
                 build,
-                build_resolver.resolve(build),
+                resolver.resolve(build),
                 unsafe_scope,
                 check_mode.to_string(),
                 statement_count,
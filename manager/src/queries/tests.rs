@@ -0,0 +1,131 @@
+//! Tests of the queries written with `datapond_query!`.
+
+use corpus_database::tables::{Loader, Tables};
+use corpus_database::types::{BasicBlock, UnwindAction};
+use corpus_queries_derive::datapond_query;
+
+/// Store `tables` in a temporary directory and create a loader for them.
+fn store_tables(tables: Tables) -> (tempfile::TempDir, Loader) {
+    let database_dir = tempfile::tempdir().unwrap();
+    tables.store_multifile(database_dir.path()).unwrap();
+    let loader = Loader::new(database_dir.path().to_path_buf());
+    (database_dir, loader)
+}
+
+#[test]
+fn test_load_optional_ids() {
+    let mut tables = Tables::default();
+    let block = tables.get_fresh_basicblock();
+    let cleanup = tables.get_fresh_basicblock();
+    tables.register_terminators_unwind_action(block, UnwindAction::Cleanup, Some(cleanup));
+    tables.register_terminators_unwind_action(cleanup, UnwindAction::Continue, None);
+    let (_database_dir, loader) = store_tables(tables);
+    let cleanup_actions;
+    datapond_query! {
+        load loader {
+            relations(terminators_unwind_action),
+        }
+        output cleanup_actions(block: BasicBlock, cleanup: Option<BasicBlock>)
+        cleanup_actions(block, cleanup) :-
+            terminators_unwind_action(.block=block, .cleanup=cleanup).
+    }
+    assert_eq!(
+        cleanup_actions.elements,
+        vec![(block, Some(cleanup)), (cleanup, None)]
+    );
+}
+
+#[test]
+fn test_aggregates() {
+    let edges = vec![
+        (1u32, 1u32, 7u32),
+        (1, 2, 5),
+        (1, 3, 7),
+        (1, 4, 7),
+        (2, 3, 1),
+        (3, 1, 4),
+    ];
+    let stats;
+    let weights;
+    let total_counts;
+    datapond_query! {
+        input edges(from: u32, to: u32, weight: u32)
+        output stats(from: u32, count: u16, total: u64, lightest: u32, heaviest: u32)
+        output weights(from: u32, weight: u32, count: u32)
+        output total_counts(count: u32)
+        // The bindings of `from` and `weight` are distinct, so the three
+        // edges of `1` with the weight `7` are counted once.
+        stats(from, count(), sum(weight), min(weight), max(weight)) :-
+            edges(from, _, weight).
+        weights(from, weight, count()) :- edges(from, to, weight), edges(to, _, _).
+        total_counts(count()) :- edges(_, to, _).
+    }
+    assert_eq!(
+        stats.elements,
+        vec![(1, 2, 12, 5, 7), (2, 1, 1, 1, 1), (3, 1, 4, 4, 4)]
+    );
+    assert_eq!(
+        weights.elements,
+        vec![(1, 5, 1), (1, 7, 2), (2, 1, 1), (3, 4, 1)]
+    );
+    assert_eq!(total_counts.elements, vec![(4,)]);
+}
+
+#[test]
+#[should_panic(
+    expected = "The aggregate in the column `total` of `totals` does not fit into `u8`."
+)]
+fn test_aggregate_overflow() {
+    let values = vec![(1u32, 200u32), (1, 100)];
+    let totals;
+    datapond_query! {
+        input values(key: u32, value: u32)
+        output totals(key: u32, total: u8)
+        totals(key, sum(value)) :- values(key, value).
+    }
+    drop(totals);
+}
+
+#[test]
+#[should_panic(
+    expected = "The aggregate in the column `largest` of `maxima` does not fit into `u8`."
+)]
+fn test_aggregate_conversion_overflow() {
+    let values = vec![(1u32, 300u32)];
+    let maxima;
+    datapond_query! {
+        input values(key: u32, value: u32)
+        output maxima(key: u32, largest: u8)
+        maxima(key, max(value)) :- values(key, value).
+    }
+    drop(maxima);
+}
+
+#[test]
+fn test_negations() {
+    let nodes = vec![(1u32,), (2,), (3,), (4,)];
+    let edges = vec![(1u32, 2u32), (2, 2), (2, 3), (4, 1)];
+    let sinks;
+    let sources;
+    let without_loops;
+    let inner_nodes;
+    datapond_query! {
+        input nodes(node: u32)
+        input edges(from: u32, to: u32)
+        output sinks(node: u32)
+        output sources(node: u32)
+        output without_loops(node: u32)
+        output inner_nodes(node: u32)
+        sinks(node) :- nodes(node), !edges(.from=node).
+        sources(node) :- nodes(node), !edges(_, node).
+        // The repeated variable matches only the edges from a node to
+        // itself.
+        without_loops(node) :- edges(node, _), !edges(node, node).
+        // The negated relations are computed by the query.
+        inner_nodes(node) :- nodes(node), !sinks(node), !sources(node).
+    }
+    assert_eq!(sinks.elements, vec![(3,)]);
+    assert_eq!(sources.elements, vec![(4,)]);
+    assert_eq!(without_loops.elements, vec![(1,), (4,)]);
+    assert_eq!(inner_nodes.elements, vec![(1,), (2,)]);
+}
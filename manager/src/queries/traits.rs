@@ -1,9 +1,8 @@
 //! Report information about traits and their implementations.
 
-use super::utils::DefPathResolver;
 use super::utils::GroupByIterator;
 use crate::write_csv;
-use corpus_database::tables::Loader;
+use corpus_database::{tables::Loader, Resolver};
 use log::info;
 use std::collections::HashMap;
 use std::path::Path;
@@ -12,13 +11,13 @@ pub fn query(loader: &Loader, report_path: &Path) {
     let selected_builds = loader.load_selected_builds();
     let def_paths = loader.load_def_paths();
     let all_traits_relation = loader.load_traits();
-    let def_path_resolver = DefPathResolver::new(loader);
+    let resolver = Resolver::new(loader);
     info!("Loaded relations.");
 
     let all_traits = all_traits_relation.iter().map(
         |&(item, def_path, _name, visibility, is_auto, is_marker, unsafety)| {
             (
-                def_path_resolver.resolve(def_path),
+                resolver.resolve(def_path),
                 item,
                 visibility.to_string(),
                 unsafety.to_string(),
@@ -56,7 +55,7 @@ pub fn query(loader: &Loader, report_path: &Path) {
         |&(build, item, def_path, _name, visibility, is_auto, is_marker, unsafety)| {
             (
                 build,
-                def_path_resolver.resolve(def_path),
+                resolver.resolve(def_path),
                 item,
                 visibility.to_string(),
                 unsafety.to_string(),
@@ -143,14 +142,14 @@ pub fn query(loader: &Loader, report_path: &Path) {
             impl_traits.get(&item).map(|&trait_def_path| {
                 (
                     build,
-                    def_path_resolver.resolve(def_path),
+                    resolver.resolve(def_path),
                     item,
                     visibility.to_string(),
                     unsafety.to_string(),
                     polarity.to_string(),
                     defaultness.to_string(),
                     constness.to_string(),
-                    def_path_resolver.resolve(*trait_def_path),
+                    resolver.resolve(*trait_def_path),
                 )
             })
         },
@@ -1,14 +1,13 @@
-use super::utils::{BuildResolver, DefPathResolver};
 use crate::write_csv;
-use corpus_database::tables::Loader;
+use corpus_database::rows::SelectedAdtFieldType;
+use corpus_database::{tables::Loader, Resolver};
 use log::info;
 use std::collections::HashMap;
 use std::path::Path;
 
 /// Collect general information about types.
 pub fn query(loader: &Loader, report_path: &Path) {
-    let def_path_resolver = DefPathResolver::new(loader);
-    let build_resolver = BuildResolver::new(loader);
+    let resolver = Resolver::new(loader);
 
     let selected_builds = loader.load_selected_builds();
     let def_paths = loader.load_def_paths();
@@ -47,10 +46,10 @@ pub fn query(loader: &Loader, report_path: &Path) {
     let selected_type_defs = selected_type_defs_relation.iter().map(
         |&(build, item, typ, def_path, name, visibility, type_kind, def_kind)| {
             (
-                build_resolver.resolve(build),
+                resolver.resolve(build),
                 item,
                 typ,
-                def_path_resolver.resolve(def_path),
+                resolver.resolve(def_path),
                 &strings[name],
                 visibility.to_string(),
                 &strings[type_kinds[type_kind]],
@@ -109,11 +108,11 @@ pub fn query(loader: &Loader, report_path: &Path) {
             is_phantom,
         )| {
             (
-                build_resolver.resolve(build),
+                resolver.resolve(build),
                 item,
                 typ,
-                def_path_resolver.resolve(def_path),
-                def_path_resolver.resolve(resolved_def_path),
+                resolver.resolve(def_path),
+                resolver.resolve(resolved_def_path),
                 &strings[name],
                 visibility.to_string(),
                 &strings[type_kinds[type_kind]],
@@ -219,51 +218,31 @@ pub fn query(loader: &Loader, report_path: &Path) {
         "Number of selected ADT fields: {}",
         selected_adt_field_types_relation.len()
     );
-    let selected_adt_field_types = selected_adt_field_types_relation.iter().map(
-        |&(
-            build,
-            item,
-            adt,
-            adt_variant,
-            adt_def_path,
-            resolved_adt_def_path,
-            field_def_path,
-            name,
-            visibility,
-            type_kind,
-            def_kind,
-            kind,
-            c_repr,
-            is_phantom,
-            field_name,
-            field_visibility,
-            field_type,
-            field_type_kind,
-        )| {
+    let selected_adt_field_types = selected_adt_field_types_relation.iter().map(|&fact| {
+        let field = SelectedAdtFieldType::from(fact);
+        (
+            resolver.resolve(field.build),
+            field.item,
+            field.adt,
+            field.adt_variant,
+            resolver.resolve(field.adt_def_path),
+            resolver.resolve(field.resolved_adt_def_path),
+            resolver.resolve(field.field_def_path),
             (
-                build_resolver.resolve(build),
-                item,
-                adt,
-                adt_variant,
-                def_path_resolver.resolve(adt_def_path),
-                def_path_resolver.resolve(resolved_adt_def_path),
-                def_path_resolver.resolve(field_def_path),
-                (
-                    &strings[name],
-                    visibility.to_string(),
-                    &strings[type_kinds[type_kind]],
-                    def_kind.to_string(),
-                    kind.to_string(),
-                    c_repr,
-                    is_phantom,
-                ),
-                &strings[field_name],
-                field_visibility.to_string(),
-                field_type,
-                &strings[type_kinds[field_type_kind]],
-            )
-        },
-    );
+                &strings[field.name],
+                field.visibility.to_string(),
+                &strings[type_kinds[field.type_kind]],
+                field.def_kind.to_string(),
+                field.kind.to_string(),
+                field.c_repr,
+                field.is_phantom,
+            ),
+            &strings[field.field_name],
+            field.field_visibility.to_string(),
+            field.field_type,
+            &strings[type_kinds[field.field_type_kind]],
+        )
+    });
 
     write_csv!(report_path, selected_adt_field_types);
 
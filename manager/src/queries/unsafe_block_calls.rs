@@ -1,15 +1,15 @@
 //! Report information about calls in our codebase. For calls from unsafe blocks
 //! report additional information.
 
-use super::utils::{BuildResolver, SpanResolver};
+use super::utils::SpanResolver;
 use crate::write_csv;
-use corpus_database::tables::Loader;
+use corpus_database::{tables::Loader, Resolver};
 use std::collections::{HashMap, HashSet};
 use std::path::Path;
 
 /// Report information about calls from unsafe blocks.
 fn report_unsafe_block_calls(loader: &Loader, report_path: &Path) {
-    let build_resolver = BuildResolver::new(loader);
+    let resolver = Resolver::new(loader);
     let span_resolver = SpanResolver::new(loader);
 
     let def_paths = loader.load_def_paths();
@@ -61,7 +61,7 @@ fn report_unsafe_block_calls(loader: &Loader, report_path: &Path) {
             let unsafe_scope_span = scope_spans[&unsafe_scope];
             (
                 build,
-                build_resolver.resolve(build),
+                resolver.resolve(build),
                 block,
                 unsafe_scope,
                 span_resolver.resolve(unsafe_scope_span),
@@ -1,26 +1,30 @@
 //! Report information about function calls.
 
-use super::utils::GroupByIterator;
-use super::utils::{BuildResolver, DefPathResolver};
 use crate::write_csv;
-use corpus_database::tables::Loader;
+use corpus_database::{tables::Loader, Resolver};
 use corpus_queries_derive::datapond_query;
 use log::info;
 use std::collections::HashSet;
-use std::convert::TryInto;
 use std::path::Path;
 
 /// Count how many functions are called from each unsafe block.
 fn count_called_functions(loader: &Loader) {
     let unsafe_block_calls;
+    let unsafe_block_call_counts;
+    let unsafe_block_no_calls;
     datapond_query! {
         load loader {
-            relations(unsafe_terminators, terminators_call),
+            relations(unsafe_terminators, terminators_call, unsafe_blocks),
         }
         output unsafe_block_calls(
             build: Build, block: BasicBlock, unsafe_scope: Scope,
             check_mode: BlockCheckMode, call: FunctionCall,
             unsafety: Unsafety, abi: Abi, return_ty: Type)
+        output unsafe_block_call_counts(
+            build: Build, unsafe_scope: Scope, check_mode: BlockCheckMode, call_count: u16)
+        output unsafe_block_no_calls(
+            build: Build, mir_body_def_path: DefPath, scope: Scope,
+            expansion_kind: SpanExpansionKind, check_mode: BlockCheckMode, span: Span)
         unsafe_block_calls(
             build, block, unsafe_scope, check_mode, call, unsafety, abi, return_ty
         ) :-
@@ -30,6 +34,14 @@ fn count_called_functions(loader: &Loader) {
             terminators_call(
                 .block=block, .call=call, .unsafety=unsafety,
                 .abi=abi, .return_ty=return_ty).
+        unsafe_block_call_counts(build, unsafe_scope, check_mode, count()) :-
+            unsafe_block_calls(build, _, unsafe_scope, check_mode, call, _, _, _).
+        unsafe_block_no_calls(
+            build, mir_body_def_path, scope, expansion_kind, check_mode, span
+        ) :-
+            unsafe_blocks(
+                build, mir_body_def_path, scope, expansion_kind, check_mode, span),
+            !unsafe_block_calls(.unsafe_scope=scope).
     }
     let unsafe_block_calls_relation = unsafe_block_calls.elements;
     info!(
@@ -37,43 +49,13 @@ fn count_called_functions(loader: &Loader) {
         unsafe_block_calls_relation.len()
     );
 
-    let unsafe_block_call_counts_relation: Vec<_> = unsafe_block_calls_relation
-        .iter()
-        .safe_group_by(
-            |&&(build, _block, unsafe_scope, check_mode, _call, _unsafety, _abi, _return_ty)| {
-                (build, unsafe_scope, check_mode)
-            },
-        )
-        .into_iter()
-        .map(|((build, unsafe_scope, check_mode), group)| {
-            (
-                build,
-                unsafe_scope,
-                check_mode,
-                group.count().try_into().unwrap(),
-            )
-        })
-        .collect();
+    let unsafe_block_call_counts_relation = unsafe_block_call_counts.elements;
     info!(
         "Number of unsafe blocks with calls: {}",
         unsafe_block_call_counts_relation.len()
     );
 
-    let blocks_with_calls: HashSet<_> = unsafe_block_call_counts_relation
-        .iter()
-        .map(|&(_build, unsafe_scope, _check_mode, _call_count)| unsafe_scope)
-        .collect();
-
-    let unsafe_block_no_calls_relation: Vec<_> = loader
-        .load_unsafe_blocks()
-        .iter()
-        .filter(
-            |(_build, _mir_body_def_path, scope, _expansion_kind, _check_mode, _span)| {
-                !blocks_with_calls.contains(scope)
-            },
-        )
-        .cloned()
-        .collect();
+    let unsafe_block_no_calls_relation = unsafe_block_no_calls.elements;
     info!(
         "Number of unsafe blocks with non-const calls: {}",
         unsafe_block_no_calls_relation.len()
@@ -86,8 +68,7 @@ fn count_called_functions(loader: &Loader) {
 
 /// Report how many function calls each unsafe block contains.
 fn report_called_functions(loader: &Loader, report_path: &Path) {
-    let def_path_resolver = DefPathResolver::new(loader);
-    let build_resolver = BuildResolver::new(loader);
+    let resolver = Resolver::new(loader);
     let strings = loader.load_strings();
     let abis = loader.load_abis();
 
@@ -96,7 +77,7 @@ fn report_called_functions(loader: &Loader, report_path: &Path) {
         |&(build, block, unsafe_scope, check_mode, call, unsafety, abi, _return_ty)| {
             (
                 build,
-                build_resolver.resolve(build),
+                resolver.resolve(build),
                 block,
                 unsafe_scope,
                 check_mode.to_string(),
@@ -116,7 +97,7 @@ fn report_called_functions(loader: &Loader, report_path: &Path) {
             .map(|&(build, unsafe_scope, check_mode, call_count)| {
                 (
                     build,
-                    build_resolver.resolve(build),
+                    resolver.resolve(build),
                     unsafe_scope,
                     check_mode.to_string(),
                     call_count,
@@ -130,8 +111,8 @@ fn report_called_functions(loader: &Loader, report_path: &Path) {
         |&(build, mir_body_def_path, scope, expansion_kind, check_mode, _span)| {
             (
                 build,
-                build_resolver.resolve(build),
-                def_path_resolver.resolve(mir_body_def_path),
+                resolver.resolve(build),
+                resolver.resolve(mir_body_def_path),
                 scope,
                 expansion_kind.to_string(),
                 check_mode.to_string(),
@@ -150,7 +131,7 @@ fn report_non_const_call_targets(loader: &Loader, report_path: &Path) {
         .iter()
         .map(|(call, _def_path)| *call)
         .collect();
-    let build_resolver = BuildResolver::new(loader);
+    let resolver = Resolver::new(loader);
     let strings = loader.load_strings();
     let abis = loader.load_abis();
     let unsafe_block_calls = loader.load_unsafe_block_calls();
@@ -161,7 +142,7 @@ fn report_non_const_call_targets(loader: &Loader, report_path: &Path) {
             } else {
                 Some((
                     build,
-                    build_resolver.resolve(*build),
+                    resolver.resolve(*build),
                     block,
                     unsafe_scope,
                     check_mode.to_string(),
@@ -185,8 +166,7 @@ fn report_non_const_call_targets(loader: &Loader, report_path: &Path) {
 /// 4. Calls of closures.
 fn report_const_call_targets(loader: &Loader, report_path: &Path) {
     let const_calls_map = loader.load_terminators_call_const_target_as_map();
-    let def_path_resolver = DefPathResolver::new(loader);
-    let build_resolver = BuildResolver::new(loader);
+    let resolver = Resolver::new(loader);
     let strings = loader.load_strings();
     let abis = loader.load_abis();
     let unsafe_block_calls = loader.load_unsafe_block_calls();
@@ -195,8 +175,8 @@ fn report_const_call_targets(loader: &Loader, report_path: &Path) {
             const_calls_map.get(call).map(|def_path| {
                 Some((
                     build,
-                    build_resolver.resolve(*build),
-                    def_path_resolver.resolve(*def_path),
+                    resolver.resolve(*build),
+                    resolver.resolve(*def_path),
                     block,
                     unsafe_scope,
                     check_mode.to_string(),
@@ -1,16 +1,15 @@
 //! Report spans of the selected unsafe functions so that it is possible to
 //! quickly look up their source code.
 
-use super::utils::{BuildResolver, DefPathResolver, SpanResolver};
+use super::utils::SpanResolver;
 use crate::write_csv;
-use corpus_database::{tables::Loader, types};
+use corpus_database::{tables::Loader, types, Resolver};
 use std::collections::HashMap;
 use std::path::Path;
 
 /// Report the spans of the selected unsafe functions.
 fn report_unsafe_function_spans(loader: &Loader, report_path: &Path) {
-    let def_path_resolver = DefPathResolver::new(loader);
-    let build_resolver = BuildResolver::new(loader);
+    let resolver = Resolver::new(loader);
     let span_resolver = SpanResolver::new(loader);
     let strings = loader.load_strings();
     let abis = loader.load_abis();
@@ -23,8 +22,8 @@ fn report_unsafe_function_spans(loader: &Loader, report_path: &Path) {
             if unsafety == types::Unsafety::Unsafe {
                 Some((
                     build,
-                    build_resolver.resolve(build),
-                    def_path_resolver.resolve(def_path),
+                    resolver.resolve(build),
+                    resolver.resolve(def_path),
                     visibility.to_string(),
                     &strings[abis[abi]],
                     uses_unsafe,
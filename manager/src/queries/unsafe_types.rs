@@ -1,21 +1,20 @@
 //! Collect information about unsafe types.
 
 use super::utils::GroupByIterator;
-use super::utils::{BuildResolver, DefPathResolver};
 use crate::write_csv;
-use corpus_database::tables::Loader;
 use corpus_database::types;
+use corpus_database::{tables::Loader, Resolver};
 use corpus_queries_derive::datapond_query;
 use log::info;
 use std::collections::HashSet;
 use std::path::Path;
 
 fn report_types_foreign(loader: &Loader, report_path: &Path) {
-    let def_path_resolver = DefPathResolver::new(loader);
+    let resolver = Resolver::new(loader);
     let types_foreign = loader.load_types_foreign();
     let types_foreign = types_foreign
         .iter()
-        .map(|&(typ, def_path)| (typ, def_path_resolver.resolve(def_path)));
+        .map(|&(typ, def_path)| (typ, resolver.resolve(def_path)));
     write_csv!(report_path, types_foreign);
 }
 
@@ -43,10 +42,10 @@ fn collect_unsafe_cell_types(loader: &Loader, report_path: &Path) {
         "Number of UnsafeCell types: {}",
         unsafe_cell_types_relation.len()
     );
-    let def_path_resolver = DefPathResolver::new(loader);
+    let resolver = Resolver::new(loader);
     let unsafe_cell_types = unsafe_cell_types_relation
         .iter()
-        .map(|&(typ, def_path)| (typ, def_path_resolver.resolve(def_path)));
+        .map(|&(typ, def_path)| (typ, resolver.resolve(def_path)));
     write_csv!(report_path, unsafe_cell_types);
     loader.store_types_unsafe_cell(unsafe_cell_types_relation);
 }
@@ -109,8 +108,7 @@ fn collect_unsafe_types(loader: &Loader) {
 }
 
 fn report_unsafe_type_defs(loader: &Loader, report_path: &Path) {
-    let def_path_resolver = DefPathResolver::new(loader);
-    let build_resolver = BuildResolver::new(loader);
+    let resolver = Resolver::new(loader);
     let strings = loader.load_strings();
     let type_kinds = loader.load_type_kinds();
     let unsafe_types: HashSet<_> = loader
@@ -125,10 +123,10 @@ fn report_unsafe_type_defs(loader: &Loader, report_path: &Path) {
             if unsafe_types.contains(&typ) {
                 Some((
                     build,
-                    build_resolver.resolve(build),
+                    resolver.resolve(build),
                     item,
                     typ,
-                    def_path_resolver.resolve(def_path),
+                    resolver.resolve(def_path),
                     &strings[name],
                     visibility.to_string(),
                     &strings[type_kinds[type_kind]],
@@ -177,8 +175,7 @@ fn collect_safe_wrapper_types(loader: &Loader) {
 }
 
 fn report_safe_wrapper_type_defs(loader: &Loader, report_path: &Path) {
-    let def_path_resolver = DefPathResolver::new(loader);
-    let build_resolver = BuildResolver::new(loader);
+    let resolver = Resolver::new(loader);
     let strings = loader.load_strings();
     let type_kinds = loader.load_type_kinds();
     let safe_wrapper_types: HashSet<_> = loader
@@ -196,10 +193,10 @@ fn report_safe_wrapper_type_defs(loader: &Loader, report_path: &Path) {
             if safe_wrapper_types.contains(&typ) {
                 Some((
                     build,
-                    build_resolver.resolve(build),
+                    resolver.resolve(build),
                     item,
                     typ,
-                    def_path_resolver.resolve(def_path),
+                    resolver.resolve(def_path),
                     &strings[name],
                     visibility.to_string(),
                     &strings[type_kinds[type_kind]],